@@ -1,5 +1,6 @@
 //! Console API — abstraksi output untuk userspace
 
+pub use crate::sys::vga::{reset_color, set_color, Color};
 
 pub struct Style;
 impl Style {
@@ -1,3 +1,11 @@
 //! FS API — abstraksi filesystem untuk userspace
 
 pub use crate::sys::fs::{FileIO, PollEvent};
+pub use crate::sys::ipc::MAILBOX;
+
+/// Flush writes made through `handle` so that reopening the file sees them.
+/// Err holds the (negative) return value of the syscall.
+pub fn fsync(handle: usize) -> Result<(), isize> {
+    let ret = crate::api::syscall::fsync(handle);
+    if ret < 0 { Err(ret) } else { Ok(()) }
+}
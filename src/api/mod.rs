@@ -8,3 +8,4 @@ pub mod process;
 pub mod syscall;
 pub mod fs;
 pub mod io;
+pub mod net;
@@ -0,0 +1,33 @@
+//! Net API — datagram sockets addressed by local port number
+//!
+//! Processes that agree on a port can talk without knowing each other's
+//! PID. Errors are the negative error numbers from `sys::syscall::error`.
+
+use crate::api::syscall;
+pub use crate::sys::net::{SocketAddr, MAX_DATAGRAM};
+
+/// Open a socket and claim `port` for it
+pub fn bind(port: u16) -> Result<usize, isize> {
+    let handle = check(syscall::socket())?;
+    if let Err(e) = check(syscall::bind(handle, &SocketAddr::loopback(port))) {
+        syscall::close(handle);
+        return Err(e);
+    }
+    Ok(handle)
+}
+
+/// Queue `data` for the socket bound to `port`
+pub fn sendto(handle: usize, port: u16, data: &[u8]) -> Result<usize, isize> {
+    check(syscall::sendto(handle, port, data))
+}
+
+/// Wait for a datagram; returns the sender's port and the byte count
+pub fn recvfrom(handle: usize, buf: &mut [u8]) -> Result<(u16, usize), isize> {
+    let mut port = 0;
+    let n = check(syscall::recvfrom(handle, buf, &mut port))?;
+    Ok((port, n))
+}
+
+fn check(ret: isize) -> Result<usize, isize> {
+    if ret < 0 { Err(ret) } else { Ok(ret as usize) }
+}
@@ -9,6 +9,10 @@ pub enum ExitCode {
     IoError    = 3,
     ExecError  = 4,
     PageFault  = 5,
+    /// Not enough memory to load the program
+    NoMemory   = 6,
+    /// Every process slot is taken
+    Busy       = 7,
 }
 
 impl From<usize> for ExitCode {
@@ -19,6 +23,8 @@ impl From<usize> for ExitCode {
             3 => Self::IoError,
             4 => Self::ExecError,
             5 => Self::PageFault,
+            6 => Self::NoMemory,
+            7 => Self::Busy,
             _ => Self::Failure,
         }
     }
@@ -28,8 +34,108 @@ impl From<ExitCode> for usize {
     fn from(e: ExitCode) -> usize { e as usize }
 }
 
+impl core::fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Success   => "success",
+            Self::Failure   => "failure",
+            Self::NotFound  => "not found",
+            Self::IoError   => "I/O error",
+            Self::ExecError => "not an executable",
+            Self::PageFault => "page fault",
+            Self::NoMemory  => "out of memory",
+            Self::Busy      => "too many processes",
+        })
+    }
+}
+
 /// Exit the current process
 pub fn exit(code: ExitCode) -> ! {
     unsafe { crate::sys::syscall::syscall1(crate::sys::syscall::number::EXIT, code as usize); }
     loop { x86_64::instructions::hlt(); }
 }
+
+/// PID of the current process, the address other processes SEND to
+pub fn getpid() -> usize {
+    unsafe { crate::sys::syscall::syscall0(crate::sys::syscall::number::GETPID) }
+}
+
+/// PID of the process that started this one
+pub fn getppid() -> usize {
+    unsafe { crate::sys::syscall::syscall0(crate::sys::syscall::number::GETPPID) }
+}
+
+/// CPU time `pid` (0 for this process) has used, in milliseconds
+pub fn cpu_time_ms(pid: usize) -> Result<u64, isize> {
+    let ret = unsafe {
+        crate::sys::syscall::syscall1(crate::sys::syscall::number::PROC_STAT, pid) as isize
+    };
+    if ret < 0 { Err(ret) } else { Ok(ret as u64) }
+}
+
+pub use crate::sys::sched::{PRIO_DEFAULT, PRIO_HIGHEST, PRIO_LOWEST};
+
+/// Let another ready process run now instead of at the end of this
+/// process's time slice
+pub fn yield_now() {
+    unsafe { crate::sys::syscall::syscall0(crate::sys::syscall::number::YIELD); }
+}
+
+/// Move `pid` (0 for this process) to priority class `prio`, 0 first
+pub fn setprio(pid: usize, prio: u8) -> Result<(), isize> {
+    let ret = unsafe {
+        crate::sys::syscall::syscall2(crate::sys::syscall::number::SETPRIO, pid, prio as usize) as isize
+    };
+    if ret < 0 { Err(ret) } else { Ok(()) }
+}
+
+pub use crate::sys::signal::{
+    SIGINT, SIGKILL, SIGTERM, SIGUSR1, SIGUSR2, SIGCHLD, SIGCONT, SIGSTOP, SIGXCPU,
+    SIG_BLOCK, SIG_UNBLOCK, SIG_SETMASK,
+};
+
+pub use crate::sys::process::{
+    WaitInfo, CLD_EXITED, CLD_KILLED, CLD_STOPPED, CLD_CONTINUED,
+    WNOHANG, WSTOPPED, WEXITED, WCONTINUED, SPAWN_OVERLAY, SPAWN_SUSPENDED,
+};
+
+/// Wait for child `pid`, or any child if 0, to terminate, and return its
+/// pid and exit code
+pub fn wait(pid: usize) -> Result<(usize, ExitCode), isize> {
+    let mut status = 0;
+    let child = crate::api::syscall::wait(pid, &mut status);
+    if child < 0 {
+        return Err(child);
+    }
+    Ok((child as usize, ExitCode::from(status)))
+}
+
+/// Wait for child `pid`, or any child if 0, to exit, stop or continue,
+/// as picked by `options`. None with WNOHANG when nothing changed yet.
+pub fn waitid(pid: usize, options: usize) -> Result<Option<WaitInfo>, isize> {
+    let mut info = WaitInfo::default();
+    let ret = crate::api::syscall::waitid(pid, options, &mut info);
+    if ret < 0 {
+        return Err(ret);
+    }
+    Ok(if info.pid == 0 { None } else { Some(info) })
+}
+
+/// Run `handler` when signal `sig` arrives
+pub fn signal(sig: u32, handler: extern "C" fn(u32)) -> Result<(), isize> {
+    let ret = crate::api::syscall::sigaction(sig, handler as usize, sigreturn as usize);
+    if ret < 0 { Err(ret) } else { Ok(()) }
+}
+
+/// Go back to the default action for `sig`, or ignore it
+pub fn reset_signal(sig: u32, ignore: bool) -> Result<(), isize> {
+    let action = if ignore { crate::sys::signal::SIG_IGN } else { crate::sys::signal::SIG_DFL };
+    let ret = crate::api::syscall::sigaction(sig, action, 0);
+    if ret < 0 { Err(ret) } else { Ok(()) }
+}
+
+/// Return address of every signal handler: resumes the interrupted code
+extern "C" fn sigreturn() -> ! {
+    unsafe { crate::sys::syscall::syscall0(crate::sys::syscall::number::SIGRETURN); }
+    loop { x86_64::instructions::hlt(); }
+}
@@ -1,6 +1,6 @@
 //! Syscall API for Chilena — ergonomic wrappers for userspace
 
-use crate::sys::syscall::number;
+use crate::sys::syscall::{number, IoVec};
 use crate::api::process::ExitCode;
 
 pub fn exit(code: ExitCode) -> ! {
@@ -23,6 +23,15 @@ pub fn open(path: &str, flags: u8) -> isize {
     }
 }
 
+pub use crate::sys::fs::{O_APPEND, O_CREATE, O_NONBLOCK, O_RDONLY, O_TRUNC, O_WRONLY};
+pub use crate::sys::syscall::service::{F_GETFL, F_SETFL};
+pub use crate::sys::process::SECCOMP_KILL;
+
+/// Get (F_GETFL) or set (F_SETFL) the flags of a handle
+pub fn fcntl(handle: usize, cmd: usize, arg: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall3(number::FCNTL, handle, cmd, arg) as isize }
+}
+
 pub fn close(handle: usize) {
     unsafe { crate::sys::syscall::syscall1(number::CLOSE, handle); }
 }
@@ -49,6 +58,26 @@ pub fn write(handle: usize, buf: &[u8]) -> isize {
     }
 }
 
+/// Read into `bufs` in order with one syscall
+pub fn readv(handle: usize, bufs: &mut [&mut [u8]]) -> isize {
+    let iov: alloc::vec::Vec<IoVec> = bufs.iter_mut()
+        .map(|b| IoVec { base: b.as_mut_ptr() as usize, len: b.len() })
+        .collect();
+    unsafe {
+        crate::sys::syscall::syscall3(number::READV, handle, iov.as_ptr() as usize, iov.len()) as isize
+    }
+}
+
+/// Write `bufs` back to back with one syscall
+pub fn writev(handle: usize, bufs: &[&[u8]]) -> isize {
+    let iov: alloc::vec::Vec<IoVec> = bufs.iter()
+        .map(|b| IoVec { base: b.as_ptr() as usize, len: b.len() })
+        .collect();
+    unsafe {
+        crate::sys::syscall::syscall3(number::WRITEV, handle, iov.as_ptr() as usize, iov.len()) as isize
+    }
+}
+
 pub fn send(target: usize, kind: u32, data: &[u8]) -> usize {
     unsafe {
         crate::sys::syscall::syscall4(
@@ -69,3 +98,292 @@ pub fn recv(out: &mut crate::sys::ipc::Message) -> usize {
         )
     }
 }
+
+/// Register this process as the server for `name`
+pub fn register(name: &str) -> usize {
+    unsafe { crate::sys::syscall::syscall2(number::REGISTER, name.as_ptr() as usize, name.len()) }
+}
+
+/// Send to the server registered as `name`, waiting up to `timeout_ms`
+/// for it to register (0 = not at all, u64::MAX = forever)
+pub fn send_to_name(name: &str, kind: u32, data: &[u8], timeout_ms: u64) -> usize {
+    let Ok(msg) = crate::sys::ipc::Message::new(kind, data) else {
+        return usize::MAX;
+    };
+    unsafe {
+        crate::sys::syscall::syscall4(
+            number::SENDNAME,
+            name.as_ptr() as usize,
+            name.len(),
+            &msg as *const _ as usize,
+            timeout_ms as usize,
+        )
+    }
+}
+
+/// Send a request to `target` and block until the matching reply is in `out`
+pub fn call(target: usize, kind: u32, data: &[u8], out: &mut crate::sys::ipc::Message) -> usize {
+    let Ok(req) = crate::sys::ipc::Message::new(kind, data) else {
+        return usize::MAX;
+    };
+    unsafe {
+        crate::sys::syscall::syscall3(
+            number::CALL,
+            target,
+            &req as *const _ as usize,
+            out as *mut _ as usize,
+        )
+    }
+}
+
+/// Answer a request received via `recv` that was sent with `call`
+pub fn reply(req: &crate::sys::ipc::Message, data: &[u8]) -> usize {
+    unsafe {
+        crate::sys::syscall::syscall3(
+            number::REPLY,
+            req as *const _ as usize,
+            data.as_ptr() as usize,
+            data.len(),
+        )
+    }
+}
+
+pub fn stat(path: &str, info: &mut crate::sys::fs::FileInfo) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall3(
+            number::STAT,
+            path.as_ptr() as usize,
+            path.len(),
+            info as *mut _ as usize,
+        ) as isize
+    }
+}
+
+pub fn fsync(handle: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall1(number::FSYNC, handle) as isize }
+}
+
+pub fn sync() {
+    unsafe { crate::sys::syscall::syscall0(number::SYNC); }
+}
+
+pub fn setrlimit(resource: usize, soft: usize, hard: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall3(number::SETRLIMIT, resource, soft, hard) as isize }
+}
+
+pub fn getrlimit(resource: usize, out: &mut crate::sys::process::RLimit) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall2(number::GETRLIMIT, resource, out as *mut _ as usize) as isize
+    }
+}
+
+/// Confine this process and its future children to the directory `path`
+pub fn chroot(path: &str) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall2(number::CHROOT, path.as_ptr() as usize, path.len()) as isize
+    }
+}
+
+/// Current `CAP_*` bits; those spawned children get go into `child`
+pub fn capget(child: &mut u32) -> isize {
+    unsafe { crate::sys::syscall::syscall1(number::CAPGET, child as *mut u32 as usize) as isize }
+}
+
+/// Keep only `caps`, and give spawned children `child_caps`. Bits can be
+/// dropped, never regained.
+pub fn capset(caps: u32, child_caps: u32) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::CAPSET, caps as usize, child_caps as usize) as isize }
+}
+
+/// Start the program at `path` with `args`, as the `SPAWN_*` bits in
+/// `flags` ask. Returns the child's start-up failure code; negative on
+/// failure.
+pub fn spawn(path: &str, args: &[&str], flags: usize) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall5(
+            number::SPAWN,
+            path.as_ptr() as usize,
+            path.len(),
+            args.as_ptr() as usize,
+            args.len(),
+            flags,
+        ) as isize
+    }
+}
+
+/// Allow only the syscalls whose bits are set in `allowed`, here and in
+/// children; with `SECCOMP_KILL` in `flags` a refused one kills the
+/// process. Syscalls can be taken away, never given back.
+pub fn seccomp_set(allowed: u64, flags: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::SECCOMP_SET, allowed as usize, flags) as isize }
+}
+
+/// Duplicate this process. The parent gets the child's pid, the child
+/// 0; negative on failure.
+pub fn fork() -> isize {
+    unsafe { crate::sys::syscall::syscall0(number::FORK) as isize }
+}
+
+/// Wait for child `pid` (0 = any) to terminate. Returns its pid and puts
+/// its exit code in `status`; negative on failure.
+pub fn wait(pid: usize, status: &mut usize) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::WAIT, pid, status as *mut usize as usize) as isize }
+}
+
+/// Wait for child `pid` (0 = any) to change state; see `process::waitid`
+pub fn waitid(pid: usize, options: usize, info: &mut crate::sys::process::WaitInfo) -> isize {
+    let ptr = info as *mut crate::sys::process::WaitInfo as usize;
+    unsafe { crate::sys::syscall::syscall3(number::WAITID, pid, options, ptr) as isize }
+}
+
+/// Grow the heap by `increment` bytes and return the new break (0 only
+/// reports it), or usize::MAX if it cannot grow
+pub fn brk(increment: usize) -> usize {
+    unsafe { crate::sys::syscall::syscall1(number::BRK, increment) }
+}
+
+/// Map the first `len` bytes of the file open as `handle`, with
+/// `sys::mmap::PROT_*` bits. Returns the address, negative on failure.
+pub fn mmap_file(handle: usize, len: usize, prot: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall3(number::MMAP_FILE, handle, len, prot) as isize }
+}
+
+/// Write changes to `len` mapped bytes at `addr` back to the file
+pub fn msync(addr: usize, len: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::MSYNC, addr, len) as isize }
+}
+
+/// Write back and remove the mapping starting at `addr`
+pub fn munmap(addr: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall1(number::MUNMAP, addr) as isize }
+}
+
+/// Copy up to `len` bytes from `src` to `dst` without a userspace buffer
+pub fn copy_range(src: usize, dst: usize, len: usize) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall3(number::COPY_RANGE, src, dst, len) as isize
+    }
+}
+
+/// Return the index of the first ready entry in `list`, or -1 if none is.
+/// Include `api::fs::MAILBOX` to also wake on incoming IPC messages.
+pub fn poll(list: &[(usize, crate::sys::fs::PollEvent)]) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall2(
+            number::POLL,
+            list.as_ptr() as usize,
+            list.len(),
+        ) as isize
+    }
+}
+
+/// Create a UDP socket; returns its handle
+pub fn socket() -> isize {
+    unsafe { crate::sys::syscall::syscall0(number::SOCKET) as isize }
+}
+
+pub fn bind(handle: usize, addr: &crate::sys::net::SocketAddr) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall2(number::BIND, handle, addr as *const _ as usize) as isize
+    }
+}
+
+pub fn connect(handle: usize, addr: &crate::sys::net::SocketAddr) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall2(number::CONNECT, handle, addr as *const _ as usize) as isize
+    }
+}
+
+/// Send one datagram to the address the socket is connected to
+pub fn sock_send(handle: usize, data: &[u8]) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall3(
+            number::SOCK_SEND,
+            handle,
+            data.as_ptr() as usize,
+            data.len(),
+        ) as isize
+    }
+}
+
+/// Block until a datagram arrives on the socket's bound port
+pub fn sock_recv(handle: usize, buf: &mut [u8]) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall3(
+            number::SOCK_RECV,
+            handle,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+        ) as isize
+    }
+}
+
+pub fn sendto(handle: usize, port: u16, data: &[u8]) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall4(
+            number::SENDTO,
+            handle,
+            port as usize,
+            data.as_ptr() as usize,
+            data.len(),
+        ) as isize
+    }
+}
+
+pub fn recvfrom(handle: usize, buf: &mut [u8], from: &mut u16) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall4(
+            number::RECVFROM,
+            handle,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            from as *mut u16 as usize,
+        ) as isize
+    }
+}
+
+/// Send signal `sig` to process `pid`
+pub fn kill(pid: usize, sig: u32) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::KILL, pid, sig as usize) as isize }
+}
+
+pub fn sigaction(sig: u32, handler: usize, restorer: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall3(number::SIGACTION, sig as usize, handler, restorer) as isize }
+}
+
+/// Block, unblock or set the signal mask; returns the previous mask
+pub fn sigprocmask(how: usize, set: u32) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::SIGPROCMASK, how, set as usize) as isize }
+}
+
+/// Create an empty poll set; returns its handle
+pub fn pollset_create() -> isize {
+    unsafe { crate::sys::syscall::syscall0(number::POLLSET_CREATE) as isize }
+}
+
+/// Watch `handle` for `event` in `set`
+pub fn pollset_add(set: usize, handle: usize, event: crate::sys::fs::PollEvent) -> isize {
+    let event = match event {
+        crate::sys::fs::PollEvent::Read  => 0,
+        crate::sys::fs::PollEvent::Write => 1,
+    };
+    unsafe { crate::sys::syscall::syscall3(number::POLLSET_ADD, set, handle, event) as isize }
+}
+
+pub fn pollset_del(set: usize, handle: usize) -> isize {
+    unsafe { crate::sys::syscall::syscall2(number::POLLSET_DEL, set, handle) as isize }
+}
+
+/// Wait up to `timeout_ms` (`sys::pollset::WAIT_FOREVER` for no limit)
+/// for members of `set` to be ready; they are stored in `out`
+pub fn pollset_wait(set: usize, out: &mut [(usize, crate::sys::fs::PollEvent)], timeout_ms: usize) -> isize {
+    unsafe {
+        crate::sys::syscall::syscall4(
+            number::POLLSET_WAIT,
+            set,
+            out.as_mut_ptr() as usize,
+            out.len(),
+            timeout_ms,
+        ) as isize
+    }
+}
@@ -21,20 +21,20 @@ macro_rules! println {
 macro_rules! klog {
     ($($arg:tt)*) => {{ if !cfg!(test) {
         let t = $crate::sys::clk::uptime_secs();
-        $crate::sys::console::print_fmt(format_args!("\x1b[32m[{:8.3}]\x1b[0m {}\n", t, format_args!($($arg)*)));
+        $crate::sys::log::log($crate::sys::log::Level::Info, format_args!("\x1b[32m[{:8.3}]\x1b[0m {}\n", t, format_args!($($arg)*)));
     }}};
 }
 #[macro_export]
 macro_rules! kerror {
-    ($($arg:tt)*) => {{ $crate::sys::console::print_fmt(format_args!("\x1b[31mError:\x1b[0m {}\n", format_args!($($arg)*))); }};
+    ($($arg:tt)*) => {{ $crate::sys::log::log($crate::sys::log::Level::Error, format_args!("\x1b[31mError:\x1b[0m {}\n", format_args!($($arg)*))); }};
 }
 #[macro_export]
 macro_rules! kwarn {
-    ($($arg:tt)*) => {{ $crate::sys::console::print_fmt(format_args!("\x1b[33mWarn:\x1b[0m {}\n", format_args!($($arg)*))); }};
+    ($($arg:tt)*) => {{ $crate::sys::log::log($crate::sys::log::Level::Warn, format_args!("\x1b[33mWarn:\x1b[0m {}\n", format_args!($($arg)*))); }};
 }
 #[macro_export]
 macro_rules! kdebug {
-    ($($arg:tt)*) => {{ #[cfg(debug_assertions)] $crate::sys::console::print_fmt(format_args!("\x1b[34mDebug:\x1b[0m {}\n", format_args!($($arg)*))); }};
+    ($($arg:tt)*) => {{ #[cfg(debug_assertions)] $crate::sys::log::log($crate::sys::log::Level::Debug, format_args!("\x1b[34mDebug:\x1b[0m {}\n", format_args!($($arg)*))); }};
 }
 
 pub mod sys;
@@ -56,6 +56,7 @@ pub fn init(boot_info: &'static BootInfo) {
     sys::serial::init();
     sys::keyboard::init();
     sys::clk::init();
+    sys::cmdline::init();
     klog!("SYS Chilena v{}", VERSION);
     sys::cpu::init();
     sys::acpi::init();
@@ -5,7 +5,7 @@ extern crate alloc;
 
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
-use chilena::{sys, usr, hlt_loop};
+use chilena::{sys, usr};
 use chilena::{kerror, kwarn, print};
 
 entry_point!(kernel_main);
@@ -13,13 +13,36 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     chilena::init(boot_info);
     print!("\x1b[?25h");
-    boot_sequence();
-    loop { x86_64::instructions::hlt(); }
+    init();
 }
 
-fn boot_sequence() {
-    let boot_script = "/ini/boot.sh";
-    if sys::fs::exists(boot_script) {
+/// PID 1 — runs the boot sequence as init and respawns the shell
+/// whenever it exits, reaping the orphans it inherited as it waits.
+/// PID 0 stays behind as the idle task.
+fn init() -> ! {
+    sys::process::start_init();
+    sys::process::set_pid(sys::process::INIT_PID);
+    let mut recovery = sys::bootguard::begin_attempt();
+    if !recovery {
+        sys::cmdline::load_file();
+    }
+    sys::hostname::load();
+    loop {
+        boot_sequence(recovery);
+        sys::process::reap_orphans();
+        kwarn!("init: shell exited, respawning");
+        // A shell that keeps dying before its prompt is a boot loop too
+        recovery = sys::bootguard::begin_attempt();
+    }
+}
+
+fn boot_sequence(recovery: bool) {
+    let boot_script = sys::cmdline::get("boot").unwrap_or_else(|| "/ini/boot.sh".into());
+    let boot_script = boot_script.as_str();
+    if recovery {
+        kerror!("init: too many failed boots in a row, skipping '{}' (recovery mode)", boot_script);
+        usr::cl::shell::run_interactive().ok();
+    } else if sys::fs::exists(boot_script) {
         usr::cl::shell::run_script(boot_script).ok();
     } else {
         if sys::fs::is_mounted() {
@@ -33,10 +56,5 @@ fn boot_sequence() {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    if let Some(loc) = info.location() {
-        kerror!("PANIC at {}:{}:{}", loc.file(), loc.line(), loc.column());
-    } else {
-        kerror!("PANIC: {}", info);
-    }
-    hlt_loop();
+    sys::power::on_panic(info);
 }
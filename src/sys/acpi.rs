@@ -1,6 +1,7 @@
 //! ACPI — Power management (shutdown/reboot)
 //!
-//! Minimal implementation: only supports power off via ACPI PM1a.
+//! Minimal implementation: power off through the ACPI PM1a ports of known
+//! emulators, no table parsing.
 
 use x86_64::instructions::port::Port;
 
@@ -19,15 +20,30 @@ pub fn init() {
     unsafe { PM1A_CNT = 0x604; SLP_TYPA = 0; }
 }
 
-/// Shut down the system
+/// Emulator shutdown ports, tried in order: (environment, port, value).
+/// Writing the value to the port powers the VM off where it is understood
+/// and is ignored everywhere else, so trying them all is safe.
+const POWER_OFF_PORTS: [(&str, u16, u16); 4] = [
+    // QEMU >= 2.0 (PIIX4/ICH9 ACPI PM1a control block)
+    ("QEMU",             0x604,  0x2000),
+    // Bochs, and QEMU before 2.0
+    ("Bochs / old QEMU", 0xB004, 0x2000),
+    // VirtualBox ACPI PM1a control block
+    ("VirtualBox",       0x4004, 0x3400),
+    // Cloud Hypervisor shutdown port
+    ("Cloud Hypervisor", 0x600,  0x34),
+];
+
+/// Shut down the system. Without ACPI table parsing we only know the
+/// emulator magic above; on anything else this halts with interrupts off.
 pub fn power_off() -> ! {
     klog!("ACPI: power off...");
-    unsafe {
-        // QEMU: write to port 0x604
-        let mut port: Port<u16> = Port::new(0x604);
-        port.write(0x2000);
-
-        // Fallback: halt loop
-        loop { x86_64::instructions::hlt(); }
+    x86_64::instructions::interrupts::disable();
+    for (name, port, value) in POWER_OFF_PORTS {
+        kdebug!("ACPI: trying {} shutdown port {:#X}", name, port);
+        unsafe { Port::<u16>::new(port).write(value); }
     }
+
+    kwarn!("ACPI: power off failed, halting");
+    loop { x86_64::instructions::hlt(); }
 }
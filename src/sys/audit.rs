@@ -0,0 +1,126 @@
+//! Audit log — a record of denied syscalls
+//!
+//! When a syscall is refused for a security-relevant reason (a pointer
+//! outside userspace, a missing capability, a resource limit) one line
+//! naming the PID, the syscall number and the reason goes to the kernel
+//! log, and so into the kmsg ring. Entries are rate-limited so a process
+//! looping on a denied call cannot flood the ring; the number of dropped
+//! entries is reported with the next one that gets through.
+
+use crate::sys;
+use crate::sys::log::Level;
+
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Entries logged per second at most
+const RATE: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// A buffer argument is not inside userspace
+    BadPointer,
+    /// The process lacks this `CAP_*` bit
+    Capability(u32),
+    /// A resource limit was hit, e.g. "RLIMIT_NOFILE"
+    Limit(&'static str),
+    /// The syscall is not in the process's SECCOMP_SET filter
+    Filtered,
+}
+
+impl Reason {
+    /// Capability, pointer and filter denials are warnings; hitting a limit is
+    /// routine enough to only show at `info`
+    fn level(&self) -> Level {
+        match self {
+            Reason::BadPointer | Reason::Capability(_) | Reason::Filtered => Level::Warn,
+            Reason::Limit(_) => Level::Info,
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::BadPointer     => f.write_str("bad pointer"),
+            Reason::Capability(c)  => write!(f, "missing {}", sys::process::cap_name(*c)),
+            Reason::Limit(limit)   => write!(f, "over {}", limit),
+            Reason::Filtered       => f.write_str("filtered by SECCOMP_SET"),
+        }
+    }
+}
+
+struct Limiter {
+    /// Second of uptime the current window started in
+    window: u64,
+    logged: usize,
+    suppressed: usize,
+}
+
+static LIMITER: Mutex<Limiter> = Mutex::new(Limiter { window: 0, logged: 0, suppressed: 0 });
+
+/// Record that syscall `n` of the current process was denied.
+/// Returns `usize::MAX`, the dispatcher's error value.
+pub fn deny(n: usize, reason: Reason) -> usize {
+    record(format_args!("syscall {:#04X}", n), reason);
+    usize::MAX
+}
+
+/// Record that the current process was refused `op`, a kernel operation
+/// that is not a syscall of its own, e.g. "set hostname"
+pub fn deny_op(op: &str, reason: Reason) {
+    record(format_args!("{}", op), reason);
+}
+
+fn record(what: fmt::Arguments, reason: Reason) {
+    if !sys::log::enabled(reason.level()) {
+        return;
+    }
+    let pid = sys::process::current_pid();
+    let now = sys::clk::uptime_ns() / 1_000_000_000;
+    let suppressed = interrupts::without_interrupts(|| {
+        let mut limiter = LIMITER.lock();
+        if limiter.window != now {
+            limiter.window = now;
+            limiter.logged = 0;
+        }
+        if limiter.logged >= RATE {
+            limiter.suppressed += 1;
+            return None;
+        }
+        limiter.logged += 1;
+        Some(core::mem::take(&mut limiter.suppressed))
+    });
+    match suppressed {
+        None => {}
+        Some(0) => sys::log::log(reason.level(), format_args!(
+            "audit: pid {} {} denied: {}\n", pid, what, reason
+        )),
+        Some(dropped) => sys::log::log(reason.level(), format_args!(
+            "audit: pid {} {} denied: {} ({} earlier entries suppressed)\n",
+            pid, what, reason, dropped
+        )),
+    }
+}
+
+#[test_case]
+fn denied_reboot_is_audited() {
+    use crate::sys::process::{FakeProcess, CAP_ALL, CAP_REBOOT};
+    use crate::sys::syscall::{error::EPERM, service};
+
+    let start = sys::log::end();
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *LIMITER.lock() = Limiter { window: 0, logged: 0, suppressed: 0 };
+        let _process = FakeProcess::enter(2);
+
+        let caps = (CAP_ALL & !CAP_REBOOT) as usize;
+        assert_eq!(service::capset(caps, caps), 0);
+        assert_eq!(service::halt(0xCAFE), EPERM);
+    });
+
+    let mut buf = [0u8; 512];
+    let (n, _) = sys::log::read_at(start, &mut buf);
+    let log = core::str::from_utf8(&buf[..n]).unwrap();
+    assert!(log.contains("audit: pid 2 syscall 0x0A denied: missing CAP_REBOOT"));
+}
@@ -0,0 +1,214 @@
+//! Buffer cache — recently used disk sectors kept in memory
+//!
+//! Every sector access to the disk goes through here. Reads are served
+//! from the cache when possible; writes only update the cached copy and
+//! mark it dirty, and reach the device when the sector is evicted or on
+//! `sync`. The cache holds at most `CAPACITY` sectors and evicts the
+//! least recently used one.
+
+use crate::sys::fs::readahead::{BlockDevice, RawVirtio, SECTOR};
+use crate::sys::fs::IoError;
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
+/// Sectors kept in memory (256 KiB)
+pub const CAPACITY: usize = 512;
+
+struct Buffer {
+    data:     [u8; SECTOR],
+    dirty:    bool,
+    /// Value of the use counter when last touched
+    last_use: u64,
+}
+
+pub struct BufferCache {
+    capacity: usize,
+    buffers:  BTreeMap<u64, Buffer>,
+    clock:    u64,
+}
+
+impl BufferCache {
+    pub const fn new(capacity: usize) -> Self {
+        Self { capacity, buffers: BTreeMap::new(), clock: 0 }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Make room for one more sector, writing the victim back if dirty
+    fn evict(&mut self, dev: &mut impl BlockDevice) -> Result<(), IoError> {
+        while self.buffers.len() >= self.capacity.max(1) {
+            let Some((&sector, _)) = self.buffers.iter().min_by_key(|(_, b)| b.last_use) else {
+                break;
+            };
+            if self.buffers[&sector].dirty {
+                dev.write_sector(sector, &self.buffers[&sector].data)?;
+            }
+            self.buffers.remove(&sector);
+        }
+        Ok(())
+    }
+
+    /// Copy `sector` into the first SECTOR bytes of `buf`
+    pub fn read(&mut self, dev: &mut impl BlockDevice, sector: u64, buf: &mut [u8]) -> Result<(), IoError> {
+        let buf = buf.get_mut(..SECTOR).ok_or(IoError::OutOfRange)?;
+        let now = self.tick();
+        if let Some(b) = self.buffers.get_mut(&sector) {
+            b.last_use = now;
+            buf.copy_from_slice(&b.data);
+            return Ok(());
+        }
+        let mut data = [0u8; SECTOR];
+        dev.read_sectors(sector, &mut data)?;
+        self.evict(dev)?;
+        buf.copy_from_slice(&data);
+        self.buffers.insert(sector, Buffer { data, dirty: false, last_use: now });
+        Ok(())
+    }
+
+    /// Copy the sectors from `start` into `buf`, a whole number of them.
+    /// Cached sectors are served from memory; the span from the first
+    /// to the last missing one comes from the device in a single request
+    /// and is cached clean.
+    pub fn read_sectors(&mut self, dev: &mut impl BlockDevice, start: u64, buf: &mut [u8]) -> Result<(), IoError> {
+        let count = buf.len() / SECTOR;
+        let missing = |i: &usize| !self.buffers.contains_key(&(start + *i as u64));
+        let first = (0..count).find(missing);
+        let last  = (0..count).rev().find(missing);
+        if let (Some(first), Some(last)) = (first, last) {
+            dev.read_sectors(start + first as u64, &mut buf[first * SECTOR..(last + 1) * SECTOR])?;
+        }
+        let now = self.tick();
+        for (i, chunk) in buf.chunks_exact_mut(SECTOR).enumerate() {
+            let sector = start + i as u64;
+            if let Some(b) = self.buffers.get_mut(&sector) {
+                b.last_use = now;
+                chunk.copy_from_slice(&b.data);
+                continue;
+            }
+            self.evict(dev)?;
+            let mut data = [0u8; SECTOR];
+            data.copy_from_slice(chunk);
+            self.buffers.insert(sector, Buffer { data, dirty: false, last_use: now });
+        }
+        Ok(())
+    }
+
+    /// Replace `sector` with the first SECTOR bytes of `buf` (zero padded).
+    /// The device sees it on eviction or `flush`.
+    pub fn write(&mut self, dev: &mut impl BlockDevice, sector: u64, buf: &[u8]) -> Result<(), IoError> {
+        let mut data = [0u8; SECTOR];
+        let n = buf.len().min(SECTOR);
+        data[..n].copy_from_slice(&buf[..n]);
+        let now = self.tick();
+        if !self.buffers.contains_key(&sector) {
+            self.evict(dev)?;
+        }
+        self.buffers.insert(sector, Buffer { data, dirty: true, last_use: now });
+        Ok(())
+    }
+
+    /// Write back the dirty sectors in `start..end`
+    pub fn flush_range(&mut self, dev: &mut impl BlockDevice, start: u64, end: u64) -> Result<(), IoError> {
+        for (&sector, b) in self.buffers.range_mut(start..end) {
+            if b.dirty {
+                dev.write_sector(sector, &b.data)?;
+                b.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self, dev: &mut impl BlockDevice) -> Result<(), IoError> {
+        self.flush_range(dev, 0, u64::MAX)
+    }
+
+    pub fn dirty(&self) -> usize {
+        self.buffers.values().filter(|b| b.dirty).count()
+    }
+}
+
+static CACHE: Mutex<BufferCache> = Mutex::new(BufferCache::new(CAPACITY));
+
+/// Read a disk sector through the cache
+pub fn read(sector: u64, buf: &mut [u8]) -> Result<(), IoError> {
+    CACHE.lock().read(&mut RawVirtio, sector, buf)
+}
+
+/// Read consecutive disk sectors through the cache
+pub fn read_sectors(start: u64, buf: &mut [u8]) -> Result<(), IoError> {
+    CACHE.lock().read_sectors(&mut RawVirtio, start, buf)
+}
+
+/// Write a disk sector into the cache
+pub fn write(sector: u64, buf: &[u8]) -> Result<(), IoError> {
+    CACHE.lock().write(&mut RawVirtio, sector, buf)
+}
+
+pub fn flush_range(start: u64, end: u64) -> Result<(), IoError> {
+    CACHE.lock().flush_range(&mut RawVirtio, start, end)
+}
+
+/// Write every dirty sector to the disk
+pub fn sync() -> Result<(), IoError> {
+    if !crate::sys::virtio::is_available() {
+        return Ok(());
+    }
+    CACHE.lock().flush(&mut RawVirtio)
+}
+
+#[test_case]
+fn cache_serves_repeats_and_holds_writes() {
+    use crate::sys::fs::readahead::RamDisk;
+
+    let mut disk  = RamDisk::new(alloc::vec![7u8; 16 * SECTOR]);
+    let mut cache = BufferCache::new(4);
+    let mut buf   = [0u8; SECTOR];
+
+    cache.read(&mut disk, 3, &mut buf).unwrap();
+    cache.read(&mut disk, 3, &mut buf).unwrap();
+    assert_eq!(disk.reads, 1);
+
+    // Written data is read back before it reaches the disk
+    cache.write(&mut disk, 5, &[1; SECTOR]).unwrap();
+    cache.read(&mut disk, 5, &mut buf).unwrap();
+    assert_eq!(buf, [1; SECTOR]);
+    assert_eq!((disk.writes, cache.dirty()), (0, 1));
+    assert_eq!(disk.data[5 * SECTOR], 7);
+
+    cache.flush(&mut disk).unwrap();
+    assert_eq!((disk.writes, cache.dirty()), (1, 0));
+    assert_eq!(disk.data[5 * SECTOR], 1);
+
+    // Filling the cache evicts the least recently used, writing it back
+    cache.write(&mut disk, 0, &[2; SECTOR]).unwrap();
+    for sector in 8..12 {
+        cache.read(&mut disk, sector, &mut buf).unwrap();
+    }
+    assert_eq!(cache.buffers.len(), 4);
+    assert_eq!(disk.data[0], 2);
+}
+
+#[test_case]
+fn window_reads_fill_only_the_misses() {
+    use crate::sys::fs::readahead::RamDisk;
+
+    let mut disk  = RamDisk::new(alloc::vec![7u8; 16 * SECTOR]);
+    let mut cache = BufferCache::new(8);
+    let mut buf   = [0u8; 4 * SECTOR];
+
+    // A dirty sector in the window is served from memory, not the disk
+    cache.write(&mut disk, 2, &[1; SECTOR]).unwrap();
+    cache.read_sectors(&mut disk, 0, &mut buf).unwrap();
+    assert_eq!(disk.reads, 1);
+    assert_eq!(buf[2 * SECTOR], 1);
+    assert_eq!(buf[3 * SECTOR], 7);
+
+    // The whole window is cached now
+    cache.read_sectors(&mut disk, 0, &mut buf).unwrap();
+    assert_eq!(disk.reads, 1);
+    assert_eq!(cache.dirty(), 1);
+}
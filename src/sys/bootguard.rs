@@ -0,0 +1,58 @@
+//! Boot-loop guard
+//!
+//! init counts boot attempts in a battery-backed CMOS byte, and the shell
+//! clears the count once it shows an interactive prompt. A boot script
+//! that crashes or reboots before that point keeps the count growing
+//! across resets; after `MAX_FAILED_BOOTS` attempts in a row init skips
+//! the script and `/ini/cmdline` and starts a plain shell instead.
+
+use crate::sys::clk::{cmos_read, cmos_write, RtcReg};
+
+/// Attempts that may fail in a row before recovery mode
+pub const MAX_FAILED_BOOTS: u8 = 3;
+
+/// High nibble of a count we wrote; anything else in the byte is left
+/// over from the BIOS and counts as no attempts
+const MAGIC: u8 = 0xB0;
+const COUNT: u8 = 0x0F;
+
+fn attempts() -> u8 {
+    let byte = cmos_read(RtcReg::BootCount);
+    if byte & !COUNT == MAGIC { byte & COUNT } else { 0 }
+}
+
+/// Count one more boot attempt. True if too many before it failed and
+/// this one should boot into recovery mode.
+pub fn begin_attempt() -> bool {
+    let failed = attempts();
+    cmos_write(RtcReg::BootCount, MAGIC | (failed + 1).min(COUNT));
+    failed >= MAX_FAILED_BOOTS
+}
+
+/// The boot got as far as an interactive prompt
+pub fn boot_succeeded() {
+    if attempts() != 0 {
+        cmos_write(RtcReg::BootCount, MAGIC);
+    }
+}
+
+#[test_case]
+fn repeated_failed_boots_trip_recovery() {
+    let saved = cmos_read(RtcReg::BootCount);
+
+    boot_succeeded();
+    for _ in 0..MAX_FAILED_BOOTS {
+        assert!(!begin_attempt());
+    }
+    assert!(begin_attempt());
+    assert!(begin_attempt()); // and stays there
+
+    boot_succeeded();
+    assert!(!begin_attempt());
+
+    // A byte the BIOS left behind is not a count
+    cmos_write(RtcReg::BootCount, 0x07);
+    assert_eq!(attempts(), 0);
+
+    cmos_write(RtcReg::BootCount, saved);
+}
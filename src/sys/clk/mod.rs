@@ -3,23 +3,50 @@
 //! Provides:
 //!   - uptime: time since boot (via PIT timer)
 //!   - date: date/time from CMOS RTC
-//!   - sleep: delay execution for N seconds
+//!   - sleep: block the current process for N seconds
 
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use x86_64::instructions::{interrupts, port::Port};
 
 // ---------------------------------------------------------------------------
-// PIT Timer (IRQ 0) — measure uptime in milliseconds
+// PIT Timer (IRQ 0) — measure uptime
 // ---------------------------------------------------------------------------
 
-/// Ticks per second (PIT configured at ~1000 Hz)
-const TICKS_PER_SEC: u64 = 1000;
+/// PIT input clock in Hz
+const PIT_FREQ: u64 = 1_193_182;
 
-static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Default tick rate
+pub const DEFAULT_TICK_HZ: u64 = 1000;
+/// Slowest rate the 16-bit PIT divisor can express
+pub const MIN_TICK_HZ: u64 = PIT_FREQ / 0xFFFF + 1;
+/// Upper bound to keep IRQ 0 overhead reasonable
+pub const MAX_TICK_HZ: u64 = 10_000;
+
+static TICK_HZ:     AtomicU64 = AtomicU64::new(DEFAULT_TICK_HZ);
+/// Real length of one tick, from the divisor actually programmed
+static NS_PER_TICK: AtomicU64 = AtomicU64::new(ns_per_tick(DEFAULT_TICK_HZ));
+/// Time since boot — advanced by NS_PER_TICK on every tick, so changing
+/// the rate at runtime does not disturb uptime
+static UPTIME_NS:   AtomicU64 = AtomicU64::new(0);
+
+const fn divisor(hz: u64) -> u64 {
+    PIT_FREQ / hz
+}
+
+const fn ns_per_tick(hz: u64) -> u64 {
+    divisor(hz) * 1_000_000_000 / PIT_FREQ
+}
 
 pub fn init() {
-    // Configure PIT channel 0, mode 3 (square wave), ~1000 Hz
-    let divisor = 1193182u32 / TICKS_PER_SEC as u32;
+    program_pit(tick_hz());
+
+    // Register IRQ 0 handler (timer)
+    crate::sys::idt::set_irq_handler(0, on_tick);
+}
+
+fn program_pit(hz: u64) {
+    // Configure PIT channel 0, mode 3 (square wave)
+    let divisor = divisor(hz) as u16;
     unsafe {
         let mut cmd: Port<u8>  = Port::new(0x43);
         let mut ch0: Port<u8>  = Port::new(0x40);
@@ -27,57 +54,205 @@ pub fn init() {
         ch0.write((divisor & 0xFF) as u8);
         ch0.write((divisor >> 8) as u8);
     }
+}
 
-    // Register IRQ 0 handler (timer)
-    crate::sys::idt::set_irq_handler(0, on_tick);
+/// Change the timer frequency. Can be called before or after `init`.
+/// False if the PIT cannot run at `hz`.
+#[must_use]
+pub fn set_tick_hz(hz: u64) -> bool {
+    if !(MIN_TICK_HZ..=MAX_TICK_HZ).contains(&hz) {
+        return false;
+    }
+    interrupts::without_interrupts(|| {
+        TICK_HZ.store(hz, Ordering::Relaxed);
+        NS_PER_TICK.store(ns_per_tick(hz), Ordering::Relaxed);
+        program_pit(hz);
+    });
+    true
+}
+
+pub fn tick_hz() -> u64 {
+    TICK_HZ.load(Ordering::Relaxed)
+}
+
+/// Length of one tick in nanoseconds
+pub fn tick_ns() -> u64 {
+    NS_PER_TICK.load(Ordering::Relaxed)
 }
 
 fn on_tick() {
-    TICK_COUNT.fetch_add(1, Ordering::Relaxed);
-    crate::sys::sched::tick();
+    UPTIME_NS.fetch_add(NS_PER_TICK.load(Ordering::Relaxed), Ordering::Relaxed);
+    crate::sys::sched::wake_sleepers(uptime_ns());
+}
+
+/// Kernel uptime in nanoseconds
+pub fn uptime_ns() -> u64 {
+    UPTIME_NS.load(Ordering::Relaxed)
 }
 
 /// Kernel uptime in seconds (floating point)
 pub fn uptime_secs() -> f64 {
-    TICK_COUNT.load(Ordering::Relaxed) as f64 / TICKS_PER_SEC as f64
+    uptime_ns() as f64 / 1e9
 }
 
-/// Sleep for N seconds (busy-wait via tick counter)
-pub fn sleep(seconds: f64) {
-    let target = TICK_COUNT.load(Ordering::Relaxed)
-        + (seconds * TICKS_PER_SEC as f64) as u64;
+/// Uptime in nanoseconds `seconds` from now
+pub fn deadline_ns(seconds: f64) -> u64 {
+    uptime_ns() + (seconds * 1e9) as u64
+}
 
-    while TICK_COUNT.load(Ordering::Relaxed) < target {
-        interrupts::enable_and_hlt();
-    }
+/// Sleep for N seconds. The current process is blocked meanwhile, so it
+/// is not scheduled just to look at the clock.
+pub fn sleep(seconds: f64) {
+    crate::sys::sched::fall_asleep(deadline_ns(seconds));
+    crate::sys::sched::wait_until_awake();
 }
 
 // ---------------------------------------------------------------------------
 // RTC — Read date/time from CMOS
 // ---------------------------------------------------------------------------
 
-fn cmos_read(reg: u8) -> u8 {
-    unsafe {
-        let mut addr: Port<u8> = Port::new(0x70);
-        let mut data: Port<u8> = Port::new(0x71);
-        addr.write(reg);
+const CMOS_ADDR: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Bit 7 of the index port masks NMIs while set
+const NMI_DISABLE: u8 = 0x80;
+
+/// RTC registers we know how to interpret
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum RtcReg {
+    Seconds = 0x00,
+    Minutes = 0x02,
+    Hours   = 0x04,
+    Day     = 0x07,
+    Month   = 0x08,
+    Year    = 0x09,
+    StatusA = 0x0A,
+    StatusB = 0x0B,
+    /// POST diagnostics on old PCs, free for us to keep the boot-loop
+    /// guard's count in
+    BootCount = 0x0E,
+    /// BIOS shutdown status: what POST does after the next reset
+    Shutdown = 0x0F,
+    /// Not standard: most PCs put it here, but nothing guarantees it
+    Century = 0x32,
+}
+
+/// Port 0x70 cannot be read back, so the NMI mask we last asked for is
+/// kept here and written again with every register index
+static NMI_MASKED: AtomicBool = AtomicBool::new(false);
+
+pub fn nmi_masked() -> bool {
+    NMI_MASKED.load(Ordering::SeqCst)
+}
+
+/// Mask or unmask NMIs at the CMOS index port
+pub fn set_nmi_masked(masked: bool) {
+    interrupts::without_interrupts(|| {
+        NMI_MASKED.store(masked, Ordering::SeqCst);
+        unsafe { Port::<u8>::new(CMOS_ADDR).write(index_byte(RtcReg::StatusA)); }
+    });
+}
+
+/// Value to write to the index port to select `reg` without changing
+/// the NMI mask
+fn index_byte(reg: RtcReg) -> u8 {
+    reg as u8 | if nmi_masked() { NMI_DISABLE } else { 0 }
+}
+
+pub fn cmos_read(reg: RtcReg) -> u8 {
+    interrupts::without_interrupts(|| unsafe {
+        let mut addr: Port<u8> = Port::new(CMOS_ADDR);
+        let mut data: Port<u8> = Port::new(CMOS_DATA);
+        addr.write(index_byte(reg));
         data.read()
+    })
+}
+
+pub fn cmos_write(reg: RtcReg, value: u8) {
+    interrupts::without_interrupts(|| unsafe {
+        let mut addr: Port<u8> = Port::new(CMOS_ADDR);
+        let mut data: Port<u8> = Port::new(CMOS_DATA);
+        addr.write(index_byte(reg));
+        data.write(value);
+    })
+}
+
+/// Tell the BIOS how to handle the next reset (CMOS register 0x0F)
+pub fn set_shutdown_status(code: u8) {
+    cmos_write(RtcReg::Shutdown, code);
+}
+
+/// Raw seconds register of the RTC, read outside of its update cycle.
+/// Works without timer interrupts, for counting seconds when they are
+/// not to be trusted.
+pub fn rtc_seconds() -> u8 {
+    // Status A bit 7: update in progress, the time registers may be torn
+    while cmos_read(RtcReg::StatusA) & 0x80 != 0 {
+        core::hint::spin_loop();
     }
+    cmos_read(RtcReg::Seconds)
 }
 
 fn bcd_to_bin(bcd: u8) -> u8 {
     (bcd & 0x0F) + ((bcd >> 4) * 10)
 }
 
+/// Decode a BCD register, rejecting bytes that are not valid BCD
+fn bcd_checked(bcd: u8) -> Option<u8> {
+    if bcd & 0x0F > 9 || bcd >> 4 > 9 { None } else { Some(bcd_to_bin(bcd)) }
+}
+
+/// Full year from the year register and the (unreliable) century register.
+/// A century outside 19..=21 means the register is not there; assume 20xx.
+fn full_year(year: u8, century: u8) -> u16 {
+    let century = match bcd_checked(century) {
+        Some(c @ 19..=21) => c as u16,
+        _ => 20,
+    };
+    century * 100 + bcd_to_bin(year) as u16
+}
+
 /// Read current date and time from RTC CMOS
 pub fn date_string() -> alloc::string::String {
-    let sec  = bcd_to_bin(cmos_read(0x00));
-    let min  = bcd_to_bin(cmos_read(0x02));
-    let hour = bcd_to_bin(cmos_read(0x04));
-    let day  = bcd_to_bin(cmos_read(0x07));
-    let mon  = bcd_to_bin(cmos_read(0x08));
-    let year = bcd_to_bin(cmos_read(0x09)) as u16 + 2000;
+    let sec  = bcd_to_bin(cmos_read(RtcReg::Seconds));
+    let min  = bcd_to_bin(cmos_read(RtcReg::Minutes));
+    let hour = bcd_to_bin(cmos_read(RtcReg::Hours));
+    let day  = bcd_to_bin(cmos_read(RtcReg::Day));
+    let mon  = bcd_to_bin(cmos_read(RtcReg::Month));
+    let year = full_year(cmos_read(RtcReg::Year), cmos_read(RtcReg::Century));
 
     alloc::format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
         year, mon, day, hour, min, sec)
 }
+
+#[test_case]
+fn uptime_follows_tick_rate() {
+    interrupts::without_interrupts(|| {
+        for hz in [100, 1000] {
+            assert!(set_tick_hz(hz));
+            let start = uptime_ns();
+            for _ in 0..hz {
+                on_tick();
+            }
+            let elapsed = uptime_ns() - start;
+            // One second, within the PIT divisor rounding
+            assert!(elapsed.abs_diff(1_000_000_000) < 1_000_000);
+        }
+        assert!(!set_tick_hz(MIN_TICK_HZ - 1));
+        assert!(!set_tick_hz(MAX_TICK_HZ + 1));
+        assert!(set_tick_hz(DEFAULT_TICK_HZ));
+    });
+}
+
+#[test_case]
+fn rtc_reads_keep_nmi_mask() {
+    for masked in [true, false] {
+        set_nmi_masked(masked);
+        date_string();
+        assert_eq!(nmi_masked(), masked);
+        assert_eq!(index_byte(RtcReg::Year) & NMI_DISABLE != 0, masked);
+    }
+    assert_eq!(full_year(0x25, 0x20), 2025);
+    assert_eq!(full_year(0x99, 0x19), 1999);
+    assert_eq!(full_year(0x25, 0xFF), 2025); // no century register
+}
@@ -0,0 +1,118 @@
+//! Kernel command line — boot options as `key=value` words
+//!
+//! The bootloader we use has no command line field, so options come from
+//! the build (`CHILENA_CMDLINE` environment variable at compile time) and
+//! are then overridden by `/ini/cmdline` once init can read the VFS.
+//!
+//! Recognised options:
+//!   loglevel=debug|info|warn|error   runtime log level
+//!   boot=<path>                      boot script run by init
+//!   hz=<n>                           PIT tick rate
+//!   nopreempt                        no timer-driven process switches
+//!   spawnrate=<n>                    default SPAWN calls per second
+//!   readahead=<n>                    sectors prefetched on sequential disk reads
+//!   vga=80x25|80x50                  text mode
+//!   panic=halt|reboot[=secs]         what a kernel panic does (default halt)
+//!   noapic, nosmp                    accepted for compatibility (no effect)
+
+use crate::sys;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use spin::RwLock;
+
+pub const CMDLINE_FILE: &str = "/ini/cmdline";
+
+static OPTIONS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// Split a command line into options. Bare words map to an empty value.
+pub fn parse(line: &str) -> BTreeMap<String, String> {
+    line.split_whitespace()
+        .map(|word| match word.split_once('=') {
+            Some((k, v)) => (k.to_string(), v.to_string()),
+            None         => (word.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Value of option `key` (empty for flags given without `=`)
+pub fn get(key: &str) -> Option<String> {
+    OPTIONS.read().get(key).cloned()
+}
+
+pub fn has(key: &str) -> bool {
+    OPTIONS.read().contains_key(key)
+}
+
+/// Merge `line` into the current options and apply the ones that
+/// configure running subsystems
+pub fn load(line: &str) {
+    let opts = parse(line);
+    for (key, val) in &opts {
+        apply(key, val);
+    }
+    OPTIONS.write().extend(opts);
+}
+
+/// Built-in command line, applied early in kernel init
+pub fn init() {
+    if let Some(line) = option_env!("CHILENA_CMDLINE") {
+        load(line);
+    }
+}
+
+/// Re-read options from `/ini/cmdline`, if present
+pub fn load_file() {
+    if let Some(file) = sys::fs::open_file(CMDLINE_FILE) {
+        load(&String::from_utf8_lossy(file.data()));
+    }
+}
+
+fn apply(key: &str, val: &str) {
+    match key {
+        "loglevel" => match sys::log::Level::parse(val) {
+            Some(level) => sys::log::set_log_level(level),
+            None        => kwarn!("cmdline: bad loglevel '{}'", val),
+        },
+        "hz" => {
+            if !val.parse().is_ok_and(sys::clk::set_tick_hz) {
+                kwarn!("cmdline: bad hz '{}'", val);
+            }
+        }
+        "nopreempt" => sys::sched::set_preempt(false),
+        "spawnrate" => match val.parse() {
+            Ok(rate) => sys::process::set_spawn_rate(rate),
+            Err(_)   => kwarn!("cmdline: bad spawnrate '{}'", val),
+        },
+        "vga" => match sys::vga::VgaMode::parse(val) {
+            Some(mode) => sys::vga::set_mode(mode),
+            None       => kwarn!("cmdline: bad vga mode '{}'", val),
+        },
+        "readahead" => match val.parse() {
+            Ok(depth) => sys::fs::readahead::set_depth(depth),
+            Err(_)    => kwarn!("cmdline: bad readahead '{}'", val),
+        },
+        "panic" => match sys::power::PanicPolicy::parse(val) {
+            Some(policy) => sys::power::set_panic_policy(policy),
+            None         => kwarn!("cmdline: bad panic policy '{}'", val),
+        },
+        _ => {}
+    }
+}
+
+#[test_case]
+fn loglevel_option_suppresses_info() {
+    use crate::sys::log::{self, Level};
+
+    let saved = log::log_level();
+    load("loglevel=error boot=/ini/alt.sh nosmp");
+    assert_eq!(get("boot").as_deref(), Some("/ini/alt.sh"));
+    assert!(has("nosmp"));
+
+    let before = log::end();
+    log::log(Level::Info, format_args!("should not be logged\n"));
+    assert_eq!(log::end(), before);
+
+    log::set_log_level(saved);
+    OPTIONS.write().clear();
+}
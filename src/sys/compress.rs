@@ -0,0 +1,121 @@
+//! LZSS compression for in-memory file storage
+//!
+//! The output is a series of groups: a flag byte, then eight items, one
+//! per flag bit starting at the lowest. A clear bit is a literal byte; a
+//! set bit is a two-byte back-reference `oooo_oooo oooo_llll` copying
+//! `l + MIN_MATCH` bytes from `o + 1` bytes back. Matches are found with a
+//! one-entry hash table, so compression is a single fast pass.
+
+use alloc::vec::Vec;
+
+const WINDOW:    usize = 4096;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = MIN_MATCH + 15;
+const HASH_BITS: usize = 12;
+
+fn hash(b: &[u8]) -> usize {
+    let v = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out   = Vec::with_capacity(data.len() / 2 + 16);
+    let mut table = alloc::vec![usize::MAX; 1 << HASH_BITS];
+    let mut flag_at = 0;
+    let mut items   = 8;
+    let mut i = 0;
+
+    while i < data.len() {
+        if items == 8 {
+            flag_at = out.len();
+            out.push(0);
+            items = 0;
+        }
+
+        let mut len = 0;
+        let mut dist = 0;
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(&data[i..]);
+            let cand = table[h];
+            table[h] = i;
+            if cand != usize::MAX && i - cand <= WINDOW {
+                let max = MAX_MATCH.min(data.len() - i);
+                while len < max && data[cand + len] == data[i + len] {
+                    len += 1;
+                }
+                dist = i - cand;
+            }
+        }
+
+        if len >= MIN_MATCH {
+            out[flag_at] |= 1 << items;
+            let code = ((dist - 1) << 4) | (len - MIN_MATCH);
+            out.push((code >> 8) as u8);
+            out.push(code as u8);
+            i += len;
+        } else {
+            out.push(data[i]);
+            i += 1;
+        }
+        items += 1;
+    }
+    out
+}
+
+/// Expand `data` back into `len` bytes. Fails on a reference before the
+/// start of the output or if the result is not exactly `len` bytes.
+pub(crate) fn decompress(data: &[u8], len: usize) -> Result<Vec<u8>, ()> {
+    let mut out = Vec::new();
+    out.try_reserve_exact(len).map_err(|_| ())?;
+    let mut pos = 0;
+
+    while pos < data.len() && out.len() < len {
+        let flags = data[pos];
+        pos += 1;
+        for bit in 0..8 {
+            if pos >= data.len() || out.len() >= len {
+                break;
+            }
+            if flags & (1 << bit) == 0 {
+                out.push(data[pos]);
+                pos += 1;
+                continue;
+            }
+            let code = (*data.get(pos).ok_or(())? as usize) << 8
+                | *data.get(pos + 1).ok_or(())? as usize;
+            pos += 2;
+            let dist = (code >> 4) + 1;
+            let n    = (code & 0xF) + MIN_MATCH;
+            if dist > out.len() || out.len() + n > len {
+                return Err(());
+            }
+            // Byte by byte: the source may overlap what is being written
+            let start = out.len() - dist;
+            for k in 0..n {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    if out.len() == len { Ok(out) } else { Err(()) }
+}
+
+#[test_case]
+fn roundtrip_random_and_repetitive() {
+    let mut seed = 0x2545_F491u32;
+    let random: Vec<u8> = (0..5000).map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as u8
+    }).collect();
+    let text = b"all work and no play makes jack a dull boy\n".repeat(200);
+
+    for data in [&random[..], &text[..], b"", b"ab", &[0u8; 1000][..]] {
+        let packed = compress(data);
+        assert_eq!(decompress(&packed, data.len()).as_deref(), Ok(data));
+    }
+
+    // Random data barely grows, repetitive text shrinks a lot
+    assert!(compress(&random).len() <= random.len() + random.len() / 8 + 1);
+    assert!(compress(&text).len() < text.len() / 5);
+}
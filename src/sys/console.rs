@@ -7,9 +7,11 @@ use crate::sys;
 use crate::sys::fs::{FileIO, PollEvent};
 
 use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::fmt;
-use core::sync::atomic::{AtomicBool, Ordering};
-use spin::Mutex;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::{Mutex, Once};
 use x86_64::instructions::interrupts;
 
 // ---------------------------------------------------------------------------
@@ -17,8 +19,18 @@ use x86_64::instructions::interrupts;
 // ---------------------------------------------------------------------------
 
 pub static STDIN:  Mutex<String> = Mutex::new(String::new());
+/// Output capture stack — while non-empty, output goes to the top entry
+/// instead of the screen (used by shell pipelines)
+static CAPTURE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Input fed to `read_line` in place of the keyboard (pipeline stdin)
+static PIPE_IN: Mutex<Option<String>> = Mutex::new(None);
 pub static ECHO:   AtomicBool    = AtomicBool::new(true);
 pub static RAW:    AtomicBool    = AtomicBool::new(false);
+/// Process that owns keyboard input; everyone else waits in read
+static FOREGROUND: AtomicUsize   = AtomicUsize::new(NO_FOREGROUND);
+
+/// Nobody claimed the console yet — any reader gets input
+pub const NO_FOREGROUND: usize = usize::MAX;
 
 // Control characters
 pub const BS:  char = '\x08'; // Backspace
@@ -30,17 +42,25 @@ pub const ETX: char = '\x03'; // End of Text (Ctrl+C)
 // Console device — implements FileIO
 // ---------------------------------------------------------------------------
 
-#[derive(Clone, Debug)]
+/// The one console. Handles 0/1/2 of every process share it through
+/// `shared()`, so a child's stdio is the same device as its parent's.
+#[derive(Debug)]
 pub struct Console;
 
-impl Console {
-    pub fn new() -> Self { Self }
+static SHARED: Once<Arc<Console>> = Once::new();
+
+pub fn shared() -> Arc<Console> {
+    SHARED.call_once(|| Arc::new(Console)).clone()
 }
 
-impl FileIO for Console {
+impl FileIO for Arc<Console> {
+    /// Returns Ok(0) at end of input (Ctrl+D at the start of a line)
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
         let text = if buf.len() == 4 {
-            read_char().to_string()
+            match read_char() {
+                EOT => return Ok(0),
+                c   => c.to_string(),
+            }
         } else {
             read_line()
         };
@@ -60,7 +80,10 @@ impl FileIO for Console {
 
     fn poll(&mut self, event: PollEvent) -> bool {
         match event {
-            PollEvent::Read  => STDIN.lock().contains('\n'),
+            PollEvent::Read  => {
+                sys::workq::run();
+                is_foreground() && STDIN.lock().contains(['\n', EOT])
+            }
             PollEvent::Write => true,
         }
     }
@@ -76,17 +99,38 @@ impl FileIO for Console {
 pub fn print_fmt(args: fmt::Arguments) {
     interrupts::without_interrupts(|| {
         use fmt::Write;
+        if let Some(out) = CAPTURE.lock().last_mut() {
+            out.write_fmt(args).ok();
+            return;
+        }
         sys::vga::WRITER.lock().write_fmt(args).ok();
         sys::serial::print_fmt(args);
     });
 }
 
 fn print_raw(s: &str) {
-    interrupts::without_interrupts(|| {
-        use fmt::Write;
-        sys::vga::WRITER.lock().write_str(s).ok();
-        sys::serial::write_str(s);
-    });
+    print_fmt(format_args!("{}", s));
+}
+
+/// Start collecting output instead of printing it. Captures nest.
+pub fn begin_capture() {
+    interrupts::without_interrupts(|| CAPTURE.lock().push(String::new()));
+}
+
+/// Stop the innermost capture and return what was printed during it
+pub fn end_capture() -> String {
+    interrupts::without_interrupts(|| CAPTURE.lock().pop().unwrap_or_default())
+}
+
+/// Feed `read_line` from `input` instead of the keyboard; `None` restores
+/// the keyboard. Once the input runs out `read_line` returns "" (EOF).
+pub fn set_pipe_input(input: Option<String>) {
+    *PIPE_IN.lock() = input;
+}
+
+/// Whether `read_line` is fed by a pipeline rather than the keyboard
+pub fn is_piped() -> bool {
+    PIPE_IN.lock().is_some()
 }
 
 // ---------------------------------------------------------------------------
@@ -97,6 +141,12 @@ fn print_raw(s: &str) {
 pub fn input_char(c: char) {
     let mut stdin = STDIN.lock();
 
+    // Raw mode: no line editing, the reader sees every key as typed
+    if RAW.load(Ordering::SeqCst) {
+        stdin.push(c);
+        return;
+    }
+
     match c {
         BS => {
             if !stdin.is_empty() && ECHO.load(Ordering::SeqCst) {
@@ -104,9 +154,17 @@ pub fn input_char(c: char) {
                 print_raw("\x08 \x08"); // erase character on screen
             }
         }
+        EOT => {
+            // Ctrl+D — ends the current read; on an empty line that means EOF
+            stdin.push(EOT);
+        }
         ETX => {
-            // Ctrl+C — clear buffer and send signal
+            // Ctrl+C — clear buffer and interrupt the foreground process
             stdin.clear();
+            let fg = foreground();
+            if fg != NO_FOREGROUND && fg > sys::process::INIT_PID {
+                sys::signal::send(fg, sys::signal::SIGINT).ok();
+            }
             if ECHO.load(Ordering::SeqCst) {
                 print_raw("^C\n");
             }
@@ -114,7 +172,7 @@ pub fn input_char(c: char) {
         }
         c => {
             stdin.push(c);
-            if ECHO.load(Ordering::SeqCst) && !RAW.load(Ordering::SeqCst) {
+            if ECHO.load(Ordering::SeqCst) {
                 let s = c.to_string();
                 print_raw(&s);
             }
@@ -122,10 +180,30 @@ pub fn input_char(c: char) {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Foreground process
+// ---------------------------------------------------------------------------
+
+pub fn foreground() -> usize {
+    FOREGROUND.load(Ordering::SeqCst)
+}
+
+pub fn set_foreground(pid: usize) {
+    FOREGROUND.store(pid, Ordering::SeqCst);
+}
+
+/// May the current process take input right now?
+fn is_foreground() -> bool {
+    let fg = foreground();
+    fg == NO_FOREGROUND || fg == sys::process::current_pid()
+}
+
 /// Read a single character from stdin (blocking)
 pub fn read_char() -> char {
     loop {
         x86_64::instructions::hlt();
+        sys::workq::run();
+        if !is_foreground() { continue; }
         let mut stdin = STDIN.lock();
         if !stdin.is_empty() {
             let c = stdin.remove(0);
@@ -134,19 +212,90 @@ pub fn read_char() -> char {
     }
 }
 
-/// Read a line from stdin (blocking, until newline)
+/// Take the next character from stdin if one is waiting and the caller
+/// owns the console
+pub fn try_read_char() -> Option<char> {
+    sys::workq::run();
+    if !is_foreground() {
+        return None;
+    }
+    let mut stdin = STDIN.lock();
+    if stdin.is_empty() { None } else { Some(stdin.remove(0)) }
+}
+
+/// Read a line from stdin (blocking, until newline or Ctrl+D).
+/// Returns "" at end of input; a line cut short by Ctrl+D comes back
+/// without its newline.
 pub fn read_line() -> String {
+    if let Some(input) = PIPE_IN.lock().as_mut() {
+        let end = input.find('\n').map(|i| i + 1).unwrap_or(input.len());
+        return input.drain(..end).collect();
+    }
     loop {
-        x86_64::instructions::hlt();
-        let mut stdin = STDIN.lock();
-        if let Some(pos) = stdin.find('\n') {
-            let line: String = stdin.drain(..=pos).collect();
+        sys::sched::idle();
+        if let Some(line) = try_read_line() {
             return line;
         }
     }
 }
 
+/// Take a complete line if one is waiting and the caller owns the console
+pub fn try_read_line() -> Option<String> {
+    sys::workq::run();
+    if !is_foreground() {
+        return None;
+    }
+    let mut stdin = STDIN.lock();
+    let pos = stdin.find(['\n', EOT])?;
+    let mut line: String = stdin.drain(..=pos).collect();
+    if line.ends_with(EOT) {
+        line.pop();
+    }
+    Some(line)
+}
+
+/// Cooked mode with echo, and nothing left over in the input buffer
+pub fn reset() {
+    disable_raw();
+    enable_echo();
+    STDIN.lock().clear();
+}
+
 pub fn enable_echo()  { ECHO.store(true,  Ordering::SeqCst); }
 pub fn disable_echo() { ECHO.store(false, Ordering::SeqCst); }
 pub fn enable_raw()   { RAW.store(true,   Ordering::SeqCst); }
 pub fn disable_raw()  { RAW.store(false,  Ordering::SeqCst); }
+pub fn is_raw() -> bool { RAW.load(Ordering::SeqCst) }
+
+#[test_case]
+fn eot_ends_input() {
+    disable_echo();
+    STDIN.lock().clear();
+
+    for c in "ab".chars().chain([EOT, EOT]) {
+        input_char(c);
+    }
+    assert_eq!(read_line(), "ab");
+    assert_eq!(read_line(), "");
+
+    enable_echo();
+}
+
+#[test_case]
+fn background_reader_does_not_steal_input() {
+    use crate::sys::process::FakeProcess;
+
+    interrupts::without_interrupts(|| {
+        let child = 2;
+        set_foreground(child);
+        STDIN.lock().clear();
+        STDIN.lock().push_str("for the child\n");
+
+        assert_eq!(try_read_line(), None);
+        assert!(!shared().poll(PollEvent::Read));
+
+        let _child = FakeProcess::enter(child);
+        assert_eq!(try_read_line().as_deref(), Some("for the child\n"));
+        set_foreground(NO_FOREGROUND);
+    });
+}
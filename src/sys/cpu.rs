@@ -2,6 +2,10 @@
 
 use raw_cpuid::CpuId;
 
+pub fn has_pcid() -> bool {
+    CpuId::new().get_feature_info().is_some_and(|f| f.has_pcid())
+}
+
 pub fn init() {
     let cpuid = CpuId::new();
 
@@ -19,4 +23,6 @@ pub fn init() {
             klog!("CPU: {} MHz", mhz);
         }
     }
+
+    crate::sys::sched::init_pcid();
 }
@@ -0,0 +1,112 @@
+//! /dev/hda — the VirtIO disk as one long file
+//!
+//! A handle reads and writes at its own byte position, a sector at a
+//! time underneath: reads go through the handle's readahead, writes
+//! through the buffer cache. Writing part of a sector reads the rest of
+//! it first. Nothing is read or written past the end of the disk.
+
+use super::readahead::{BlockDevice, Readahead, Virtio, SECTOR};
+use super::IoError;
+use crate::sys::virtio;
+
+#[derive(Clone, Debug)]
+pub struct Disk {
+    pos:       u64,
+    readahead: Readahead,
+}
+
+impl Disk {
+    /// None if there is no disk
+    pub fn open() -> Option<Self> {
+        virtio::is_available().then(Self::new)
+    }
+
+    fn new() -> Self {
+        Self { pos: 0, readahead: Readahead::new() }
+    }
+
+    /// Move to byte `pos`, which may be the end of the disk but not past it
+    pub fn seek(&mut self, pos: u64) -> Result<(), IoError> {
+        self.seek_on(&Virtio, pos)
+    }
+
+    fn seek_on(&mut self, dev: &impl BlockDevice, pos: u64) -> Result<(), IoError> {
+        if pos > dev.capacity() * SECTOR as u64 {
+            return Err(IoError::OutOfRange);
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        self.read_from(&mut Virtio, buf)
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        self.write_to(&mut Virtio, buf)
+    }
+
+    fn read_from(&mut self, dev: &mut impl BlockDevice, buf: &mut [u8]) -> Result<usize, IoError> {
+        let size = dev.capacity() * SECTOR as u64;
+        let mut sector = [0u8; SECTOR];
+        let mut done = 0;
+        while done < buf.len() && self.pos < size {
+            let offset = (self.pos % SECTOR as u64) as usize;
+            let n = (SECTOR - offset).min(buf.len() - done);
+            self.readahead.read(dev, self.pos / SECTOR as u64, &mut sector)?;
+            buf[done..done + n].copy_from_slice(&sector[offset..offset + n]);
+            done += n;
+            self.pos += n as u64;
+        }
+        Ok(done)
+    }
+
+    fn write_to(&mut self, dev: &mut impl BlockDevice, buf: &[u8]) -> Result<usize, IoError> {
+        let size = dev.capacity() * SECTOR as u64;
+        let mut sector = [0u8; SECTOR];
+        let mut done = 0;
+        while done < buf.len() && self.pos < size {
+            let offset = (self.pos % SECTOR as u64) as usize;
+            let n = (SECTOR - offset).min(buf.len() - done);
+            let index = self.pos / SECTOR as u64;
+            if n < SECTOR {
+                self.readahead.read(dev, index, &mut sector)?;
+            }
+            sector[offset..offset + n].copy_from_slice(&buf[done..done + n]);
+            self.readahead.write(dev, index, &sector)?;
+            done += n;
+            self.pos += n as u64;
+        }
+        // A full disk takes nothing more
+        if done == 0 && !buf.is_empty() {
+            return Err(IoError::OutOfRange);
+        }
+        Ok(done)
+    }
+}
+
+#[test_case]
+fn disk_reads_and_writes_across_sectors() {
+    use super::readahead::RamDisk;
+    use alloc::vec;
+
+    let mut dev = RamDisk::new(vec![0xAA; 4 * SECTOR]);
+    let mut disk = Disk::new();
+
+    // A write straddling a sector boundary keeps the rest of both sectors
+    disk.seek_on(&dev, SECTOR as u64 - 2).unwrap();
+    assert_eq!(disk.write_to(&mut dev, &[1, 2, 3, 4]), Ok(4));
+    assert_eq!(&dev.data[SECTOR - 3..SECTOR + 3], &[0xAA, 1, 2, 3, 4, 0xAA]);
+
+    disk.seek_on(&dev, SECTOR as u64 - 3).unwrap();
+    let mut buf = [0u8; 6];
+    assert_eq!(disk.read_from(&mut dev, &mut buf), Ok(6));
+    assert_eq!(buf, [0xAA, 1, 2, 3, 4, 0xAA]);
+
+    // The end of the disk is the end of the file
+    disk.seek_on(&dev, 4 * SECTOR as u64 - 1).unwrap();
+    assert_eq!(disk.read_from(&mut dev, &mut buf), Ok(1));
+    assert_eq!(disk.read_from(&mut dev, &mut buf), Ok(0));
+    assert_eq!(disk.write_to(&mut dev, &buf), Err(IoError::OutOfRange));
+    assert!(disk.seek_on(&dev, 4 * SECTOR as u64 + 1).is_err());
+}
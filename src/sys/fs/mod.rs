@@ -5,22 +5,60 @@
 //!
 //! A full disk-based filesystem can be developed later.
 
-use alloc::collections::BTreeMap;
+pub mod disk;
+pub mod mount;
+pub mod overlay;
+pub mod proc;
+pub mod readahead;
+pub mod tar;
+
+use crate::sys::path;
+
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use spin::RwLock;
 
 // ---------------------------------------------------------------------------
 // I/O Traits
 // ---------------------------------------------------------------------------
 
+/// Open / FCNTL handle flag: fail with EAGAIN instead of waiting
+pub const O_NONBLOCK: u8 = 1 << 0;
+
+/// Open flags for files. Without O_RDONLY or O_WRONLY a handle both
+/// reads and writes.
+pub const O_RDONLY: u8 = 1 << 1;
+pub const O_WRONLY: u8 = 1 << 2;
+/// Create the file, empty, if it does not exist
+pub const O_CREATE: u8 = 1 << 3;
+/// Empty an existing file
+pub const O_TRUNC:  u8 = 1 << 4;
+/// Every write goes to the end of the file
+pub const O_APPEND: u8 = 1 << 5;
+
 /// Event type for poll syscall
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PollEvent {
     Read,
     Write,
 }
 
+/// Why data could not be moved to or from where it is stored. Syscalls
+/// report any of them as EIO.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoError {
+    /// The VFS would not take the data back, e.g. its directory is gone
+    WriteBack,
+    /// The device failed the request
+    Device,
+    /// Past the end of the device, or a buffer shorter than a sector
+    OutOfRange,
+    /// The handle cannot do that, e.g. seek on the console
+    Unsupported,
+}
+
 /// All "files" or "devices" must implement this trait
 pub trait FileIO: Send + Sync {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()>;
@@ -28,6 +66,10 @@ pub trait FileIO: Send + Sync {
     fn close(&mut self);
     fn poll(&mut self, event: PollEvent) -> bool;
     fn kind(&self) -> u8 { 0 }
+    /// Push buffered writes to the backing store (no-op by default)
+    fn sync(&mut self) -> Result<(), IoError> { Ok(()) }
+    /// True if there are writes `sync` has not pushed out yet
+    fn is_dirty(&self) -> bool { false }
 }
 
 // ---------------------------------------------------------------------------
@@ -35,51 +77,174 @@ pub trait FileIO: Send + Sync {
 // ---------------------------------------------------------------------------
 
 use crate::sys::console::Console;
+use alloc::sync::Arc;
+
+use crate::sys::log::Kmsg;
+use disk::Disk;
+use overlay::SharedOverlay;
+use crate::sys::net::Socket;
+use crate::sys::pollset::PollSet;
 
 #[derive(Clone, Debug)]
 pub enum Device {
-    Console(Console),
+    Console(Arc<Console>),
     Null,
+    Kmsg(Kmsg),
+    /// COM1, reading from the serial input buffer
+    Serial,
+    /// Reads as endless zeros
+    Zero,
+    /// Reads as endless pseudo-random bytes, from this xorshift state
+    Random(u64),
+    /// The VirtIO disk, as `/dev/hda`
+    Disk(Disk),
+}
+
+/// Next byte of the xorshift generator behind `/dev/random`
+fn next_random(state: &mut u64) -> u8 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 32) as u8
 }
 
 impl FileIO for Device {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        match self { Device::Console(c) => c.read(buf), Device::Null => Ok(0) }
+        match self {
+            Device::Console(c) => c.read(buf),
+            Device::Null       => Ok(0),
+            Device::Kmsg(k)    => k.read(buf),
+            Device::Serial     => Ok(crate::sys::serial::read_blocking(buf)),
+            Device::Zero       => { buf.fill(0); Ok(buf.len()) }
+            Device::Random(s)  => { buf.fill_with(|| next_random(s)); Ok(buf.len()) }
+            Device::Disk(d)    => d.read(buf).map_err(|_| ()),
+        }
     }
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        match self { Device::Console(c) => c.write(buf), Device::Null => Ok(buf.len()) }
+        match self {
+            Device::Console(c) => c.write(buf),
+            Device::Null | Device::Zero | Device::Random(_) => Ok(buf.len()),
+            Device::Kmsg(k)    => k.write(buf),
+            Device::Disk(d)    => d.write(buf).map_err(|_| ()),
+            Device::Serial     => {
+                crate::sys::serial::write_str(&String::from_utf8_lossy(buf));
+                Ok(buf.len())
+            }
+        }
     }
     fn close(&mut self) {}
     fn poll(&mut self, e: PollEvent) -> bool {
-        match self { Device::Console(c) => c.poll(e), Device::Null => false }
+        match self {
+            Device::Console(c) => c.poll(e),
+            Device::Null       => false,
+            Device::Kmsg(k)    => k.poll(e),
+            Device::Serial     => match e {
+                PollEvent::Read  => crate::sys::serial::has_input(),
+                PollEvent::Write => true,
+            },
+            Device::Zero | Device::Random(_) | Device::Disk(_) => true,
+        }
     }
     fn kind(&self) -> u8 { 1 }
+    fn sync(&mut self) -> Result<(), IoError> {
+        match self {
+            Device::Disk(_) => crate::sys::bcache::sync(),
+            _ => Ok(()),
+        }
+    }
 }
 
+/// Open file: a private copy of the VFS contents, written back on sync
 #[derive(Clone, Debug)]
 pub struct MemFile {
     data:   Vec<u8>,
     cursor: usize,
+    /// VFS path the file was opened from, if any
+    path:   Option<String>,
+    /// Written since the last sync
+    dirty:  bool,
+    /// Stored compressed in the VFS, and written back that way
+    compressed: bool,
+    /// O_RDONLY, O_WRONLY and O_APPEND as opened
+    flags:  u8,
+    /// Overlay of the process that opened it, where syncing writes to
+    /// whichever process it happens in
+    overlay: Option<SharedOverlay>,
 }
 
 impl MemFile {
-    fn new(data: Vec<u8>) -> Self { Self { data, cursor: 0 } }
+    fn new(data: Vec<u8>) -> Self {
+        Self { data, cursor: 0, path: None, dirty: false, compressed: false, flags: 0, overlay: None }
+    }
+    /// A file written through an overlay; syncing writes it back there
+    fn overlaid(path: &str, data: &[u8]) -> Option<Self> {
+        Some(Self { path: Some(path.to_string()), ..Self::new(try_copy(data).ok()?) })
+    }
+    fn at(path: &str, stored: &Stored) -> Option<Self> {
+        Some(Self {
+            path:       Some(path.to_string()),
+            compressed: matches!(stored, Stored::Compressed { .. }),
+            ..Self::new(stored.bytes()?)
+        })
+    }
+    /// A copy, or Err if the heap cannot hold the data twice
+    fn try_clone(&self) -> Result<Self, ()> {
+        Ok(Self {
+            data:       try_copy(&self.data)?,
+            cursor:     self.cursor,
+            path:       self.path.clone(),
+            dirty:      self.dirty,
+            compressed: self.compressed,
+            flags:      self.flags,
+            overlay:    self.overlay.clone(),
+        })
+    }
     pub fn size(&self) -> usize   { self.data.len() }
+    pub fn data(&self) -> &[u8]   { &self.data }
+    pub fn path(&self) -> Option<&str> { self.path.as_deref() }
+    /// O_RDONLY, O_WRONLY and O_APPEND as opened
+    pub fn flags(&self) -> u8     { self.flags }
+    /// Move the read and write position, at most to the end
+    pub fn seek(&mut self, pos: u64) -> Result<(), IoError> {
+        self.cursor = usize::try_from(pos).ok().filter(|&p| p <= self.data.len()).ok_or(IoError::OutOfRange)?;
+        Ok(())
+    }
 }
 
 impl FileIO for MemFile {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        if self.flags & O_WRONLY != 0 {
+            return Err(());
+        }
         let remaining = &self.data[self.cursor..];
         let n = remaining.len().min(buf.len());
         buf[..n].copy_from_slice(&remaining[..n]);
         self.cursor += n;
         Ok(n)
     }
+    /// Overwrites from the cursor on, growing the file past its end
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        self.data.extend_from_slice(buf);
+        if self.flags & O_RDONLY != 0 {
+            return Err(());
+        }
+        if self.flags & O_APPEND != 0 {
+            self.cursor = self.data.len();
+        }
+        let end = self.cursor + buf.len();
+        let overlap = end.min(self.data.len()) - self.cursor;
+        self.data.try_reserve(buf.len() - overlap).map_err(|_| ())?;
+        self.data[self.cursor..self.cursor + overlap].copy_from_slice(&buf[..overlap]);
+        self.data.extend_from_slice(&buf[overlap..]);
+        self.cursor = end;
+        self.dirty = true;
         Ok(buf.len())
     }
-    fn close(&mut self) {}
+    /// Flushes what was written back to the VFS
+    fn close(&mut self) {
+        if self.is_dirty() && self.sync().is_err() {
+            kwarn!("fs: could not write back {}", self.path.as_deref().unwrap_or(""));
+        }
+    }
     fn poll(&mut self, e: PollEvent) -> bool {
         match e {
             PollEvent::Read  => self.cursor < self.data.len(),
@@ -87,53 +252,304 @@ impl FileIO for MemFile {
         }
     }
     fn kind(&self) -> u8 { 0 }
+    fn sync(&mut self) -> Result<(), IoError> {
+        if let Some(path) = &self.path {
+            write_file_to(self.overlay.as_ref(), path, &self.data, self.compressed)
+                .map_err(|_| IoError::WriteBack)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+    fn is_dirty(&self) -> bool { self.dirty && self.path.is_some() }
 }
 
 #[derive(Clone, Debug)]
 pub enum Resource {
     Device(Device),
     File(MemFile),
+    Socket(Socket),
+    PollSet(PollSet),
 }
 
 impl Resource {
+    /// Like `clone`, but Err instead of aborting when the heap is full
+    pub(crate) fn try_clone(&self) -> Result<Self, ()> {
+        match self {
+            Resource::File(f) => f.try_clone().map(Resource::File),
+            _ => Ok(self.clone()),
+        }
+    }
     pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        match self { Resource::Device(d) => d.read(buf), Resource::File(f) => f.read(buf) }
+        match self {
+            Resource::Device(d) => d.read(buf),
+            Resource::File(f)   => f.read(buf),
+            Resource::Socket(s) => s.read(buf),
+            Resource::PollSet(p) => p.read(buf),
+        }
     }
     pub fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        match self { Resource::Device(d) => d.write(buf), Resource::File(f) => f.write(buf) }
+        match self {
+            Resource::Device(d) => d.write(buf),
+            Resource::File(f)   => f.write(buf),
+            Resource::Socket(s) => s.write(buf),
+            Resource::PollSet(p) => p.write(buf),
+        }
     }
     pub fn close(&mut self) {
-        match self { Resource::Device(d) => d.close(), Resource::File(f) => f.close() }
+        match self {
+            Resource::Device(d) => d.close(),
+            Resource::File(f)   => f.close(),
+            Resource::Socket(s) => s.close(),
+            Resource::PollSet(p) => p.close(),
+        }
     }
     pub fn poll(&mut self, e: PollEvent) -> bool {
-        match self { Resource::Device(d) => d.poll(e), Resource::File(f) => f.poll(e) }
+        match self {
+            Resource::Device(d) => d.poll(e),
+            Resource::File(f)   => f.poll(e),
+            Resource::Socket(s) => s.poll(e),
+            Resource::PollSet(p) => p.poll(e),
+        }
     }
     pub fn kind(&self) -> u8 {
-        match self { Resource::Device(d) => d.kind(), Resource::File(f) => f.kind() }
+        match self {
+            Resource::Device(d) => d.kind(),
+            Resource::File(f)   => f.kind(),
+            Resource::Socket(s) => s.kind(),
+            Resource::PollSet(p) => p.kind(),
+        }
     }
     pub fn size(&self) -> usize {
         match self { Resource::File(f) => f.size(), _ => 0 }
     }
+    /// Move the read position of a file, or the position of the disk.
+    /// Writes to a file still append.
+    pub fn seek(&mut self, pos: u64) -> Result<(), IoError> {
+        match self {
+            Resource::File(f) => f.seek(pos),
+            Resource::Device(Device::Disk(d)) => d.seek(pos),
+            Resource::Device(Device::Null | Device::Zero | Device::Random(_)) => Ok(()),
+            _ => Err(IoError::Unsupported),
+        }
+    }
+    /// True if `read` or `write` would have to wait. Files and the null
+    /// device never do (they report end of file instead).
+    pub fn would_block(&mut self, e: PollEvent) -> bool {
+        match self {
+            Resource::File(_) | Resource::Device(Device::Null) => false,
+            _ => !self.poll(e),
+        }
+    }
+    pub fn sync(&mut self) -> Result<(), IoError> {
+        match self {
+            Resource::Device(d) => d.sync(),
+            Resource::File(f)   => f.sync(),
+            Resource::Socket(s) => s.sync(),
+            Resource::PollSet(p) => p.sync(),
+        }
+    }
+    /// Forget unsynced writes, for a copy handed to a child: they are the
+    /// parent's to write back, and would go stale in the child
+    pub fn mark_clean(&mut self) {
+        if let Resource::File(f) = self {
+            f.dirty = false;
+        }
+    }
+    pub fn is_dirty(&self) -> bool {
+        match self {
+            Resource::Device(d) => d.is_dirty(),
+            Resource::File(f)   => f.is_dirty(),
+            Resource::Socket(s) => s.is_dirty(),
+            Resource::PollSet(p) => p.is_dirty(),
+        }
+    }
 }
 
 // File handle type alias
 pub type FileHandle = Resource;
 
+/// Move up to `len` bytes from `src` to `dst` without a userspace buffer.
+/// Returns the number of bytes copied (short at end of `src`).
+pub(crate) fn copy_range(src: &mut Resource, dst: &mut Resource, len: usize) -> Result<usize, ()> {
+    // File to file: straight from one Vec into the other
+    if let (Resource::File(s), Resource::File(d)) = (&mut *src, &mut *dst) {
+        if s.flags & O_WRONLY != 0 {
+            return Err(());
+        }
+        let n = len.min(s.data.len() - s.cursor);
+        d.write(&s.data[s.cursor..s.cursor + n])?;
+        s.cursor += n;
+        return Ok(n);
+    }
+
+    // Anything else goes through a small kernel bounce buffer
+    let mut buf   = [0u8; 512];
+    let mut total = 0;
+    while total < len {
+        let want = (len - total).min(buf.len());
+        let n = src.read(&mut buf[..want])?;
+        if n == 0 { break; }
+        dst.write(&buf[..n])?;
+        total += n;
+    }
+    Ok(total)
+}
+
 // ---------------------------------------------------------------------------
 // In-memory VFS (Virtual File System)
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct FileInfo {
     pub size:    usize,
     pub is_dir:  bool,
     pub name:    String,
+    /// Paths naming the same data (hard links)
+    pub links:   usize,
+}
+
+/// File contents as kept in the VFS
+#[derive(Debug)]
+enum Stored {
+    Plain(Vec<u8>),
+    /// LZSS-compressed (`sys::compress`), `len` bytes once expanded
+    Compressed { len: usize, data: Vec<u8> },
+}
+
+impl Stored {
+    fn len(&self) -> usize {
+        match self {
+            Stored::Plain(data)            => data.len(),
+            Stored::Compressed { len, .. } => *len,
+        }
+    }
+
+    /// Heap bytes taken by the contents
+    fn stored_len(&self) -> usize {
+        match self {
+            Stored::Plain(data) | Stored::Compressed { data, .. } => data.len(),
+        }
+    }
+
+    /// Heap bytes reserved for the contents, used or not
+    fn capacity(&self) -> usize {
+        match self {
+            Stored::Plain(data) | Stored::Compressed { data, .. } => data.capacity(),
+        }
+    }
+
+    fn shrink(&mut self) {
+        match self {
+            Stored::Plain(data) | Stored::Compressed { data, .. } => data.shrink_to_fit(),
+        }
+    }
+
+    /// The file contents; None if they do not fit in the heap expanded
+    fn bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Stored::Plain(data) => try_copy(data).ok(),
+            Stored::Compressed { len, data } => {
+                crate::sys::compress::decompress(data, *len).ok()
+            }
+        }
+    }
+}
+
+/// Inode number
+type Ino = u64;
+
+/// A file's contents, shared by every path linked to it
+#[derive(Debug)]
+struct Inode {
+    stored: Stored,
+    links:  usize,
+}
+
+/// Directory entries (path → inode) over the inode table. The data of an
+/// inode goes away with its last link.
+#[derive(Debug, Default)]
+struct Vfs {
+    entries:  BTreeMap<String, Ino>,
+    inodes:   BTreeMap<Ino, Inode>,
+    next_ino: Ino,
 }
 
-type Vfs = BTreeMap<String, Vec<u8>>;
+impl Vfs {
+    fn inode(&self, path: &str) -> Option<&Inode> {
+        self.inodes.get(self.entries.get(path)?)
+    }
+
+    fn get(&self, path: &str) -> Option<&Stored> {
+        self.inode(path).map(|inode| &inode.stored)
+    }
+
+    fn get_mut(&mut self, path: &str) -> Option<&mut Stored> {
+        self.inodes.get_mut(self.entries.get(path)?).map(|inode| &mut inode.stored)
+    }
+
+    fn contains_key(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Set the contents at `path`. An existing file keeps its inode, so
+    /// every link to it sees the new data. Returns the inode.
+    fn insert(&mut self, path: String, stored: Stored) -> Ino {
+        if let Some(&ino) = self.entries.get(&path) {
+            if let Some(inode) = self.inodes.get_mut(&ino) {
+                inode.stored = stored;
+                return ino;
+            }
+        }
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.inodes.insert(ino, Inode { stored, links: 1 });
+        self.entries.insert(path, ino);
+        ino
+    }
+
+    /// Make `new` another name for the inode of `old`
+    fn link(&mut self, old: &str, new: String) -> Result<(), ()> {
+        if self.entries.contains_key(&new) {
+            return Err(());
+        }
+        let ino = *self.entries.get(old).ok_or(())?;
+        self.inodes.get_mut(&ino).ok_or(())?.links += 1;
+        self.entries.insert(new, ino);
+        Ok(())
+    }
+
+    /// Drop the entry `path`. Returns its inode and whether that was the
+    /// last link, freeing the data.
+    fn remove(&mut self, path: &str) -> Option<(Ino, bool)> {
+        let ino = self.entries.remove(path)?;
+        let inode = self.inodes.get_mut(&ino)?;
+        inode.links -= 1;
+        let last = inode.links == 0;
+        if last {
+            self.inodes.remove(&ino);
+        }
+        Some((ino, last))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&String, &Inode)> {
+        self.entries.iter().filter_map(|(path, ino)| Some((path, self.inodes.get(ino)?)))
+    }
+}
+
+/// Writes and removes since the last compaction
+static CHURN: AtomicUsize = AtomicUsize::new(0);
+/// Inodes written since the last compaction; the others count as cold
+static TOUCHED: RwLock<BTreeSet<Ino>> = RwLock::new(BTreeSet::new());
+/// Uptime (ms) of the last compaction
+static LAST_COMPACT: AtomicU64 = AtomicU64::new(0);
+
+/// Least time between two idle compactions
+const IDLE_COMPACT_MS: u64 = 10_000;
+/// Smallest cold file worth compressing
+const COLD_MIN: usize = 4096;
 
 lazy_static::lazy_static! {
-    static ref VFS: RwLock<Vfs> = RwLock::new(BTreeMap::new());
+    static ref VFS: RwLock<Vfs> = RwLock::new(Vfs::default());
     static ref MOUNTED: spin::Once<()> = spin::Once::new();
 }
 
@@ -146,71 +562,379 @@ pub fn is_mounted() -> bool {
 }
 
 pub fn mount_memfs() {
-    MOUNTED.call_once(|| ());
+    MOUNTED.call_once(|| {
+        mount::mount("/", mount::FsKind::MemFs, "memfs", false).ok();
+    });
     klog!("FS: MemFS mounted");
 }
 
+/// Ask the overlay of the current process about `path`. None if there
+/// is no overlay or it leaves the path to the VFS; otherwise `f` gets the
+/// contents, None for a file removed in the overlay.
+fn overlaid<R>(path: &str, f: impl FnOnce(Option<&[u8]>) -> R) -> Option<R> {
+    let overlay = crate::sys::process::overlay()?;
+    let overlay = overlay.read();
+    overlay.get(path).map(f)
+}
+
 pub fn exists(path: &str) -> bool {
+    if let Some(found) = overlaid(path, |data| data.is_some()) {
+        return found;
+    }
     VFS.read().contains_key(path)
 }
 
+/// `path` as the current process sees it: absolute, relative to its cwd
+/// if it was not, with `.` and `..` resolved. `..` at `/` stays at `/`.
+pub(crate) fn view_path(path: &str) -> Result<String, ()> {
+    Ok(normalize(&path::join(&crate::sys::process::cwd(), path)))
+}
+
+/// The VFS path `path` refers to for the current process: its view path
+/// placed under the process root, so a chrooted process cannot name
+/// anything outside it
 pub fn canonicalize(path: &str) -> Result<String, ()> {
-    // Simple implementation: normalize slashes
-    let canonical = if path.starts_with('/') {
-        path.to_string()
-    } else {
-        let cwd = crate::sys::process::cwd();
-        if cwd.ends_with('/') {
-            alloc::format!("{}{}", cwd, path)
-        } else {
-            alloc::format!("{}/{}", cwd, path)
+    let view = view_path(path)?;
+    let root = crate::sys::process::root();
+    Ok(match (root.as_str(), view.as_str()) {
+        ("/", _) => view,
+        (_, "/") => root,
+        _        => alloc::format!("{}{}", root, view),
+    })
+}
+
+/// Like `canonicalize`, for a file or directory about to be created:
+/// the directory it goes in must already exist
+pub(crate) fn canonicalize_new(path: &str) -> Result<String, ()> {
+    let path = canonicalize(path)?;
+    if !dir_exists(path::dirname(&path)) {
+        return Err(());
+    }
+    Ok(path)
+}
+
+/// Resolve `.` and `..` in an absolute path and drop empty components
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".."     => { parts.pop(); }
+            _        => parts.push(part),
         }
-    };
-    Ok(canonical)
+    }
+    let mut out = String::new();
+    for part in &parts {
+        out.push('/');
+        out.push_str(part);
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    out
+}
+
+/// Directories searched for commands when `PATH` is not set
+pub const DEFAULT_PATH: &str = "/bin";
+
+/// Find the program `name` refers to. Names containing `/` are paths
+/// (relative to cwd); bare names are looked up in each directory of the
+/// colon-separated `PATH`, first match wins. Both stay under the process
+/// root.
+pub fn resolve_command(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return None;
+    }
+    if name.contains('/') {
+        return canonicalize(name).ok().filter(|p| exists(p));
+    }
+    let path = crate::sys::process::env_var("PATH").unwrap_or_else(|| DEFAULT_PATH.into());
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .filter_map(|dir| canonicalize(&path::join(dir, name)).ok())
+        .find(|p| exists(p))
 }
 
 pub fn open_file(path: &str) -> Option<MemFile> {
-    VFS.read().get(path).map(|data| MemFile::new(data.clone()))
+    if let Some(data) = proc::read(path) {
+        return Some(MemFile::new(data));
+    }
+    let file = match overlaid(path, |data| data.and_then(|d| MemFile::overlaid(path, d))) {
+        Some(file) => file,
+        None => VFS.read().get(path).and_then(|stored| MemFile::at(path, stored)),
+    };
+    let overlay = crate::sys::process::overlay();
+    file.map(|file| MemFile { overlay, ..file })
+}
+
+pub fn open_resource(path: &str, flags: u8) -> Option<Resource> {
+    if let Some(name) = path.strip_prefix("/dev/") {
+        return open_device(name).map(Resource::Device);
+    }
+    if let Some(data) = proc::read(path) {
+        return Some(Resource::File(MemFile::new(data)));
+    }
+    let found = exists(path);
+    let create = flags & O_CREATE != 0 && !found;
+    if create && (dir_exists(path) || !dir_exists(path::dirname(path))) {
+        return None;
+    }
+    if create || found && flags & O_TRUNC != 0 {
+        write_file(path, b"").ok()?;
+    }
+    let mut file = open_file(path)?;
+    file.flags = flags & (O_RDONLY | O_WRONLY | O_APPEND);
+    if flags & O_APPEND != 0 {
+        file.cursor = file.data.len();
+    }
+    Some(Resource::File(file))
+}
+
+/// Seed for a new `/dev/random` handle: the time stamp counter, never 0
+fn random_seed() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() | 1 }
+}
+
+/// Capability needed to open `path`, for the devices that bypass the
+/// filesystem: the raw disk could rewrite what is mounted from it
+pub fn device_cap(path: &str) -> Option<u32> {
+    (path == "/dev/hda").then_some(crate::sys::process::CAP_MOUNT)
 }
 
-pub fn open_resource(path: &str, _flags: u8) -> Option<Resource> {
-    VFS.read().get(path).map(|data| Resource::File(MemFile::new(data.clone())))
+/// The device behind `/dev/<name>`
+fn open_device(name: &str) -> Option<Device> {
+    let cap = device_cap(&alloc::format!("/dev/{}", name));
+    if cap.is_some_and(|cap| !crate::sys::process::has_cap(cap)) {
+        return None;
+    }
+    match name {
+        "console" => Some(Device::Console(crate::sys::console::shared())),
+        "null"    => Some(Device::Null),
+        "kmsg"    => Some(Device::Kmsg(Kmsg::new())),
+        "serial"  => Some(Device::Serial),
+        "zero"    => Some(Device::Zero),
+        "random"  => Some(Device::Random(random_seed())),
+        "hda"     => Disk::open().map(Device::Disk),
+        _         => None,
+    }
 }
 
 pub fn stat(path: &str) -> Option<FileInfo> {
-    VFS.read().get(path).map(|data| FileInfo {
-        size:   data.len(),
-        is_dir: false,
-        name:   path.rsplit('/').next().unwrap_or(path).to_string(),
-    })
+    let name = path::basename(path).to_string();
+    match overlaid(path, |data| data.map(<[u8]>::len)) {
+        Some(Some(size)) => return Some(FileInfo { size, is_dir: false, name, links: 1 }),
+        Some(None) => {}
+        None => if let Some(inode) = VFS.read().inode(path) {
+            return Some(FileInfo { size: inode.stored.len(), is_dir: false, name, links: inode.links });
+        },
+    }
+    dir_exists(path).then_some(FileInfo { size: 0, is_dir: true, name, links: 1 })
 }
 
 pub fn write_file(path: &str, data: &[u8]) -> Result<(), ()> {
-    VFS.write().insert(path.to_string(), data.to_vec());
+    write_file_with(path, data, false)
+}
+
+/// Write a file, compressing it in memory if `compress` is set and that
+/// actually saves space. Readers see the plain contents either way.
+pub(crate) fn write_file_with(path: &str, data: &[u8], compress: bool) -> Result<(), ()> {
+    write_file_to(crate::sys::process::overlay().as_ref(), path, data, compress)
+}
+
+/// Write a file into `overlay`, or into the VFS if there is none
+fn write_file_to(overlay: Option<&SharedOverlay>, path: &str, data: &[u8], compress: bool) -> Result<(), ()> {
+    if let Some(overlay) = overlay {
+        overlay.write().write(path.to_string(), try_copy(data)?);
+        return Ok(());
+    }
+    let packed = if compress { Some(crate::sys::compress::compress(data)) } else { None };
+    let stored = match packed {
+        Some(packed) if packed.len() < data.len() => {
+            Stored::Compressed { len: data.len(), data: packed }
+        }
+        _ => Stored::Plain(try_copy(data)?),
+    };
+    let path = try_copy(path.as_bytes())?;
+    let path = String::from_utf8(path).map_err(|_| ())?;
+    let ino = VFS.write().insert(path, stored);
+    TOUCHED.write().insert(ino);
+    CHURN.fetch_add(1, Ordering::Relaxed);
     Ok(())
 }
 
+/// Overwrite parts of an existing file in place: each `(offset, bytes)`
+/// chunk replaces what is there, cut short at the end of the file. The
+/// size of the file and how it is stored stay the same.
+pub fn patch_file(path: &str, chunks: &[(usize, &[u8])]) -> Result<(), IoError> {
+    let mut file = open_file(path).filter(|f| f.path.is_some()).ok_or(IoError::WriteBack)?;
+    for &(offset, bytes) in chunks {
+        let end = offset.saturating_add(bytes.len()).min(file.data.len());
+        if offset < end {
+            file.data[offset..end].copy_from_slice(&bytes[..end - offset]);
+        }
+    }
+    file.sync()
+}
+
 /// Append data to an existing file, or create it if it does not exist
 pub fn append_file(path: &str, data: &[u8]) -> Result<(), ()> {
+    if crate::sys::process::overlay().is_some() {
+        let mut all = open_file(path).map(|f| f.data).unwrap_or_default();
+        all.try_reserve(data.len()).map_err(|_| ())?;
+        all.extend_from_slice(data);
+        return write_file(path, &all);
+    }
     let mut vfs = VFS.write();
-    let entry = vfs.entry(path.to_string()).or_insert_with(alloc::vec::Vec::new);
-    entry.extend_from_slice(data);
-    Ok(())
+    if !vfs.contains_key(path) {
+        let path = String::from_utf8(try_copy(path.as_bytes())?).map_err(|_| ())?;
+        vfs.insert(path, Stored::Plain(Vec::new()));
+    }
+    if let Some(&ino) = vfs.entries.get(path) {
+        TOUCHED.write().insert(ino);
+    }
+    CHURN.fetch_add(1, Ordering::Relaxed);
+    match vfs.get_mut(path).ok_or(())? {
+        Stored::Plain(entry) => {
+            entry.try_reserve(data.len()).map_err(|_| ())?;
+            entry.extend_from_slice(data);
+            Ok(())
+        }
+        compressed => {
+            let mut all = compressed.bytes().ok_or(())?;
+            all.extend_from_slice(data);
+            let packed = crate::sys::compress::compress(&all);
+            *compressed = Stored::Compressed { len: all.len(), data: packed };
+            Ok(())
+        }
+    }
+}
+
+/// Heap bytes used by the contents of `path`, less than its size if it
+/// is stored compressed
+pub fn stored_size(path: &str) -> Option<usize> {
+    if let Some(size) = overlaid(path, |data| data.map(<[u8]>::len)) {
+        return size;
+    }
+    VFS.read().get(path).map(Stored::stored_len)
+}
+
+/// Copy `data` to the heap, failing instead of aborting when it is full.
+/// (The map node itself is still allocated infallibly, but it is small.)
+fn try_copy(data: &[u8]) -> Result<Vec<u8>, ()> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(data.len()).map_err(|_| ())?;
+    v.extend_from_slice(data);
+    Ok(v)
 }
 
 /// Check if path is a registered directory
+/// A directory exists once `mkdir` made it, or while files are in it
 pub fn dir_exists(path: &str) -> bool {
     if path == "/" { return true; }
-    VFS.read().contains_key(&alloc::format!("{}/.dir", path))
+    exists(&alloc::format!("{}/.dir", path)) || !list_files(path).is_empty()
 }
 
 /// Create a directory entry in VFS
 pub fn mkdir(path: &str) {
-    VFS.write().insert(alloc::format!("{}/.dir", path), alloc::vec::Vec::new());
+    if let Some(overlay) = crate::sys::process::overlay() {
+        overlay.write().write(alloc::format!("{}/.dir", path), Vec::new());
+        return;
+    }
+    VFS.write().insert(alloc::format!("{}/.dir", path), Stored::Plain(Vec::new()));
 }
 
+/// Remove the name `path`; the data stays while other hard links remain
 pub fn remove(path: &str) -> Result<(), ()> {
-    VFS.write().remove(path).map(|_| ()).ok_or(())
+    if let Some(overlay) = crate::sys::process::overlay() {
+        if !exists(path) {
+            return Err(());
+        }
+        overlay.write().remove(path);
+        return Ok(());
+    }
+    let (ino, last) = VFS.write().remove(path).ok_or(())?;
+    if last {
+        TOUCHED.write().remove(&ino);
+    }
+    CHURN.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Create `new` as a hard link to the file `old`: both names share the
+/// same data. Fails if `old` is missing or `new` exists, and in an
+/// overlay, which has no inodes to share.
+pub(crate) fn link(old: &str, new: &str) -> Result<(), ()> {
+    if crate::sys::process::overlay().is_some() {
+        return Err(());
+    }
+    let new = String::from_utf8(try_copy(new.as_bytes())?).map_err(|_| ())?;
+    VFS.write().link(old, new)?;
+    CHURN.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// What a compaction pass did
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompactStats {
+    pub files: usize,
+    /// Cold files that were compressed
+    pub compressed: usize,
+    /// Heap bytes given back
+    pub freed: usize,
+}
+
+/// Give back the spare capacity files accumulated through appends, and
+/// compress large files nobody wrote since the previous pass. One file
+/// at a time, so a writer waits for one file at most and readers only
+/// for the swap.
+pub fn compact() -> CompactStats {
+    let mut stats = CompactStats::default();
+    // Files written from here on count for the next pass
+    let touched = core::mem::take(&mut *TOUCHED.write());
+    let inos: Vec<Ino> = VFS.read().inodes.keys().copied().collect();
+    for ino in inos {
+        let vfs = VFS.upgradeable_read();
+        let packed = match vfs.inodes.get(&ino).map(|inode| &inode.stored) {
+            Some(Stored::Plain(data)) if data.len() >= COLD_MIN && !touched.contains(&ino) => {
+                let packed = crate::sys::compress::compress(data);
+                (packed.len() < data.len() - data.len() / 4).then_some((data.len(), packed))
+            }
+            _ => None,
+        };
+        let mut vfs = vfs.upgrade();
+        let Some(inode) = vfs.inodes.get_mut(&ino) else {
+            continue; // removed since the list was taken
+        };
+        let stored = &mut inode.stored;
+        let before = stored.capacity();
+        if let Some((len, data)) = packed {
+            *stored = Stored::Compressed { len, data };
+            stats.compressed += 1;
+        }
+        stored.shrink();
+        stats.files += 1;
+        stats.freed += before.saturating_sub(stored.capacity());
+    }
+    CHURN.store(0, Ordering::Relaxed);
+    LAST_COMPACT.store(crate::sys::clk::uptime_ns() / 1_000_000, Ordering::Relaxed);
+    stats
+}
+
+/// Idle hook: compact if files changed, a while has passed since the
+/// last pass and no user process is waiting for the CPU
+pub fn compact_if_idle() {
+    let now = crate::sys::clk::uptime_ns() / 1_000_000;
+    if (CHURN.load(Ordering::Relaxed) == 0 && TOUCHED.read().is_empty())
+        || now - LAST_COMPACT.load(Ordering::Relaxed) < IDLE_COMPACT_MS
+        || crate::sys::process::has_runnable_user()
+    {
+        return;
+    }
+    let stats = compact();
+    if stats.freed > 0 {
+        kdebug!("FS: compacted {} file(s), compressed {}, freed {} bytes",
+                stats.files, stats.compressed, stats.freed);
+    }
 }
 
 /// List all files in the VFS, optionally filtered by directory prefix
@@ -224,7 +948,7 @@ pub fn list_files(dir: &str) -> alloc::vec::Vec<FileInfo> {
         alloc::format!("{}/", dir)
     };
 
-    vfs.iter()
+    let mut files: BTreeMap<String, FileInfo> = vfs.iter()
         .filter(|(path, _)| {
             if dir == "/" {
                 true
@@ -232,20 +956,246 @@ pub fn list_files(dir: &str) -> alloc::vec::Vec<FileInfo> {
                 path.starts_with(&prefix)
             }
         })
-        .map(|(path, data)| FileInfo {
-            size:   data.len(),
+        .map(|(path, inode)| (path.clone(), FileInfo {
+            size:   inode.stored.len(),
             is_dir: false,
             name:   path.clone(),
-        })
-        .collect()
+            links:  inode.links,
+        }))
+        .collect();
+
+    if let Some(overlay) = crate::sys::process::overlay() {
+        for (path, data) in overlay.read().iter().filter(|(path, _)| path.starts_with(&prefix)) {
+            match data {
+                Some(data) => {
+                    let info = FileInfo { size: data.len(), is_dir: false, name: path.clone(), links: 1 };
+                    files.insert(path.clone(), info);
+                }
+                None => { files.remove(path); }
+            }
+        }
+    }
+    files.into_values().collect()
+}
+
+/// Immediate children of `dir`, by name: its files, and its
+/// subdirectories, whether `mkdir` made them or the paths of the files
+/// in them only imply them
+pub fn read_dir(dir: &str) -> Vec<FileInfo> {
+    let prefix = if dir.ends_with('/') { dir.to_string() } else { alloc::format!("{}/", dir) };
+    let mut children: BTreeMap<String, FileInfo> = BTreeMap::new();
+    for file in list_files(dir) {
+        let Some(rest) = file.name.strip_prefix(&prefix) else { continue };
+        match rest.split_once('/') {
+            None if rest == ".dir" => {}
+            None => {
+                children.insert(rest.to_string(), FileInfo { name: rest.to_string(), ..file });
+            }
+            Some((sub, _)) => {
+                let info = FileInfo { size: 0, is_dir: true, name: sub.to_string(), links: 1 };
+                children.insert(sub.to_string(), info);
+            }
+        }
+    }
+    children.into_values().collect()
 }
 
 /// Called during sys::mem::init
 pub fn init() {
     mount_memfs();
 
+    for dir in ["/ini", "/tmp"] {
+        if !dir_exists(dir) {
+            mkdir(dir);
+        }
+    }
+
     // Write default boot script if it doesn't exist
     if !exists("/ini/boot.sh") {
         write_file("/ini/boot.sh", b"shell\n").ok();
     }
 }
+
+#[test_case]
+fn copy_range_between_handles() {
+    let mut src = Resource::File(MemFile::new(b"hello world".to_vec()));
+    let mut dst = Resource::File(MemFile::new(b">".to_vec()));
+    dst.seek(1).unwrap();
+
+    assert_eq!(copy_range(&mut src, &mut dst, 5), Ok(5));
+    assert_eq!(copy_range(&mut src, &mut dst, 100), Ok(6));
+    assert_eq!(copy_range(&mut src, &mut dst, 100), Ok(0));
+    match &dst {
+        Resource::File(f) => assert_eq!(f.data(), b">hello world"),
+        _ => unreachable!(),
+    }
+
+    // Non-file destinations take the buffered path
+    let mut src  = Resource::File(MemFile::new(alloc::vec![7; 1500]));
+    let mut null = Resource::Device(Device::Null);
+    assert_eq!(copy_range(&mut src, &mut null, 2000), Ok(1500));
+}
+
+#[test_case]
+fn fsync_makes_writes_visible() {
+    let path = "/tmp/fsync-test";
+    write_file(path, b"a").ok();
+
+    let mut res = open_resource(path, O_APPEND).unwrap();
+    res.write(b"bc").unwrap();
+    assert_eq!(open_file(path).unwrap().data(), b"a");
+
+    res.sync().unwrap();
+    assert_eq!(open_file(path).unwrap().data(), b"abc");
+    remove(path).ok();
+}
+
+#[test_case]
+fn open_flags_create_truncate_and_append() {
+    let path = "/tmp/oflags-test";
+    assert!(open_resource(path, 0).is_none());
+    assert!(open_resource("/tmp/no-such-dir/x", O_CREATE).is_none());
+
+    // Created empty, and the writes reach the VFS on close
+    let mut res = open_resource(path, O_CREATE | O_WRONLY).unwrap();
+    assert!(exists(path));
+    res.write(b"hello").unwrap();
+    assert_eq!(res.read(&mut [0; 4]), Err(()));
+    res.close();
+    assert_eq!(open_file(path).unwrap().data(), b"hello");
+
+    // Writes go at the cursor, or at the end with O_APPEND
+    let mut res = open_resource(path, O_CREATE).unwrap();
+    res.write(b"J").unwrap();
+    res.close();
+    let mut res = open_resource(path, O_APPEND).unwrap();
+    res.seek(0).unwrap();
+    res.write(b"!").unwrap();
+    res.close();
+    assert_eq!(open_file(path).unwrap().data(), b"Jello!");
+
+    let mut res = open_resource(path, O_RDONLY | O_TRUNC).unwrap();
+    assert_eq!(open_file(path).unwrap().size(), 0);
+    assert_eq!(res.write(b"x"), Err(()));
+    remove(path).ok();
+}
+
+#[test_case]
+fn write_fails_cleanly_when_heap_is_full() {
+    // Take the largest block we can get, so a second copy cannot fit
+    let mut big = Vec::new();
+    let mut size = crate::sys::mem::free_memory();
+    while big.try_reserve_exact(size).is_err() {
+        size -= size / 16;
+    }
+    big.resize(size, 0u8);
+    assert_eq!(write_file("/tmp/oom-test", &big), Err(()));
+    assert!(!exists("/tmp/oom-test"));
+
+    let mut file = MemFile::new(Vec::new());
+    assert_eq!(file.write(&big), Err(()));
+    assert_eq!(file.size(), 0);
+}
+
+#[test_case]
+fn compressed_files_read_back_plain() {
+    let path = "/tmp/lz-test";
+    let text = b"the quick brown fox jumps over the lazy dog\n".repeat(100);
+    write_file_with(path, &text, true).unwrap();
+
+    assert_eq!(stat(path).unwrap().size, text.len());
+    assert!(stored_size(path).unwrap() < text.len() / 4);
+    assert_eq!(open_file(path).unwrap().data(), &text[..]);
+
+    // Appends and synced writes keep the file compressed
+    append_file(path, b"end\n").unwrap();
+    let mut res = open_resource(path, O_APPEND).unwrap();
+    res.write(b"!").unwrap();
+    res.sync().unwrap();
+    let data = open_file(path).unwrap();
+    assert_eq!(data.size(), text.len() + 5);
+    assert!(data.data().ends_with(b"end\n!"));
+    assert!(stored_size(path).unwrap() < text.len() / 4);
+    remove(path).ok();
+}
+
+#[test_case]
+fn compaction_returns_append_slack() {
+    let paths = ["/tmp/gc-a", "/tmp/gc-b", "/tmp/gc-c"];
+    for round in 0..200 {
+        for path in paths {
+            append_file(path, &[round as u8; 7]).unwrap();
+        }
+        write_file("/tmp/gc-scratch", &[0; 64]).unwrap();
+        remove("/tmp/gc-scratch").unwrap();
+    }
+
+    let before = crate::sys::mem::used_memory();
+    let stats  = compact();
+    assert!(stats.freed > 0);
+    assert!(crate::sys::mem::used_memory() < before);
+    assert_eq!(open_file(paths[0]).unwrap().size(), 1400);
+
+    // A large file left alone for a whole pass gets compressed
+    let log = b"idle idle idle\n".repeat(400);
+    write_file("/tmp/gc-log", &log).unwrap();
+    compact();
+    assert_eq!(stored_size("/tmp/gc-log"), Some(log.len()));
+    assert!(compact().compressed >= 1);
+    assert!(stored_size("/tmp/gc-log").unwrap() < log.len());
+    assert_eq!(open_file("/tmp/gc-log").unwrap().data(), &log[..]);
+
+    for path in paths.iter().chain(&["/tmp/gc-log"]) {
+        remove(path).ok();
+    }
+}
+
+#[test_case]
+fn hard_links_share_data() {
+    let (a, b) = ("/tmp/link-a", "/tmp/link-b");
+    write_file(a, b"one").unwrap();
+    link(a, b).unwrap();
+    assert_eq!(stat(b).unwrap().links, 2);
+    assert!(link(a, b).is_err());
+
+    // A write through either name is seen through the other
+    let mut res = open_resource(b, O_APPEND).unwrap();
+    res.write(b"two").unwrap();
+    res.sync().unwrap();
+    assert_eq!(open_file(a).unwrap().data(), b"onetwo");
+
+    // The data outlives the first name
+    remove(a).unwrap();
+    assert_eq!(open_file(b).unwrap().data(), b"onetwo");
+    assert_eq!(stat(b).unwrap().links, 1);
+    let ino = VFS.read().entries[b];
+    remove(b).unwrap();
+    assert!(!VFS.read().inodes.contains_key(&ino));
+}
+
+#[test_case]
+fn directories_list_their_immediate_children() {
+    let dir = "/tmp/dir-test";
+    mkdir(dir);
+    mkdir("/tmp/dir-test/empty");
+    write_file("/tmp/dir-test/a", b"1").unwrap();
+    write_file("/tmp/dir-test/sub/b", b"22").unwrap();
+
+    let children: Vec<(String, bool)> = read_dir(dir).into_iter().map(|f| (f.name, f.is_dir)).collect();
+    assert_eq!(children, [("a".into(), false), ("empty".into(), true), ("sub".into(), true)]);
+    assert_eq!(read_dir("/tmp/dir-test/sub")[0].size, 2);
+
+    // A directory only implied by the file in it is one all the same
+    assert!(stat("/tmp/dir-test/sub").unwrap().is_dir);
+    assert!(!stat("/tmp/dir-test/a").unwrap().is_dir);
+
+    // Nothing can be created where there is no directory to hold it
+    assert_eq!(canonicalize_new("/tmp/dir-test/sub/c"), Ok("/tmp/dir-test/sub/c".into()));
+    assert!(canonicalize_new("/tmp/dir-test/nowhere/c").is_err());
+    assert!(canonicalize_new("/tmp/dir-test/a/c").is_err());
+
+    for path in ["/tmp/dir-test/a", "/tmp/dir-test/sub/b", "/tmp/dir-test/empty/.dir", "/tmp/dir-test/.dir"] {
+        remove(path).unwrap();
+    }
+    assert!(!dir_exists(dir));
+}
@@ -0,0 +1,85 @@
+//! Mount table — which filesystem serves which part of the namespace
+//!
+//! `/proc` and `/dev` are synthetic and always present; `/` appears once
+//! the MemFS is mounted. Other filesystems register themselves here when
+//! they are mounted so `mount` and `/proc/mounts` can show them.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use spin::RwLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsKind {
+    MemFs,
+    Disk,
+    ProcFs,
+    DevFs,
+}
+
+impl fmt::Display for FsKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FsKind::MemFs  => "memfs",
+            FsKind::Disk   => "disk",
+            FsKind::ProcFs => "procfs",
+            FsKind::DevFs  => "devfs",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mount {
+    pub path:      String,
+    pub kind:      FsKind,
+    /// Backing device, or the filesystem name for synthetic ones
+    pub source:    String,
+    pub read_only: bool,
+}
+
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+fn seed(mounts: &mut Vec<Mount>) {
+    if mounts.is_empty() {
+        for (path, kind) in [("/proc", FsKind::ProcFs), ("/dev", FsKind::DevFs)] {
+            mounts.push(Mount { path: path.into(), kind, source: kind.to_string(), read_only: false });
+        }
+    }
+}
+
+/// Record a filesystem mounted at `path`. Fails if something is already there.
+pub(crate) fn mount(path: &str, kind: FsKind, source: &str, read_only: bool) -> Result<(), ()> {
+    let mut mounts = MOUNTS.write();
+    seed(&mut mounts);
+    if mounts.iter().any(|m| m.path == path) {
+        return Err(());
+    }
+    mounts.push(Mount { path: path.into(), kind, source: source.into(), read_only });
+    Ok(())
+}
+
+/// Forget the filesystem mounted at `path`, None if there is none
+pub fn umount(path: &str) -> Option<Mount> {
+    let mut mounts = MOUNTS.write();
+    let i = mounts.iter().position(|m| m.path == path)?;
+    Some(mounts.remove(i))
+}
+
+/// Every mount, shortest path first
+pub fn list() -> Vec<Mount> {
+    let mut mounts = MOUNTS.write();
+    seed(&mut mounts);
+    let mut out = mounts.clone();
+    out.sort_by(|a, b| a.path.len().cmp(&b.path.len()).then(a.path.cmp(&b.path)));
+    out
+}
+
+/// One mount per line: `source on path type kind (rw|ro)`
+pub fn table() -> String {
+    let mut out = String::new();
+    for m in list() {
+        let mode = if m.read_only { "ro" } else { "rw" };
+        writeln!(out, "{} on {} type {} ({})", m.source, m.path, m.kind, mode).ok();
+    }
+    out
+}
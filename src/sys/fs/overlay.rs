@@ -0,0 +1,46 @@
+//! Copy-on-write overlay over the MemFS
+//!
+//! A process spawned with `SPAWN_OVERLAY` gets an overlay of its own:
+//! the files it writes or removes are recorded here instead of in the
+//! shared VFS, and every path it has not touched falls through to the
+//! VFS. Neither the parent nor any other process sees the changes, and
+//! they are gone once the last process using the overlay exits. Children
+//! share their parent's overlay unless they ask for one of their own.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::RwLock;
+
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    /// Contents written through the overlay, None where a file was removed
+    entries: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+/// An overlay as held by the processes using it
+pub type SharedOverlay = Arc<RwLock<Overlay>>;
+
+impl Overlay {
+    pub fn new() -> Self { Self::default() }
+
+    /// Some if the overlay has an answer for `path`: its contents, or
+    /// None if it was removed. None leaves the path to the VFS.
+    pub fn get(&self, path: &str) -> Option<Option<&[u8]>> {
+        self.entries.get(path).map(|data| data.as_deref())
+    }
+
+    pub fn write(&mut self, path: String, data: Vec<u8>) {
+        self.entries.insert(path, Some(data));
+    }
+
+    /// Hide `path`, whether it was written here or lives in the VFS
+    pub fn remove(&mut self, path: &str) {
+        self.entries.insert(path.into(), None);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, Option<&Vec<u8>>)> {
+        self.entries.iter().map(|(path, data)| (path, data.as_ref()))
+    }
+}
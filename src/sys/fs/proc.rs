@@ -0,0 +1,92 @@
+//! procfs — synthetic files describing running processes
+//!
+//! Contents are generated on open from the process table:
+//!   /proc/self/maps, /proc/<pid>/maps — address space layout
+//!   /proc/mounts                      — the mount table
+//!   /proc/iomem                       — the physical memory map
+
+use crate::sys::process::{current_pid, MAX_PROCS, PROC_TABLE};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Generate the contents of a /proc path, or None if it doesn't exist
+pub fn read(path: &str) -> Option<Vec<u8>> {
+    let rest = path.strip_prefix("/proc/")?;
+    if rest == "mounts" {
+        return Some(super::mount::table().into_bytes());
+    }
+    if rest == "iomem" {
+        return Some(crate::sys::mem::iomem().into_bytes());
+    }
+    let (pid, file) = rest.split_once('/')?;
+    let pid = match pid {
+        "self" => current_pid(),
+        n      => n.parse().ok()?,
+    };
+    match file {
+        "maps" => maps(pid).map(String::into_bytes),
+        _      => None,
+    }
+}
+
+/// Memory map of `pid`, one region per line:
+/// `start-end perms name` (end exclusive, like Linux)
+pub fn maps(pid: usize) -> Option<String> {
+    if pid >= MAX_PROCS {
+        return None;
+    }
+    let table = PROC_TABLE.read();
+    let proc  = &table[pid];
+    if proc.id != pid || proc.code_base == 0 {
+        return None; // free slot, or a kernel-resident task without a user image
+    }
+
+    let layout = proc.layout;
+
+    // All user pages are mapped read/write/execute, except files mapped
+    // without PROT_WRITE
+    let mut out = String::new();
+    let mut line = |start: u64, end: u64, perms: &str, name: &str| {
+        if end > start {
+            writeln!(out, "{:016x}-{:016x} {} {}", start, end, perms, name).ok();
+        }
+    };
+    line(layout.code.start, layout.code.end, "rwxp", "[code]");
+    line(layout.bss.start, layout.bss.end, "rwxp", "[bss]");
+    line(layout.args.start, layout.args.end, "rwxp", "[args]");
+    line(layout.heap.start, layout.heap.end, "rwxp", "[heap]");
+    let mut mappings: Vec<_> = proc.mappings.iter().collect();
+    mappings.sort_by_key(|m| m.region.start);
+    for m in mappings {
+        line(m.region.start, m.region.end, if m.writable { "rwxp" } else { "r-xp" }, &m.path);
+    }
+    line(layout.stack.start, layout.stack.end, "rwxp", "[stack]");
+    Some(out)
+}
+
+#[test_case]
+fn maps_lists_code_and_stack() {
+    use crate::sys::process::{MemoryLayout, Process, MAX_PROC_MEM};
+    use alloc::format;
+
+    let pid = MAX_PROCS - 1;
+    {
+        let mut table = PROC_TABLE.write();
+        let p = &mut table[pid];
+        p.id         = pid;
+        p.code_base  = 0x0080_0000;
+        p.layout     = MemoryLayout::new(0x0080_0000, 0x2000, 0x2800, 64).unwrap();
+        p.stack_base = p.layout.stack.end;
+    }
+
+    let maps = maps(pid).unwrap();
+    assert!(maps.contains("0000000000800000-0000000000802000 rwxp [code]"));
+    assert!(maps.contains("0000000000802000-0000000000803000 rwxp [bss]"));
+    assert!(maps.contains("0000000000803000-0000000000804000 rwxp [args]"));
+    assert!(maps.contains(&format!("-{:016x} rwxp [stack]", 0x0080_0000 + MAX_PROC_MEM as u64)));
+    assert_eq!(read(&format!("/proc/{}/maps", pid)).unwrap(), maps.into_bytes());
+
+    PROC_TABLE.write()[pid] = alloc::boxed::Box::new(Process::new());
+}
@@ -0,0 +1,205 @@
+//! Readahead — prefetch for sequential reads from a block device
+//!
+//! A `Readahead` belongs to one open handle. When a read asks for the
+//! sector right after the previous one, it fetches that sector and the
+//! next `depth` in a single device request and serves the following
+//! reads from memory. Writes through the same handle drop any cached
+//! copy of the sector. Nothing on disk is mounted as a filesystem yet;
+//! this is the cache its read path will sit on.
+
+use super::IoError;
+use crate::sys::virtio;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub const SECTOR: usize = virtio::SECTOR_SIZE;
+
+/// Sectors fetched past a sequential read, `readahead=` on the command line
+pub const DEFAULT_DEPTH: usize = 3;
+
+static DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_DEPTH);
+
+pub fn depth() -> usize {
+    DEPTH.load(Ordering::Relaxed)
+}
+
+/// Change the depth new handles get; 0 turns readahead off
+pub fn set_depth(depth: usize) {
+    DEPTH.store(depth.min(virtio::MAX_SECTORS - 1), Ordering::Relaxed);
+}
+
+pub trait BlockDevice {
+    /// Read consecutive sectors from `sector` into `buf`, a whole number
+    /// of sectors no longer than `max_sectors`
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), IoError>;
+    fn write_sector(&mut self, sector: u64, buf: &[u8]) -> Result<(), IoError>;
+    /// Most sectors one `read_sectors` call accepts
+    fn max_sectors(&self) -> usize;
+    /// Size in sectors
+    fn capacity(&self) -> u64;
+}
+
+/// The VirtIO block device, as seen through the buffer cache
+pub struct Virtio;
+
+impl BlockDevice for Virtio {
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), IoError> {
+        crate::sys::bcache::read_sectors(sector, buf)
+    }
+    fn write_sector(&mut self, sector: u64, buf: &[u8]) -> Result<(), IoError> {
+        crate::sys::bcache::write(sector, buf)
+    }
+    fn max_sectors(&self) -> usize { virtio::MAX_SECTORS }
+    fn capacity(&self) -> u64 { virtio::capacity() }
+}
+
+/// The bare VirtIO block device, for the buffer cache underneath
+pub struct RawVirtio;
+
+impl BlockDevice for RawVirtio {
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), IoError> {
+        virtio::read_sectors(sector, buf).map_err(|_| IoError::Device)
+    }
+    fn write_sector(&mut self, sector: u64, buf: &[u8]) -> Result<(), IoError> {
+        let mut copy = [0u8; SECTOR];
+        let n = buf.len().min(SECTOR);
+        copy[..n].copy_from_slice(&buf[..n]);
+        virtio::write_sector(sector, &mut copy).map_err(|_| IoError::Device)
+    }
+    fn max_sectors(&self) -> usize { virtio::MAX_SECTORS }
+    fn capacity(&self) -> u64 { virtio::capacity() }
+}
+
+#[derive(Clone, Debug)]
+pub struct Readahead {
+    depth: usize,
+    /// Sectors `start..start + cache.len() / SECTOR`
+    cache: Vec<u8>,
+    start: u64,
+    /// Sector a sequential reader would ask for next
+    next:  Option<u64>,
+}
+
+impl Readahead {
+    pub fn new() -> Self {
+        Self::with_depth(depth())
+    }
+
+    pub fn with_depth(depth: usize) -> Self {
+        Self { depth, cache: Vec::new(), start: 0, next: None }
+    }
+
+    fn cached(&self, sector: u64) -> Option<&[u8]> {
+        let i = sector.checked_sub(self.start)? as usize;
+        self.cache.get(i * SECTOR..(i + 1) * SECTOR)
+    }
+
+    /// Read one sector into the first SECTOR bytes of `buf`
+    pub fn read(&mut self, dev: &mut impl BlockDevice, sector: u64, buf: &mut [u8]) -> Result<(), IoError> {
+        let buf = buf.get_mut(..SECTOR).ok_or(IoError::OutOfRange)?;
+        if let Some(data) = self.cached(sector) {
+            buf.copy_from_slice(data);
+            self.next = Some(sector + 1);
+            return Ok(());
+        }
+
+        let count = if self.next == Some(sector) {
+            let left = dev.capacity().saturating_sub(sector) as usize;
+            (1 + self.depth).min(dev.max_sectors()).min(left).max(1)
+        } else {
+            1
+        };
+        self.next = Some(sector + 1);
+        if count == 1 {
+            return dev.read_sectors(sector, buf);
+        }
+
+        self.cache.clear();
+        self.cache.resize(count * SECTOR, 0);
+        if let Err(e) = dev.read_sectors(sector, &mut self.cache) {
+            self.cache.clear();
+            return Err(e);
+        }
+        self.start = sector;
+        buf.copy_from_slice(&self.cache[..SECTOR]);
+        Ok(())
+    }
+
+    /// Write one sector, dropping the prefetched data if it covers it
+    pub fn write(&mut self, dev: &mut impl BlockDevice, sector: u64, buf: &[u8]) -> Result<(), IoError> {
+        self.invalidate(sector);
+        dev.write_sector(sector, buf)
+    }
+
+    pub fn invalidate(&mut self, sector: u64) {
+        if self.cached(sector).is_some() {
+            self.cache.clear();
+        }
+    }
+}
+
+impl Default for Readahead {
+    fn default() -> Self { Self::new() }
+}
+
+/// In-memory disk counting its requests, for tests
+#[cfg(test)]
+pub struct RamDisk {
+    pub data:   Vec<u8>,
+    pub reads:  usize,
+    pub writes: usize,
+}
+
+#[cfg(test)]
+impl RamDisk {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data, reads: 0, writes: 0 }
+    }
+}
+
+#[cfg(test)]
+impl BlockDevice for RamDisk {
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), IoError> {
+        self.reads += 1;
+        let start = sector as usize * SECTOR;
+        buf.copy_from_slice(self.data.get(start..start + buf.len()).ok_or(IoError::OutOfRange)?);
+        Ok(())
+    }
+    fn write_sector(&mut self, sector: u64, buf: &[u8]) -> Result<(), IoError> {
+        self.writes += 1;
+        let start = sector as usize * SECTOR;
+        self.data.get_mut(start..start + SECTOR).ok_or(IoError::OutOfRange)?.copy_from_slice(&buf[..SECTOR]);
+        Ok(())
+    }
+    fn max_sectors(&self) -> usize { 8 }
+    fn capacity(&self) -> u64 { (self.data.len() / SECTOR) as u64 }
+}
+
+#[test_case]
+fn sequential_reads_hit_the_device_less() {
+    let data: Vec<u8> = (0..64 * SECTOR).map(|i| (i / SECTOR) as u8 ^ i as u8).collect();
+    let mut disk = RamDisk::new(data.clone());
+    let mut buf = [0u8; SECTOR];
+
+    let mut count_reads = |depth| {
+        disk.reads = 0;
+        let mut ra = Readahead::with_depth(depth);
+        for sector in 0..64u64 {
+            ra.read(&mut disk, sector, &mut buf).unwrap();
+            let start = sector as usize * SECTOR;
+            assert_eq!(&buf[..], &data[start..start + SECTOR]);
+        }
+        disk.reads
+    };
+    assert_eq!(count_reads(0), 64);
+    assert!(count_reads(3) <= 17);
+
+    // A write through the handle is seen by the next read
+    let mut ra = Readahead::with_depth(3);
+    ra.read(&mut disk, 0, &mut buf).unwrap();
+    ra.read(&mut disk, 1, &mut buf).unwrap();
+    ra.write(&mut disk, 2, &[0xAA; SECTOR]).unwrap();
+    ra.read(&mut disk, 2, &mut buf).unwrap();
+    assert_eq!(buf, [0xAA; SECTOR]);
+}
@@ -0,0 +1,195 @@
+//! USTAR archives — bundle files to populate the VFS in one go
+//!
+//! Only what an initial filesystem needs: regular files and directories.
+//! Each entry is a 512-byte header followed by its data padded to 512
+//! bytes; two zero blocks end the archive. Links, devices and the GNU
+//! long-name extensions are skipped when reading and never written.
+
+use crate::sys::path;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const BLOCK: usize = 512;
+
+const NAME:     core::ops::Range<usize> = 0..100;
+const MODE:     core::ops::Range<usize> = 100..108;
+const SIZE:     core::ops::Range<usize> = 124..136;
+const MTIME:    core::ops::Range<usize> = 136..148;
+const CHECKSUM: core::ops::Range<usize> = 148..156;
+const TYPEFLAG: usize = 156;
+const MAGIC:    core::ops::Range<usize> = 257..265;
+const PREFIX:   core::ops::Range<usize> = 345..500;
+
+const USTAR_MAGIC: &[u8; 8] = b"ustar\x0000";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// Anything else (links, devices, extension headers)
+    Other,
+}
+
+#[derive(Debug)]
+pub struct Entry<'a> {
+    /// Relative path, without leading `./` or `/`
+    pub path: String,
+    pub kind: EntryKind,
+    pub data: &'a [u8],
+}
+
+fn octal(field: &[u8]) -> Result<usize, ()> {
+    let mut n: usize = 0;
+    for &b in field.iter().skip_while(|&&b| b == b' ') {
+        match b {
+            b'0'..=b'7' => n = n.checked_mul(8).ok_or(())? + (b - b'0') as usize,
+            0 | b' '    => break,
+            _           => return Err(()),
+        }
+    }
+    Ok(n)
+}
+
+fn cstr(field: &[u8]) -> Result<&str, ()> {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..end]).map_err(|_| ())
+}
+
+/// Sum of the header bytes, counting the checksum field as spaces
+fn checksum(header: &[u8]) -> usize {
+    header.iter().enumerate()
+        .map(|(i, &b)| if CHECKSUM.contains(&i) { b' ' as usize } else { b as usize })
+        .sum()
+}
+
+/// Relative path from the name fields, rejecting anything that would
+/// escape the destination directory
+fn entry_path(header: &[u8]) -> Result<String, ()> {
+    let (prefix, name) = (cstr(&header[PREFIX])?, cstr(&header[NAME])?);
+    let mut path = String::new();
+    for part in prefix.split('/').chain(name.split('/')) {
+        match part {
+            "" | "." => {}
+            ".."     => return Err(()),
+            _ => {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(part);
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Parse every entry of `archive`. Fails on a corrupt header or an entry
+/// that runs past the end.
+pub(crate) fn entries(archive: &[u8]) -> Result<Vec<Entry<'_>>, ()> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos + BLOCK <= archive.len() {
+        let header = &archive[pos..pos + BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break; // end of archive
+        }
+        if octal(&header[CHECKSUM])? != checksum(header) {
+            return Err(());
+        }
+        let size  = octal(&header[SIZE])?;
+        let start = pos + BLOCK;
+        let data  = archive.get(start..start.checked_add(size).ok_or(())?).ok_or(())?;
+        let kind  = match header[TYPEFLAG] {
+            b'0' | 0 => EntryKind::File,
+            b'5'     => EntryKind::Dir,
+            _        => EntryKind::Other,
+        };
+        out.push(Entry { path: entry_path(header)?, kind, data });
+        pos = start + size.div_ceil(BLOCK) * BLOCK;
+    }
+    Ok(out)
+}
+
+/// Build an archive of regular files. None if a path does not fit the
+/// 100-byte name field.
+pub fn pack(files: &[(&str, &[u8])]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    for &(path, data) in files {
+        let name = path.trim_start_matches('/').as_bytes();
+        if name.is_empty() || name.len() > NAME.len() {
+            return None;
+        }
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name);
+        header[MODE][..7].copy_from_slice(b"0000644");
+        header[SIZE][..11].copy_from_slice(alloc::format!("{:011o}", data.len()).as_bytes());
+        header[MTIME][..11].copy_from_slice(b"00000000000");
+        header[TYPEFLAG] = b'0';
+        header[MAGIC].copy_from_slice(USTAR_MAGIC);
+        let sum = alloc::format!("{:06o}\0 ", checksum(&header));
+        header[CHECKSUM].copy_from_slice(sum.as_bytes());
+
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(BLOCK) * BLOCK, 0);
+    }
+    out.resize(out.len() + 2 * BLOCK, 0);
+    Some(out)
+}
+
+/// Write every file and directory of `archive` under `dest`.
+/// Returns the number of files written.
+pub(crate) fn unpack(archive: &[u8], dest: &str) -> Result<usize, ()> {
+    let mut files = 0;
+    for entry in entries(archive)? {
+        if entry.path.is_empty() {
+            continue;
+        }
+        // Absolute names in the archive still land under `dest`
+        let path = path::join(dest, entry.path.trim_start_matches('/'));
+        match entry.kind {
+            EntryKind::Dir => make_dirs(&path),
+            EntryKind::File => {
+                make_dirs(path::dirname(&path));
+                super::write_file(&path, entry.data)?;
+                files += 1;
+            }
+            EntryKind::Other => kdebug!("tar: skipping special entry '{}'", entry.path),
+        }
+    }
+    Ok(files)
+}
+
+/// Register `path` and each of its parents as directories
+fn make_dirs(path: &str) {
+    let mut end = 0;
+    while end < path.len() {
+        end = path[end + 1..].find('/').map_or(path.len(), |i| end + 1 + i);
+        let dir = &path[..end];
+        if !dir.is_empty() && !super::dir_exists(dir) {
+            super::mkdir(dir);
+        }
+    }
+}
+
+#[test_case]
+fn pack_then_unpack_restores_files() {
+    let bin: Vec<u8> = (0..=255).collect();
+    let archive = pack(&[("bin/blob", &bin), ("readme.txt", b"hello\n")]).unwrap();
+    assert_eq!(archive.len() % BLOCK, 0);
+
+    assert_eq!(unpack(&archive, "/tmp/tar-test"), Ok(2));
+    assert_eq!(super::open_file("/tmp/tar-test/bin/blob").unwrap().data(), &bin[..]);
+    assert_eq!(super::open_file("/tmp/tar-test/readme.txt").unwrap().data(), b"hello\n");
+    assert!(super::dir_exists("/tmp/tar-test/bin"));
+
+    // A flipped byte in a header is caught by the checksum
+    let mut bad = archive.clone();
+    bad[0] ^= 1;
+    assert!(entries(&bad).is_err());
+
+    for path in ["/tmp/tar-test/bin/blob", "/tmp/tar-test/readme.txt",
+                 "/tmp/tar-test/bin/.dir", "/tmp/tar-test/.dir"] {
+        super::remove(path).ok();
+    }
+}
@@ -0,0 +1,97 @@
+//! Hostname — the name the system goes by
+//!
+//! Held in the kernel and shown in the shell prompt. Setting it also
+//! writes `/etc/hostname`, which is read back at boot.
+
+use crate::sys;
+use crate::sys::audit::Reason;
+use crate::sys::process::CAP_MOUNT;
+use crate::sys::syscall::error::{EINVAL, EIO, EPERM};
+
+use alloc::string::String;
+use spin::RwLock;
+
+/// Name used until one is set
+pub const DEFAULT: &str = "chilena";
+
+/// Longest name accepted, as for a DNS label
+pub const MAX_LEN: usize = 63;
+
+const HOSTNAME_FILE: &str = "/etc/hostname";
+
+static HOSTNAME: RwLock<String> = RwLock::new(String::new());
+
+pub fn get() -> String {
+    let name = HOSTNAME.read();
+    if name.is_empty() { DEFAULT.into() } else { name.clone() }
+}
+
+/// Letters, digits and `-`, not at either end
+fn is_valid(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_LEN
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// Change the hostname and record it in `/etc/hostname`. EPERM without
+/// CAP_MOUNT, EINVAL for a bad name, EIO if the file cannot be written.
+pub fn set(name: &str) -> Result<(), isize> {
+    if !sys::process::has_cap(CAP_MOUNT) {
+        sys::audit::deny_op("set hostname", Reason::Capability(CAP_MOUNT));
+        return Err(EPERM);
+    }
+    if !is_valid(name) {
+        return Err(EINVAL);
+    }
+    *HOSTNAME.write() = name.into();
+    if !sys::fs::dir_exists("/etc") {
+        sys::fs::mkdir("/etc");
+    }
+    let mut line = String::from(name);
+    line.push('\n');
+    sys::fs::write_file(HOSTNAME_FILE, line.as_bytes()).map_err(|()| EIO)
+}
+
+/// Take the hostname from `/etc/hostname`, if there is a valid one
+pub fn load() {
+    let Some(file) = sys::fs::open_file(HOSTNAME_FILE) else { return };
+    let text = String::from_utf8_lossy(file.data());
+    let name = text.trim();
+    if is_valid(name) {
+        *HOSTNAME.write() = name.into();
+    } else {
+        kwarn!("hostname: ignoring bad name in {}", HOSTNAME_FILE);
+    }
+}
+
+#[test_case]
+fn hostname_is_set_saved_and_checked() {
+    assert!(!is_valid("-box") && !is_valid("my box") && !is_valid(""));
+
+    set("testbox").unwrap();
+    assert_eq!(get(), "testbox");
+    assert_eq!(sys::fs::open_file(HOSTNAME_FILE).unwrap().data(), b"testbox\n");
+    assert_eq!(set("not valid"), Err(EINVAL));
+    assert_eq!(get(), "testbox");
+
+    // Without CAP_MOUNT the name stays
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        use crate::sys::process::{FakeProcess, CAP_ALL, PROC_TABLE};
+
+        let _process = FakeProcess::enter(2);
+        PROC_TABLE.write()[2].data.caps = CAP_ALL & !CAP_MOUNT;
+        assert_eq!(set("otherbox"), Err(EPERM));
+    });
+    assert_eq!(get(), "testbox");
+
+    // Read back at boot
+    HOSTNAME.write().clear();
+    assert_eq!(get(), DEFAULT);
+    load();
+    assert_eq!(get(), "testbox");
+
+    HOSTNAME.write().clear();
+    sys::fs::remove(HOSTNAME_FILE).ok();
+}
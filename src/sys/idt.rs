@@ -5,6 +5,7 @@
 //!   - Hardware IRQs 0-15
 //!   - Syscalls via int 0x80 (ring 3 accessible)
 
+use crate::api::process::ExitCode;
 use crate::sys;
 use crate::sys::mem::phys_mem_offset;
 use crate::sys::process::CpuRegisters;
@@ -99,6 +100,20 @@ macro_rules! irq_fn {
     };
 }
 
+// IRQ 7 and 15 may be spurious: check the ISR before running the driver
+// handler and let the PIC module decide which EOIs to send
+macro_rules! irq_fn_spurious {
+    ($name:ident, $n:expr) => {
+        extern "x86-interrupt" fn $name(_: InterruptStackFrame) {
+            let eoi = sys::pic::eoi_for($n, sys::pic::read_isr());
+            if eoi == sys::pic::Eoi::All {
+                IRQ_HANDLERS.lock()[$n]();
+            }
+            sys::pic::acknowledge($n, eoi);
+        }
+    };
+}
+
 // IRQ 0 (timer) — naked function untuk proper context save/restore
 //
 // URUTAN PUSH harus cocok dengan layout struct CpuRegisters:
@@ -170,14 +185,32 @@ extern "sysv64" fn timer_handler(
             .notify_end_of_interrupt(sys::pic::irq_vector(0));
     }
 
+    // Charge the tick to whoever it interrupted, before switching away
+    sys::process::account_tick(frame.code_segment & 3 == 3);
+
     // Sekarang baru schedule — bisa modifikasi frame+regs untuk context switch
     sys::sched::schedule(frame, regs);
+
+    // Userspace holds no kernel locks, so the deferred IRQ work can run
+    // here; a process that never reads the console still gets its Ctrl+C
+    if frame.code_segment & 3 == 3 {
+        sys::workq::run();
+    }
+
+    let forked = sys::process::is_forked();
+    if sys::signal::deliver(frame, regs) {
+        resume_parent(frame, regs, forked, ExitCode::Failure as usize);
+    }
+    if frame.code_segment & 3 == 3 {
+        sys::process::wait_while_stopped();
+    }
 }
 
 irq_fn!(irq1,  1);  irq_fn!(irq2,  2);  irq_fn!(irq3,  3);
-irq_fn!(irq4,  4);  irq_fn!(irq5,  5);  irq_fn!(irq6,  6);  irq_fn!(irq7,  7);
+irq_fn!(irq4,  4);  irq_fn!(irq5,  5);  irq_fn!(irq6,  6);
 irq_fn!(irq8,  8);  irq_fn!(irq9,  9);  irq_fn!(irq10, 10); irq_fn!(irq11, 11);
-irq_fn!(irq12, 12); irq_fn!(irq13, 13); irq_fn!(irq14, 14); irq_fn!(irq15, 15);
+irq_fn!(irq12, 12); irq_fn!(irq13, 13); irq_fn!(irq14, 14);
+irq_fn_spurious!(irq7, 7); irq_fn_spurious!(irq15, 15);
 
 // ---------------------------------------------------------------------------
 // Exception handlers
@@ -205,11 +238,33 @@ extern "x86-interrupt" fn on_segment_not_present(frame: InterruptStackFrame, cod
 }
 
 extern "x86-interrupt" fn on_page_fault(
-    _frame: InterruptStackFrame,
+    frame: InterruptStackFrame,
     error: PageFaultErrorCode,
 ) {
     let fault_addr = Cr2::read().as_u64();
 
+    // A write to a page shared with a forked process gets its own copy
+    let cow_fault = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    if error.contains(cow_fault) && sys::mem::cow::handle_fault(fault_addr) {
+        return;
+    }
+
+    // Stack overflow or a stray write past the heap: kill the process
+    // instead of mapping a page where its layout has none. Nothing gets
+    // mapped, so a fault from userspace repeats until the next timer
+    // tick, which is taken before the instruction can run again and
+    // delivers the SIGKILL.
+    if let Some(what) = sys::process::bad_access(fault_addr) {
+        let pid = sys::process::current_pid();
+        kerror!("{} in pid {} at {:#X}", what, pid, fault_addr);
+        sys::signal::send(pid, sys::signal::SIGKILL).ok();
+        if frame.code_segment & 3 == 3 {
+            return;
+        }
+        // Kernel code writing to the stack on the process's behalf gets
+        // to finish; the kill happens on the way back to userspace
+    }
+
     // FIX BUG #8: Gunakan active_page_table() yang membaca dari CR3 langsung,
     // BUKAN sys::process::page_table() yang membaca PROC_TABLE[CURRENT_PID].pt_frame.
     // Ada race window di scheduler antara Cr3::write() dan CURRENT_PID.store(),
@@ -298,14 +353,25 @@ extern "sysv64" fn syscall_handler(
     let a2 = regs.rsi;
     let a3 = regs.rdx;
     let a4 = regs.r8;
+    let a5 = regs.r9;
 
-    // Save context before spawning a new process
-    if number == sys::syscall::number::SPAWN {
+    // Save context before spawning a new process, or for the child of
+    // FORK to start from
+    if number == sys::syscall::number::SPAWN || number == sys::syscall::number::FORK {
         sys::process::save_stack_frame(**frame);
         sys::process::save_registers(*regs);
     }
 
-    let result = sys::syscall::dispatch(number, a1, a2, a3, a4);
+    // SIGRETURN puts back the whole interrupted context, rax included
+    if number == sys::syscall::number::SIGRETURN {
+        if !sys::signal::sigreturn(frame, regs) {
+            regs.rax = usize::MAX;
+        }
+        return;
+    }
+
+    let forked = number == sys::syscall::number::EXIT && sys::process::is_forked();
+    let result = sys::syscall::dispatch(number, a1, a2, a3, a4, a5);
 
     // Restore context after process exit.
     // FIX BUG #9: Setelah dispatch(EXIT) → terminate() sudah jalan,
@@ -314,17 +380,42 @@ extern "sysv64" fn syscall_handler(
     // Kalau tidak ada (parent adalah kernel/PID 0 atau belum pernah spawn) →
     // biarkan frame apa adanya, parent akan lanjut dari titik setelah syscall ini.
     if number == sys::syscall::number::EXIT {
-        // saved_stack_frame() sekarang membaca dari parent (CURRENT_PID sudah berubah)
-        if let Some(sf) = sys::process::saved_stack_frame() {
-            unsafe { frame.as_mut().write(sf); }
-            *regs = sys::process::saved_registers();
-        }
-        // Jika None: parent tidak punya saved frame → tidak perlu restore,
-        // iretq akan kembali ke titik parent memanggil syscall SPAWN sebelumnya.
-        // regs.rax akan di-set ke result di bawah (exit code).
+        resume_parent(frame, regs, forked, result);
+    } else {
+        regs.rax = result;
     }
 
-    regs.rax = result;
+    // The caller's context is saved with the result already in rax
+    if number == sys::syscall::number::YIELD {
+        sys::sched::yield_syscall(frame, regs);
+    } else if number == sys::syscall::number::SLEEP {
+        sys::sched::sleep_off_cpu(frame, regs);
+    }
+
+    // A signal that kills the process also returns control to the parent
+    let forked = sys::process::is_forked();
+    if sys::signal::deliver(frame, regs) {
+        resume_parent(frame, regs, forked, ExitCode::Failure as usize);
+    }
+    if frame.code_segment & 3 == 3 {
+        sys::process::wait_while_stopped();
+    }
+}
+
+/// After the current process terminated, continue the parent from the
+/// frame it saved at SPAWN, with `code` in rax. The parent of a `forked`
+/// child was preempted mid-flight instead and keeps all its registers.
+fn resume_parent(frame: &mut InterruptStackFrame, regs: &mut CpuRegisters, forked: bool, code: usize) {
+    // saved_stack_frame() sekarang membaca dari parent (CURRENT_PID sudah berubah)
+    if let Some(sf) = sys::process::saved_stack_frame() {
+        unsafe { frame.as_mut().write(sf); }
+        *regs = sys::process::saved_registers();
+    }
+    // Jika None: parent tidak punya saved frame → tidak perlu restore,
+    // iretq akan kembali ke titik parent memanggil syscall SPAWN sebelumnya.
+    if !forked {
+        regs.rax = code;
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -2,17 +2,53 @@
 //!
 //! Implements synchronous message passing:
 //!   - Sender blocks until receiver reads the message
-//!   - Fixed-size 64-byte payload (enough for pointer + length for larger data)
+//!   - Fixed-size inline payload, 64 bytes unless set at build time with
+//!     `CHILENA_MSG_PAYLOAD` (enough for pointer + length for larger data)
 //!   - Single mailbox slot per process (simple, no heap allocation)
+//!   - CALL/REPLY pair requests with replies through a per-call cookie
+//!   - Servers can register a name; SENDNAME finds them by it, waiting
+//!     for one that has not registered yet
 
-use crate::sys::process::{current_pid, PROC_TABLE};
+use crate::sys::process::{current_pid, Process, MAX_HANDLES, PROC_TABLE};
+
+use alloc::boxed::Box;
+
+use core::sync::atomic::{AtomicU32, Ordering};
 
 // ---------------------------------------------------------------------------
 // Message structure
 // ---------------------------------------------------------------------------
 
 /// Message payload size in bytes
-pub const MSG_PAYLOAD: usize = 64;
+pub const MSG_PAYLOAD: usize = payload_size(option_env!("CHILENA_MSG_PAYLOAD"));
+
+/// Largest payload a build may ask for; every process carries two
+/// messages inline, so this bounds the size of the process table
+pub const MAX_MSG_PAYLOAD: usize = 1024;
+
+const DEFAULT_MSG_PAYLOAD: usize = 64;
+
+/// Parse `CHILENA_MSG_PAYLOAD`, failing the build if it is out of range
+const fn payload_size(env: Option<&str>) -> usize {
+    let bytes = match env {
+        Some(s) => s.as_bytes(),
+        None    => return DEFAULT_MSG_PAYLOAD,
+    };
+    let mut n = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "CHILENA_MSG_PAYLOAD must be a number");
+        n = n * 10 + (bytes[i] - b'0') as usize;
+        assert!(n <= MAX_MSG_PAYLOAD, "CHILENA_MSG_PAYLOAD is larger than MAX_MSG_PAYLOAD");
+        i += 1;
+    }
+    assert!(n > 0, "CHILENA_MSG_PAYLOAD must not be zero");
+    n
+}
+
+/// Pseudo-handle for the calling process's mailbox, accepted by POLL.
+/// Sits just past the real handle range so it can never alias a file.
+pub const MAILBOX: usize = MAX_HANDLES;
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -21,6 +57,10 @@ pub struct Message {
     pub sender:  usize,
     /// Message type — freely defined by userspace
     pub kind:    u32,
+    /// Correlation id set by CALL and echoed by REPLY (0 = plain message)
+    pub cookie:  u32,
+    /// Number of valid bytes at the start of `data`
+    pub payload_len: u32,
     /// Fixed-size payload, can hold small data or a pointer + length
     pub data:    [u8; MSG_PAYLOAD],
 }
@@ -30,9 +70,29 @@ impl Message {
         Self {
             sender: 0,
             kind:   0,
+            cookie: 0,
+            payload_len: 0,
             data:   [0u8; MSG_PAYLOAD],
         }
     }
+
+    /// Message of type `kind` carrying `data`. Fails if `data` does not
+    /// fit in MSG_PAYLOAD bytes.
+    pub(crate) fn new(kind: u32, data: &[u8]) -> Result<Self, ()> {
+        if data.len() > MSG_PAYLOAD {
+            return Err(());
+        }
+        let mut msg = Self::empty();
+        msg.kind = kind;
+        msg.payload_len = data.len() as u32;
+        msg.data[..data.len()].copy_from_slice(data);
+        Ok(msg)
+    }
+
+    /// The valid bytes of the payload
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..(self.payload_len as usize).min(MSG_PAYLOAD)]
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -47,6 +107,54 @@ pub enum BlockState {
     WaitingSend { target: usize },
     /// Waiting for incoming message (during RECV)
     WaitingRecv,
+    /// Waiting for `server` to reply to a CALL tagged with `cookie`
+    WaitingReply { server: usize, cookie: u32 },
+    /// Waiting in WAITID for a child to change state
+    WaitingChild,
+    /// Sleeping until the uptime reaches `wake_ns`
+    Sleeping { wake_ns: u64 },
+    /// Waiting in SENDNAME for a server to register, until `wake_ns`
+    WaitingService { wake_ns: u64 },
+}
+
+impl BlockState {
+    /// Name of the state as `ps` shows it
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockState::Running              => "Running",
+            BlockState::WaitingSend { .. }   => "WaitingSend",
+            BlockState::WaitingRecv          => "WaitingRecv",
+            BlockState::WaitingReply { .. }  => "WaitingReply",
+            BlockState::WaitingChild         => "WaitingChild",
+            BlockState::Sleeping { .. }      => "Sleeping",
+            BlockState::WaitingService { .. } => "WaitingService",
+        }
+    }
+
+    /// Uptime at which the timer ends this wait, if it does
+    pub fn deadline(&self) -> Option<u64> {
+        match *self {
+            BlockState::Sleeping { wake_ns } | BlockState::WaitingService { wake_ns } => Some(wake_ns),
+            _ => None,
+        }
+    }
+}
+
+/// Source of CALL cookies; 0 is reserved for plain messages
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+fn next_cookie() -> u32 {
+    loop {
+        let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed);
+        if cookie != 0 {
+            return cookie;
+        }
+    }
+}
+
+/// True if a message is waiting in the mailbox of `pid`
+pub fn has_message(pid: usize) -> bool {
+    PROC_TABLE.read()[pid].mailbox.is_some()
 }
 
 // ---------------------------------------------------------------------------
@@ -54,23 +162,27 @@ pub enum BlockState {
 // ---------------------------------------------------------------------------
 
 /// Send a message to `target_pid`.
-/// Returns: 0 = success, usize::MAX = error (invalid PID)
+/// Returns: 0 = success, usize::MAX = error (invalid PID, data too large)
 pub fn send(target_pid: usize, kind: u32, data: &[u8]) -> usize {
     let sender_pid = current_pid();
 
     // Validate target
     {
         let table = PROC_TABLE.read();
-        if target_pid >= table.len() || (table[target_pid].id == 0 && target_pid != 0) {
+        if target_pid >= table.len() || (!table[target_pid].is_alive() && target_pid != 0) {
             return usize::MAX;
         }
     }
 
-    let mut payload = [0u8; MSG_PAYLOAD];
-    let copy_len = data.len().min(MSG_PAYLOAD);
-    payload[..copy_len].copy_from_slice(&data[..copy_len]);
+    match Message::new(kind, data) {
+        Ok(msg) => post(target_pid, Message { sender: sender_pid, ..msg }),
+        Err(()) => usize::MAX,
+    }
+}
 
-    let msg = Message { sender: sender_pid, kind, data: payload };
+/// Place `msg` in the mailbox of `target_pid`, blocking while it is full
+fn post(target_pid: usize, msg: Message) -> usize {
+    let sender_pid = msg.sender;
 
     // FIX: Spin + yield dengan enable_and_hlt agar scheduler bisa jalan
     // Ini memungkinkan proses penerima mendapat giliran di single-core
@@ -79,9 +191,16 @@ pub fn send(target_pid: usize, kind: u32, data: &[u8]) -> usize {
         {
             let mut table = PROC_TABLE.write();
 
+            // Killed while we waited for its mailbox
+            if !table[target_pid].is_alive() && target_pid != 0 {
+                table[sender_pid].block = BlockState::Running;
+                return usize::MAX;
+            }
             if table[target_pid].mailbox.is_none() {
                 table[target_pid].mailbox = Some(msg);
-                table[target_pid].block   = BlockState::Running;
+                if table[target_pid].block == BlockState::WaitingRecv {
+                    table[target_pid].block = BlockState::Running;
+                }
                 table[sender_pid].block   = BlockState::Running;
                 return 0;
             }
@@ -126,3 +245,360 @@ pub fn recv(out: &mut Message) -> usize {
         x86_64::instructions::interrupts::enable_and_hlt();
     }
 }
+
+// ---------------------------------------------------------------------------
+// call / reply — request/response on top of the mailbox
+// ---------------------------------------------------------------------------
+
+/// Send `req` to `target_pid` tagged with a fresh cookie and block until the
+/// matching reply arrives. Writes the reply to `out`, returns: 0 = success
+pub fn call(target_pid: usize, req: &Message, out: &mut Message) -> usize {
+    let pid    = current_pid();
+    let cookie = next_cookie();
+
+    if req.payload_len as usize > MSG_PAYLOAD {
+        return usize::MAX;
+    }
+
+    let msg = Message { sender: pid, cookie, ..*req };
+    if post(target_pid, msg) == usize::MAX {
+        return usize::MAX;
+    }
+
+    loop {
+        if let Some(msg) = take_reply(pid, target_pid, cookie) {
+            *out = msg;
+            return 0;
+        }
+        // A server that died with the request will never answer
+        if !PROC_TABLE.read()[target_pid].is_alive() && target_pid != 0 {
+            PROC_TABLE.write()[pid].block = BlockState::Running;
+            return usize::MAX;
+        }
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
+/// Take the reply for `cookie` if it has arrived, otherwise mark `pid` as
+/// waiting for `server` to send it. A reply carrying any other cookie is
+/// left in place for `reply` to deal with.
+fn take_reply(pid: usize, server: usize, cookie: u32) -> Option<Message> {
+    let mut table = PROC_TABLE.write();
+    match table[pid].reply {
+        Some(msg) if msg.cookie == cookie => {
+            table[pid].reply = None;
+            table[pid].block = BlockState::Running;
+            Some(msg)
+        }
+        _ => {
+            table[pid].block = BlockState::WaitingReply { server, cookie };
+            None
+        }
+    }
+}
+
+/// Answer `req` (a message received from a CALL) with `data`.
+/// Returns: 0 = success, usize::MAX = error (not a call, caller gone,
+/// or data too large)
+pub fn reply(req: &Message, data: &[u8]) -> usize {
+    if req.cookie == 0 {
+        return usize::MAX;
+    }
+    let Ok(msg) = Message::new(req.kind, data) else {
+        return usize::MAX;
+    };
+
+    let target = req.sender;
+    let mut table = PROC_TABLE.write();
+    if target >= table.len() || (!table[target].is_alive() && target != 0) {
+        return usize::MAX;
+    }
+    // A caller has one call outstanding at a time, so a reply still sitting
+    // in the slot while it waits for this one belongs to a call it gave up
+    // on. Anything else is left for its own caller.
+    let waiting = matches!(table[target].block, BlockState::WaitingReply { cookie, .. } if cookie == req.cookie);
+    if table[target].reply.is_some() && !waiting {
+        return usize::MAX;
+    }
+
+    table[target].reply = Some(Message { sender: current_pid(), cookie: req.cookie, ..msg });
+    if waiting {
+        table[target].block = BlockState::Running;
+    }
+    0
+}
+
+/// Wake everything blocked on `pid`, which is going away. Senders
+/// waiting for its mailbox and callers waiting for its reply find it gone
+/// when they retry, and fail instead of waiting forever.
+pub fn forget(table: &mut [Box<Process>], pid: usize) {
+    for proc in table.iter_mut() {
+        match proc.block {
+            BlockState::WaitingSend { target } if target == pid => proc.block = BlockState::Running,
+            BlockState::WaitingReply { server, .. } if server == pid => proc.block = BlockState::Running,
+            _ => {}
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Service names — reach a server without knowing its PID
+// ---------------------------------------------------------------------------
+
+/// Longest name REGISTER accepts
+pub const MAX_SERVICE_NAME: usize = 32;
+
+fn lookup_in(table: &[Box<Process>], name: &str) -> Option<usize> {
+    table.iter().position(|p| p.is_alive() && p.service.as_deref() == Some(name))
+}
+
+/// PID of the live process registered as `name`
+pub fn lookup(name: &str) -> Option<usize> {
+    lookup_in(&*PROC_TABLE.read(), name)
+}
+
+/// Register the current process as the server for `name`, which it keeps
+/// until it exits. Processes waiting in SENDNAME look again.
+/// Returns: 0 = success, usize::MAX = error (bad name, or another
+/// process holds it)
+pub fn register(name: &str) -> usize {
+    if name.is_empty() || name.len() > MAX_SERVICE_NAME {
+        return usize::MAX;
+    }
+    let pid = current_pid();
+    let mut table = PROC_TABLE.write();
+    if lookup_in(&*table, name).is_some_and(|p| p != pid) {
+        return usize::MAX;
+    }
+    table[pid].service = Some(name.into());
+    for proc in table.iter_mut() {
+        if matches!(proc.block, BlockState::WaitingService { .. }) {
+            proc.block = BlockState::Running;
+        }
+    }
+    0
+}
+
+/// Send `msg` to the process registered as `name`. If none is yet, wait
+/// up to `timeout_ms` for one to register (0 = not at all, u64::MAX =
+/// forever), so a client started before its server still gets through.
+/// Returns: 0 = success, usize::MAX = error (no server in time, data too
+/// large or mailbox full)
+pub fn send_to_name(name: &str, msg: &Message, timeout_ms: u64) -> usize {
+    if msg.payload_len as usize > MSG_PAYLOAD {
+        return usize::MAX;
+    }
+    let pid = current_pid();
+    let deadline = crate::sys::clk::uptime_ns().saturating_add(timeout_ms.saturating_mul(1_000_000));
+    loop {
+        if let Some(target) = await_service(name, deadline) {
+            return post(target, Message { sender: pid, cookie: 0, ..*msg });
+        }
+        if !waiting_service(pid) {
+            return usize::MAX; // Timed out
+        }
+        while waiting_service(pid) {
+            x86_64::instructions::interrupts::enable_and_hlt();
+        }
+    }
+}
+
+/// The process registered as `name`. Without one, the current process is
+/// marked as waiting for it until `deadline`, unless that has passed.
+fn await_service(name: &str, deadline: u64) -> Option<usize> {
+    // A REGISTER cannot slip in between the lookup and the blocking
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let found = lookup(name);
+        if found.is_none() && crate::sys::clk::uptime_ns() < deadline {
+            crate::sys::sched::block_until(BlockState::WaitingService { wake_ns: deadline });
+        }
+        found
+    })
+}
+
+fn waiting_service(pid: usize) -> bool {
+    matches!(PROC_TABLE.read()[pid].block, BlockState::WaitingService { .. })
+}
+
+#[test_case]
+fn poll_wakes_on_key_or_message() {
+    use crate::sys::console::STDIN;
+    use crate::sys::fs::PollEvent;
+    use crate::sys::syscall::service;
+
+    let list = [(0, PollEvent::Read), (MAILBOX, PollEvent::Read)];
+    let pid  = current_pid();
+
+    STDIN.lock().clear();
+    assert_eq!(service::poll(&list), -1);
+
+    PROC_TABLE.write()[pid].mailbox = Some(Message::empty());
+    assert_eq!(service::poll(&list), 1);
+    PROC_TABLE.write()[pid].mailbox = None;
+
+    STDIN.lock().push_str("k\n");
+    assert_eq!(service::poll(&list), 0);
+    STDIN.lock().clear();
+}
+
+#[test_case]
+fn replies_reach_the_matching_caller() {
+    use crate::sys::process::{set_pid, FakeProcess};
+
+    let (first, second, server) = (2usize, 3usize, 4usize);
+    let running = |pid: usize| PROC_TABLE.read()[pid].block == BlockState::Running;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _server = FakeProcess::enter(server);
+        let _second = FakeProcess::enter(second);
+        let _first  = FakeProcess::enter(first);
+
+        // A late answer to a call the first client gave up on
+        let stale = next_cookie();
+        PROC_TABLE.write()[first].reply = Some(Message { sender: server, cookie: stale, ..Message::empty() });
+
+        // Both clients call, each starting to wait before the server answers
+        let mut cookies = [0; 2];
+        for (i, &pid) in [first, second].iter().enumerate() {
+            set_pid(pid);
+            cookies[i] = next_cookie();
+            let req = Message { sender: pid, cookie: cookies[i], ..Message::new(7, &[]).unwrap() };
+            assert_eq!(post(server, req), 0);
+            assert!(take_reply(pid, server, cookies[i]).is_none());
+
+            set_pid(server);
+            let mut got = Message::empty();
+            assert_eq!(recv(&mut got), 0);
+            assert_eq!((got.sender, got.cookie), (pid, cookies[i]));
+        }
+
+        // The stale reply is kept, not taken for the new call
+        assert!(take_reply(first, server, cookies[0]).is_none());
+        assert_eq!(PROC_TABLE.read()[first].reply.map(|m| m.cookie), Some(stale));
+
+        // Answered out of order; the first client is not woken by the
+        // second one's reply
+        for (i, &pid) in [first, second].iter().enumerate().rev() {
+            let req = Message { sender: pid, cookie: cookies[i], ..Message::new(7, &[]).unwrap() };
+            assert_eq!(reply(&req, &[pid as u8]), 0);
+            if pid == second {
+                assert!(!running(first));
+            }
+        }
+
+        for (i, &pid) in [first, second].iter().enumerate() {
+            assert!(running(pid));
+            let msg = take_reply(pid, server, cookies[i]).expect("reply");
+            assert_eq!((msg.sender, msg.cookie, msg.data[0]), (server, cookies[i], pid as u8));
+        }
+
+        // A reply nobody is waiting for does not push out one that is kept
+        PROC_TABLE.write()[first].reply = Some(Message { sender: server, cookie: stale, ..Message::empty() });
+        let req = Message { sender: first, cookie: next_cookie(), ..Message::new(7, &[]).unwrap() };
+        assert_eq!(reply(&req, &[]), usize::MAX);
+        assert_eq!(PROC_TABLE.read()[first].reply.map(|m| m.cookie), Some(stale));
+    });
+}
+
+#[test_case]
+fn binary_payload_survives_roundtrip() {
+    let data: [u8; 64] = core::array::from_fn(|i| if i % 3 == 0 { 0 } else { i as u8 });
+    let data = &data[..MSG_PAYLOAD.min(64)];
+    let pid  = current_pid();
+
+    assert_eq!(send(pid, 5, data), 0);
+    let mut out = Message::empty();
+    assert_eq!(recv(&mut out), 0);
+    assert_eq!((out.kind, out.payload()), (5, data));
+
+    // Too big is an error, not a silent truncation
+    assert_eq!(send(pid, 5, &[1; MSG_PAYLOAD + 1]), usize::MAX);
+    assert!(!has_message(pid));
+}
+
+#[test_case]
+fn client_reaches_server_that_registers_late() {
+    use crate::sys::process::{set_pid, FakeProcess};
+
+    let (client, server) = (2usize, 3usize);
+    let msg = Message::new(9, b"hello").unwrap();
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _server = FakeProcess::enter(server);
+        let _client = FakeProcess::enter(client);
+
+        // Not there and not waiting for it: an error right away
+        assert_eq!(send_to_name("test.echo", &msg, 0), usize::MAX);
+
+        // The client starts waiting before the server is up
+        let deadline = crate::sys::clk::uptime_ns() + 1_000_000_000;
+        assert_eq!(await_service("test.echo", deadline), None);
+        assert!(!PROC_TABLE.read()[client].is_runnable());
+
+        // Registering wakes it, and the retry delivers
+        set_pid(server);
+        assert_eq!(register("test.echo"), 0);
+        assert!(PROC_TABLE.read()[client].is_runnable());
+        set_pid(client);
+        assert_eq!(register("test.echo"), usize::MAX);
+        assert_eq!(send_to_name("test.echo", &msg, 1_000), 0);
+        let got = PROC_TABLE.write()[server].mailbox.take().unwrap();
+        assert_eq!((got.sender, got.kind, got.payload()), (client, 9, &b"hello"[..]));
+
+        // The name goes with the process
+        PROC_TABLE.write()[server] = Box::new(Process::new());
+        assert_eq!(lookup("test.echo"), None);
+    });
+}
+
+#[test_case]
+fn waiting_for_a_service_times_out() {
+    use crate::sys::process::FakeProcess;
+    use crate::sys::sched::wake_sleepers;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+
+        let deadline = crate::sys::clk::uptime_ns() + 1_000_000;
+        assert_eq!(await_service("test.nobody", deadline), None);
+        assert!(waiting_service(2));
+        wake_sleepers(deadline);
+        assert!(!waiting_service(2));
+    });
+}
+
+#[test_case]
+fn killed_server_does_not_strand_its_clients() {
+    use crate::sys::process::{set_pid, FakeProcess};
+    use crate::sys::signal::SIGKILL;
+    use crate::sys::syscall::{error::EINVAL, service};
+
+    let (sender, server, caller) = (2usize, 3usize, 4usize);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let process = FakeProcess::enter(sender);
+        {
+            let mut table = PROC_TABLE.write();
+            for pid in [server, caller] {
+                table[pid].id = pid;
+            }
+            table[server].block = BlockState::WaitingRecv;
+            table[server].mailbox = Some(Message::empty());
+            table[sender].block = BlockState::WaitingSend { target: server };
+            table[caller].block = BlockState::WaitingReply { server, cookie: next_cookie() };
+
+            // What burying the server does to the rest of the table
+            forget(&mut table[..], server);
+            table[server] = Box::new(Process::new());
+        }
+        assert!(PROC_TABLE.read()[sender].is_runnable());
+        assert!(PROC_TABLE.read()[caller].is_runnable());
+
+        // The sender's retry fails instead of filling an empty slot
+        assert_eq!(post(server, Message { sender, ..Message::empty() }), usize::MAX);
+        assert!(PROC_TABLE.read()[server].mailbox.is_none());
+
+        // There is always something at PID 0 that must not go
+        set_pid(process.saved);
+        assert_eq!(service::kill(0, SIGKILL), EINVAL);
+
+        PROC_TABLE.write()[caller] = Box::new(Process::new());
+    });
+}
@@ -1,11 +1,11 @@
 //! Keyboard — PS/2 driver via IRQ 1
 //!
-//! Translates scan codes to Unicode characters
-//! and pushes them into the console stdin buffer.
+//! The IRQ only queues the raw scan code; decoding into Unicode
+//! characters for the console stdin buffer happens in the work queue.
 
 use crate::sys;
 use lazy_static::lazy_static;
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, Keyboard, ScancodeSet1};
 use spin::Mutex;
 use x86_64::instructions::port::Port;
 
@@ -14,7 +14,7 @@ lazy_static! {
         Mutex::new(Keyboard::new(
             ScancodeSet1::new(),
             layouts::Us104Key,
-            HandleControl::Ignore,
+            HandleControl::MapLettersToUnicode, // Ctrl+A..Z → 0x01..0x1A
         ))
     };
 }
@@ -25,15 +25,73 @@ pub fn init() {
 
 fn on_interrupt() {
     let scancode: u8 = unsafe { Port::<u8>::new(0x60).read() };
+    sys::workq::push(sys::workq::Work::Scancode(scancode));
+}
 
+/// Decode a queued scan code (bottom half, interrupts enabled)
+pub fn handle_scancode(scancode: u8) {
     let mut kb = KB.lock();
     if let Ok(Some(event)) = kb.add_byte(scancode) {
         if let Some(key) = kb.process_keyevent(event) {
-            let ch = match key {
-                DecodedKey::Unicode(c) => c,
-                DecodedKey::RawKey(_)  => return,
-            };
-            sys::console::input_char(ch);
+            match key {
+                DecodedKey::Unicode(c) => {
+                    // A line reader has no use for Ctrl+letter chords
+                    // other than the ones cooked input acts on
+                    if sys::console::is_raw() || !is_stray_chord(c) {
+                        sys::console::input_char(c);
+                    }
+                }
+                DecodedKey::RawKey(code) => {
+                    // Navigation keys only make sense to raw-mode readers
+                    if sys::console::is_raw() {
+                        if let Some(seq) = escape_sequence(code) {
+                            seq.chars().for_each(sys::console::input_char);
+                        }
+                    }
+                }
+            }
         }
     }
 }
+
+/// A Ctrl+letter chord that is not Backspace, Tab, Enter, Ctrl+C or
+/// Ctrl+D, all of which share their codes with some chord
+fn is_stray_chord(c: char) -> bool {
+    use sys::console::{BS, EOT, ETX};
+    matches!(c, '\x01'..='\x1A') && !matches!(c, BS | EOT | ETX | '\t' | '\n')
+}
+
+/// VT100 sequence sent for a navigation key, as a serial terminal would
+fn escape_sequence(code: KeyCode) -> Option<&'static str> {
+    match code {
+        KeyCode::ArrowUp    => Some("\x1b[A"),
+        KeyCode::ArrowDown  => Some("\x1b[B"),
+        KeyCode::ArrowRight => Some("\x1b[C"),
+        KeyCode::ArrowLeft  => Some("\x1b[D"),
+        KeyCode::Home       => Some("\x1b[H"),
+        KeyCode::End        => Some("\x1b[F"),
+        _                   => None,
+    }
+}
+
+#[test_case]
+fn cooked_input_drops_stray_chords() {
+    use crate::sys::console::{self, STDIN};
+
+    console::disable_echo();
+    STDIN.lock().clear();
+    // Ctrl down, q, q up, Ctrl up, then a plain 'a'
+    for scancode in [0x1D, 0x10, 0x90, 0x9D, 0x1E, 0x9E] {
+        handle_scancode(scancode);
+    }
+    assert_eq!(STDIN.lock().as_str(), "a");
+
+    console::enable_raw();
+    for scancode in [0x1D, 0x10, 0x90, 0x9D] {
+        handle_scancode(scancode);
+    }
+    console::disable_raw();
+    assert_eq!(STDIN.lock().as_str(), "a\x11");
+    STDIN.lock().clear();
+    console::enable_echo();
+}
@@ -0,0 +1,188 @@
+//! Kernel log — levels and the message ring behind `dmesg`
+//!
+//! Every message that passes the runtime log level is printed to the
+//! console and appended to a fixed-size byte ring. Readers (`/dev/kmsg`,
+//! `dmesg`) keep their own position in the stream and block until new
+//! bytes arrive; old bytes are overwritten once the ring wraps.
+
+use crate::sys;
+use crate::sys::fs::{FileIO, PollEvent};
+
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+// ---------------------------------------------------------------------------
+// Levels
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Debug = 0,
+    Info  = 1,
+    Warn  = 2,
+    Error = 3,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "debug" => Some(Level::Debug),
+            "info"  => Some(Level::Info),
+            "warn"  => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _       => None,
+        }
+    }
+}
+
+/// Lowest level that still gets through
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Debug as u8);
+
+pub fn set_log_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Debug,
+        1 => Level::Info,
+        2 => Level::Warn,
+        _ => Level::Error,
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 >= LEVEL.load(Ordering::Relaxed)
+}
+
+// ---------------------------------------------------------------------------
+// Ring buffer
+// ---------------------------------------------------------------------------
+
+const RING_SIZE: usize = 8192;
+
+struct Ring {
+    buf: [u8; RING_SIZE],
+    /// Total bytes ever written — a reader position is an offset in this stream
+    end: u64,
+}
+
+impl Ring {
+    fn start(&self) -> u64 {
+        self.end.saturating_sub(RING_SIZE as u64)
+    }
+}
+
+impl fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &b in s.as_bytes() {
+            self.buf[(self.end % RING_SIZE as u64) as usize] = b;
+            self.end += 1;
+        }
+        Ok(())
+    }
+}
+
+static RING: Mutex<Ring> = Mutex::new(Ring { buf: [0; RING_SIZE], end: 0 });
+
+/// Print a message and record it in the ring, if `level` is enabled
+pub fn log(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    sys::console::print_fmt(args);
+    interrupts::without_interrupts(|| {
+        RING.lock().write_fmt(args).ok();
+    });
+}
+
+/// Position just past the newest byte in the ring
+pub fn end() -> u64 {
+    interrupts::without_interrupts(|| RING.lock().end)
+}
+
+/// Copy bytes from position `pos` into `buf` without blocking.
+/// Returns the byte count and the position to continue from; a reader
+/// that fell behind the ring skips ahead to the oldest byte still kept.
+pub fn read_at(pos: u64, buf: &mut [u8]) -> (usize, u64) {
+    interrupts::without_interrupts(|| {
+        let ring = RING.lock();
+        let mut pos = pos.max(ring.start());
+        let mut n = 0;
+        while n < buf.len() && pos < ring.end {
+            buf[n] = ring.buf[(pos % RING_SIZE as u64) as usize];
+            pos += 1;
+            n += 1;
+        }
+        (n, pos)
+    })
+}
+
+/// Like `read_at`, but wait until there is at least one byte to read
+pub fn read_blocking(pos: u64, buf: &mut [u8]) -> (usize, u64) {
+    loop {
+        let (n, next) = read_at(pos, buf);
+        if n > 0 || buf.is_empty() {
+            return (n, next);
+        }
+        interrupts::enable_and_hlt();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// /dev/kmsg
+// ---------------------------------------------------------------------------
+
+/// Reader over the log ring, starting at the oldest kept message
+#[derive(Clone, Debug)]
+pub struct Kmsg {
+    pos: u64,
+}
+
+impl Kmsg {
+    pub fn new() -> Self { Self { pos: 0 } }
+}
+
+impl Default for Kmsg {
+    fn default() -> Self { Self::new() }
+}
+
+impl FileIO for Kmsg {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let (n, pos) = read_blocking(self.pos, buf);
+        self.pos = pos;
+        Ok(n)
+    }
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let s = alloc::string::String::from_utf8_lossy(buf);
+        log(Level::Info, format_args!("{}", s));
+        Ok(buf.len())
+    }
+    fn close(&mut self) {}
+    fn poll(&mut self, e: PollEvent) -> bool {
+        match e {
+            PollEvent::Read  => end() > self.pos,
+            PollEvent::Write => true,
+        }
+    }
+    fn kind(&self) -> u8 { 1 }
+}
+
+#[test_case]
+fn error_level_suppresses_warnings() {
+    let saved = log_level();
+    set_log_level(Level::Error);
+
+    let before = end();
+    kwarn!("should not be logged");
+    assert_eq!(end(), before);
+
+    kerror!("should be logged");
+    assert!(end() > before);
+
+    set_log_level(saved);
+}
@@ -7,7 +7,7 @@ use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
 use bit_field::BitField;
 use core::{cmp, slice};
 use spin::{Mutex, Once};
-use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB};
 use x86_64::PhysAddr;
 
 // ---------------------------------------------------------------------------
@@ -53,6 +53,7 @@ pub struct BitmapAllocator {
     regions:    [Option<MemRegion>; MAX_REGIONS],
     n_regions:  usize,
     n_frames:   usize,
+    n_used:     usize,
 }
 
 impl BitmapAllocator {
@@ -71,6 +72,7 @@ impl BitmapAllocator {
             regions:   [None; MAX_REGIONS],
             n_regions: 0,
             n_frames:  0,
+            n_used:    0,
         };
 
         let mut bitmap_placed = false;
@@ -115,6 +117,116 @@ impl BitmapAllocator {
         alloc
     }
 
+    /// Number of frames currently handed out
+    pub fn used_frames(&self) -> usize {
+        self.n_used
+    }
+
+    /// Number of frames still available
+    pub fn free_frames(&self) -> usize {
+        self.n_frames - self.n_used
+    }
+
+    /// Number of frames the allocator manages
+    pub fn total_frames(&self) -> usize {
+        self.n_frames
+    }
+
+    /// Frames the allocator manages in `[start, end)`, and how many of
+    /// them are handed out
+    pub fn usage(&self, start: u64, end: u64) -> (usize, usize) {
+        let (mut frames, mut used) = (0, 0);
+        let mut base = 0;
+        for r in self.regions[..self.n_regions].iter().flatten() {
+            let first = r.first_frame.start_address().as_u64();
+            let last  = r.last_frame().start_address().as_u64();
+            let lo = cmp::max(first, start.next_multiple_of(4096));
+            for addr in (lo..=last).step_by(4096).take_while(|&a| a + 4096 <= end) {
+                frames += 1;
+                if self.is_used(base + ((addr - first) / 4096) as usize) {
+                    used += 1;
+                }
+            }
+            base += r.frame_count;
+        }
+        (frames, used)
+    }
+
+    /// Number of usable regions, the valid arguments of
+    /// `allocate_frame_in_region`
+    pub fn region_count(&self) -> usize {
+        self.n_regions
+    }
+
+    /// Allocate the lowest free frame that ends at or below physical
+    /// address `limit`, for devices that cannot reach all of memory
+    /// (ISA DMA below 16 MB, 32-bit DMA below 4 GB)
+    pub fn allocate_frame_below(&mut self, limit: u64) -> Option<PhysFrame> {
+        let mut base = 0;
+        for r in self.regions[..self.n_regions].iter().flatten() {
+            let first = r.first_frame.start_address().as_u64();
+            let fit = (limit.saturating_sub(first) / 4096) as usize;
+            let idx = (base..base + fit.min(r.frame_count)).find(|&idx| !self.is_used(idx));
+            if let Some(idx) = idx {
+                return Some(self.take(idx));
+            }
+            base += r.frame_count;
+        }
+        None
+    }
+
+    /// Allocate a free frame from usable region `region` only
+    pub fn allocate_frame_in_region(&mut self, region: usize) -> Option<PhysFrame> {
+        if region >= self.n_regions {
+            return None;
+        }
+        let base: usize = self.regions[..region].iter().flatten().map(|r| r.frame_count).sum();
+        let count = self.regions[region]?.frame_count;
+        let idx = (base..base + count).find(|&idx| !self.is_used(idx))?;
+        Some(self.take(idx))
+    }
+
+    /// Allocate 512 contiguous free frames starting on a 2 MiB boundary,
+    /// to back one huge page
+    pub fn allocate_2mib_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        const FRAMES: usize = 512;
+        let regions = self.regions;
+        let mut base = 0;
+        for r in regions[..self.n_regions].iter().flatten() {
+            let first = r.first_frame.start_address().as_u64();
+            let end   = first + r.frame_count as u64 * 4096;
+            let mut addr = first.next_multiple_of(Size2MiB::SIZE);
+            while addr + Size2MiB::SIZE <= end {
+                let idx = base + ((addr - first) / 4096) as usize;
+                if !(idx..idx + FRAMES).any(|i| self.is_used(i)) {
+                    for i in idx..idx + FRAMES {
+                        self.set_used(i, true);
+                    }
+                    self.n_used += FRAMES;
+                    return PhysFrame::from_start_address(PhysAddr::new(addr)).ok();
+                }
+                addr += Size2MiB::SIZE;
+            }
+            base += r.frame_count;
+        }
+        None
+    }
+
+    /// Give back the 512 frames of a huge page
+    pub unsafe fn deallocate_2mib_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let first = PhysFrame::<Size4KiB>::containing_address(frame.start_address());
+        for f in PhysFrame::range(first, first + 512) {
+            FrameDeallocator::<Size4KiB>::deallocate_frame(self, f);
+        }
+    }
+
+    /// Mark the free frame at `idx` used and return it
+    fn take(&mut self, idx: usize) -> PhysFrame {
+        self.set_used(idx, true);
+        self.n_used += 1;
+        self.frame_at_index(idx).expect("frame index out of range")
+    }
+
     fn frame_at_index(&self, idx: usize) -> Option<PhysFrame> {
         if idx >= self.n_frames { return None; }
         let mut base = 0;
@@ -156,9 +268,8 @@ unsafe impl FrameAllocator<Size4KiB> for BitmapAllocator {
         for i in 0..self.n_frames {
             let idx = (self.next_hint + i) % self.n_frames;
             if !self.is_used(idx) {
-                self.set_used(idx, true);
                 self.next_hint = idx + 1;
-                return self.frame_at_index(idx);
+                return Some(self.take(idx));
             }
         }
         None
@@ -170,6 +281,7 @@ impl FrameDeallocator<Size4KiB> for BitmapAllocator {
         if let Some(idx) = self.index_of_frame(frame) {
             if self.is_used(idx) {
                 self.set_used(idx, false);
+                self.n_used -= 1;
                 self.next_hint = cmp::min(self.next_hint, idx);
             }
         }
@@ -194,3 +306,26 @@ where
 {
     f(&mut ALLOCATOR.get().expect("frame allocator not ready").lock())
 }
+
+#[test_case]
+fn constrained_allocation_respects_limits() {
+    const ISA_DMA_LIMIT: u64 = 16 << 20;
+
+    with_frame_allocator(|a| {
+        let used = a.used_frames();
+        let low = a.allocate_frame_below(ISA_DMA_LIMIT).expect("no frame below 16 MB");
+        assert!(low.start_address().as_u64() + 4096 <= ISA_DMA_LIMIT);
+        assert!(a.allocate_frame_below(0).is_none());
+
+        let last = a.region_count() - 1;
+        let frame = a.allocate_frame_in_region(last).unwrap();
+        assert!(a.regions[last].unwrap().contains(frame));
+        assert!(a.allocate_frame_in_region(a.region_count()).is_none());
+
+        unsafe {
+            a.deallocate_frame(low);
+            a.deallocate_frame(frame);
+        }
+        assert_eq!(a.used_frames(), used);
+    });
+}
@@ -0,0 +1,304 @@
+//! Copy-on-write — sharing user frames between forked address spaces
+//!
+//! FORK gives the child a copy of the parent's page tables for its slot,
+//! with every user page shared: on both sides the entries lose WRITABLE
+//! and get `COW`. The first write from either side faults and
+//! `handle_fault` hands the writer a copy of its own, or just makes the
+//! page writable again once nobody else maps the frame.
+//!
+//! Frames mapped more than once are counted here; a frame that is not in
+//! the table has a single owner. `unmap_page` frees a frame only when its
+//! last mapping goes.
+
+use super::{phys_mem_offset, phys_to_virt, with_frame_allocator};
+use super::tlb;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+use spin::Mutex;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+    PageTableFlags, PageTableIndex, PhysFrame, Size1GiB, Size2MiB, Size4KiB, Translate,
+    mapper::{MappedFrame, TranslateResult},
+    page_table::PageTableEntry,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Marks a page that is read-only only because its frame is shared
+pub const COW: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Mappings of each shared frame, by start address (always at least 2)
+static SHARERS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+/// Record one more mapping of the frame at `addr`
+fn share(addr: PhysAddr) {
+    *SHARERS.lock().entry(addr.as_u64()).or_insert(1) += 1;
+}
+
+/// Drop one mapping of the frame at `addr`. True if it was the last
+/// one and the caller should free the frame.
+pub fn release(addr: PhysAddr) -> bool {
+    let mut sharers = SHARERS.lock();
+    match sharers.get_mut(&addr.as_u64()) {
+        None => true,
+        Some(n) if *n <= 2 => {
+            sharers.remove(&addr.as_u64());
+            false
+        }
+        Some(n) => {
+            *n -= 1;
+            false
+        }
+    }
+}
+
+fn is_shared(addr: PhysAddr) -> bool {
+    SHARERS.lock().contains_key(&addr.as_u64())
+}
+
+fn table_at(addr: PhysAddr) -> &'static mut PageTable {
+    unsafe { &mut *phys_to_virt(addr).as_mut_ptr() }
+}
+
+/// Copy of the page tables in `parent` for a forked child, sharing the
+/// user pages in `range` copy-on-write. Returns the child's top-level
+/// table and the lower-level tables copied for it; everything outside
+/// `range` stays shared with the parent.
+pub(crate) fn fork_address_space(parent: PhysFrame, range: Range<u64>) -> Result<(PhysFrame, Vec<PhysFrame>), ()> {
+    // At worst a PDPT and a PD per GiB and a PT per 2 MiB, plus the
+    // top-level table. With that many free frames nothing below can run
+    // out halfway through.
+    let len = range.end.saturating_sub(range.start);
+    let needed = 1 + 2 * (len / Size1GiB::SIZE + 2) + len / Size2MiB::SIZE + 2;
+    if (super::frame_stats().1 as u64) < needed {
+        return Err(());
+    }
+
+    let child = new_table(parent.start_address());
+    let (p4_parent, p4_child) = (table_at(parent.start_address()), table_at(child.start_address()));
+    let mut copies = Vec::new();
+
+    let mut addr = range.start & !(Size2MiB::SIZE - 1);
+    while addr < range.end {
+        let virt = VirtAddr::new(addr);
+        addr += Size2MiB::SIZE;
+
+        let Some((p3_parent, p3_child)) = descend(p4_parent, p4_child, virt.p4_index(), &mut copies) else { continue };
+        let Some((p2_parent, p2_child)) = descend(p3_parent, p3_child, virt.p3_index(), &mut copies) else { continue };
+        let i = virt.p2_index();
+        if p2_parent[i].is_unused() {
+            continue;
+        }
+        if p2_parent[i].flags().contains(PageTableFlags::HUGE_PAGE) {
+            if range.contains(&virt.as_u64()) {
+                share_entry(&mut p2_parent[i], &mut p2_child[i]);
+            }
+            continue;
+        }
+        let Some((p1_parent, p1_child)) = descend(p2_parent, p2_child, i, &mut copies) else { continue };
+        for j in 0..512 {
+            let page = virt.as_u64() + j as u64 * Size4KiB::SIZE;
+            let j = PageTableIndex::new(j);
+            if range.contains(&page) && !p1_parent[j].is_unused() {
+                share_entry(&mut p1_parent[j], &mut p1_child[j]);
+            }
+        }
+    }
+
+    // The parent's pages just became read-only
+    if Cr3::read().0 == parent {
+        tlb::shootdown(range);
+    }
+    Ok((child, copies))
+}
+
+/// A fresh frame holding a copy of the table at `from`
+fn new_table(from: PhysAddr) -> PhysFrame {
+    let frame = with_frame_allocator(|fa| fa.allocate_frame()).expect("frames counted in advance");
+    *table_at(frame.start_address()) = table_at(from).clone();
+    frame
+}
+
+/// The tables entry `i` of `parent` and `child` point to, after giving
+/// the child its own copy if it still shares the parent's. None where
+/// nothing is mapped.
+fn descend(
+    parent: &PageTable,
+    child:  &mut PageTable,
+    i:      PageTableIndex,
+    copies: &mut Vec<PhysFrame>,
+) -> Option<(&'static mut PageTable, &'static mut PageTable)> {
+    let entry = &parent[i];
+    if entry.is_unused() || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+        return None;
+    }
+    if child[i].addr() == entry.addr() {
+        let copy = new_table(entry.addr());
+        let flags = child[i].flags();
+        child[i].set_addr(copy.start_address(), flags);
+        copies.push(copy);
+    }
+    Some((table_at(entry.addr()), table_at(child[i].addr())))
+}
+
+/// Map the frame of `parent` from both entries, read-only if it was
+/// writable
+fn share_entry(
+    parent: &mut PageTableEntry,
+    child:  &mut PageTableEntry,
+) {
+    let mut flags = parent.flags();
+    if flags.intersects(PageTableFlags::WRITABLE | COW) {
+        flags = (flags - PageTableFlags::WRITABLE) | COW;
+    }
+    parent.set_flags(flags);
+    child.set_addr(parent.addr(), flags);
+    share(parent.addr());
+}
+
+/// Resolve a write fault at `addr` in the active address space if it
+/// hit a copy-on-write page. False if the page is not one.
+pub fn handle_fault(addr: u64) -> bool {
+    let mut mapper = unsafe {
+        OffsetPageTable::new(super::active_page_table(), VirtAddr::new(phys_mem_offset()))
+    };
+    let virt = VirtAddr::new(addr);
+    let (frame, flags) = match mapper.translate(virt) {
+        TranslateResult::Mapped { frame, flags, .. } if flags.contains(COW) => (frame, flags),
+        _ => return false,
+    };
+    let flags = (flags - COW) | PageTableFlags::WRITABLE;
+
+    let done = match frame {
+        MappedFrame::Size4KiB(old) => {
+            let page = Page::<Size4KiB>::containing_address(virt);
+            remap(&mut mapper, page, old, flags, |fa| fa.allocate_frame())
+        }
+        MappedFrame::Size2MiB(old) => {
+            let page = Page::<Size2MiB>::containing_address(virt);
+            remap(&mut mapper, page, old, flags, |fa| fa.allocate_2mib_frame())
+        }
+        MappedFrame::Size1GiB(_) => false,
+    };
+    if done {
+        let start = addr & !(frame.size() - 1);
+        tlb::shootdown(start..start + frame.size());
+    }
+    done
+}
+
+/// Make `page` writable: in place if its frame is no longer shared,
+/// otherwise on a copy of the frame
+fn remap<S: PageSize>(
+    mapper:   &mut OffsetPageTable,
+    page:     Page<S>,
+    old:      PhysFrame<S>,
+    flags:    PageTableFlags,
+    allocate: impl FnOnce(&mut super::bitmap::BitmapAllocator) -> Option<PhysFrame<S>>,
+) -> bool
+where
+    for<'a> OffsetPageTable<'a>: Mapper<S>,
+{
+    if !is_shared(old.start_address()) {
+        return unsafe { mapper.update_flags(page, flags) }.map(|f| f.ignore()).is_ok();
+    }
+    with_frame_allocator(|fa| {
+        let Some(new) = allocate(fa) else { return false };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(old.start_address()).as_ptr::<u8>(),
+                phys_to_virt(new.start_address()).as_mut_ptr::<u8>(),
+                S::SIZE as usize,
+            );
+        }
+        let Ok((_, flush)) = mapper.unmap(page) else { return false };
+        flush.ignore();
+        match unsafe { mapper.map_to(page, new, flags, fa) } {
+            Ok(flush) => flush.ignore(),
+            Err(_) => return false,
+        }
+        release(old.start_address());
+        true
+    })
+}
+
+/// Does the top-level table `pml4` point into any of `tables`?
+pub fn uses_tables(pml4: PhysFrame, tables: &[PhysFrame]) -> bool {
+    table_at(pml4.start_address()).iter()
+        .any(|e| !e.is_unused() && tables.contains(&PhysFrame::containing_address(e.addr())))
+}
+
+/// Free the tables FORK copied for the address space `pml4`, once its
+/// pages are unmapped. Those the unmapping already freed are skipped.
+pub fn free_tables(pml4: PhysFrame, tables: &[PhysFrame]) {
+    let copied = |e: &PageTableEntry| {
+        !e.is_unused()
+            && !e.flags().contains(PageTableFlags::HUGE_PAGE)
+            && tables.contains(&PhysFrame::containing_address(e.addr()))
+    };
+    let mut free = Vec::new();
+    for e4 in table_at(pml4.start_address()).iter_mut().filter(|e| copied(e)) {
+        for e3 in table_at(e4.addr()).iter_mut().filter(|e| copied(e)) {
+            for e2 in table_at(e3.addr()).iter_mut().filter(|e| copied(e)) {
+                free.push(PhysFrame::containing_address(e2.addr()));
+                e2.set_unused();
+            }
+            free.push(PhysFrame::containing_address(e3.addr()));
+            e3.set_unused();
+        }
+        free.push(PhysFrame::containing_address(e4.addr()));
+        e4.set_unused();
+    }
+    with_frame_allocator(|fa| {
+        for frame in free {
+            unsafe { fa.deallocate_frame(frame); }
+        }
+    });
+}
+
+#[test_case]
+fn forked_pages_are_copied_on_write() {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    // Away from the kernel heap, the process slots and the other tests
+    let base = 0x5558_0000_0000u64;
+    let mapper = super::mapper();
+    let read = |m: &OffsetPageTable, a: u64| unsafe {
+        *phys_to_virt(m.translate_addr(VirtAddr::new(a)).unwrap()).as_ptr::<u64>()
+    };
+
+    without_interrupts(|| {
+        let (used, _) = super::frame_stats();
+        super::map_page(mapper, base, 2 * 4096).unwrap();
+        unsafe {
+            (base as *mut u64).write_volatile(1);
+            ((base + 4096) as *mut u64).write_volatile(2);
+        }
+
+        let (child, tables) = fork_address_space(Cr3::read().0, base..base + 2 * 4096).unwrap();
+        let mut forked = unsafe {
+            OffsetPageTable::new(table_at(child.start_address()), VirtAddr::new(phys_mem_offset()))
+        };
+        assert_eq!((read(&forked, base), read(&forked, base + 4096)), (1, 2));
+
+        // Writing faults and gives the writer a copy; the child keeps
+        // seeing the original
+        unsafe { (base as *mut u64).write_volatile(10); }
+        assert_eq!(read(mapper, base), 10);
+        assert_eq!(read(&forked, base), 1);
+
+        // Once the child is gone the frame has one owner again and a
+        // write makes it writable in place
+        super::unmap_page(&mut forked, base, 2 * 4096);
+        let frame = mapper.translate_addr(VirtAddr::new(base + 4096));
+        unsafe { ((base + 4096) as *mut u64).write_volatile(20); }
+        assert_eq!(mapper.translate_addr(VirtAddr::new(base + 4096)), frame);
+        assert_eq!(read(mapper, base + 4096), 20);
+
+        free_tables(child, &tables);
+        with_frame_allocator(|fa| unsafe { fa.deallocate_frame(child) });
+        super::unmap_page(mapper, base, 2 * 4096);
+        assert_eq!(super::frame_stats().0, used);
+    });
+}
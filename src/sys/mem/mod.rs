@@ -4,18 +4,27 @@
 //!   - frame_alloc: physical frame allocation via bitmap
 //!   - paging: x86_64 page table manipulation
 //!   - heap: global kernel heap (linked_list_allocator)
+//!   - tlb: TLB invalidation after mappings are removed
+//!   - cow: frames shared copy-on-write between forked processes
 
 mod bitmap;
+pub mod cow;
 mod heap;
 mod paging;
+pub mod tlb;
 
 pub use bitmap::{with_frame_allocator, FrameAllocatorHandle};
 pub use paging::{map_page, unmap_page, active_page_table, create_page_table_from_frame};
+pub(crate) use paging::map_huge_page;
 
 use crate::sys;
-use bootloader::bootinfo::{BootInfo, MemoryMap};
+use alloc::string::String;
+use alloc::vec::Vec;
+use bootloader::bootinfo::{BootInfo, MemoryMap, MemoryRegionType};
+use core::fmt::Write;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Once;
+use x86_64::registers::control::{Cr0, Cr0Flags};
 use x86_64::structures::paging::{OffsetPageTable, Translate};
 use x86_64::{PhysAddr, VirtAddr};
 
@@ -70,6 +79,10 @@ pub fn init(boot_info: &'static BootInfo) {
         });
     }
 
+    // Kernel writes to read-only pages fault too, so copy-on-write
+    // pages are copied whoever writes to them first
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)); }
+
     bitmap::init_frame_allocator(&boot_info.memory_map);
     heap::init_kernel_heap().expect("heap init failed");
 
@@ -93,14 +106,64 @@ pub fn total_memory() -> usize {
     TOTAL_BYTES.load(Ordering::Relaxed)
 }
 
+/// Bytes in use: kernel heap allocations plus every other frame handed
+/// out (page tables, process memory). The heap's own frames count by
+/// what is allocated in them, not as a whole.
 pub fn used_memory() -> usize {
-    (total_memory() - heap::heap_size()) + heap::heap_used()
+    let (used_frames, _) = frame_stats();
+    heap::heap_used() + (used_frames * 4096).saturating_sub(heap::heap_size())
+}
+
+/// `(used, free)` physical frames of the frame allocator
+pub fn frame_stats() -> (usize, usize) {
+    with_frame_allocator(|fa| (fa.used_frames(), fa.free_frames()))
 }
 
 pub fn free_memory() -> usize {
     heap::heap_free()
 }
 
+/// One entry of the boot memory map, with the frame allocator's view of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegionInfo {
+    pub start:  u64,
+    /// Exclusive
+    pub end:    u64,
+    pub kind:   MemoryRegionType,
+    /// Frames of this region the allocator manages, and how many are in use
+    pub frames: usize,
+    pub used:   usize,
+}
+
+/// The physical memory map as the bootloader passed it
+pub fn regions() -> Vec<RegionInfo> {
+    let map = match MEM_MAP.get() {
+        Some(map) => map,
+        None => return Vec::new(),
+    };
+    with_frame_allocator(|alloc| {
+        map.iter().map(|r| {
+            let (start, end) = (r.range.start_addr(), r.range.end_addr());
+            let (frames, used) = alloc.usage(start, end);
+            RegionInfo { start, end, kind: r.region_type, frames, used }
+        }).collect()
+    })
+}
+
+/// One region per line: `start-end : type`, inclusive bounds like
+/// /proc/iomem on Linux, plus frame usage where the allocator has any
+pub fn iomem() -> String {
+    let mut out = String::new();
+    for r in regions() {
+        write!(out, "{:016x}-{:016x} : {:?}", r.start, r.end - 1, r.kind).ok();
+        if r.frames > 0 {
+            write!(out, " ({}/{} frames used)", r.used, r.frames).ok();
+        }
+        out.push('\n');
+    }
+    out
+}
+
 pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
     VirtAddr::new(phys.as_u64() + phys_mem_offset())
 }
@@ -108,3 +171,38 @@ pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
 pub fn virt_to_phys(virt: VirtAddr) -> Option<PhysAddr> {
     mapper().translate_addr(virt)
 }
+
+#[test_case]
+fn iomem_lists_allocator_regions() {
+    let regions = regions();
+    let usable: Vec<_> = regions.iter().filter(|r| r.kind == MemoryRegionType::Usable).collect();
+    assert!(!usable.is_empty());
+
+    // Every frame the allocator owns lies in a usable region
+    let owned: usize = regions.iter().map(|r| r.frames).sum();
+    assert_eq!(owned, with_frame_allocator(|a| a.total_frames()));
+    assert!(regions.iter().all(|r| r.kind == MemoryRegionType::Usable || r.frames == 0));
+
+    let text = iomem();
+    for r in usable {
+        assert!(r.frames as u64 <= (r.end - r.start) / 4096);
+        assert!(text.contains(&alloc::format!("{:016x}-{:016x} : Usable", r.start, r.end - 1)));
+    }
+}
+
+#[test_case]
+fn frame_stats_follow_allocations() {
+    use x86_64::structures::paging::{FrameAllocator, FrameDeallocator};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let (used, free) = frame_stats();
+        let before = used_memory();
+        let frame = with_frame_allocator(|fa| fa.allocate_frame()).unwrap();
+        assert_eq!(frame_stats(), (used + 1, free - 1));
+        assert_eq!(used_memory(), before + 4096);
+
+        with_frame_allocator(|fa| unsafe { fa.deallocate_frame(frame) });
+        assert_eq!(frame_stats(), (used, free));
+        assert_eq!(used_memory(), before);
+    });
+}
@@ -1,14 +1,16 @@
 //! Paging — x86_64 page table management
 //!
 //! Provides functions to map/unmap virtual pages to physical frames.
+//! Large regions can use 2 MiB pages, which need fewer page table
+//! frames and TLB entries than 512 separate 4 KiB pages.
 
 use super::with_frame_allocator;
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::{
     FrameAllocator, FrameDeallocator,
-    Mapper, OffsetPageTable, Page, PageTable,
-    PageTableFlags, PhysFrame, Size4KiB,
-    mapper::CleanUp,
+    Mapper, OffsetPageTable, Page, PageSize, PageTable,
+    PageTableFlags, PhysFrame, Size2MiB, Size4KiB, Translate,
+    mapper::{CleanUp, MappedFrame, TranslateResult},
 };
 use x86_64::VirtAddr;
 
@@ -39,34 +41,163 @@ pub fn map_page(mapper: &mut OffsetPageTable, addr: u64, count: usize) -> Result
     let end   = Page::containing_address(VirtAddr::new(addr + count));
     let range = Page::range_inclusive(start, end);
 
-    with_frame_allocator(|fa| {
+    let mut mapped = 0usize;
+    let result = with_frame_allocator(|fa| {
         for page in range {
             let frame = fa.allocate_frame().ok_or(())?;
             let result = unsafe { mapper.map_to(page, frame, USER_FLAGS, fa) };
             match result {
                 Ok(flush) => flush.flush(),
-                Err(_) => return Err(()),
+                Err(_) => {
+                    unsafe { fa.deallocate_frame(frame); }
+                    return Err(());
+                }
             }
+            mapped += 1;
         }
         Ok(())
+    });
+
+    // Out of frames halfway through: roll back the pages this call already
+    // mapped so a failed request never leaves a partial mapping behind
+    if result.is_err() && mapped > 0 {
+        unmap_page(mapper, start.start_address().as_u64(), mapped * 4096);
+    }
+    result
+}
+
+/// Like `map_page`, but 2 MiB-aligned stretches of at least 2 MiB get
+/// huge pages. The edges, and any stretch no free 2 MiB frame is left
+/// for, get 4 KiB pages.
+pub(crate) fn map_huge_page(mapper: &mut OffsetPageTable, addr: u64, count: usize) -> Result<(), ()> {
+    let start = addr & !(Size4KiB::SIZE - 1);
+    let end   = ((addr + count.saturating_sub(1) as u64) & !(Size4KiB::SIZE - 1)) + Size4KiB::SIZE;
+
+    let mut cur = start;
+    while cur < end {
+        let huge = cur.is_multiple_of(Size2MiB::SIZE) && end - cur >= Size2MiB::SIZE;
+        if huge && map_2mib(mapper, cur).is_ok() {
+            cur += Size2MiB::SIZE;
+        } else if map_page(mapper, cur, Size4KiB::SIZE as usize).is_ok() {
+            cur += Size4KiB::SIZE;
+        } else {
+            // Same all-or-nothing rule as map_page
+            if cur > start {
+                unmap_page(mapper, start, (cur - start) as usize);
+            }
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+/// Map one 2 MiB page at the aligned address `addr`
+fn map_2mib(mapper: &mut OffsetPageTable, addr: u64) -> Result<(), ()> {
+    let page = Page::<Size2MiB>::from_start_address(VirtAddr::new(addr)).map_err(|_| ())?;
+    with_frame_allocator(|fa| {
+        let frame = fa.allocate_2mib_frame().ok_or(())?;
+        match unsafe { mapper.map_to(page, frame, USER_FLAGS, fa) } {
+            Ok(flush) => {
+                flush.flush();
+                Ok(())
+            }
+            Err(_) => {
+                unsafe { fa.deallocate_2mib_frame(frame); }
+                Err(())
+            }
+        }
     })
 }
 
-/// Unmap and free pages in the given range
+/// Unmap and free pages in the given range. A 2 MiB page goes only if
+/// the range covers all of it, and a frame shared copy-on-write is only
+/// freed with its last mapping. The TLB is flushed once for the whole
+/// range, by `tlb::shootdown`.
 pub fn unmap_page(mapper: &mut OffsetPageTable, addr: u64, size: usize) {
-    let size = size.saturating_sub(1) as u64;
-    let start = Page::containing_address(VirtAddr::new(addr));
-    let end   = Page::containing_address(VirtAddr::new(addr + size));
-
-    for page in Page::<Size4KiB>::range_inclusive(start, end) {
-        if let Ok((frame, flush)) = mapper.unmap(page) {
-            flush.flush();
-            unsafe {
-                with_frame_allocator(|fa| {
-                    mapper.clean_up(fa);
-                    fa.deallocate_frame(frame);
-                });
+    let start = addr & !(Size4KiB::SIZE - 1);
+    let end   = ((addr + size.saturating_sub(1) as u64) & !(Size4KiB::SIZE - 1)) + Size4KiB::SIZE;
+
+    let mut unmapped = false;
+    let mut cur = start;
+    while cur < end {
+        let virt = VirtAddr::new(cur);
+        match mapper.translate(virt) {
+            TranslateResult::Mapped { frame: MappedFrame::Size2MiB(_), .. } => {
+                let page = Page::<Size2MiB>::containing_address(virt);
+                let huge_start = page.start_address().as_u64();
+                if huge_start >= start && huge_start + Size2MiB::SIZE <= end {
+                    if let Ok((frame, flush)) = mapper.unmap(page) {
+                        flush.ignore();
+                        unmapped = true;
+                        let last = super::cow::release(frame.start_address());
+                        unsafe {
+                            with_frame_allocator(|fa| {
+                                mapper.clean_up(fa);
+                                if last {
+                                    fa.deallocate_2mib_frame(frame);
+                                }
+                            });
+                        }
+                    }
+                }
+                cur = huge_start + Size2MiB::SIZE;
+            }
+            _ => {
+                let page = Page::<Size4KiB>::containing_address(virt);
+                if let Ok((frame, flush)) = mapper.unmap(page) {
+                    flush.ignore();
+                    unmapped = true;
+                    let last = super::cow::release(frame.start_address());
+                    unsafe {
+                        with_frame_allocator(|fa| {
+                            mapper.clean_up(fa);
+                            if last {
+                                fa.deallocate_frame(frame);
+                            }
+                        });
+                    }
+                }
+                cur += Size4KiB::SIZE;
             }
         }
     }
+    if unmapped {
+        super::tlb::shootdown(start..end);
+    }
+}
+
+#[test_case]
+fn huge_pages_cover_the_aligned_middle() {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    // Well away from the kernel heap and the process slots
+    let base = 0x5555_0000_0000u64;
+    let addr = base - Size4KiB::SIZE;
+    let size = (Size2MiB::SIZE + 2 * Size4KiB::SIZE) as usize;
+    let mapper = super::mapper();
+
+    without_interrupts(|| {
+        let (used, _) = super::frame_stats();
+        map_huge_page(mapper, addr, size).unwrap();
+        let kind = |mapper: &OffsetPageTable, a: u64| match mapper.translate(VirtAddr::new(a)) {
+            TranslateResult::Mapped { frame: MappedFrame::Size2MiB(_), .. } => 2,
+            TranslateResult::Mapped { .. } => 1,
+            _ => 0,
+        };
+        let kinds = |mapper: &OffsetPageTable| {
+            [addr, base, base + 12345, base + Size2MiB::SIZE].map(|a| kind(mapper, a))
+        };
+        assert_eq!(kinds(mapper), [1, 2, 2, 1]);
+        assert!(super::frame_stats().0 >= used + 512 + 2);
+
+        unsafe {
+            (base as *mut u64).write_volatile(42);
+            ((base + Size2MiB::SIZE - 8) as *mut u64).write_volatile(7);
+            assert_eq!((base as *mut u64).read_volatile(), 42);
+        }
+
+        unmap_page(mapper, addr, size);
+        assert_eq!(kinds(mapper), [0; 4]);
+        assert!(super::frame_stats().0 <= used);
+    });
 }
@@ -0,0 +1,82 @@
+//! TLB shootdown
+//!
+//! Every change that takes a mapping away goes through `shootdown`, so
+//! stale translations are invalidated in one place. Only the boot CPU
+//! runs for now and a shootdown is a local `invlpg` per page; once other
+//! cores can share an address space, this is where they get an IPI to
+//! invalidate the same range, and `shootdown` waits for their acks.
+//!
+//! Page tables that are not loaded are not covered: with PCIDs their
+//! tags are dropped when they are freed (`sched::forget_address_space`).
+
+use core::ops::Range;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::Cr3;
+use x86_64::VirtAddr;
+
+/// Past this many pages one CR3 reload is cheaper than an `invlpg` each
+const FULL_FLUSH_PAGES: u64 = 64;
+
+/// Invalidate the translations of the virtual addresses in `pages` in
+/// the active address space
+pub fn shootdown(pages: Range<u64>) {
+    if pages.is_empty() {
+        return;
+    }
+    flush_local(&pages);
+    // Remote CPUs go here: send each an IPI carrying `pages`, then wait
+    // until all of them have flushed
+}
+
+fn flush_local(pages: &Range<u64>) {
+    let start = pages.start & !0xFFF;
+    let count = (pages.end - start).div_ceil(4096);
+    if count > FULL_FLUSH_PAGES {
+        // Rewriting CR3 as is (PCID included, no-flush bit clear) drops
+        // the non-global entries of the current address space
+        let (frame, low) = Cr3::read_raw();
+        unsafe { Cr3::write_raw(frame, low); }
+    } else {
+        for i in 0..count {
+            tlb::flush(VirtAddr::new(start + i * 4096));
+        }
+    }
+}
+
+#[test_case]
+fn shootdown_drops_stale_translations() {
+    use super::{mapper, phys_to_virt, with_frame_allocator};
+    use x86_64::instructions::interrupts::without_interrupts;
+    use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, Size4KiB};
+
+    // Away from the kernel heap, the process slots and the huge page test
+    let addr = 0x5556_0000_0000u64;
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let mapper = mapper();
+
+    without_interrupts(|| {
+        let (a, b) = with_frame_allocator(|fa| (fa.allocate_frame(), fa.allocate_frame()));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        unsafe {
+            phys_to_virt(b.start_address()).as_mut_ptr::<u64>().write_volatile(0xBB);
+            with_frame_allocator(|fa| mapper.map_to(page, a, flags, fa)).unwrap().flush();
+            (addr as *mut u64).write_volatile(0xAA);
+
+            // Point the page at another frame behind the TLB's back; the
+            // old translation is cached until the shootdown drops it
+            mapper.unmap(page).unwrap().1.ignore();
+            with_frame_allocator(|fa| mapper.map_to(page, b, flags, fa)).unwrap().ignore();
+            shootdown(addr..addr + 4096);
+            assert_eq!((addr as *const u64).read_volatile(), 0xBB);
+
+            mapper.unmap(page).unwrap().1.ignore();
+            shootdown(addr..addr + 4096);
+            with_frame_allocator(|fa| {
+                fa.deallocate_frame(a);
+                fa.deallocate_frame(b);
+            });
+        }
+        assert!(mapper.translate_page(page).is_err());
+    });
+}
@@ -0,0 +1,272 @@
+//! Memory-mapped files (MMAP_FILE, MSYNC, MUNMAP)
+//!
+//! Mapping a file copies its contents into fresh pages of the process,
+//! placed top down in the room between the heap break and the stack
+//! guard page, so BRK and the mappings grow towards each other. Reads
+//! and writes are plain memory accesses; the file only sees the changes
+//! when they are written back, by MSYNC, MUNMAP or the process exiting.
+//! Only pages the CPU marked dirty since the last write back are copied
+//! into the VFS, at their offset in the file.
+
+use crate::sys;
+use crate::sys::fs::{IoError, Resource, O_RDONLY};
+use crate::sys::mem::{phys_mem_offset, phys_to_virt, tlb};
+use crate::sys::process::{current_pid, MemoryLayout, Region, PROC_TABLE};
+use crate::sys::syscall::error::{EACCES, EBADF, EINVAL, EIO, ENOMEM};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::ops::Range;
+use x86_64::structures::paging::{
+    Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Size4KiB, Translate,
+    mapper::TranslateResult,
+};
+use x86_64::VirtAddr;
+
+/// MMAP_FILE protection bits
+pub const PROT_READ:  usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+
+#[derive(Clone, Debug)]
+pub struct FileMapping {
+    /// Whole pages the file was copied into
+    pub region:   Region,
+    /// Bytes of the file mapped, from its start
+    pub len:      usize,
+    /// VFS path changes are written back to
+    pub path:     String,
+    /// Mapped with PROT_WRITE; read-only mappings are never written back
+    pub writable: bool,
+}
+
+fn mapper_of(pt_frame: PhysFrame) -> OffsetPageTable<'static> {
+    let pt = unsafe { sys::mem::create_page_table_from_frame(pt_frame) };
+    unsafe { OffsetPageTable::new(pt, VirtAddr::new(phys_mem_offset())) }
+}
+
+/// The kernel's view of the page mapped at `addr`
+fn page_at(mapper: &OffsetPageTable, addr: u64) -> &'static mut [u8; 4096] {
+    let phys = mapper.translate_addr(VirtAddr::new(addr)).expect("mapped file page");
+    unsafe { &mut *phys_to_virt(phys).as_mut_ptr() }
+}
+
+/// Was the page at `addr` written since the last call? Clears the
+/// CPU's dirty bit, so the next write sets it again.
+fn take_dirty(mapper: &mut OffsetPageTable, addr: u64) -> bool {
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+    let flags = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { flags, .. } if flags.contains(PageTableFlags::DIRTY) => flags,
+        _ => return false,
+    };
+    if unsafe { mapper.update_flags(page, flags - PageTableFlags::DIRTY) }.is_err() {
+        return false;
+    }
+    // A cached translation would keep the CPU from setting it again
+    tlb::shootdown(addr..addr + 4096);
+    true
+}
+
+/// Lowest address the mappings of a process use, the most BRK can
+/// grow the heap to
+pub fn floor(layout: &MemoryLayout, mappings: &[FileMapping]) -> u64 {
+    mappings.iter().map(|m| m.region.start).min().unwrap_or(layout.guard.start)
+}
+
+/// Start of the highest free stretch of `size` bytes between the heap
+/// break and the guard page, if there is one
+fn place(layout: &MemoryLayout, mappings: &[FileMapping], size: u64) -> Option<u64> {
+    let mut taken: Vec<Region> = mappings.iter().map(|m| m.region).collect();
+    taken.sort_by_key(|r| Reverse(r.start));
+    let mut end = layout.guard.start;
+    for r in taken {
+        if end - r.end >= size {
+            break;
+        }
+        end = r.start;
+    }
+    end.checked_sub(size).filter(|&start| start >= layout.heap.end)
+}
+
+/// Map the first `len` bytes of the file open as `handle` into the
+/// current process and return their address
+pub fn map(handle: usize, len: usize, prot: usize) -> Result<u64, isize> {
+    let file = match sys::process::get_handle(handle).map(|r| *r) {
+        Some(Resource::File(file)) => file,
+        _ => return Err(EBADF),
+    };
+    // Only files with a VFS path have somewhere to write back to
+    let path = file.path().ok_or(EINVAL)?.to_string();
+    if len == 0 || len > file.size() || prot & !(PROT_READ | PROT_WRITE) != 0 {
+        return Err(EINVAL);
+    }
+    // What is written to the mapping goes back into the file
+    if prot & PROT_WRITE != 0 && file.flags() & O_RDONLY != 0 {
+        return Err(EACCES);
+    }
+    let size = (len as u64).next_multiple_of(4096);
+
+    let (pt_frame, layout, mappings) = {
+        let table = PROC_TABLE.read();
+        let proc  = &table[current_pid()];
+        (proc.pt_frame, proc.layout, proc.mappings.clone())
+    };
+    if layout.heap.is_empty() {
+        return Err(EINVAL); // Kernel-resident, there is no window to map into
+    }
+    let start = place(&layout, &mappings, size).ok_or(ENOMEM)?;
+
+    let mut mapper = mapper_of(pt_frame);
+    sys::mem::map_page(&mut mapper, start, size as usize).map_err(|_| ENOMEM)?;
+    // Filled through the kernel's view, so the pages start out clean
+    for (i, chunk) in file.data()[..len].chunks(4096).enumerate() {
+        let page = page_at(&mapper, start + i as u64 * 4096);
+        page[..chunk.len()].copy_from_slice(chunk);
+        page[chunk.len()..].fill(0);
+    }
+    let writable = prot & PROT_WRITE != 0;
+    if !writable {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        for addr in (start..start + size).step_by(4096) {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+            unsafe { mapper.update_flags(page, flags) }.map(|f| f.ignore()).ok();
+        }
+        tlb::shootdown(start..start + size);
+    }
+
+    let region = Region { start, end: start + size };
+    PROC_TABLE.write()[current_pid()].mappings.push(FileMapping { region, len, path, writable });
+    Ok(start)
+}
+
+/// Copy the dirty pages of `m` in `pages` back into its file
+fn write_back(mapper: &mut OffsetPageTable, m: &FileMapping, pages: Range<u64>) -> Result<(), IoError> {
+    if !m.writable {
+        return Ok(());
+    }
+    let start = pages.start & !0xFFF;
+    let mut chunks = Vec::new();
+    for addr in (start..pages.end).step_by(4096) {
+        if take_dirty(mapper, addr) {
+            let offset = (addr - m.region.start) as usize;
+            let n = (m.len - offset).min(4096);
+            chunks.push((offset, &page_at(mapper, addr)[..n]));
+        }
+    }
+    if chunks.is_empty() {
+        return Ok(());
+    }
+    sys::fs::patch_file(&m.path, &chunks)
+}
+
+/// The mapping of the current process that holds `addr`, and the page
+/// table it is mapped in
+fn find(addr: u64) -> Option<(usize, FileMapping, PhysFrame)> {
+    let table = PROC_TABLE.read();
+    let proc  = &table[current_pid()];
+    let i = proc.mappings.iter().position(|m| m.region.contains(addr))?;
+    Some((i, proc.mappings[i].clone(), proc.pt_frame))
+}
+
+/// Write the changes to `len` bytes at `addr` back to the file they
+/// were mapped from. The range must lie within one mapping.
+pub fn msync(addr: u64, len: usize) -> Result<(), isize> {
+    let (_, m, pt_frame) = find(addr).ok_or(EINVAL)?;
+    let end = addr.checked_add(len as u64).filter(|&end| end <= m.region.end).ok_or(EINVAL)?;
+    write_back(&mut mapper_of(pt_frame), &m, addr..end).map_err(|_| EIO)
+}
+
+/// Write the mapping starting at `addr` back and take its pages away
+pub fn unmap(addr: u64) -> Result<(), isize> {
+    let (i, m, pt_frame) = find(addr).filter(|(_, m, _)| m.region.start == addr).ok_or(EINVAL)?;
+    let mut mapper = mapper_of(pt_frame);
+    let synced = write_back(&mut mapper, &m, m.region.start..m.region.end);
+    sys::mem::unmap_page(&mut mapper, m.region.start, m.region.len() as usize);
+    PROC_TABLE.write()[current_pid()].mappings.remove(i);
+    synced.map_err(|_| EIO)
+}
+
+/// Write back every mapping of the current process before it exits.
+/// The pages go with the rest of its address space.
+pub fn write_back_all() {
+    let (mappings, pt_frame) = {
+        let mut table = PROC_TABLE.write();
+        let proc = &mut table[current_pid()];
+        (core::mem::take(&mut proc.mappings), proc.pt_frame)
+    };
+    let mut mapper = mapper_of(pt_frame);
+    for m in &mappings {
+        if write_back(&mut mapper, m, m.region.start..m.region.end).is_err() {
+            kwarn!("mmap: could not write back {}", m.path);
+        }
+    }
+}
+
+#[test_case]
+fn mapped_file_changes_reach_the_file_on_msync() {
+    use crate::sys::process::FakeProcess;
+    use crate::sys::syscall::service;
+
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    let path = "/tmp/mmap-test";
+    let text = b"hello, mapped world";
+    // Away from the kernel heap, the process slots and the other tests
+    let base = 0x5559_0000_0000u64;
+    sys::fs::write_file(path, text).unwrap();
+
+    without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+        {
+            let mut table = PROC_TABLE.write();
+            table[2].layout.heap  = Region { start: base, end: base + 4096 };
+            table[2].layout.guard = Region { start: base + 4 * 4096, end: base + 5 * 4096 };
+        }
+        let open = || sys::process::alloc_handle(sys::fs::open_resource(path, 0).unwrap()).unwrap();
+
+        // Placed right under the guard page, and BRK stops below it
+        let handle = open();
+        let addr = map(handle, text.len(), PROT_READ | PROT_WRITE).unwrap();
+        assert_eq!(addr, base + 3 * 4096);
+        assert_eq!(floor(&PROC_TABLE.read()[2].layout, &PROC_TABLE.read()[2].mappings), addr);
+        assert_eq!(map(handle, text.len() + 1, PROT_READ), Err(EINVAL));
+
+        let bytes = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, text.len()) };
+        assert_eq!(bytes, text);
+        bytes[..5].copy_from_slice(b"HELLO");
+
+        // The file sees the change once it is synced, through a normal read
+        let mut buf = [0u8; 32];
+        assert_eq!(service::read(open(), &mut buf), text.len() as isize);
+        assert_eq!(&buf[..text.len()], text);
+        assert_eq!(msync(addr, text.len()), Ok(()));
+        assert_eq!(service::read(open(), &mut buf), text.len() as isize);
+        assert_eq!(&buf[..text.len()], b"HELLO, mapped world");
+
+        // A clean page is not written again, so a change made through
+        // the file in the meantime survives
+        sys::fs::write_file(path, b"hello, mapped again").unwrap();
+        assert_eq!(unmap(addr), Ok(()));
+        assert_eq!(sys::fs::open_file(path).unwrap().data(), b"hello, mapped again");
+        assert!(PROC_TABLE.read()[2].mappings.is_empty());
+        assert_eq!(unmap(addr), Err(EINVAL));
+    });
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn read_only_handle_cannot_be_mapped_writable() {
+    use crate::sys::process::FakeProcess;
+
+    let path = "/tmp/mmap-ro-test";
+    sys::fs::write_file(path, b"read only").unwrap();
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+        let res = sys::fs::open_resource(path, O_RDONLY).unwrap();
+        let handle = sys::process::alloc_handle(res).unwrap();
+        assert_eq!(map(handle, 4, PROT_READ | PROT_WRITE), Err(EACCES));
+    });
+    assert_eq!(sys::fs::open_file(path).unwrap().data(), b"read only");
+    sys::fs::remove(path).ok();
+}
@@ -1,18 +1,32 @@
 pub mod acpi;
+pub mod audit;
+pub mod bcache;
+pub mod bootguard;
 pub mod clk;
+pub mod cmdline;
+pub mod compress;
 pub mod console;
 pub mod cpu;
 pub mod fs;
 pub mod gdt;
+pub mod hostname;
 pub mod idt;
 pub mod ipc;
 pub mod keyboard;
+pub mod log;
 pub mod mem;
+pub mod mmap;
+pub mod net;
+pub mod path;
 pub mod pci;
 pub mod pic;
+pub mod pollset;
+pub mod power;
 pub mod process;
 pub mod sched;
 pub mod serial;
+pub mod signal;
 pub mod syscall;
 pub mod vga;
 pub mod virtio;
+pub mod workq;
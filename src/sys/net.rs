@@ -0,0 +1,273 @@
+//! Network stack for Chilena — loopback UDP only
+//!
+//! There is no NIC driver yet. The only interface is loopback: a datagram
+//! sent to 127.0.0.0/8 is queued straight onto the receive queue of the
+//! socket bound to the destination port. Sockets are ordinary handles
+//! (`Resource::Socket`), so read/write/poll/close work on them as on any
+//! file; the socket syscalls add binding and addressing on top.
+
+use crate::sys::fs::{FileIO, PollEvent};
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+// ---------------------------------------------------------------------------
+// Addresses
+// ---------------------------------------------------------------------------
+
+pub const LOOPBACK: [u8; 4] = [127, 0, 0, 1];
+pub const ANY:      [u8; 4] = [0, 0, 0, 0];
+
+/// Ports below this need CAP_NET to bind
+pub const PRIVILEGED_PORTS: u16 = 1024;
+
+/// Ports handed out to sockets that send before binding
+const EPHEMERAL: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+/// Datagrams kept per port before new ones are dropped
+const QUEUE_LIMIT: usize = 64;
+/// Largest datagram accepted
+pub const MAX_DATAGRAM: usize = 1472;
+
+/// IPv4 address and port, shared with userspace
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct SocketAddr {
+    pub ip:   [u8; 4],
+    pub port: u16,
+}
+
+impl SocketAddr {
+    pub const fn new(ip: [u8; 4], port: u16) -> Self { Self { ip, port } }
+    pub const fn loopback(port: u16) -> Self { Self::new(LOOPBACK, port) }
+
+    fn is_loopback(&self) -> bool { self.ip[0] == 127 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetError {
+    /// Port already bound by another socket
+    AddrInUse,
+    /// Destination is not on an interface we have (anything but loopback)
+    Unreachable,
+    /// Nothing is bound to the destination port
+    Refused,
+    /// Socket has no peer, or no local port to receive on
+    NotConnected,
+    /// Datagram larger than MAX_DATAGRAM
+    TooBig,
+}
+
+// ---------------------------------------------------------------------------
+// Loopback interface
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+struct Datagram {
+    from: SocketAddr,
+    data: Vec<u8>,
+}
+
+/// Receive queue of every bound port
+static PORTS: Mutex<BTreeMap<u16, VecDeque<Datagram>>> = Mutex::new(BTreeMap::new());
+
+fn claim(port: u16) -> Result<u16, NetError> {
+    interrupts::without_interrupts(|| {
+        let mut ports = PORTS.lock();
+        let port = if port == 0 {
+            EPHEMERAL.clone().find(|p| !ports.contains_key(p)).ok_or(NetError::AddrInUse)?
+        } else {
+            port
+        };
+        if ports.contains_key(&port) {
+            return Err(NetError::AddrInUse);
+        }
+        ports.insert(port, VecDeque::new());
+        Ok(port)
+    })
+}
+
+fn release(port: u16) {
+    interrupts::without_interrupts(|| PORTS.lock().remove(&port));
+}
+
+/// Deliver a datagram over loopback. Like UDP, a full queue drops it silently.
+fn deliver(from: SocketAddr, to: SocketAddr, data: &[u8]) -> Result<(), NetError> {
+    if !to.is_loopback() {
+        return Err(NetError::Unreachable);
+    }
+    if data.len() > MAX_DATAGRAM {
+        return Err(NetError::TooBig);
+    }
+    interrupts::without_interrupts(|| {
+        let mut ports = PORTS.lock();
+        let queue = ports.get_mut(&to.port).ok_or(NetError::Refused)?;
+        if queue.len() < QUEUE_LIMIT {
+            queue.push_back(Datagram { from, data: data.to_vec() });
+        }
+        Ok(())
+    })
+}
+
+fn take(port: u16) -> Option<Datagram> {
+    interrupts::without_interrupts(|| PORTS.lock().get_mut(&port)?.pop_front())
+}
+
+fn has_datagram(port: u16) -> bool {
+    interrupts::without_interrupts(|| {
+        PORTS.lock().get(&port).is_some_and(|q| !q.is_empty())
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Socket
+// ---------------------------------------------------------------------------
+
+/// A claimed port, given back when the last handle holding it goes
+#[derive(Debug)]
+struct Binding(SocketAddr);
+
+impl Drop for Binding {
+    fn drop(&mut self) {
+        release(self.0.port);
+    }
+}
+
+/// UDP socket. Copies made by FORK, SPAWN or DUP share its port, which
+/// stays claimed until the last of them is closed.
+#[derive(Clone, Debug, Default)]
+pub struct Socket {
+    local: Option<Arc<Binding>>,
+    peer:  Option<SocketAddr>,
+}
+
+impl Socket {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> { self.local.as_ref().map(|b| b.0) }
+
+    /// Claim `addr.port` (0 = any free port) on loopback or the wildcard address
+    pub fn bind(&mut self, addr: SocketAddr) -> Result<(), NetError> {
+        if self.local.is_some() {
+            return Err(NetError::AddrInUse);
+        }
+        if addr.ip != ANY && !addr.is_loopback() {
+            return Err(NetError::Unreachable);
+        }
+        let port = claim(addr.port)?;
+        self.local = Some(Arc::new(Binding(SocketAddr::new(LOOPBACK, port))));
+        Ok(())
+    }
+
+    /// Set the default destination for `send`, binding a port if needed
+    pub fn connect(&mut self, addr: SocketAddr) -> Result<(), NetError> {
+        if !addr.is_loopback() {
+            return Err(NetError::Unreachable);
+        }
+        self.local_or_bind()?;
+        self.peer = Some(addr);
+        Ok(())
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> Result<usize, NetError> {
+        let peer = self.peer.ok_or(NetError::NotConnected)?;
+        self.send_to(peer, data)
+    }
+
+    pub fn send_to(&mut self, to: SocketAddr, data: &[u8]) -> Result<usize, NetError> {
+        let from = self.local_or_bind()?;
+        deliver(from, to, data)?;
+        Ok(data.len())
+    }
+
+    /// Block until a datagram arrives. Bytes beyond `buf` are discarded.
+    pub fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), NetError> {
+        let port = self.local_addr().ok_or(NetError::NotConnected)?.port;
+        loop {
+            if let Some(dgram) = take(port) {
+                let n = dgram.data.len().min(buf.len());
+                buf[..n].copy_from_slice(&dgram.data[..n]);
+                return Ok((n, dgram.from));
+            }
+            interrupts::enable_and_hlt();
+        }
+    }
+
+    fn local_or_bind(&mut self) -> Result<SocketAddr, NetError> {
+        if self.local.is_none() {
+            self.bind(SocketAddr::new(ANY, 0))?;
+        }
+        Ok(self.local_addr().unwrap())
+    }
+}
+
+impl FileIO for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        self.recv_from(buf).map(|(n, _)| n).map_err(|_| ())
+    }
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        self.send(buf).map_err(|_| ())
+    }
+    fn close(&mut self) {
+        self.local = None;
+    }
+    fn poll(&mut self, e: PollEvent) -> bool {
+        match e {
+            PollEvent::Read  => self.local_addr().is_some_and(|a| has_datagram(a.port)),
+            PollEvent::Write => true,
+        }
+    }
+    fn kind(&self) -> u8 { 2 }
+}
+
+#[test_case]
+fn loopback_datagram_roundtrip() {
+    let mut server = Socket::new();
+    server.bind(SocketAddr::loopback(7000)).unwrap();
+    assert_eq!(Socket::new().bind(SocketAddr::loopback(7000)), Err(NetError::AddrInUse));
+
+    let mut client = Socket::new();
+    client.connect(SocketAddr::loopback(7000)).unwrap();
+    assert_eq!(client.send(b"ping"), Ok(4));
+    assert!(server.poll(PollEvent::Read));
+
+    let mut buf = [0u8; 16];
+    let (n, from) = server.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"ping");
+    assert_eq!(Some(from), client.local_addr());
+
+    // Replies go back to the client's ephemeral port
+    server.send_to(from, b"pong").unwrap();
+    assert_eq!(client.read(&mut buf), Ok(4));
+    assert_eq!(&buf[..4], b"pong");
+
+    // Nothing bound, and no route off the machine
+    assert_eq!(client.send_to(SocketAddr::loopback(7001), b"x"), Err(NetError::Refused));
+    assert_eq!(client.send_to(SocketAddr::new([10, 0, 0, 1], 7000), b"x"), Err(NetError::Unreachable));
+
+    server.close();
+    client.close();
+    assert_eq!(client.send_to(SocketAddr::loopback(7000), b"x"), Err(NetError::Refused));
+    client.close();
+}
+
+#[test_case]
+fn copied_socket_keeps_its_port_until_the_last_close() {
+    let mut server = Socket::new();
+    server.bind(SocketAddr::loopback(7002)).unwrap();
+    let mut copy = server.clone();
+
+    // One copy closing, as a forked child exiting would
+    copy.close();
+    assert_eq!(Socket::new().bind(SocketAddr::loopback(7002)), Err(NetError::AddrInUse));
+    Socket::new().send_to(SocketAddr::loopback(7002), b"still here").unwrap();
+    assert!(server.poll(PollEvent::Read));
+
+    server.close();
+    let mut again = Socket::new();
+    assert_eq!(again.bind(SocketAddr::loopback(7002)), Ok(()));
+    again.close();
+}
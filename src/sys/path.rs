@@ -0,0 +1,84 @@
+//! Path helpers — splitting and joining `/`-separated paths
+//!
+//! These work on the text of a path only; nothing here looks at the VFS.
+//! Trailing slashes are ignored the way POSIX `basename` and `dirname`
+//! ignore them, so `/ini/` names the same directory as `/ini`.
+
+use alloc::string::String;
+
+pub fn is_absolute(path: &str) -> bool {
+    path.starts_with('/')
+}
+
+/// Last component of `path`: `b` for `/a/b` and `/a/b/`, `/` for `/`
+pub fn basename(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return if path.is_empty() { "" } else { "/" };
+    }
+    trimmed.rsplit('/').next().unwrap_or(trimmed)
+}
+
+/// Everything before the last component: `/a` for `/a/b`, `/` for `/a`
+/// and `/`, `.` for a bare name
+pub fn dirname(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return if path.is_empty() { "." } else { "/" };
+    }
+    match trimmed.rfind('/') {
+        None => ".",
+        Some(i) => match trimmed[..i].trim_end_matches('/') {
+            "" => "/",
+            dir => dir,
+        },
+    }
+}
+
+/// `rel` looked up from `base`: `rel` itself if it is absolute,
+/// otherwise the two with one `/` between them
+pub fn join(base: &str, rel: &str) -> String {
+    if is_absolute(rel) || base.is_empty() {
+        return rel.into();
+    }
+    let base = base.trim_end_matches('/');
+    alloc::format!("{}/{}", base, rel)
+}
+
+/// What follows the last `.` of the last component, if there is a
+/// name before it: `gz` for `a.tar.gz`, none for `.profile` or `a.`
+pub fn extension(path: &str) -> Option<&str> {
+    match basename(path).rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => Some(ext),
+        _ => None,
+    }
+}
+
+#[test_case]
+fn path_parts_handle_root_slashes_and_bare_names() {
+    assert_eq!(basename("/ini/boot.sh"), "boot.sh");
+    assert_eq!(basename("/ini/"), "ini");
+    assert_eq!(basename("/"), "/");
+    assert_eq!(basename("boot.sh"), "boot.sh");
+    assert_eq!(basename(""), "");
+
+    assert_eq!(dirname("/ini/boot.sh"), "/ini");
+    assert_eq!(dirname("/ini//boot.sh/"), "/ini");
+    assert_eq!(dirname("/ini"), "/");
+    assert_eq!(dirname("/"), "/");
+    assert_eq!(dirname("boot.sh"), ".");
+    assert_eq!(dirname("a/b"), "a");
+    assert_eq!(dirname(""), ".");
+
+    assert_eq!(join("/", "tmp"), "/tmp");
+    assert_eq!(join("/ini/", "boot.sh"), "/ini/boot.sh");
+    assert_eq!(join("/ini", "/tmp/x"), "/tmp/x");
+    assert_eq!(join("", "x"), "x");
+
+    assert_eq!(extension("/tmp/a.tar.gz"), Some("gz"));
+    assert_eq!(extension("/home/.profile"), None);
+    assert_eq!(extension("/a.d/file"), None);
+    assert_eq!(extension("a."), None);
+
+    assert!(is_absolute("/ini") && !is_absolute("ini"));
+}
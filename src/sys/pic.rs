@@ -3,8 +3,10 @@
 //! Manages two chained PICs (master + slave) to handle
 //! 16 external hardware IRQs.
 
+use core::sync::atomic::{AtomicUsize, Ordering};
 use pic8259::ChainedPics;
 use spin::Mutex;
+use x86_64::instructions::port::Port;
 
 /// IRQ offset in IDT (IRQ 0-7 → vectors 32-39, IRQ 8-15 → vectors 40-47)
 pub const PIC_MASTER_OFFSET: u8 = 32;
@@ -27,3 +29,79 @@ pub fn init() {
 pub fn irq_vector(irq: u8) -> u8 {
     PIC_MASTER_OFFSET + irq
 }
+
+// ---------------------------------------------------------------------------
+// Spurious IRQ 7 / IRQ 15
+// ---------------------------------------------------------------------------
+
+const MASTER_CMD: u16 = 0x20;
+const SLAVE_CMD:  u16 = 0xA0;
+/// OCW3: next read of the command port returns the in-service register
+const OCW3_READ_ISR: u8 = 0x0B;
+const EOI: u8 = 0x20;
+
+static SPURIOUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Which PICs must be sent an end-of-interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eoi {
+    /// Real interrupt — acknowledge normally
+    All,
+    /// Spurious IRQ 15 — the master still saw IRQ 2 from the slave
+    MasterOnly,
+    /// Spurious IRQ 7 — nothing to acknowledge
+    None,
+}
+
+/// Combined in-service register (master in the low byte, slave in the high)
+pub fn read_isr() -> u16 {
+    unsafe {
+        let mut master: Port<u8> = Port::new(MASTER_CMD);
+        let mut slave:  Port<u8> = Port::new(SLAVE_CMD);
+        master.write(OCW3_READ_ISR);
+        slave.write(OCW3_READ_ISR);
+        (slave.read() as u16) << 8 | master.read() as u16
+    }
+}
+
+/// Decide how to acknowledge `irq` given the in-service register.
+/// Only IRQ 7 and IRQ 15 can be spurious: the PIC raises them when a
+/// request disappears before it is acknowledged, without setting the bit.
+pub fn eoi_for(irq: u8, isr: u16) -> Eoi {
+    if (irq != 7 && irq != 15) || isr & (1 << irq) != 0 {
+        return Eoi::All;
+    }
+    if irq == 15 { Eoi::MasterOnly } else { Eoi::None }
+}
+
+/// Acknowledge `irq` as decided by `eoi_for`, counting spurious ones
+pub fn acknowledge(irq: u8, eoi: Eoi) {
+    match eoi {
+        Eoi::All => unsafe {
+            PICS.lock().notify_end_of_interrupt(irq_vector(irq));
+        },
+        Eoi::MasterOnly => unsafe {
+            Port::<u8>::new(MASTER_CMD).write(EOI);
+        },
+        Eoi::None => {}
+    }
+    if eoi != Eoi::All {
+        SPURIOUS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Number of spurious interrupts seen since boot
+pub fn spurious_count() -> usize {
+    SPURIOUS.load(Ordering::Relaxed)
+}
+
+#[test_case]
+fn spurious_irqs_skip_eoi() {
+    assert_eq!(eoi_for(7, 0), Eoi::None);
+    assert_eq!(eoi_for(7, 1 << 7), Eoi::All);
+    assert_eq!(eoi_for(15, 1 << 2), Eoi::MasterOnly);
+    assert_eq!(eoi_for(15, 1 << 15 | 1 << 2), Eoi::All);
+    // Other lines are never treated as spurious
+    assert_eq!(eoi_for(1, 0), Eoi::All);
+    assert_eq!(eoi_for(14, 0), Eoi::All);
+}
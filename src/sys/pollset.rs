@@ -0,0 +1,89 @@
+//! Poll sets — a persistent interest list for event loops
+//!
+//! A poll set is a handle (`Resource::PollSet`) holding the handles a
+//! process wants to watch and the event it cares about for each. Members
+//! are registered once with POLLSET_ADD instead of being passed to every
+//! wait. There are no wakeup callbacks yet, so `wait` still asks each
+//! member whether it is ready, but only the registered ones.
+
+use crate::sys;
+use crate::sys::fs::{FileIO, PollEvent};
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Most handles one set can watch
+pub const MAX_MEMBERS: usize = 64;
+
+/// Wait without a time limit
+pub const WAIT_FOREVER: usize = usize::MAX;
+
+#[derive(Clone, Debug, Default)]
+pub struct PollSet {
+    interest: BTreeMap<usize, PollEvent>,
+}
+
+impl PollSet {
+    pub fn new() -> Self { Self::default() }
+
+    /// Watch `handle` for `event`, replacing any earlier interest in it
+    pub(crate) fn add(&mut self, handle: usize, event: PollEvent) -> Result<(), ()> {
+        if self.interest.len() >= MAX_MEMBERS && !self.interest.contains_key(&handle) {
+            return Err(());
+        }
+        self.interest.insert(handle, event);
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, handle: usize) -> Result<(), ()> {
+        self.interest.remove(&handle).map(|_| ()).ok_or(())
+    }
+
+    /// Members of the current process that are ready right now
+    pub fn ready(&self) -> Vec<(usize, PollEvent)> {
+        self.interest.iter()
+            .filter(|&(&handle, &event)| is_ready(handle, event))
+            .map(|(&handle, &event)| (handle, event))
+            .collect()
+    }
+
+    /// Block until at least one member is ready or `timeout_ms` passes
+    /// (WAIT_FOREVER for no limit). Returns the ready members, empty on
+    /// timeout.
+    pub fn wait(&self, timeout_ms: usize) -> Vec<(usize, PollEvent)> {
+        let start = sys::clk::uptime_ns() / 1_000_000;
+        loop {
+            let ready = self.ready();
+            if !ready.is_empty() {
+                return ready;
+            }
+            let waited = sys::clk::uptime_ns() / 1_000_000 - start;
+            if timeout_ms != WAIT_FOREVER && waited >= timeout_ms as u64 {
+                return ready;
+            }
+            x86_64::instructions::interrupts::enable_and_hlt();
+        }
+    }
+}
+
+/// Is `handle` of the current process ready for `event`? Also accepts
+/// the IPC mailbox pseudo-handle.
+pub fn is_ready(handle: usize, event: PollEvent) -> bool {
+    if handle == sys::ipc::MAILBOX {
+        // Mailbox only ever becomes readable
+        return matches!(event, PollEvent::Read)
+            && sys::ipc::has_message(sys::process::current_pid());
+    }
+    match sys::process::get_handle(handle) {
+        Some(mut res) => res.poll(event),
+        None => false,
+    }
+}
+
+impl FileIO for PollSet {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, ()> { Err(()) }
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> { Err(()) }
+    fn close(&mut self) {}
+    fn poll(&mut self, _e: PollEvent) -> bool { false }
+    fn kind(&self) -> u8 { 3 }
+}
@@ -0,0 +1,264 @@
+//! Orderly shutdown — sync data and stop jobs before power off / reset
+//!
+//! `halt` and `reboot` go through `prepare` first. Unsynced file data is
+//! always written back; running jobs block the shutdown unless it is
+//! forced, in which case they get SIGTERM, then SIGKILL if they linger,
+//! before the final sync.
+//!
+//! A reboot is cold by default: a triple fault, which resets the CPU as
+//! if the machine had been switched on again. A warm reset instead asks
+//! the BIOS to skip the memory test and most of POST, and pulls the reset
+//! line through the 8042 keyboard controller; firmware that ignores it
+//! still falls through to the triple fault.
+//!
+//! After a kernel panic the machine halts by default, which keeps the
+//! state on screen for debugging. `panic=reboot[=secs]` resets it
+//! instead once the delay is over, so an unattended system comes back.
+
+use crate::sys;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+/// How long jobs get to handle SIGTERM, in 50 ms steps
+const TERM_WAIT_STEPS: usize = 10;
+
+/// CMOS shutdown code: resume through the 40:67 vector without EOI
+const SHUTDOWN_JMP_467: u8 = 0x0A;
+/// BIOS data area: far pointer used by shutdown code 0x0A
+const BDA_RESUME_VECTOR: u64 = 0x467;
+/// BIOS data area: 0x1234 here tells POST this is a warm boot
+const BDA_RESET_FLAG: u64 = 0x472;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetKind {
+    /// Triple fault: a full reset, like power cycling
+    Cold,
+    /// BIOS warm boot through the 8042, with the triple fault as fallback
+    Warm,
+}
+
+/// What stands in the way of a clean shutdown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pending {
+    /// Open handles with writes not yet synced
+    pub dirty: usize,
+    /// Live user processes
+    pub jobs:  usize,
+}
+
+pub fn pending() -> Pending {
+    Pending {
+        dirty: sys::process::dirty_handles(),
+        jobs:  sys::process::user_pids().len(),
+    }
+}
+
+/// Get the system ready to go down. Refuses (returning what is still
+/// running) if there are jobs and `force` is not set.
+pub fn prepare(force: bool) -> Result<(), Pending> {
+    let p = pending();
+    if p.jobs > 0 && !force {
+        return Err(p);
+    }
+    if p.dirty > 0 {
+        klog!("shutdown: syncing {} open file(s)", p.dirty);
+    }
+    // Flush first so jobs that are stopped below lose nothing
+    sys::process::sync_all();
+    stop_jobs();
+    sys::process::sync_all();
+    Ok(())
+}
+
+/// Ask every job to exit with SIGTERM, give them a moment, then kill
+/// whatever is left
+fn stop_jobs() {
+    let pids = sys::process::user_pids();
+    if pids.is_empty() {
+        return;
+    }
+    for &pid in &pids {
+        klog!("shutdown: sending SIGTERM to pid {}", pid);
+        sys::signal::send(pid, sys::signal::SIGTERM).ok();
+    }
+    for _ in 0..TERM_WAIT_STEPS {
+        if sys::process::user_pids().is_empty() {
+            return;
+        }
+        sys::clk::sleep(0.05);
+    }
+    for pid in sys::process::user_pids() {
+        kwarn!("shutdown: killing pid {}", pid);
+        sys::process::kill(pid).ok();
+    }
+}
+
+pub fn power_off(force: bool) -> Result<(), Pending> {
+    prepare(force)?;
+    sys::acpi::power_off();
+}
+
+pub fn reboot(force: bool, kind: ResetKind) -> Result<(), Pending> {
+    prepare(force)?;
+    reset(kind);
+}
+
+/// Reset the machine now, without preparing for it
+pub fn reset(kind: ResetKind) -> ! {
+    if kind == ResetKind::Warm {
+        warm_reset();
+    }
+    sys::idt::trigger_reset();
+}
+
+fn warm_reset() {
+    let bda = |addr| sys::mem::phys_to_virt(PhysAddr::new(addr)).as_mut_ptr::<u16>();
+    unsafe {
+        // Resume at the BIOS entry point F000:FFF0, skipping the memory test
+        bda(BDA_RESUME_VECTOR).write_volatile(0xFFF0);
+        bda(BDA_RESUME_VECTOR + 2).write_volatile(0xF000);
+        bda(BDA_RESET_FLAG).write_volatile(0x1234);
+    }
+    sys::clk::set_shutdown_status(SHUTDOWN_JMP_467);
+
+    let mut status: Port<u8> = Port::new(0x64);
+    unsafe {
+        // Wait for the controller's input buffer to drain, then pulse reset
+        for _ in 0..0x10000 {
+            if status.read() & 0x02 == 0 {
+                break;
+            }
+        }
+        status.write(0xFE);
+    }
+    // Give the controller a moment before falling back
+    for _ in 0..0x100000 {
+        core::hint::spin_loop();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Panic policy
+// ---------------------------------------------------------------------------
+
+/// Seconds a `panic=reboot` without a delay waits before the reset
+const PANIC_REBOOT_DELAY: u64 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PanicPolicy {
+    /// Stop the CPU and leave the panic on screen
+    Halt,
+    /// Cold reset after this many seconds
+    Reboot(u64),
+}
+
+impl PanicPolicy {
+    /// `halt`, `reboot` or `reboot=<secs>`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once('=') {
+            None if s == "halt"   => Some(Self::Halt),
+            None if s == "reboot" => Some(Self::Reboot(PANIC_REBOOT_DELAY)),
+            Some(("reboot", secs)) => secs.parse().ok().map(Self::Reboot),
+            _ => None,
+        }
+    }
+}
+
+/// u64::MAX for halt, otherwise the reboot delay in seconds
+static PANIC_POLICY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+pub fn set_panic_policy(policy: PanicPolicy) {
+    let value = match policy {
+        PanicPolicy::Halt => u64::MAX,
+        PanicPolicy::Reboot(secs) => secs.min(u64::MAX - 1),
+    };
+    PANIC_POLICY.store(value, Ordering::SeqCst);
+}
+
+pub fn panic_policy() -> PanicPolicy {
+    match PANIC_POLICY.load(Ordering::SeqCst) {
+        u64::MAX => PanicPolicy::Halt,
+        secs => PanicPolicy::Reboot(secs),
+    }
+}
+
+/// The panic handler. Nothing here waits for a lock or needs interrupts:
+/// the panic may have hit with either in a bad state, so the report goes
+/// straight to the serial port rather than through the log, and to the
+/// screen only if nobody holds it.
+pub fn on_panic(info: &PanicInfo) -> ! {
+    match info.location() {
+        Some(loc) => report(format_args!(
+            "\x1b[31mError:\x1b[0m PANIC at {}:{}:{}: {}\n",
+            loc.file(), loc.line(), loc.column(), info.message()
+        )),
+        None => report(format_args!("\x1b[31mError:\x1b[0m PANIC: {}\n", info.message())),
+    }
+    match panic_policy() {
+        PanicPolicy::Halt => crate::hlt_loop(),
+        PanicPolicy::Reboot(secs) => {
+            report(format_args!("Rebooting in {} s\n", secs));
+            wait_seconds(secs);
+            sys::idt::trigger_reset();
+        }
+    }
+}
+
+/// Print from the panic handler without waiting on the console locks
+fn report(args: fmt::Arguments) {
+    sys::serial::write_fmt_unlocked(args);
+    if let Some(mut vga) = sys::vga::WRITER.try_lock() {
+        vga.write_fmt(args).ok();
+    }
+}
+
+/// Busy-wait `secs` seconds on the RTC, with interrupts off
+fn wait_seconds(secs: u64) {
+    x86_64::instructions::interrupts::disable();
+    let mut last = sys::clk::rtc_seconds();
+    for _ in 0..secs {
+        loop {
+            let now = sys::clk::rtc_seconds();
+            if now != last {
+                last = now;
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+#[test_case]
+fn halt_syncs_dirty_files() {
+    let path = "/tmp/halt-test";
+    sys::fs::write_file(path, b"a").ok();
+
+    let mut res = sys::fs::open_resource(path, sys::fs::O_APPEND).unwrap();
+    res.write(b"bc").unwrap();
+    let h = sys::process::alloc_handle(res).unwrap();
+    assert!(pending().dirty >= 1);
+
+    assert_eq!(prepare(false), Ok(()));
+    assert_eq!(sys::fs::open_file(path).unwrap().data(), b"abc");
+    assert_eq!(pending().dirty, 0);
+
+    sys::process::free_handle(h);
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn panic_policy_comes_from_the_cmdline() {
+    assert_eq!(PanicPolicy::parse("halt"), Some(PanicPolicy::Halt));
+    assert_eq!(PanicPolicy::parse("reboot"), Some(PanicPolicy::Reboot(PANIC_REBOOT_DELAY)));
+    assert_eq!(PanicPolicy::parse("reboot=0"), Some(PanicPolicy::Reboot(0)));
+    assert_eq!(PanicPolicy::parse("reboot=x"), None);
+    assert_eq!(PanicPolicy::parse("halt=3"), None);
+
+    assert_eq!(panic_policy(), PanicPolicy::Halt);
+    sys::cmdline::load("panic=reboot=30");
+    assert_eq!(panic_policy(), PanicPolicy::Reboot(30));
+    set_panic_policy(PanicPolicy::Halt);
+}
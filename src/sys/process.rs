@@ -5,8 +5,8 @@
 
 use crate::api::process::ExitCode;
 use crate::sys;
-use crate::sys::console::Console;
 use crate::sys::fs::{Resource, Device};
+use crate::sys::fs::overlay::SharedOverlay;
 use crate::sys::gdt::GDT;
 use crate::sys::ipc::{BlockState, Message};
 use crate::sys::mem::{phys_mem_offset, with_frame_allocator};
@@ -15,6 +15,7 @@ use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::alloc::{GlobalAlloc, Layout};
 use core::arch::asm;
 use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
@@ -41,9 +42,17 @@ pub const MAX_HANDLES:  usize = 64;
 pub const MAX_PROCS:    usize = 8;
 pub const MAX_PROC_MEM: usize = 10 << 20; // 10 MB per process
 
+/// Room the stack may grow into before it reaches its guard page
+pub const STACK_SIZE: u64 = 256 << 10;
+
 /// Start address of userspace (must be above kernel)
 const USER_BASE: u64 = 0x0080_0000;
 
+/// Idle task — the boot context, never runs userspace work
+pub const IDLE_PID: usize = 0;
+/// Init — parent of every process, runs the shell in kernel context
+pub const INIT_PID: usize = 1;
+
 // ---------------------------------------------------------------------------
 // Global state
 // ---------------------------------------------------------------------------
@@ -62,15 +71,29 @@ lazy_static! {
     };
 }
 
+/// Claim the init slot. Init shares the kernel page table and runs
+/// kernel-resident, so the scheduler never picks it as a user process.
+pub fn start_init() {
+    let mut table = PROC_TABLE.write();
+    if table[INIT_PID].id == INIT_PID {
+        return;
+    }
+    let mut init = Process::new();
+    init.id        = INIT_PID;
+    init.parent_id = IDLE_PID;
+    table[INIT_PID] = Box::new(init);
+    ACTIVE_PROCS.fetch_add(1, Ordering::SeqCst);
+}
+
 pub fn set_proc_code_base(addr: u64) {
     PROC_CODE_BASE.store(addr, Ordering::SeqCst);
 }
 
-/// Cari slot kosong di process table (PID > 0)
+/// Cari slot kosong di process table (PID > INIT_PID)
 /// FIX: slot reuse — slot yang id==0 dan bukan PID 0 berarti free
 fn find_free_slot() -> Option<usize> {
     let table = PROC_TABLE.read();
-    for i in 1..MAX_PROCS {
+    for i in INIT_PID + 1..MAX_PROCS {
         if table[i].id == 0 {
             return Some(i);
         }
@@ -126,6 +149,84 @@ pub struct CpuRegisters {
     pub rax: usize,
 }
 
+// ---------------------------------------------------------------------------
+// Resource limits
+// ---------------------------------------------------------------------------
+
+/// Highest handle number + 1 the process may open
+pub const RLIMIT_NOFILE: usize = 0;
+/// Bytes of userspace heap the process may allocate
+pub const RLIMIT_AS:     usize = 1;
+/// CPU time in milliseconds. Past the soft limit the process gets
+/// SIGXCPU, past the hard limit SIGKILL.
+pub const RLIMIT_CPU:    usize = 2;
+/// SPAWN calls per second
+pub const RLIMIT_SPAWN:  usize = 3;
+pub const RLIMIT_COUNT:  usize = 4;
+
+/// Value meaning "no limit"
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct RLimit {
+    pub soft: usize,
+    pub hard: usize,
+}
+
+impl RLimit {
+    const fn new(max: usize) -> Self { Self { soft: max, hard: max } }
+}
+
+/// Soft spawn rate limit of new processes, `spawnrate=` on the command line
+static SPAWN_RATE: AtomicUsize = AtomicUsize::new(20);
+
+// Capabilities — privileges a process holds, checked by the syscalls
+// that need them. Root holds all of them; a process can only drop bits.
+
+/// HALT: reboot or power off
+pub const CAP_REBOOT: u32 = 1 << 0;
+/// KILL: signal processes other than itself
+pub const CAP_KILL:   u32 = 1 << 1;
+/// SETRLIMIT: raise hard limits
+pub const CAP_MEM:    u32 = 1 << 2;
+/// BIND: use ports below `net::PRIVILEGED_PORTS`
+pub const CAP_NET:    u32 = 1 << 3;
+/// CHROOT, opening /dev/hda and setting the hostname
+pub const CAP_MOUNT:  u32 = 1 << 4;
+/// SETPRIO: move a process to a better priority class
+pub const CAP_NICE:   u32 = 1 << 5;
+pub const CAP_ALL:    u32 = CAP_REBOOT | CAP_KILL | CAP_MEM | CAP_NET | CAP_MOUNT | CAP_NICE;
+
+pub fn cap_name(cap: u32) -> &'static str {
+    match cap {
+        CAP_REBOOT => "CAP_REBOOT",
+        CAP_KILL   => "CAP_KILL",
+        CAP_MEM    => "CAP_MEM",
+        CAP_NET    => "CAP_NET",
+        CAP_MOUNT  => "CAP_MOUNT",
+        CAP_NICE   => "CAP_NICE",
+        _          => "capability",
+    }
+}
+
+fn default_rlimits() -> [RLimit; RLIMIT_COUNT] {
+    [
+        RLimit::new(MAX_HANDLES),
+        RLimit::new(MAX_PROC_MEM),
+        RLimit::new(RLIM_INFINITY),
+        RLimit { soft: SPAWN_RATE.load(Ordering::Relaxed), hard: RLIM_INFINITY },
+    ]
+}
+
+/// Change the default spawn rate limit, and that of the current process
+pub fn set_spawn_rate(rate: usize) {
+    SPAWN_RATE.store(rate, Ordering::Relaxed);
+    let mut table = PROC_TABLE.write();
+    let limit = &mut table[current_pid()].data.rlimits[RLIMIT_SPAWN];
+    limit.soft = rate.min(limit.hard);
+}
+
 // ---------------------------------------------------------------------------
 // Process data (env, cwd, handles)
 // ---------------------------------------------------------------------------
@@ -133,9 +234,26 @@ pub struct CpuRegisters {
 #[derive(Clone, Debug)]
 pub struct ProcData {
     pub env:     BTreeMap<String, String>,
+    /// Seen from inside `root`, like every path the process uses
     pub cwd:     String,
+    /// VFS directory the process sees as `/`, set by CHROOT
+    pub root:    String,
     pub user:    Option<String>,
     pub handles: [Option<Box<Resource>>; MAX_HANDLES],
+    /// Per-handle flags (`fs::O_NONBLOCK`), reset when the slot is reused
+    pub handle_flags: [u8; MAX_HANDLES],
+    /// Inherited by children along with the rest of ProcData
+    pub rlimits: [RLimit; RLIMIT_COUNT],
+    /// `CAP_*` bits the process holds
+    pub caps: u32,
+    /// Bits a spawned child starts with, never more than `caps`
+    pub child_caps: u32,
+    /// Bit n set: syscall n may be made (SECCOMP_SET)
+    pub syscalls: u64,
+    /// A filtered syscall kills the process instead of failing
+    pub seccomp_kill: bool,
+    /// Where file changes go instead of the shared VFS (SPAWN_OVERLAY)
+    pub overlay: Option<SharedOverlay>,
 }
 
 impl ProcData {
@@ -143,16 +261,27 @@ impl ProcData {
         let mut handles = [(); MAX_HANDLES].map(|_| None);
 
         // stdin=0, stdout=1, stderr=2, null=3
-        handles[0] = Some(Box::new(Resource::Device(Device::Console(Console::new()))));
-        handles[1] = Some(Box::new(Resource::Device(Device::Console(Console::new()))));
-        handles[2] = Some(Box::new(Resource::Device(Device::Console(Console::new()))));
+        let console = sys::console::shared();
+        handles[0] = Some(Box::new(Resource::Device(Device::Console(console.clone()))));
+        handles[1] = Some(Box::new(Resource::Device(Device::Console(console.clone()))));
+        handles[2] = Some(Box::new(Resource::Device(Device::Console(console))));
         handles[3] = Some(Box::new(Resource::Device(Device::Null)));
 
+        let caps = if user.is_none_or(|u| u == "root") { CAP_ALL } else { 0 };
+
         Self {
             env:  BTreeMap::new(),
             cwd:  cwd.to_string(),
+            root: "/".to_string(),
             user: user.map(String::from),
             handles,
+            handle_flags: [0; MAX_HANDLES],
+            rlimits: default_rlimits(),
+            caps,
+            child_caps: caps,
+            syscalls: SECCOMP_ALLOW_ALL,
+            seccomp_kill: false,
+            overlay: None,
         }
     }
 }
@@ -163,6 +292,7 @@ impl ProcData {
 
 pub fn current_pid() -> usize       { CURRENT_PID.load(Ordering::SeqCst) }
 pub fn set_pid(id: usize)           { CURRENT_PID.store(id, Ordering::SeqCst); }
+pub fn parent_pid() -> usize        { PROC_TABLE.read()[current_pid()].parent_id }
 
 pub fn cwd() -> String {
     PROC_TABLE.read()[current_pid()].data.cwd.clone()
@@ -172,6 +302,64 @@ pub fn set_cwd(path: &str) {
     PROC_TABLE.write()[current_pid()].data.cwd = path.to_string();
 }
 
+/// SPAWN flag: the child's file changes go to a copy-on-write overlay
+/// instead of the shared VFS. Flags are the fifth SPAWN argument (r9).
+pub const SPAWN_OVERLAY: usize = 1 << 0;
+
+/// SPAWN flag: create the child stopped and return its pid instead of
+/// running it, so a debugger can set up before SIGCONT starts it
+pub const SPAWN_SUSPENDED: usize = 1 << 1;
+
+/// ProcData of a child of `parent` spawned with `flags`
+fn child_data(parent: &ProcData, flags: usize) -> ProcData {
+    let mut data = parent.clone();
+    data.caps = data.child_caps;
+    for res in data.handles.iter_mut().flatten() {
+        res.mark_clean();
+    }
+    if flags & SPAWN_OVERLAY != 0 {
+        // Start from what the parent sees, then go separate ways
+        let base = parent.overlay.as_ref().map(|o| o.read().clone()).unwrap_or_default();
+        data.overlay = Some(Arc::new(RwLock::new(base)));
+    }
+    data
+}
+
+/// Why a fault at `addr` must not be served by mapping a page, if it
+/// must not: the stack guard page, heap room past the break, or a file
+/// mapping, whose pages are all present so only a write to a read-only
+/// one faults
+pub fn bad_access(addr: u64) -> Option<&'static str> {
+    let table = PROC_TABLE.read();
+    let proc  = &table[current_pid()];
+    let layout = proc.layout;
+    if layout.guard.contains(addr) {
+        Some("Stack overflow")
+    } else if proc.mappings.iter().any(|m| m.region.contains(addr)) {
+        Some("Write to a read-only file mapping")
+    } else if (layout.heap.end..layout.guard.start).contains(&addr) {
+        Some("Access past the heap break")
+    } else {
+        None
+    }
+}
+
+pub fn overlay() -> Option<SharedOverlay> {
+    PROC_TABLE.read()[current_pid()].data.overlay.clone()
+}
+
+pub fn root() -> String {
+    PROC_TABLE.read()[current_pid()].data.root.clone()
+}
+
+/// Confine the current process to the VFS directory `dir`; its cwd
+/// becomes the new `/`. Children inherit the root.
+pub fn chroot(dir: &str) {
+    let data = &mut PROC_TABLE.write()[current_pid()].data;
+    data.root = dir.to_string();
+    data.cwd  = "/".to_string();
+}
+
 pub fn env_var(key: &str) -> Option<String> {
     PROC_TABLE.read()[current_pid()].data.env.get(key).cloned()
 }
@@ -184,6 +372,154 @@ pub fn current_user() -> Option<String> {
     PROC_TABLE.read()[current_pid()].data.user.clone()
 }
 
+pub fn has_cap(cap: u32) -> bool {
+    PROC_TABLE.read()[current_pid()].data.caps & cap == cap
+}
+
+/// `(caps, child_caps)` of the current process
+pub fn caps() -> (u32, u32) {
+    let data = &PROC_TABLE.read()[current_pid()].data;
+    (data.caps, data.child_caps)
+}
+
+/// Replace the capabilities of the current process and of the children
+/// it will spawn. Both must be subsets of what it holds now: bits can be
+/// dropped but never gained back.
+pub(crate) fn set_caps(caps: u32, child_caps: u32) -> Result<(), ()> {
+    let mut table = PROC_TABLE.write();
+    let data = &mut table[current_pid()].data;
+    if caps & !data.caps != 0 || child_caps & !caps != 0 {
+        return Err(());
+    }
+    data.caps = caps;
+    data.child_caps = child_caps;
+    Ok(())
+}
+
+/// Syscall filter of a process that was never narrowed
+pub const SECCOMP_ALLOW_ALL: u64 = u64::MAX;
+
+/// SECCOMP_SET flag: a filtered syscall kills the process with SIGKILL
+/// instead of failing with EPERM
+pub const SECCOMP_KILL: usize = 1 << 0;
+
+/// May the current process make syscall `n`? EXIT always may, so a
+/// filtered process can still end. Numbers past the bitmap only pass
+/// when nothing is filtered, so a syscall added there later is not open
+/// to a sandboxed process.
+pub fn syscall_allowed(n: usize) -> bool {
+    let syscalls = PROC_TABLE.read()[current_pid()].data.syscalls;
+    n == sys::syscall::number::EXIT
+        || syscalls == SECCOMP_ALLOW_ALL
+        || (n < u64::BITS as usize && syscalls & (1 << n) != 0)
+}
+
+/// Does a filtered syscall kill the current process?
+pub fn seccomp_kills() -> bool {
+    PROC_TABLE.read()[current_pid()].data.seccomp_kill
+}
+
+/// Narrow the syscalls the current process, and the children it spawns
+/// or forks from now on, may make to those in `allowed`. Nothing can be
+/// allowed again, and a filter that kills never goes back to EPERM.
+pub(crate) fn set_seccomp(allowed: u64, kill: bool) -> Result<(), ()> {
+    let mut table = PROC_TABLE.write();
+    let data = &mut table[current_pid()].data;
+    if allowed & !data.syscalls != 0 {
+        return Err(());
+    }
+    data.syscalls = allowed;
+    data.seccomp_kill |= kill;
+    Ok(())
+}
+
+pub fn rlimit(resource: usize) -> Option<RLimit> {
+    PROC_TABLE.read()[current_pid()].data.rlimits.get(resource).copied()
+}
+
+/// Change a limit of the current process. The soft limit may not exceed
+/// the hard one, and the hard limit can only be raised with CAP_MEM.
+pub(crate) fn set_rlimit(resource: usize, limit: RLimit) -> Result<(), ()> {
+    let raise = has_cap(CAP_MEM);
+    let mut table = PROC_TABLE.write();
+    let cur = table[current_pid()].data.rlimits.get_mut(resource).ok_or(())?;
+    if limit.soft > limit.hard || (limit.hard > cur.hard && !raise) {
+        return Err(());
+    }
+    *cur = limit;
+
+    // A CPU limit below the time already used is never crossed by a tick,
+    // so it takes effect here
+    let pid = current_pid();
+    if resource == RLIMIT_CPU && pid > INIT_PID {
+        let used = table[pid].cpu_ns / 1_000_000;
+        drop(table);
+        if let Some(sig) = cpu_signal(limit, |max| used >= max as u64) {
+            kwarn!("pid {}: CPU time limit reached, sending signal {}", pid, sig);
+            sys::signal::send(pid, sig).ok();
+        }
+    }
+    Ok(())
+}
+
+/// Signal due for `limit`, given which of its bounds have been `reached`
+fn cpu_signal(limit: RLimit, reached: impl Fn(usize) -> bool) -> Option<u32> {
+    let reached = |max: usize| max != RLIM_INFINITY && reached(max);
+    if reached(limit.hard) {
+        Some(sys::signal::SIGKILL)
+    } else if reached(limit.soft) {
+        Some(sys::signal::SIGXCPU)
+    } else {
+        None
+    }
+}
+
+/// Charge one timer tick to the current process if it was interrupted in
+/// userspace, and signal it when that takes it over its RLIMIT_CPU
+pub fn account_tick(user: bool) {
+    let pid = current_pid();
+    if !user || pid <= INIT_PID {
+        return;
+    }
+    let sig = {
+        let mut table = PROC_TABLE.write();
+        let proc  = &mut table[pid];
+        let limit = proc.data.rlimits[RLIMIT_CPU];
+        let before = proc.cpu_ns / 1_000_000;
+        proc.cpu_ns += sys::clk::tick_ns();
+        let after  = proc.cpu_ns / 1_000_000;
+        cpu_signal(limit, |max| before < max as u64 && after >= max as u64)
+    };
+    if let Some(sig) = sig {
+        kwarn!("pid {}: CPU time limit reached, sending signal {}", pid, sig);
+        sys::signal::send(pid, sig).ok();
+    }
+}
+
+/// CPU time `pid` has spent in userspace, in milliseconds
+pub fn cpu_time_ms(pid: usize) -> u64 {
+    PROC_TABLE.read().get(pid).map_or(0, |p| p.cpu_ns / 1_000_000)
+}
+
+/// Count a SPAWN by the current process against RLIMIT_SPAWN. Fails if
+/// the process already spawned its share in the last second.
+pub(crate) fn charge_spawn() -> Result<(), ()> {
+    let now = sys::clk::uptime_ns() / 1_000_000;
+    let mut table = PROC_TABLE.write();
+    let proc  = &mut table[current_pid()];
+    let limit = proc.data.rlimits[RLIMIT_SPAWN].soft;
+    let (start, count) = &mut proc.spawn_window;
+    if now.saturating_sub(*start) >= 1000 {
+        *start = now;
+        *count = 0;
+    }
+    if *count >= limit {
+        return Err(());
+    }
+    *count += 1;
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Handle management
 // ---------------------------------------------------------------------------
@@ -191,9 +527,11 @@ pub fn current_user() -> Option<String> {
 pub fn alloc_handle(res: Resource) -> Result<usize, ()> {
     let mut table = PROC_TABLE.write();
     let proc = &mut table[current_pid()];
-    for i in 4..MAX_HANDLES {
+    let max  = proc.data.rlimits[RLIMIT_NOFILE].soft.min(MAX_HANDLES);
+    for i in 4..max {
         if proc.data.handles[i].is_none() {
             proc.data.handles[i] = Some(Box::new(res));
+            proc.data.handle_flags[i] = 0;
             return Ok(i);
         }
     }
@@ -201,15 +539,70 @@ pub fn alloc_handle(res: Resource) -> Result<usize, ()> {
 }
 
 pub fn get_handle(h: usize) -> Option<Box<Resource>> {
-    PROC_TABLE.read()[current_pid()].data.handles[h].clone()
+    PROC_TABLE.read()[current_pid()].data.handles.get(h)?.clone()
+}
+
+/// Like `get_handle`, but Err if the heap cannot hold the copy
+pub fn try_get_handle(h: usize) -> Option<Result<Box<Resource>, ()>> {
+    let table = PROC_TABLE.read();
+    let res = table[current_pid()].data.handles.get(h)?.as_ref()?;
+    Some(res.try_clone().map(Box::new))
 }
 
 pub fn update_handle(h: usize, res: Resource) {
     PROC_TABLE.write()[current_pid()].data.handles[h] = Some(Box::new(res));
 }
 
+/// Flags of an open handle
+pub fn handle_flags(h: usize) -> Option<u8> {
+    let table = PROC_TABLE.read();
+    let data  = &table[current_pid()].data;
+    data.handles.get(h)?.as_ref()?;
+    Some(data.handle_flags[h])
+}
+
+pub(crate) fn set_handle_flags(h: usize, flags: u8) -> Result<(), ()> {
+    let mut table = PROC_TABLE.write();
+    let data = &mut table[current_pid()].data;
+    data.handles.get(h).ok_or(())?.as_ref().ok_or(())?;
+    data.handle_flags[h] = flags;
+    Ok(())
+}
+
+/// Flush every open file of every process to its backing store
+pub fn sync_all() {
+    let mut table = PROC_TABLE.write();
+    for proc in table.iter_mut() {
+        for res in proc.data.handles.iter_mut().flatten() {
+            res.sync().ok();
+        }
+    }
+    drop(table);
+    // Then the disk sectors that are only in the buffer cache
+    if sys::bcache::sync().is_err() {
+        kwarn!("sync: could not write back the disk cache");
+    }
+}
+
+/// True if some user process is ready to run (not blocked or stopped)
+pub fn has_runnable_user() -> bool {
+    let table = PROC_TABLE.read();
+    (INIT_PID + 1..MAX_PROCS).any(|pid| table[pid].is_runnable())
+}
+
+/// Number of open handles, in any process, holding unsynced writes
+pub fn dirty_handles() -> usize {
+    let table = PROC_TABLE.read();
+    table.iter()
+        .flat_map(|proc| proc.data.handles.iter().flatten())
+        .filter(|res| res.is_dirty())
+        .count()
+}
+
 pub fn free_handle(h: usize) {
-    PROC_TABLE.write()[current_pid()].data.handles[h] = None;
+    let mut table = PROC_TABLE.write();
+    table[current_pid()].data.handles[h] = None;
+    table[current_pid()].data.handle_flags[h] = 0;
 }
 
 // ---------------------------------------------------------------------------
@@ -250,12 +643,171 @@ pub fn is_user_addr(addr: u64) -> bool {
     USER_BASE <= addr && addr <= USER_BASE + MAX_PROC_MEM as u64
 }
 
+// ---------------------------------------------------------------------------
+// Memory layout
+// ---------------------------------------------------------------------------
+
+/// Addresses `start..end` of one part of a process image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    pub start: u64,
+    pub end:   u64,
+}
+
+impl Region {
+    fn new(start: u64, len: u64) -> Self {
+        Self { start, end: start + len }
+    }
+
+    pub fn len(&self) -> u64 { self.end - self.start }
+
+    pub fn is_empty(&self) -> bool { self.end == self.start }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+/// Where each part of a process lives inside its MAX_PROC_MEM slot,
+/// bottom up and page aligned:
+///
+/// `code | bss | args | heap | (room for BRK) | guard | stack`
+///
+/// The heap starts out with half of the room between the arguments and
+/// the guard page; BRK grows it into the rest. All regions are empty
+/// for kernel-resident processes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// File contents of the image
+    pub code:  Region,
+    /// Zero-filled rest of the image
+    pub bss:   Region,
+    /// Argument strings and the `&[&str]` pointing at them
+    pub args:  Region,
+    pub heap:  Region,
+    /// Never mapped; touching it is a stack overflow
+    pub guard: Region,
+    pub stack: Region,
+}
+
+impl MemoryLayout {
+    /// Lay out an image of `file_size` bytes that takes `mem_size` in
+    /// memory, with `args_size` bytes of arguments, in the slot at
+    /// `code_base`. Fails if it all does not fit with at least a page
+    /// of heap.
+    pub(crate) fn new(code_base: u64, file_size: u64, mem_size: u64, args_size: u64) -> Result<Self, ()> {
+        let top = code_base + MAX_PROC_MEM as u64;
+        if [file_size, mem_size, args_size].iter().any(|&n| n > MAX_PROC_MEM as u64) {
+            return Err(());
+        }
+        let page = |n: u64| n.next_multiple_of(4096);
+
+        let code  = Region::new(code_base, page(file_size));
+        let bss   = Region::new(code.end, page(mem_size.max(file_size)) - code.len());
+        let args  = Region::new(bss.end, page(args_size).max(4096));
+        let stack = Region { start: top - STACK_SIZE, end: top };
+        let guard = Region { start: stack.start - 4096, end: stack.start };
+
+        let room = guard.start.checked_sub(args.end).filter(|&r| r >= 2 * 4096).ok_or(())?;
+        let heap = Region::new(args.end, (room / 2) & !0xFFF);
+        Ok(Self { code, bss, args, heap, guard, stack })
+    }
+}
+
+/// Bytes the arguments take once copied into a process: the strings,
+/// then the aligned `&str` slice that points at them
+fn args_size(args: &[&str]) -> u64 {
+    // FIX BUG #5: Hitung total ukuran yang dibutuhkan dulu sebelum map.
+    // Sebelumnya hanya map 1 byte (= 1 page = 4096 bytes) yang bisa overflow
+    // jika total panjang argumen + slice metadata > 4096 bytes.
+    let total_str_bytes: usize = args.iter().map(|a| a.len()).sum();
+    let align = core::mem::align_of::<&str>();
+    // str data + alignment padding + slice of &str (16 bytes per entry di x86_64)
+    let slice_meta_bytes = core::mem::size_of_val(args);
+    (total_str_bytes + align + slice_meta_bytes + align) as u64
+}
+
+/// The argument list a parent passes to SPAWN
+unsafe fn spawn_args<'a>(args_ptr: usize, args_len: usize) -> &'a [&'a str] {
+    let ptr = resolve_addr(args_ptr as u64) as usize;
+    core::slice::from_raw_parts(ptr as *const &str, args_len)
+}
+
+/// Name of a command started with `args`: the last path component of the
+/// first one
+fn command_name(args: &[&str]) -> String {
+    args.first().map_or("", |a| sys::path::basename(a)).into()
+}
+
+/// `(file, memory)` size of the image in `bin`, counted from its base
+fn image_size(bin: &[u8]) -> Result<(u64, u64), ExitCode> {
+    if bin.get(0..4) == Some(&ELF_MAGIC) {
+        let obj = object::File::parse(bin).map_err(|_| ExitCode::ExecError)?;
+        let mut size = (0, 0);
+        for seg in obj.segments() {
+            let data = seg.data().map_err(|_| ExitCode::ExecError)?;
+            size.0 = size.0.max(seg.address().saturating_add(data.len() as u64));
+            size.1 = size.1.max(seg.address().saturating_add(seg.size()));
+        }
+        Ok(size)
+    } else if bin.get(0..4) == Some(&BIN_MAGIC) {
+        let len = bin.len() as u64 - 4;
+        Ok((len, len))
+    } else {
+        Err(ExitCode::ExecError)
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Per-process memory allocation
 // ---------------------------------------------------------------------------
 
 pub unsafe fn user_alloc(layout: Layout) -> *mut u8 {
-    PROC_TABLE.read()[current_pid()].allocator.alloc(layout)
+    let table = PROC_TABLE.read();
+    let proc  = &table[current_pid()];
+    let limit = proc.data.rlimits[RLIMIT_AS].soft;
+    if proc.allocator.lock().used().saturating_add(layout.size()) > limit {
+        return core::ptr::null_mut();
+    }
+    proc.allocator.alloc(layout)
+}
+
+/// `(start, end)` of the heap of the current process; the end is the break
+pub fn heap_range() -> (u64, u64) {
+    let heap = PROC_TABLE.read()[current_pid()].layout.heap;
+    (heap.start, heap.end)
+}
+
+/// Grow the heap of the current process by `increment` bytes, rounded up
+/// to whole pages, and return the new break. The pages are mapped right
+/// away and handed to the process's allocator; the heap may grow up to
+/// the lowest file mapping, or the stack guard page if there is none.
+pub(crate) fn grow_heap(increment: usize) -> Result<u64, ()> {
+    let (pt_frame, layout, allocator, ceiling) = {
+        let table = PROC_TABLE.read();
+        let proc  = &table[current_pid()];
+        let ceiling = sys::mmap::floor(&proc.layout, &proc.mappings);
+        (proc.pt_frame, proc.layout, proc.allocator.clone(), ceiling)
+    };
+    if layout.heap.is_empty() {
+        return Err(()); // Kernel-resident
+    }
+    let top = layout.heap.end;
+    let increment = (increment as u64).checked_next_multiple_of(4096).ok_or(())?;
+    if increment == 0 {
+        return Ok(top);
+    }
+    let new_top = top.checked_add(increment).filter(|&t| t <= ceiling).ok_or(())?;
+
+    let pt = unsafe { sys::mem::create_page_table_from_frame(pt_frame) };
+    let mut mapper = unsafe {
+        OffsetPageTable::new(pt, VirtAddr::new(phys_mem_offset()))
+    };
+    sys::mem::map_page(&mut mapper, top, increment as usize)?;
+    unsafe { allocator.lock().extend(increment as usize); }
+
+    PROC_TABLE.write()[current_pid()].layout.heap.end = new_top;
+    Ok(new_top)
 }
 
 pub unsafe fn user_free(ptr: *mut u8, layout: Layout) {
@@ -280,12 +832,281 @@ pub unsafe fn page_table() -> &'static mut PageTable {
     sys::mem::create_page_table_from_frame(current_page_table_frame())
 }
 
+// ---------------------------------------------------------------------------
+// Fork
+// ---------------------------------------------------------------------------
+
+/// Duplicate the current process into a free slot and return the
+/// child's pid. The child shares the parent's pages copy-on-write, gets
+/// a copy of its ProcData and resumes from the state saved at the FORK
+/// syscall, with 0 in rax.
+pub(crate) fn fork() -> Result<usize, ()> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let slot = find_free_slot().ok_or(())?;
+        let parent = PROC_TABLE.read()[current_pid()].clone();
+        if parent.layout.code.is_empty() || parent.stack_frame.is_none() {
+            return Err(()); // Kernel-resident, there is no image to duplicate
+        }
+        let range = parent.code_base..parent.code_base + MAX_PROC_MEM as u64;
+        let (pt_frame, fork_tables) = sys::mem::cow::fork_address_space(parent.pt_frame, range)?;
+
+        // The heap's free list lives in the heap pages, which the child
+        // now shares; the allocator's own fields are copied alongside
+        let allocator = Arc::new(LockedHeap::empty());
+        unsafe { *allocator.lock() = core::ptr::read(&*parent.allocator.lock()); }
+
+        let mut saved_regs = parent.saved_regs;
+        saved_regs.rax = 0;
+        let mut data = parent.data.clone();
+        for res in data.handles.iter_mut().flatten() {
+            res.mark_clean();
+        }
+        let child = Process {
+            id:          slot,
+            parent_id:   parent.id,
+            pt_frame,
+            fork_tables,
+            forked:      true,
+            resumes_parent: false,
+            saved_regs,
+            allocator,
+            mailbox:     None,
+            reply:       None,
+            block:       BlockState::Running,
+            signals:     parent.signals.fork(),
+            stopped:     false,
+            child_events: Vec::new(),
+            spawn_window: (0, 0),
+            cpu_ns:      0,
+            slice_left:  0,
+            disowned:    false,
+            service:     None,
+            data,
+            ..*parent
+        };
+
+        PROC_TABLE.write()[slot] = Box::new(child);
+        NEXT_PID.fetch_add(1, Ordering::SeqCst);
+        ACTIVE_PROCS.fetch_add(1, Ordering::SeqCst);
+        Ok(slot)
+    })
+}
+
+/// Was the current process started by FORK?
+pub fn is_forked() -> bool {
+    PROC_TABLE.read()[current_pid()].forked
+}
+
+// ---------------------------------------------------------------------------
+// Child state changes
+// ---------------------------------------------------------------------------
+
+/// `WaitInfo::kind` values, as in Linux's `si_code` for SIGCHLD
+pub const CLD_EXITED:    u32 = 1;
+pub const CLD_KILLED:    u32 = 2;
+pub const CLD_STOPPED:   u32 = 5;
+pub const CLD_CONTINUED: u32 = 6;
+
+/// WAITID options
+pub const WNOHANG:    usize = 1; // Return at once if nothing changed
+pub const WSTOPPED:   usize = 2; // Report children stopped by a signal
+pub const WEXITED:    usize = 4; // Report children that exited or were killed
+pub const WCONTINUED: usize = 8; // Report stopped children resumed by SIGCONT
+
+/// Events kept per parent; past this the oldest are dropped
+const MAX_CHILD_EVENTS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChildState {
+    /// Exited with this code
+    Exited(usize),
+    /// Terminated by this signal
+    Killed(u32),
+    /// Stopped by this signal
+    Stopped(u32),
+    Continued,
+}
+
+impl ChildState {
+    /// What WAIT reports for a child that ended this way
+    fn exit_code(&self) -> Option<ExitCode> {
+        match self {
+            ChildState::Exited(code) => Some(ExitCode::from(*code)),
+            ChildState::Killed(_)    => Some(ExitCode::Failure),
+            _ => None,
+        }
+    }
+
+    /// The WAITID option asking for this kind of change
+    fn option(&self) -> usize {
+        match self {
+            ChildState::Exited(_) | ChildState::Killed(_) => WEXITED,
+            ChildState::Stopped(_) => WSTOPPED,
+            ChildState::Continued  => WCONTINUED,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChildEvent {
+    pub pid:   usize,
+    pub state: ChildState,
+}
+
+/// What WAITID writes back: the child, how it changed (`CLD_*`) and the
+/// exit code or signal number
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WaitInfo {
+    pub pid:  usize,
+    pub kind: u32,
+    pub code: u32,
+}
+
+impl From<ChildEvent> for WaitInfo {
+    fn from(e: ChildEvent) -> Self {
+        let (kind, code) = match e.state {
+            ChildState::Exited(code) => (CLD_EXITED, code as u32),
+            ChildState::Killed(sig)  => (CLD_KILLED, sig),
+            ChildState::Stopped(sig) => (CLD_STOPPED, sig),
+            ChildState::Continued    => (CLD_CONTINUED, sys::signal::SIGCONT),
+        };
+        WaitInfo { pid: e.pid, kind, code }
+    }
+}
+
+/// Record that `pid` changed state and tell its parent: the event is
+/// queued for WAITID, a parent waiting for it is woken and SIGCHLD is
+/// sent
+pub fn notify_parent(pid: usize, state: ChildState) {
+    let parent = {
+        let mut table = PROC_TABLE.write();
+        let parent = table[pid].parent_id;
+        if parent == pid || !table[parent].is_alive() {
+            return;
+        }
+        let proc = &mut table[parent];
+        // Only the latest of a stop and a continue is worth reporting
+        if !matches!(state, ChildState::Exited(_) | ChildState::Killed(_)) {
+            proc.child_events.retain(|e| {
+                e.pid != pid || matches!(e.state, ChildState::Exited(_) | ChildState::Killed(_))
+            });
+        }
+        if proc.child_events.len() >= MAX_CHILD_EVENTS {
+            proc.child_events.remove(0);
+        }
+        proc.child_events.push(ChildEvent { pid, state });
+        if proc.block == BlockState::WaitingChild {
+            proc.block = BlockState::Running;
+        }
+        parent
+    };
+    sys::signal::send(parent, sys::signal::SIGCHLD).ok();
+}
+
+/// Stop `pid` after it took `sig`; the scheduler passes it over until
+/// `resume`
+pub fn stop(pid: usize, sig: u32) {
+    {
+        let mut table = PROC_TABLE.write();
+        if table[pid].id == 0 || table[pid].stopped {
+            return;
+        }
+        table[pid].stopped = true;
+        table[pid].slice_left = 0;
+    }
+    notify_parent(pid, ChildState::Stopped(sig));
+}
+
+/// Let a process stopped by `stop` run again
+pub fn resume(pid: usize) {
+    {
+        let mut table = PROC_TABLE.write();
+        if table[pid].id == 0 || !table[pid].stopped {
+            return;
+        }
+        table[pid].stopped = false;
+    }
+    notify_parent(pid, ChildState::Continued);
+}
+
+/// Hold the current process while it is stopped, letting the others run
+pub fn wait_while_stopped() {
+    while PROC_TABLE.read()[current_pid()].stopped {
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
+/// Wait for a child of the current process to change state: `pid`, or
+/// any child if 0. `options` holds the `W*` flags saying which changes
+/// count. Ok(None) with WNOHANG if none is ready yet, Err if there is no
+/// such child to wait for.
+pub(crate) fn wait_child(pid: usize, options: usize) -> Result<Option<WaitInfo>, ()> {
+    let me = current_pid();
+    loop {
+        {
+            let mut table = PROC_TABLE.write();
+            let found = table[me].child_events.iter().position(|e| {
+                (pid == 0 || e.pid == pid) && e.state.option() & options != 0
+            });
+            if let Some(i) = found {
+                table[me].block = BlockState::Running;
+                let event = table[me].child_events.remove(i);
+                if event.state.exit_code().is_some() {
+                    reap(&mut table, me, event.pid);
+                }
+                return Ok(Some(event.into()));
+            }
+            let has_child = (INIT_PID + 1..MAX_PROCS).any(|i| {
+                i != me && table[i].id != 0 && table[i].parent_id == me && (pid == 0 || i == pid)
+            });
+            if !has_child {
+                table[me].block = BlockState::Running;
+                return Err(());
+            }
+            if options & WNOHANG != 0 {
+                return Ok(None);
+            }
+            table[me].block = BlockState::WaitingChild;
+        }
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
+/// Wait for child `pid`, or any child if 0, to terminate and reap it.
+/// Returns its pid and exit status, or Err if there is no such child.
+pub(crate) fn wait(pid: usize) -> Result<(usize, ExitCode), ()> {
+    let me = current_pid();
+    loop {
+        {
+            let mut table = PROC_TABLE.write();
+            let children: Vec<usize> = (INIT_PID + 1..MAX_PROCS)
+                .filter(|&i| i != me && table[i].id == i && table[i].parent_id == me && (pid == 0 || i == pid))
+                .collect();
+            if children.is_empty() {
+                table[me].block = BlockState::Running;
+                return Err(());
+            }
+            for child in children {
+                if let Some(status) = reap(&mut table, me, child) {
+                    table[me].block = BlockState::Running;
+                    return Ok((child, status));
+                }
+            }
+            table[me].block = BlockState::WaitingChild;
+        }
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Process termination
 // ---------------------------------------------------------------------------
 
-pub fn terminate() {
+/// End the current process, reporting `state` to its parent
+pub fn terminate(state: ChildState) {
     let pid = current_pid();
+    notify_parent(pid, state);
 
     // FIX BUG #4: Ambil SEMUA data yang dibutuhkan dalam satu lock,
     // lalu lepas lock sebelum memanggil release_pages().
@@ -299,31 +1120,233 @@ pub fn terminate() {
     };
     // Lock sudah dilepas di sini — aman untuk operasi yang bisa trigger page fault
 
+    // Mapped files get their changes before the pages go
+    sys::mmap::write_back_all();
+
     // Release halaman proses TANPA memegang lock PROC_TABLE
     release_process_pages(pt_frame, code_base, stack_base);
 
-    // Clear slot — set id=0 menandakan slot kosong dan siap di-reuse
+    // Keep only the exit status, for the parent to collect with WAIT
+    close_handles(pid);
     {
         let mut table = PROC_TABLE.write();
-        table[pid] = Box::new(Process::new());
+        reparent_children(&mut table, pid);
+        release_fork_tables(&mut table, pid);
+        bury(&mut table, pid, state.exit_code());
     }
 
     // Update jumlah proses aktif
     ACTIVE_PROCS.fetch_sub(1, Ordering::SeqCst);
 
+    return_foreground(pid, parent_id);
     set_pid(parent_id);
 
     // Deallocate page table frame dan switch ke page table parent
     unsafe {
-        let (_, flags) = Cr3::read();
         with_frame_allocator(|fa| {
             fa.deallocate_frame(pt_frame);
         });
-        // Ambil parent_pt dalam lock singkat yang tidak bisa deadlock
-        // (tidak ada operasi memory di dalamnya)
-        let parent_pt = PROC_TABLE.read()[parent_id].pt_frame;
-        Cr3::write(parent_pt, flags);
     }
+    // Ambil parent_pt dalam lock singkat yang tidak bisa deadlock
+    // (tidak ada operasi memory di dalamnya)
+    let parent_pt = PROC_TABLE.read()[parent_id].pt_frame;
+    sys::sched::switch_address_space(parent_pt);
+}
+
+/// A process as listed by `top`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcStat {
+    pub pid:    usize,
+    pub parent: usize,
+    /// R runnable, S blocked, T stopped, Z zombie
+    pub state:  char,
+    /// What it is blocked on, if anything
+    pub block:  BlockState,
+    pub code_base: u64,
+    /// CPU time spent in userspace
+    pub cpu_ns: u64,
+    /// Bytes of its address space in use: image, arguments, heap, stack
+    pub memory: u64,
+    pub name:   String,
+}
+
+/// Every process in the table, idle and init included
+pub fn stats() -> Vec<ProcStat> {
+    let table = PROC_TABLE.read();
+    (0..MAX_PROCS).filter(|&pid| pid == 0 || table[pid].id == pid).map(|pid| {
+        let proc = &table[pid];
+        let state = if proc.is_zombie() {
+            'Z'
+        } else if proc.stopped {
+            'T'
+        } else if proc.block != BlockState::Running {
+            'S'
+        } else {
+            'R'
+        };
+        let l = proc.layout;
+        let name = match pid {
+            0        => "idle".into(),
+            INIT_PID => "init".into(),
+            _        => proc.name.clone(),
+        };
+        ProcStat {
+            pid,
+            parent: proc.parent_id,
+            state,
+            block:  proc.block,
+            code_base: proc.code_base,
+            cpu_ns: proc.cpu_ns,
+            memory: l.code.len() + l.bss.len() + l.args.len() + l.heap.len() + l.stack.len(),
+            name,
+        }
+    }).collect()
+}
+
+/// Pids of live user processes — everything except idle and init
+pub fn user_pids() -> Vec<usize> {
+    let table = PROC_TABLE.read();
+    (INIT_PID + 1..MAX_PROCS).filter(|&pid| table[pid].is_alive()).collect()
+}
+
+/// Does `pid` descend from `leader` without a disowned process on the
+/// way? Orphans reparented to init count as init's.
+fn in_session(table: &[Box<Process>; MAX_PROCS], mut pid: usize, leader: usize) -> bool {
+    for _ in 0..MAX_PROCS {
+        let proc = &table[pid];
+        if !proc.is_alive() || proc.disowned {
+            return false;
+        }
+        if proc.parent_id == leader {
+            return true;
+        }
+        if proc.parent_id == pid {
+            return false;
+        }
+        pid = proc.parent_id;
+    }
+    false
+}
+
+/// Keep `pid` running when its shell session ends
+pub(crate) fn disown(pid: usize) -> Result<(), ()> {
+    if pid <= INIT_PID || pid >= MAX_PROCS {
+        return Err(());
+    }
+    let mut table = PROC_TABLE.write();
+    if table[pid].id != pid || table[pid].is_zombie() {
+        return Err(());
+    }
+    table[pid].disowned = true;
+    Ok(())
+}
+
+/// End the session led by `leader`: send SIGTERM to every process it
+/// started, directly or not, except disowned ones. Returns how many.
+pub fn hangup(leader: usize) -> usize {
+    let jobs: Vec<usize> = {
+        let table = PROC_TABLE.read();
+        (INIT_PID + 1..MAX_PROCS)
+            .filter(|&pid| pid != leader && in_session(&table, pid, leader))
+            .collect()
+    };
+    for &pid in &jobs {
+        sys::signal::send(pid, sys::signal::SIGTERM).ok();
+    }
+    jobs.len()
+}
+
+/// Tear down another process from the kernel. Its open handles are
+/// closed, which writes back dirty files. Idle, init and the caller
+/// cannot be killed.
+pub(crate) fn kill(pid: usize) -> Result<(), ()> {
+    if pid <= INIT_PID || pid >= MAX_PROCS || pid == current_pid() {
+        return Err(());
+    }
+    if !PROC_TABLE.read()[pid].is_alive() {
+        return Err(());
+    }
+    notify_parent(pid, ChildState::Killed(sys::signal::SIGKILL));
+    reparent_children(&mut PROC_TABLE.write(), pid);
+    discard(pid, Some(ExitCode::Failure));
+    Ok(())
+}
+
+/// Give the console back to the parent if `pid` held it
+fn return_foreground(pid: usize, parent_id: usize) {
+    if sys::console::foreground() == pid {
+        sys::console::set_foreground(parent_id);
+    }
+}
+
+/// Close what a dying process left open, so sockets give up their ports
+/// and files are written back. Writing back looks at the process table,
+/// so the handles are taken out of it first.
+fn close_handles(pid: usize) {
+    let handles = core::mem::replace(
+        &mut PROC_TABLE.write()[pid].data.handles,
+        core::array::from_fn(|_| None),
+    );
+    for mut res in handles.into_iter().flatten() {
+        res.close();
+    }
+}
+
+/// Hand the children of an exiting process over to init, so a later
+/// process reusing the slot does not inherit them by accident. Those
+/// already dead are reaped, since nobody is left to wait for them.
+fn reparent_children(table: &mut [Box<Process>; MAX_PROCS], pid: usize) {
+    for proc in table.iter_mut() {
+        if proc.id != 0 && proc.id != pid && proc.parent_id == pid {
+            if proc.is_zombie() {
+                **proc = Process::new();
+            } else {
+                proc.parent_id = INIT_PID;
+            }
+        }
+    }
+}
+
+/// Empty the slot of `pid`, which has released everything it held. With
+/// a `status`, a zombie holding it stays behind until the parent reaps
+/// it, or init reaps it (`reap_orphans`). The children of a plain SPAWN
+/// go at once, since their parent is handed the status as it resumes.
+/// Senders and callers blocked on it are woken to find it gone.
+fn bury(table: &mut [Box<Process>; MAX_PROCS], pid: usize, status: Option<ExitCode>) {
+    sys::ipc::forget(table, pid);
+    let parent_id = table[pid].parent_id;
+    let waits = !table[pid].resumes_parent
+        && parent_id >= INIT_PID && table[parent_id].id == parent_id && !table[parent_id].is_zombie();
+    table[pid] = Box::new(match status {
+        Some(status) if waits => Process {
+            id:          pid,
+            parent_id,
+            exit_status: Some(status),
+            ..Process::new()
+        },
+        _ => Process::new(),
+    });
+}
+
+/// Reap every child of init that has exited, most of them orphans it
+/// inherited. Init does this whenever it waits. Returns how many went.
+pub fn reap_orphans() -> usize {
+    let mut table = PROC_TABLE.write();
+    (INIT_PID + 1..MAX_PROCS).filter(|&pid| reap(&mut table, INIT_PID, pid).is_some()).count()
+}
+
+/// Reap the zombie child `pid` of `parent`, returning its exit status
+fn reap(table: &mut [Box<Process>; MAX_PROCS], parent: usize, pid: usize) -> Option<ExitCode> {
+    let proc = &table[pid];
+    if proc.id != pid || proc.parent_id != parent {
+        return None;
+    }
+    let status = proc.exit_status?;
+    table[pid] = Box::new(Process::new());
+    table[parent].child_events.retain(|e| {
+        e.pid != pid || !matches!(e.state, ChildState::Exited(_) | ChildState::Killed(_))
+    });
+    Some(status)
 }
 
 /// Bebaskan semua halaman milik proses tanpa memegang lock PROC_TABLE.
@@ -334,6 +1357,7 @@ fn release_process_pages(pt_frame: PhysFrame, code_base: u64, _stack_base: u64)
         OffsetPageTable::new(pt, VirtAddr::new(phys_mem_offset()))
     };
     sys::mem::unmap_page(&mut mapper, code_base, MAX_PROC_MEM);
+    sys::sched::forget_address_space(pt_frame);
 
     // Juga cek apakah ada mapping di USER_BASE yang perlu dibersihkan
     match mapper.translate(VirtAddr::new(USER_BASE)) {
@@ -346,8 +1370,45 @@ fn release_process_pages(pt_frame: PhysFrame, code_base: u64, _stack_base: u64)
     }
 }
 
+/// Tear down a slot that was created but never started running, or a
+/// killed process, which leaves a zombie with `status`
+fn discard(pid: usize, status: Option<ExitCode>) {
+    let (parent_id, pt_frame, code_base, stack_base) = {
+        let table = PROC_TABLE.read();
+        let proc  = &table[pid];
+        (proc.parent_id, proc.pt_frame, proc.code_base, proc.stack_base)
+    };
+    return_foreground(pid, parent_id);
+
+    release_process_pages(pt_frame, code_base, stack_base);
+
+    close_handles(pid);
+    let mut table = PROC_TABLE.write();
+    release_fork_tables(&mut table, pid);
+    bury(&mut table, pid, status);
+    ACTIVE_PROCS.fetch_sub(1, Ordering::SeqCst);
+    with_frame_allocator(|fa| unsafe { fa.deallocate_frame(pt_frame) });
+}
+
+/// Free the page tables FORK copied for `pid`, whose pages are already
+/// unmapped. A process spawned from it copied its top-level table and
+/// still walks through them; that one inherits them instead.
+fn release_fork_tables(table: &mut [Box<Process>; MAX_PROCS], pid: usize) {
+    let tables = core::mem::take(&mut table[pid].fork_tables);
+    if tables.is_empty() {
+        return;
+    }
+    let heir = (INIT_PID + 1..MAX_PROCS).find(|&i| {
+        i != pid && table[i].is_alive() && sys::mem::cow::uses_tables(table[i].pt_frame, &tables)
+    });
+    match heir {
+        Some(i) => table[i].fork_tables.extend(tables),
+        None    => sys::mem::cow::free_tables(table[pid].pt_frame, &tables),
+    }
+}
+
 pub fn power_off_hook() {
-    terminate();
+    terminate(ChildState::Exited(0));
     sys::acpi::power_off();
 }
 
@@ -362,18 +1423,72 @@ pub struct Process {
     pub code_base:   u64,
     pub stack_base:  u64,
     pub entry_point: u64,
+    /// Regions of the slot at `code_base`; the heap is what `allocator`
+    /// manages
+    pub layout:      MemoryLayout,
     pub pt_frame:    PhysFrame,
+    /// Page tables below `pt_frame` that FORK copied for this process
+    pub fork_tables: Vec<PhysFrame>,
+    /// Started by FORK rather than SPAWN
+    pub forked:      bool,
+    /// Started by a plain SPAWN: the parent gets the exit code when it
+    /// resumes, and never reaps it
+    pub resumes_parent: bool,
     pub stack_frame: Option<InterruptStackFrameValue>,
     pub saved_regs:  CpuRegisters,
     pub data:        ProcData,
     pub allocator:   Arc<LockedHeap>,
     /// IPC mailbox — single incoming message slot
     pub mailbox:     Option<Message>,
+    /// Reply to an outstanding CALL, kept apart from the mailbox
+    pub reply:       Option<Message>,
     /// Process block state (Running / WaitingSend / WaitingRecv)
     pub block:       BlockState,
+    pub signals:     sys::signal::Signals,
+    /// Stopped by SIGSTOP until a SIGCONT
+    pub stopped:     bool,
+    /// State changes of children not yet collected by WAITID, oldest first
+    pub child_events: Vec<ChildEvent>,
+    /// Start (ms since boot) and number of SPAWN calls of the current
+    /// one-second window, for RLIMIT_SPAWN
+    pub spawn_window: (u64, usize),
+    /// Time spent running in userspace, for RLIMIT_CPU
+    pub cpu_ns:      u64,
+    /// Base priority, -20 (favoured) to 19; see `sched::effective_nice`
+    pub nice:        i8,
+    /// Priority class, 0 (first) to 7; see `sched::effective_priority`
+    pub priority:    u8,
+    /// Scheduling intervals left before the process must yield the CPU
+    pub slice_left:  u64,
+    /// Left running when the shell session that started it ends
+    pub disowned:    bool,
+    /// Set once the process terminated: the slot is a zombie kept until
+    /// the parent collects this with WAIT
+    pub exit_status: Option<ExitCode>,
+    /// Name registered with REGISTER, for SENDNAME to find it by
+    pub service:     Option<String>,
+    /// Command the process was started as, from its first argument
+    pub name:        String,
+    /// Files mapped with MMAP_FILE, between the heap break and the guard
+    pub mappings:    Vec<sys::mmap::FileMapping>,
 }
 
 impl Process {
+    /// Does the slot hold a process that has not terminated?
+    pub fn is_alive(&self) -> bool {
+        self.id != 0 && self.exit_status.is_none()
+    }
+
+    /// Terminated, waiting for its parent to reap it
+    pub fn is_zombie(&self) -> bool {
+        self.id != 0 && self.exit_status.is_some()
+    }
+
+    /// Can the scheduler give it the CPU?
+    pub fn is_runnable(&self) -> bool {
+        self.is_alive() && self.block == BlockState::Running && !self.stopped
+    }
+
     pub fn new() -> Self {
         Self {
             id:          0,
@@ -381,36 +1496,111 @@ impl Process {
             code_base:   0,
             stack_base:  0,
             entry_point: 0,
+            layout:      MemoryLayout::default(),
             pt_frame:    Cr3::read().0,
+            fork_tables: Vec::new(),
+            forked:      false,
+            resumes_parent: false,
             stack_frame: None,
             saved_regs:  CpuRegisters::default(),
             data:        ProcData::new("/", None),
             allocator:   Arc::new(LockedHeap::empty()),
             mailbox:     None,
+            reply:       None,
             block:       BlockState::Running,
+            signals:     sys::signal::Signals::new(),
+            stopped:     false,
+            child_events: Vec::new(),
+            spawn_window: (0, 0),
+            cpu_ns:      0,
+            nice:        0,
+            priority:    sys::sched::PRIO_DEFAULT,
+            slice_left:  0,
+            disowned:    false,
+            exit_status: None,
+            service:     None,
+            name:        String::new(),
+            mappings:    Vec::new(),
+        }
+    }
+
+    /// Start `bin` as a child of the current process, which it takes the
+    /// CPU from. Only comes back if the child could not be started, with
+    /// the reason.
+    pub fn spawn(bin: &[u8], flags: usize, args_ptr: usize, args_len: usize) -> ExitCode {
+        let args = unsafe { spawn_args(args_ptr, args_len) };
+        let id = match Self::create(bin, flags, args_size(args)) {
+            Ok(id) => id,
+            Err(code) => return code,
+        };
+        {
+            let mut table = PROC_TABLE.write();
+            table[id].name = command_name(args);
+            table[id].resumes_parent = true;
+        }
+        let proc = PROC_TABLE.read()[id].clone();
+
+        // The child takes keyboard input over from a parent that held it
+        let fg = sys::console::foreground();
+        if fg == proc.parent_id || fg == sys::console::NO_FOREGROUND {
+            sys::console::set_foreground(id);
         }
+        let code = proc.exec(args_ptr, args_len);
+
+        // exec only comes back if the child could not be started
+        discard(id, None);
+        code
     }
 
-    pub fn spawn(bin: &[u8], args_ptr: usize, args_len: usize) -> Result<(), ExitCode> {
-        if let Ok(id) = Self::create(bin) {
-            let proc = PROC_TABLE.read()[id].clone();
-            proc.exec(args_ptr, args_len);
-            unreachable!();
+    /// Like `spawn` with `SPAWN_SUSPENDED`: the child is created stopped
+    /// and its pid returned. It starts at its entry point once continued.
+    pub fn spawn_suspended(bin: &[u8], flags: usize, args_ptr: usize, args_len: usize) -> Result<usize, ExitCode> {
+        let args = unsafe { spawn_args(args_ptr, args_len) };
+        let id = Self::create(bin, flags, args_size(args))?;
+        PROC_TABLE.write()[id].name = command_name(args);
+        let proc = PROC_TABLE.read()[id].clone();
+        let argv = match proc.prepare(args_ptr, args_len) {
+            Ok(argv) => argv,
+            Err(code) => {
+                discard(id, None);
+                return Err(code);
+            }
+        };
+
+        // The scheduler builds the first frame from the entry point and
+        // takes the arguments from here
+        {
+            let mut table = PROC_TABLE.write();
+            table[id].saved_regs.rdi = argv.as_ptr() as usize;
+            table[id].saved_regs.rsi = argv.len();
         }
-        Err(ExitCode::ExecError)
+        stop(id, sys::signal::SIGSTOP);
+        Ok(id)
     }
 
-    fn create(bin: &[u8]) -> Result<usize, ()> {
+    fn create(bin: &[u8], flags: usize, args_size: u64) -> Result<usize, ExitCode> {
         // FIX: cari slot kosong, bukan check NEXT_PID >= MAX_PROCS
-        let slot = find_free_slot().ok_or(())?;
+        let slot = find_free_slot().ok_or(ExitCode::Busy)?;
 
         // FIX: cari virtual address range yang bisa di-reuse
-        let code_base = find_free_code_base().ok_or(())?;
-
-        // Allocate frame for new process page table
-        let pt_frame = with_frame_allocator(|fa| {
-            fa.allocate_frame().expect("could not allocate frame for page table")
-        });
+        let code_base = find_free_code_base().ok_or(ExitCode::Busy)?;
+
+        // An image or argument list too big for the slot is refused
+        // before anything is allocated
+        let (file_size, mem_size) = image_size(bin)?;
+        let layout = MemoryLayout::new(code_base, file_size, mem_size, args_size)
+            .map_err(|_| ExitCode::NoMemory)?;
+
+        // Running out of physical memory is reported to the caller
+        // instead of taking the whole kernel down. With enough frames for
+        // the image, its page tables and our own, a load that still fails
+        // is down to the binary.
+        let needed = mem_size.div_ceil(4096) + mem_size.div_ceil(2 << 20) + 4;
+        if (sys::mem::frame_stats().1 as u64) < needed {
+            return Err(ExitCode::NoMemory);
+        }
+        let pt_frame = with_frame_allocator(|fa| fa.allocate_frame())
+            .ok_or(ExitCode::NoMemory)?;
 
         let new_pt     = unsafe { sys::mem::create_page_table_from_frame(pt_frame) };
         let kernel_pt  = unsafe { sys::mem::active_page_table() };
@@ -424,28 +1614,26 @@ impl Process {
             OffsetPageTable::new(new_pt, VirtAddr::new(phys_mem_offset()))
         };
 
-        let stack_base = code_base + MAX_PROC_MEM as u64 - 4096;
-        let mut entry_point = 0u64;
+        let stack_base = layout.stack.end;
 
-        // Load ELF or flat binary
-        if bin.get(0..4) == Some(&ELF_MAGIC) {
-            if let Ok(obj) = object::File::parse(bin) {
-                entry_point = obj.entry();
-                for seg in obj.segments() {
-                    if let Ok(data) = seg.data() {
-                        let addr = code_base + seg.address();
-                        let size = seg.size() as usize;
-                        Self::load_segment(&mut mapper, addr, size, data)?;
-                    }
+        // Every range successfully mapped so far, so a failure halfway
+        // through the binary can undo exactly what was done
+        let mut mapped = Vec::new();
+
+        let entry_point = match Self::load_binary(&mut mapper, code_base, bin, &mut mapped) {
+            Ok(entry) => entry,
+            Err(code) => {
+                for &(addr, size) in &mapped {
+                    sys::mem::unmap_page(&mut mapper, addr, size);
                 }
+                with_frame_allocator(|fa| unsafe { fa.deallocate_frame(pt_frame) });
+                // Slot was never written, so it is still free (id == 0)
+                return Err(code);
             }
-        } else if bin.get(0..4) == Some(&BIN_MAGIC) {
-            Self::load_segment(&mut mapper, code_base, bin.len() - 4, &bin[4..])?;
-        } else {
-            return Err(());
-        }
+        };
 
         let parent = PROC_TABLE.read()[current_pid()].clone();
+        let data = child_data(&parent.data, flags);
 
         let proc = Process {
             id:          slot, // gunakan slot index sebagai PID
@@ -453,13 +1641,31 @@ impl Process {
             code_base,
             stack_base,
             entry_point,
+            layout,
             pt_frame,
-            data:        parent.data.clone(),
+            fork_tables: Vec::new(),
+            forked:      false,
+            resumes_parent: false,
+            data,
             stack_frame: None, // proses baru — belum punya saved frame
             saved_regs:  CpuRegisters::default(),
             allocator:   Arc::new(LockedHeap::empty()),
             mailbox:     None,
+            reply:       None,
             block:       BlockState::Running,
+            signals:     parent.signals.inherit(),
+            stopped:     false,
+            child_events: Vec::new(),
+            spawn_window: (0, 0),
+            cpu_ns:      0,
+            nice:        parent.nice,
+            priority:    parent.priority,
+            slice_left:  0,
+            disowned:    false,
+            exit_status: None,
+            service:     None,
+            name:        String::new(),
+            mappings:    Vec::new(),
         };
 
         PROC_TABLE.write()[slot] = Box::new(proc);
@@ -468,33 +1674,53 @@ impl Process {
         Ok(slot)
     }
 
-    fn exec(&self, args_ptr: usize, args_len: usize) {
+    /// Jump into the process. Only returns if its arguments could not be
+    /// set up, in which case the caller must discard the slot.
+    fn exec(&self, args_ptr: usize, args_len: usize) -> ExitCode {
+        let final_args = match self.prepare(args_ptr, args_len) {
+            Ok(argv) => argv,
+            Err(code) => return code,
+        };
+
+        set_pid(self.id);
+
+        sys::sched::switch_address_space(self.pt_frame);
+        unsafe {
+            asm!(
+                "cli",
+                "push {ss:r}",
+                "push {rsp:r}",
+                "push 0x200",
+                "push {cs:r}",
+                "push {rip:r}",
+                "iretq",
+                ss  = in(reg) GDT.1.u_data.0,
+                rsp = in(reg) self.stack_base,
+                cs  = in(reg) GDT.1.u_code.0,
+                rip = in(reg) self.code_base + self.entry_point,
+                in("rdi") final_args.as_ptr(),
+                in("rsi") final_args.len(),
+                options(noreturn),
+            );
+        }
+    }
+
+    /// Copy the arguments into the process's args region and set up its
+    /// heap, returning the argument slice its entry point gets
+    fn prepare(&self, args_ptr: usize, args_len: usize) -> Result<&'static [&'static str], ExitCode> {
         let pt  = unsafe { page_table() };
         let mut mapper = unsafe {
             OffsetPageTable::new(pt, VirtAddr::new(phys_mem_offset()))
         };
 
-        let args: &[&str] = unsafe {
-            let ptr = resolve_addr(args_ptr as u64) as usize;
-            core::slice::from_raw_parts(ptr as *const &str, args_len)
-        };
+        // create sized the args region for exactly these arguments
+        let args = unsafe { spawn_args(args_ptr, args_len) };
+        let layout = self.layout;
+        if sys::mem::map_page(&mut mapper, layout.args.start, layout.args.len() as usize).is_err() {
+            return Err(ExitCode::NoMemory);
+        }
 
-        // FIX BUG #5: Hitung total ukuran yang dibutuhkan dulu sebelum map.
-        // Sebelumnya hanya map 1 byte (= 1 page = 4096 bytes) yang bisa overflow
-        // jika total panjang argumen + slice metadata > 4096 bytes.
-        let total_str_bytes: usize = args.iter().map(|a| a.len()).sum();
-        let align = core::mem::align_of::<&str>();
-        // str data + alignment padding + slice of &str (16 bytes per entry di x86_64)
-        let slice_meta_bytes = args_len * core::mem::size_of::<&str>();
-        let needed = total_str_bytes + align + slice_meta_bytes + align;
-        // Bulatkan ke atas ke kelipatan 4096, minimal 1 page
-        let pages_needed = (needed + 4095) / 4096;
-        let map_size = pages_needed * 4096;
-
-        let args_base = self.code_base + (self.stack_base - self.code_base) / 2;
-        sys::mem::map_page(&mut mapper, args_base, map_size).expect("args alloc");
-
-        let mut cursor = args_base;
+        let mut cursor = layout.args.start;
         let mut str_slices = alloc::vec::Vec::new();
 
         for arg in args {
@@ -512,40 +1738,41 @@ impl Process {
         cursor = (cursor + align - 1) & !(align - 1);
 
         let args_slice_ptr = cursor as *mut &str;
-        let final_args: &[&str] = unsafe {
+        let final_args: &'static [&'static str] = unsafe {
             let s = core::slice::from_raw_parts_mut(args_slice_ptr, str_slices.len());
             s.copy_from_slice(&str_slices);
             s
         };
 
-        // Heap mulai setelah args region, dengan gap 4096 bytes
-        let heap_start = args_base + map_size as u64 + 4096;
-        let heap_size  = ((self.stack_base - heap_start) / 2) as usize;
         unsafe {
-            self.allocator.lock().init(heap_start as *mut u8, heap_size);
+            self.allocator.lock().init(layout.heap.start as *mut u8, layout.heap.len() as usize);
         }
+        Ok(final_args)
+    }
 
-        set_pid(self.id);
-
-        unsafe {
-            let (_, flags) = Cr3::read();
-            Cr3::write(self.pt_frame, flags);
-
-            asm!(
-                "cli",
-                "push {ss:r}",
-                "push {rsp:r}",
-                "push 0x200",
-                "push {cs:r}",
-                "push {rip:r}",
-                "iretq",
-                ss  = in(reg) GDT.1.u_data.0,
-                rsp = in(reg) self.stack_base,
-                cs  = in(reg) GDT.1.u_code.0,
-                rip = in(reg) self.code_base + self.entry_point,
-                in("rdi") final_args.as_ptr(),
-                in("rsi") final_args.len(),
-            );
+    /// Load an ELF or flat binary at `code_base`, returning its entry point
+    fn load_binary(
+        mapper:    &mut OffsetPageTable,
+        code_base: u64,
+        bin:       &[u8],
+        mapped:    &mut Vec<(u64, usize)>,
+    ) -> Result<u64, ExitCode> {
+        if bin.get(0..4) == Some(&ELF_MAGIC) {
+            let obj = object::File::parse(bin).map_err(|_| ExitCode::ExecError)?;
+            for seg in obj.segments() {
+                let data = seg.data().map_err(|_| ExitCode::ExecError)?;
+                let addr = code_base + seg.address();
+                let size = seg.size() as usize;
+                Self::load_segment(mapper, addr, size, data, mapped)
+                    .map_err(|_| ExitCode::ExecError)?;
+            }
+            Ok(obj.entry())
+        } else if bin.get(0..4) == Some(&BIN_MAGIC) {
+            Self::load_segment(mapper, code_base, bin.len() - 4, &bin[4..], mapped)
+                .map_err(|_| ExitCode::ExecError)?;
+            Ok(0)
+        } else {
+            Err(ExitCode::ExecError)
         }
     }
 
@@ -554,8 +1781,10 @@ impl Process {
         addr:   u64,
         size:   usize,
         data:   &[u8],
+        mapped: &mut Vec<(u64, usize)>,
     ) -> Result<(), ()> {
-        sys::mem::map_page(mapper, addr, size)?;
+        sys::mem::map_huge_page(mapper, addr, size)?;
+        mapped.push((addr, size));
         unsafe {
             let dst = addr as *mut u8;
             core::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
@@ -566,3 +1795,543 @@ impl Process {
         Ok(())
     }
 }
+
+/// A made-up process for tests: claims slot `pid` and makes it current.
+/// Dropping it makes the previous process current again and empties the
+/// slot, also when the test returns early.
+#[cfg(test)]
+pub struct FakeProcess {
+    pub pid:   usize,
+    /// Pid that was current before
+    pub saved: usize,
+}
+
+#[cfg(test)]
+impl FakeProcess {
+    pub fn enter(pid: usize) -> Self {
+        let saved = current_pid();
+        PROC_TABLE.write()[pid].id = pid;
+        set_pid(pid);
+        Self { pid, saved }
+    }
+}
+
+#[cfg(test)]
+impl Drop for FakeProcess {
+    fn drop(&mut self) {
+        set_pid(self.saved);
+        PROC_TABLE.write()[self.pid] = Box::new(Process::new());
+    }
+}
+
+#[test_case]
+fn spawn_without_frames_fails_and_gives_them_back() {
+    // A flat binary three pages long
+    let mut bin = alloc::vec![0u8; 4 + 3 * 4096];
+    bin[0..4].copy_from_slice(&BIN_MAGIC);
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        // Take every frame, then hand back two: room for the page table
+        // but not for the image
+        let mut hog = alloc::vec::Vec::new();
+        with_frame_allocator(|fa| {
+            while let Some(frame) = fa.allocate_frame() {
+                hog.push(frame);
+            }
+            for frame in hog.drain(..2) {
+                unsafe { fa.deallocate_frame(frame) };
+            }
+        });
+
+        assert_eq!(Process::create(&bin, 0, 0).err(), Some(ExitCode::NoMemory));
+
+        // Both frames are free again
+        let mut left = 0;
+        with_frame_allocator(|fa| {
+            while let Some(frame) = fa.allocate_frame() {
+                hog.push(frame);
+                left += 1;
+            }
+            for frame in hog {
+                unsafe { fa.deallocate_frame(frame) };
+            }
+        });
+        assert_eq!(left, 2);
+    });
+}
+
+/// Minimal ELF with one PT_LOAD header per `(vaddr, memsz)`, no data
+#[cfg(test)]
+fn test_elf(segments: &[(u64, u64)]) -> Vec<u8> {
+    let n = segments.len();
+    let mut elf = alloc::vec![0u8; 64 + n * 56];
+    elf[0..4].copy_from_slice(&ELF_MAGIC);
+    elf[4] = 2; // 64-bit
+    elf[5] = 1; // little endian
+    elf[6] = 1;
+    elf[16..18].copy_from_slice(&2u16.to_le_bytes());    // ET_EXEC
+    elf[18..20].copy_from_slice(&0x3Eu16.to_le_bytes()); // x86_64
+    elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+    elf[32..40].copy_from_slice(&64u64.to_le_bytes());   // e_phoff
+    elf[52..54].copy_from_slice(&64u16.to_le_bytes());   // e_ehsize
+    elf[54..56].copy_from_slice(&56u16.to_le_bytes());   // e_phentsize
+    elf[56..58].copy_from_slice(&(n as u16).to_le_bytes()); // e_phnum
+    for (i, &(vaddr, memsz)) in segments.iter().enumerate() {
+        let ph = &mut elf[64 + i * 56..64 + (i + 1) * 56];
+        ph[0..4].copy_from_slice(&1u32.to_le_bytes());   // PT_LOAD
+        ph[16..24].copy_from_slice(&vaddr.to_le_bytes()); // p_vaddr
+        ph[40..48].copy_from_slice(&memsz.to_le_bytes()); // p_memsz
+    }
+    elf
+}
+
+#[test_case]
+fn failed_load_releases_frames() {
+    // Two PT_LOAD headers covering the same page: the second map fails
+    // after the first succeeded, forcing a rollback
+    let elf = test_elf(&[(0x1000, 0x1000), (0x1000, 0x1000)]);
+
+    let live   = || PROC_TABLE.read().iter().filter(|p| p.id != 0).count();
+    let procs  = live();
+    let before = with_frame_allocator(|fa| fa.used_frames());
+    assert_eq!(Process::create(&elf, 0, 0).err(), Some(ExitCode::ExecError));
+    assert_eq!(with_frame_allocator(|fa| fa.used_frames()), before);
+    assert_eq!(live(), procs);
+}
+
+#[test_case]
+fn spawn_failures_say_why() {
+    // Not a binary at all
+    assert_eq!(Process::create(b"#!/bin/sh", 0, 0).err(), Some(ExitCode::ExecError));
+
+    // An image bigger than a process slot
+    let huge = test_elf(&[(0x1000, MAX_PROC_MEM as u64)]);
+    assert_eq!(Process::create(&huge, 0, 0).err(), Some(ExitCode::NoMemory));
+
+    // Every slot taken
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let free: Vec<usize> = (INIT_PID + 1..MAX_PROCS).filter(|&i| PROC_TABLE.read()[i].id == 0).collect();
+        for &i in &free {
+            PROC_TABLE.write()[i].id = i;
+        }
+        let elf = test_elf(&[(0x1000, 0x1000)]);
+        assert_eq!(Process::create(&elf, 0, 0).err(), Some(ExitCode::Busy));
+        for &i in &free {
+            PROC_TABLE.write()[i] = Box::new(Process::new());
+        }
+    });
+    assert_eq!(ExitCode::from(ExitCode::Busy as usize), ExitCode::Busy);
+}
+
+#[test_case]
+fn spawning_until_frames_run_out_fails_cleanly() {
+    let elf = test_elf(&[(0x1000, 0x1000)]);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let before = with_frame_allocator(|fa| fa.used_frames());
+
+        // What one child costs
+        let pid = Process::spawn_suspended(&elf, 0, 0, 0).unwrap();
+        let cost = with_frame_allocator(|fa| fa.used_frames()) - before;
+        discard(pid, None);
+
+        // Take every frame but enough for two and a half children
+        let spare = 2 * cost + cost / 2;
+        let hog_count = with_frame_allocator(|fa| fa.free_frames()).saturating_sub(spare);
+        let mut hog = Vec::with_capacity(hog_count);
+        with_frame_allocator(|fa| {
+            for _ in 0..hog_count {
+                hog.push(fa.allocate_frame().unwrap());
+            }
+        });
+
+        let mut pids = Vec::new();
+        let err = loop {
+            match Process::spawn_suspended(&elf, 0, 0, 0) {
+                Ok(pid) => pids.push(pid),
+                Err(code) => break code,
+            }
+        };
+        assert_eq!(err, ExitCode::NoMemory);
+        assert_eq!(pids.len(), 2);
+
+        for pid in pids {
+            discard(pid, None);
+        }
+        with_frame_allocator(|fa| {
+            for frame in hog {
+                unsafe { fa.deallocate_frame(frame) };
+            }
+        });
+        assert_eq!(with_frame_allocator(|fa| fa.used_frames()), before);
+
+        // And the kernel can still start processes afterwards
+        let pid = Process::spawn_suspended(&elf, 0, 0, 0).unwrap();
+        discard(pid, None);
+        assert_eq!(with_frame_allocator(|fa| fa.used_frames()), before);
+    });
+}
+
+#[test_case]
+fn suspended_spawn_waits_for_sigcont() {
+    use crate::sys::signal::{send, SIGCONT};
+
+    let elf = test_elf(&[(0x1000, 0x1000)]);
+    let args = ["target"];
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let before = with_frame_allocator(|fa| fa.used_frames());
+        let me = current_pid();
+        let pid = Process::spawn_suspended(&elf, 0, args.as_ptr() as usize, args.len()).unwrap();
+
+        // Created with its arguments in place, but not run
+        assert_eq!(current_pid(), me);
+        {
+            let table = PROC_TABLE.read();
+            let child = &table[pid];
+            assert!(child.stopped && !child.is_runnable());
+            assert!(child.stack_frame.is_none());
+            assert_eq!(child.saved_regs.rsi, 1);
+            assert_eq!(unsafe { *(child.saved_regs.rdi as *const &str) }, "target");
+        }
+
+        send(pid, SIGCONT).unwrap();
+        assert!(PROC_TABLE.read()[pid].is_runnable());
+
+        discard(pid, None);
+        assert_eq!(with_frame_allocator(|fa| fa.used_frames()), before);
+    });
+}
+
+#[test_case]
+fn init_owns_pid_one() {
+    start_init();
+    assert_eq!(PROC_TABLE.read()[INIT_PID].id, INIT_PID);
+    assert_ne!(find_free_slot(), Some(INIT_PID));
+}
+
+#[test_case]
+fn orphans_are_reparented_to_init() {
+    start_init();
+    let (parent, child) = (MAX_PROCS - 2, MAX_PROCS - 1);
+    let mut table = PROC_TABLE.write();
+    table[parent].id        = parent;
+    table[parent].parent_id = INIT_PID;
+    table[child].id         = child;
+    table[child].parent_id  = parent;
+
+    reparent_children(&mut table, parent);
+    assert_eq!(table[child].parent_id, INIT_PID);
+    table[parent] = Box::new(Process::new());
+
+    // Once it exits, init reaps it
+    bury(&mut table, child, Some(ExitCode::Success));
+    assert!(table[child].is_zombie());
+    drop(table);
+    assert_eq!(reap_orphans(), 1);
+    assert_eq!(PROC_TABLE.read()[child].id, 0);
+}
+
+#[test_case]
+fn handle_limit_gives_emfile() {
+    use crate::sys::syscall::{error::EMFILE, service};
+
+    let path = "/tmp/rlimit-test";
+    sys::fs::write_file(path, b"x").ok();
+    set_rlimit(RLIMIT_NOFILE, RLimit { soft: 6, hard: MAX_HANDLES }).unwrap();
+
+    // 0..=3 are the standard handles, so only 4 and 5 are left
+    assert_eq!(service::open(path, 0), 4);
+    assert_eq!(service::open(path, 0), 5);
+    assert_eq!(service::open(path, 0), EMFILE);
+
+    service::close(4);
+    service::close(5);
+    set_rlimit(RLIMIT_NOFILE, RLimit::new(MAX_HANDLES)).unwrap();
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn spawn_loop_is_throttled() {
+    use crate::sys::syscall::{error::EAGAIN, service};
+
+    let saved = rlimit(RLIMIT_SPAWN).unwrap();
+    set_rlimit(RLIMIT_SPAWN, RLimit { soft: 3, hard: saved.hard }).unwrap();
+    PROC_TABLE.write()[current_pid()].spawn_window = (0, 0);
+
+    let results: Vec<isize> = (0..10).map(|_| service::spawn("/no/such/bin", 0, 0, 0)).collect();
+    assert_eq!(results[..3], [ExitCode::NotFound as isize; 3]);
+    assert!(results[3..].iter().all(|&r| r == EAGAIN));
+
+    set_rlimit(RLIMIT_SPAWN, saved).unwrap();
+    PROC_TABLE.write()[current_pid()].spawn_window = (0, 0);
+}
+
+#[test_case]
+fn cpu_limit_counts_user_time_only() {
+    use crate::sys::signal::{bit, SIGKILL, SIGXCPU};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let pid = 2;
+        let _process = FakeProcess::enter(pid);
+        set_rlimit(RLIMIT_CPU, RLimit { soft: 100, hard: 150 }).unwrap();
+
+        // Time in the kernel (syscalls, waiting) is not charged
+        for _ in 0..1000 {
+            account_tick(false);
+        }
+        assert_eq!(cpu_time_ms(pid), 0);
+
+        let pending = || PROC_TABLE.read()[pid].signals.pending;
+        let mut ticks = 0;
+        while pending() & bit(SIGXCPU) == 0 {
+            account_tick(true);
+            ticks += 1;
+        }
+        assert_eq!(cpu_time_ms(pid), 100);
+        assert_eq!(ticks as u64 * sys::clk::tick_ns() / 1_000_000, 100);
+        assert_eq!(pending() & bit(SIGKILL), 0);
+
+        while cpu_time_ms(pid) < 150 {
+            account_tick(true);
+        }
+        assert_ne!(pending() & bit(SIGKILL), 0);
+    });
+}
+
+#[test_case]
+fn cpu_limit_below_time_used_applies_at_once() {
+    use crate::sys::signal::{bit, SIGKILL, SIGXCPU};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let pid = 2;
+        let _process = FakeProcess::enter(pid);
+        PROC_TABLE.write()[pid].cpu_ns = 120_000_000;
+        let pending = || PROC_TABLE.read()[pid].signals.pending;
+
+        set_rlimit(RLIMIT_CPU, RLimit { soft: 500, hard: 500 }).unwrap();
+        assert_eq!(pending(), 0);
+
+        set_rlimit(RLIMIT_CPU, RLimit { soft: 100, hard: 500 }).unwrap();
+        assert_eq!(pending(), bit(SIGXCPU));
+
+        set_rlimit(RLIMIT_CPU, RLimit { soft: 100, hard: 100 }).unwrap();
+        assert_ne!(pending() & bit(SIGKILL), 0);
+    });
+}
+
+#[test_case]
+fn overlay_child_writes_stay_private() {
+    let shared = "/tmp/overlay-test/shared";
+    let fresh  = "/tmp/overlay-test/fresh";
+    sys::fs::write_file(shared, b"base").unwrap();
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let process = FakeProcess::enter(2);
+        let data = child_data(&PROC_TABLE.read()[process.saved].data, SPAWN_OVERLAY);
+        PROC_TABLE.write()[2].data = data;
+
+        sys::fs::write_file(shared, b"child").unwrap();
+        sys::fs::write_file(fresh, b"new").unwrap();
+        assert_eq!(sys::fs::open_file(shared).unwrap().data(), b"child");
+        assert!(sys::fs::exists(fresh));
+
+        set_pid(process.saved);
+        assert_eq!(sys::fs::open_file(shared).unwrap().data(), b"base");
+        assert!(!sys::fs::exists(fresh));
+
+        // Removing in the overlay hides the file from the child only
+        set_pid(2);
+        sys::fs::remove(shared).unwrap();
+        assert!(!sys::fs::exists(shared));
+        set_pid(process.saved);
+        assert!(sys::fs::exists(shared));
+    });
+    sys::fs::remove(shared).ok();
+}
+
+#[test_case]
+fn handles_write_back_to_their_owners_view() {
+    let path = "/tmp/overlay-handle-test";
+    sys::fs::write_file(path, b"base").unwrap();
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let process = FakeProcess::enter(2);
+        let data = child_data(&PROC_TABLE.read()[process.saved].data, SPAWN_OVERLAY);
+        PROC_TABLE.write()[2].data = data;
+        let mut res = sys::fs::open_resource(path, sys::fs::O_TRUNC).unwrap();
+        res.write(b"sandboxed").unwrap();
+
+        // Closed by another process, as when the sandbox is killed
+        set_pid(process.saved);
+        res.clone().close();
+        assert_eq!(sys::fs::open_file(path).unwrap().data(), b"base");
+        set_pid(2);
+        assert_eq!(sys::fs::open_file(path).unwrap().data(), b"sandboxed");
+
+        // A child's copy leaves unsynced writes to the parent
+        PROC_TABLE.write()[2].data.handles[4] = Some(Box::new(res));
+        let child = child_data(&PROC_TABLE.read()[2].data, 0);
+        assert!(!child.handles[4].as_ref().unwrap().is_dirty());
+        assert!(PROC_TABLE.read()[2].data.handles[4].as_ref().unwrap().is_dirty());
+    });
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn faults_on_guard_and_past_break_are_refused() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let process = FakeProcess::enter(2);
+        let layout = MemoryLayout::new(USER_BASE, 0x2000, 0x3000, 100).unwrap();
+        let guard = layout.guard.start;
+        PROC_TABLE.write()[2].layout = layout;
+        assert_eq!(bad_access(guard), Some("Stack overflow"));
+        assert_eq!(bad_access(guard + 4095), Some("Stack overflow"));
+        assert_eq!(bad_access(guard + 4096), None);
+        assert_eq!(bad_access(layout.heap.end), Some("Access past the heap break"));
+        assert_eq!(bad_access(layout.heap.end - 1), None);
+
+        set_pid(process.saved);
+        assert_eq!(bad_access(guard), None);
+    });
+}
+
+#[test_case]
+fn layout_regions_do_not_overlap() {
+    let slot_top = USER_BASE + MAX_PROC_MEM as u64;
+    let check = |l: MemoryLayout| {
+        let regions = [l.code, l.bss, l.args, l.heap, l.guard, l.stack];
+        for pair in regions.windows(2) {
+            assert!(pair[0].end <= pair[1].start);
+        }
+        assert!(regions.iter().all(|r| r.start % 4096 == 0 && r.end % 4096 == 0));
+        assert!(l.code.start == USER_BASE && l.stack.end == slot_top);
+        assert!(!l.heap.is_empty() && l.guard.len() == 4096);
+    };
+
+    // A large image with a big bss and a large argument list still fits
+    check(MemoryLayout::new(USER_BASE, 3 << 20, 5 << 20, 1 << 20).unwrap());
+    check(MemoryLayout::new(USER_BASE, 1, 1, 0).unwrap());
+
+    // One that leaves no room for a heap is refused
+    let full = MAX_PROC_MEM as u64 - STACK_SIZE - 4096 - 4096;
+    assert!(MemoryLayout::new(USER_BASE, full, full, 1).is_err());
+    assert!(MemoryLayout::new(USER_BASE, 1 << 20, 1 << 20, u64::MAX - 100).is_err());
+    assert!(MemoryLayout::new(USER_BASE, 1 << 20, u64::MAX, 1).is_err());
+}
+
+#[test_case]
+fn heap_grows_up_to_the_stack_guard() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+        // Unused by anything else in the kernel page table
+        let start = 0x5557_0000_0000u64;
+        let mut mapper = unsafe {
+            OffsetPageTable::new(sys::mem::active_page_table(), VirtAddr::new(phys_mem_offset()))
+        };
+        sys::mem::map_page(&mut mapper, start, 4096).unwrap();
+        let allocator = Arc::new(LockedHeap::empty());
+        unsafe { allocator.lock().init(start as *mut u8, 4096); }
+        {
+            let mut table = PROC_TABLE.write();
+            table[2].layout.heap  = Region::new(start, 4096);
+            table[2].layout.guard = Region::new(start + 4 * 4096, 4096);
+            table[2].allocator    = allocator.clone();
+        }
+
+        assert_eq!(grow_heap(0), Ok(start + 4096));
+        assert_eq!(grow_heap(5000), Ok(start + 3 * 4096));
+        assert_eq!(heap_range(), (start, start + 3 * 4096));
+        assert_eq!(allocator.lock().size(), 3 * 4096);
+        unsafe { ((start + 3 * 4096 - 8) as *mut u64).write_volatile(7); }
+
+        // One more page fits below the guard, two do not
+        assert_eq!(grow_heap(2 * 4096), Err(()));
+        assert_eq!(grow_heap(1), Ok(start + 4 * 4096));
+
+        sys::mem::unmap_page(&mut mapper, start, 4 * 4096);
+    });
+}
+
+#[test_case]
+fn child_stop_and_exit_are_separate_wait_events() {
+    use sys::signal::{self, Action, SIGCHLD, SIGCONT, SIGSTOP};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+        {
+            let mut table = PROC_TABLE.write();
+            table[3].id = 3;
+            table[3].parent_id = 2;
+        }
+        assert_eq!(wait_child(3, WEXITED | WNOHANG), Ok(None));
+
+        // SIGSTOP takes effect when the child next gets its signals
+        signal::send(3, SIGSTOP).unwrap();
+        assert!(matches!(signal::take(3), Some((SIGSTOP, Action::Stop))));
+        stop(3, SIGSTOP);
+        assert!(!PROC_TABLE.read()[3].is_runnable());
+        assert_eq!(wait_child(0, WSTOPPED | WNOHANG), Ok(Some(WaitInfo { pid: 3, kind: CLD_STOPPED, code: SIGSTOP })));
+        assert!(matches!(signal::take(2), Some((SIGCHLD, Action::Ignore))));
+
+        // SIGCONT resumes it as soon as it is sent
+        signal::send(3, SIGCONT).unwrap();
+        assert!(PROC_TABLE.read()[3].is_runnable());
+        assert_eq!(wait_child(3, WCONTINUED | WNOHANG), Ok(Some(WaitInfo { pid: 3, kind: CLD_CONTINUED, code: SIGCONT })));
+
+        // The exit is a second event, still there once the slot is gone
+        notify_parent(3, ChildState::Exited(7));
+        PROC_TABLE.write()[3] = Box::new(Process::new());
+        assert_eq!(wait_child(0, WSTOPPED | WNOHANG), Err(()));
+        assert_eq!(wait_child(0, WEXITED), Ok(Some(WaitInfo { pid: 3, kind: CLD_EXITED, code: 7 })));
+        assert_eq!(wait_child(0, WEXITED | WNOHANG), Err(()));
+    });
+}
+
+#[test_case]
+fn terminated_child_stays_a_zombie_until_reaped() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        start_init();
+        let _process = FakeProcess::enter(2);
+        {
+            let mut table = PROC_TABLE.write();
+            table[3].id = 3;
+            table[3].parent_id = 2;
+            table[4].id = 4;
+            table[4].parent_id = INIT_PID;
+            table[5].id = 5;
+            table[5].parent_id = 2;
+            table[5].resumes_parent = true;
+        }
+
+        {
+            let mut table = PROC_TABLE.write();
+            bury(&mut table, 3, Some(ExitCode::IoError));
+            bury(&mut table, 4, Some(ExitCode::Failure));
+            // Those of a plain SPAWN are not kept, their parent has the status
+            bury(&mut table, 5, Some(ExitCode::Success));
+            assert!(table[3].is_zombie() && !table[3].is_runnable());
+            assert!(table[4].is_zombie());
+            assert_eq!(table[5].id, 0);
+        }
+
+        // Init reaps its own, and only those
+        assert_eq!(reap_orphans(), 1);
+        assert_eq!(PROC_TABLE.read()[4].id, 0);
+        assert!(PROC_TABLE.read()[3].is_zombie());
+        assert!(!user_pids().contains(&3));
+        assert!(sys::signal::send(3, sys::signal::SIGTERM).is_err());
+
+        assert_eq!(wait(0), Ok((3, ExitCode::IoError)));
+        assert_eq!(PROC_TABLE.read()[3].id, 0);
+        assert_eq!(wait(3), Err(()));
+
+        // A zombie whose parent goes away is reaped with it
+        {
+            let mut table = PROC_TABLE.write();
+            table[3].id = 3;
+            table[3].parent_id = 2;
+            bury(&mut table, 3, Some(ExitCode::Success));
+            reparent_children(&mut table, 2);
+            assert_eq!(table[3].id, 0);
+        }
+    });
+}
@@ -1,34 +1,292 @@
 //! Scheduler for Chilena — Round-Robin Preemptive (Proper Context Switch)
+//!
+//! Processes are picked by priority class first: the best class with a
+//! ready process wins, and its members take turns. Within a turn the nice
+//! value decides how many time slices a process gets. Blocked and stopped
+//! processes are never picked, so a busy high-priority process holds the
+//! CPU only while nothing better wakes up.
 
 use crate::sys::process::{
-    CURRENT_PID, NEXT_PID, PROC_TABLE,
+    CURRENT_PID, INIT_PID, NEXT_PID, PROC_TABLE,
     save_registers, save_stack_frame,
     CpuRegisters, MAX_PROCS,
 };
-use crate::sys::ipc::BlockState;
 use crate::sys::gdt::GDT;
+use crate::sys::ipc::BlockState;
 
-use core::sync::atomic::{AtomicU64, Ordering};
-use x86_64::registers::control::Cr3;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::arch::asm;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::{Cr3, Cr4, Cr4Flags};
 use x86_64::structures::idt::{InterruptStackFrame, InterruptStackFrameValue};
+use x86_64::structures::paging::mapper::{OffsetPageTable, Translate, TranslateResult};
+use x86_64::structures::paging::{PageTableFlags, PhysFrame};
 use x86_64::VirtAddr;
 
 // ---------------------------------------------------------------------------
 // Scheduler interval
 // ---------------------------------------------------------------------------
 
-/// Switch process every 10ms (10 ticks @ 1000 Hz)
-const SCHED_INTERVAL: u64 = 10;
+/// Switch process every 10ms, whatever the tick rate
+const SCHED_INTERVAL_MS: u64 = 10;
 
+/// Time slice length in ticks at the current tick rate
+fn interval_ticks() -> u64 {
+    (SCHED_INTERVAL_MS * crate::sys::clk::tick_hz() / 1000).max(1)
+}
+
+/// Timer interrupts seen by `schedule`, the only place that counts them
 static TICK: AtomicU64 = AtomicU64::new(0);
 
+/// Cleared by the `nopreempt` boot option to keep the timer from switching
+/// processes (handy when debugging one process at a time)
+static PREEMPT: AtomicBool = AtomicBool::new(true);
+
+pub fn set_preempt(on: bool) {
+    PREEMPT.store(on, Ordering::Relaxed);
+}
+
 // ---------------------------------------------------------------------------
-// tick() — dipanggil dari clk::on_tick, HANYA increment counter
-// Scheduling sesungguhnya ada di schedule() karena butuh akses ke stack frame
+// Load average
 // ---------------------------------------------------------------------------
 
-pub fn tick() {
-    TICK.fetch_add(1, Ordering::Relaxed);
+/// The run queue is sampled every 5 seconds, as on Unix
+const LOAD_INTERVAL_MS: u64 = 5_000;
+
+/// Loads are fixed point with 11 fractional bits
+const LOAD_SHIFT: u32 = 11;
+pub const LOAD_ONE: u64 = 1 << LOAD_SHIFT;
+
+/// How much of the old average survives one sample: e^(-5s/window) for
+/// the 1, 5 and 15 minute windows, in fixed point
+const LOAD_DECAY: [u64; 3] = [1884, 2014, 2037];
+
+static LOAD: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+/// 1, 5 and 15 minute moving averages of the number of runnable
+/// processes, in units of `LOAD_ONE`
+pub fn load_average() -> [u64; 3] {
+    LOAD.each_ref().map(|l| l.load(Ordering::Relaxed))
+}
+
+/// Fold the current number of runnable user processes into the load
+/// averages; idle and init, which wait in the kernel, do not count.
+/// Skipped if the process table is busy.
+fn sample_load() {
+    let Some(table) = PROC_TABLE.try_read() else { return };
+    let runnable = (INIT_PID + 1..MAX_PROCS).filter(|&pid| table[pid].is_runnable()).count() as u64;
+    drop(table);
+    for (load, decay) in LOAD.iter().zip(LOAD_DECAY) {
+        let old = load.load(Ordering::Relaxed);
+        let new = (old * decay + runnable * LOAD_ONE * (LOAD_ONE - decay)) >> LOAD_SHIFT;
+        load.store(new, Ordering::Relaxed);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Scheduling mode
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The timer switches processes when their time slices run out
+    Preemptive,
+    /// Processes only change at `yield_now`, so a test decides exactly
+    /// how they interleave
+    Deterministic,
+}
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+pub fn mode() -> Mode {
+    if DETERMINISTIC.load(Ordering::SeqCst) { Mode::Deterministic } else { Mode::Preemptive }
+}
+
+/// Only test builds can leave the preemptive mode
+#[cfg(test)]
+pub fn set_mode(mode: Mode) {
+    DETERMINISTIC.store(mode == Mode::Deterministic, Ordering::SeqCst);
+}
+
+/// Give up the CPU and return the pid that has it now.
+///
+/// Preemptive: the rest of the time slice is dropped and the next timer
+/// tick switches to another process, if one is ready.
+///
+/// Deterministic: the next ready process in round-robin order becomes
+/// the current one right away, without a tick. Only the process table's
+/// idea of who runs changes; the caller goes on running kernel code on
+/// behalf of that process, which is how tests play several of them.
+pub fn yield_now() -> usize {
+    let cur = CURRENT_PID.load(Ordering::SeqCst);
+    match mode() {
+        Mode::Preemptive => {
+            PROC_TABLE.write()[cur].slice_left = 0;
+            idle();
+        }
+        Mode::Deterministic => {
+            if let Some(next) = next_runnable(cur) {
+                let pt_frame = PROC_TABLE.read()[next].pt_frame;
+                CURRENT_PID.store(next, Ordering::SeqCst);
+                switch_address_space(pt_frame);
+            }
+        }
+    }
+    CURRENT_PID.load(Ordering::SeqCst)
+}
+
+// ---------------------------------------------------------------------------
+// Priorities
+// ---------------------------------------------------------------------------
+
+pub const NICE_MIN: i8 = -20;
+pub const NICE_MAX: i8 = 19;
+
+/// How far the foreground job's nice value is lowered, and every
+/// background job's raised, so that typing stays responsive
+const FOREGROUND_BOOST: i8 = 5;
+
+/// Nice value the scheduler actually uses for `pid`
+pub fn effective_nice(pid: usize) -> i8 {
+    let nice = PROC_TABLE.read()[pid].nice;
+    let adjust = if crate::sys::console::foreground() == pid { -FOREGROUND_BOOST } else { FOREGROUND_BOOST };
+    nice.saturating_add(adjust).clamp(NICE_MIN, NICE_MAX)
+}
+
+/// Priority classes, 0 (first) to PRIO_LOWEST
+pub const PRIO_HIGHEST: u8 = 0;
+pub const PRIO_LOWEST:  u8 = 7;
+pub const PRIO_DEFAULT: u8 = 4;
+
+/// Priority class the scheduler actually uses for `pid`: the foreground
+/// job moves one class up, so typing is not stuck behind a busy
+/// background job of the same class
+pub fn effective_priority(pid: usize) -> u8 {
+    boosted(pid, PROC_TABLE.read()[pid].priority, crate::sys::console::foreground())
+}
+
+fn boosted(pid: usize, prio: u8, foreground: usize) -> u8 {
+    if foreground == pid { prio.saturating_sub(1) } else { prio }
+}
+
+/// Base priority class of `pid`, None if there is no such process
+pub fn priority(pid: usize) -> Option<u8> {
+    let table = PROC_TABLE.read();
+    table.get(pid).filter(|p| p.is_alive()).map(|p| p.priority)
+}
+
+/// Set the priority class of `pid`
+pub(crate) fn set_priority(pid: usize, prio: u8) -> Result<(), ()> {
+    if prio > PRIO_LOWEST || pid >= MAX_PROCS {
+        return Err(());
+    }
+    let mut table = PROC_TABLE.write();
+    if !table[pid].is_alive() {
+        return Err(());
+    }
+    table[pid].priority = prio;
+    Ok(())
+}
+
+/// Scheduling intervals a process runs before the next one gets the CPU:
+/// 9 at nice -20, 5 at nice 0, 1 at nice 19
+pub fn time_slices(pid: usize) -> u64 {
+    (20 - effective_nice(pid) as i64) as u64 / 5 + 1
+}
+
+/// Set the base nice value of `pid`
+pub(crate) fn set_nice(pid: usize, nice: i8) -> Result<(), ()> {
+    if !(NICE_MIN..=NICE_MAX).contains(&nice) || pid >= MAX_PROCS {
+        return Err(());
+    }
+    let mut table = PROC_TABLE.write();
+    if !table[pid].is_alive() {
+        return Err(());
+    }
+    table[pid].nice = nice;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Sleeping
+// ---------------------------------------------------------------------------
+
+/// Earliest deadline among blocked processes, u64::MAX if none has one
+static NEXT_WAKE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Put the current process to sleep until the uptime reaches `wake_ns`.
+/// It is passed over until `wake_sleepers` sees that time go by.
+pub fn fall_asleep(wake_ns: u64) {
+    block_until(BlockState::Sleeping { wake_ns });
+}
+
+/// Block the current process in `state`. If the state has a deadline,
+/// `wake_sleepers` ends the wait once it passes.
+pub fn block_until(state: BlockState) {
+    interrupts::without_interrupts(|| {
+        let pid = CURRENT_PID.load(Ordering::SeqCst);
+        PROC_TABLE.write()[pid].block = state;
+        if let Some(wake_ns) = state.deadline() {
+            NEXT_WAKE.fetch_min(wake_ns, Ordering::SeqCst);
+        }
+    });
+}
+
+fn is_asleep(pid: usize) -> bool {
+    matches!(PROC_TABLE.read()[pid].block, BlockState::Sleeping { .. })
+}
+
+/// Wait in the kernel for the current process to wake up. The timer
+/// runs other processes meanwhile.
+pub fn wait_until_awake() {
+    let pid = CURRENT_PID.load(Ordering::SeqCst);
+    while is_asleep(pid) {
+        idle();
+    }
+}
+
+/// Wait for the next interrupt, then do the deferred work it queued.
+/// Time nobody else wants also goes to compacting the VFS, and init
+/// reaps the children it inherited.
+pub fn idle() {
+    interrupts::enable_and_hlt();
+    crate::sys::workq::run();
+    if CURRENT_PID.load(Ordering::SeqCst) == INIT_PID {
+        crate::sys::process::reap_orphans();
+    }
+    crate::sys::fs::compact_if_idle();
+}
+
+/// SLEEP: the caller fell asleep in the syscall. Hand the CPU to another
+/// process now instead of at the end of the time slice; the caller goes
+/// on from this frame once woken. With nobody else ready, wait here.
+pub fn sleep_off_cpu(frame: &mut InterruptStackFrame, regs: &mut CpuRegisters) {
+    let pid = CURRENT_PID.load(Ordering::SeqCst);
+    if is_asleep(pid) && !yield_to_next(frame, regs) {
+        wait_until_awake();
+    }
+}
+
+/// Let the processes whose sleep or wait is over run again; called on
+/// every tick. A tick that finds the process table locked leaves them to
+/// the next one.
+pub fn wake_sleepers(now: u64) {
+    if now < NEXT_WAKE.load(Ordering::SeqCst) {
+        return;
+    }
+    let Some(mut table) = PROC_TABLE.try_write() else { return };
+    let mut next = u64::MAX;
+    for proc in table.iter_mut() {
+        if let Some(wake_ns) = proc.block.deadline() {
+            if wake_ns <= now {
+                proc.block = BlockState::Running;
+            } else {
+                next = next.min(wake_ns);
+            }
+        }
+    }
+    NEXT_WAKE.store(next, Ordering::SeqCst);
 }
 
 // ---------------------------------------------------------------------------
@@ -40,17 +298,24 @@ pub fn schedule(
     frame: &mut InterruptStackFrame,
     regs:  &mut CpuRegisters,
 ) {
-    let t = TICK.load(Ordering::Relaxed);
-    if t % SCHED_INTERVAL != 0 {
+    let t = TICK.fetch_add(1, Ordering::Relaxed) + 1;
+    wake_senders();
+    if t.is_multiple_of((LOAD_INTERVAL_MS * crate::sys::clk::tick_hz() / 1000).max(1)) {
+        sample_load();
+    }
+    if !PREEMPT.load(Ordering::Relaxed) || t % interval_ticks() != 0 {
         return;
     }
+    if mode() == Mode::Deterministic {
+        return; // switches only happen at yield_now
+    }
 
     // Hitung jumlah proses aktif dengan scan tabel langsung
     // (tidak pakai NEXT_PID karena bisa tidak sinkron setelah terminate)
     let has_other = {
         let table = PROC_TABLE.read();
         let cur   = CURRENT_PID.load(Ordering::SeqCst);
-        (1..MAX_PROCS).any(|i| i != cur && table[i].id != 0 && table[i].block == BlockState::Running)
+        (INIT_PID + 1..MAX_PROCS).any(|i| i != cur && table[i].is_runnable())
     };
     if !has_other {
         return; // tidak ada proses lain yang siap jalan
@@ -58,34 +323,71 @@ pub fn schedule(
 
     let cur = CURRENT_PID.load(Ordering::SeqCst);
 
-    // Simpan state proses yang sedang jalan
-    save_stack_frame(**frame);
-    save_registers(*regs);
+    // A runnable process keeps the CPU until its time slices are used up,
+    // unless a process of a better class became ready
+    let outranked = best_priority().is_some_and(|best| best < effective_priority(cur));
+    if !outranked {
+        let mut table = PROC_TABLE.write();
+        let p = &mut table[cur];
+        if p.is_runnable() && p.slice_left > 0 {
+            p.slice_left -= 1;
+            return;
+        }
+    }
 
-    // Cari proses berikutnya yang ready — scan 1..MAX_PROCS (bukan 1..NEXT_PID)
-    // Ini fix BUG #3: NEXT_PID tidak mencerminkan slot tertinggi yang aktif
-    let next = {
-        let table = PROC_TABLE.read();
-        let mut found = None;
-        for i in 1..MAX_PROCS {
-            let candidate = if cur == 0 {
-                i
-            } else {
-                ((cur - 1 + i) % (MAX_PROCS - 1)) + 1  // round-robin di range 1..MAX_PROCS
-            };
-            if candidate == 0 { continue; }
-            if table[candidate].id != 0 && table[candidate].block == BlockState::Running {
-                found = Some(candidate);
-                break;
+    if let Some(next) = next_runnable(cur).filter(|&p| p != cur) {
+        switch_to(next, frame, regs);
+    }
+}
+
+/// Let processes blocked in SEND on a full mailbox retry once it has
+/// been emptied; nothing else wakes them
+fn wake_senders() {
+    let Some(mut table) = PROC_TABLE.try_write() else { return };
+    for pid in 0..MAX_PROCS {
+        if let BlockState::WaitingSend { target } = table[pid].block {
+            if table[target].mailbox.is_none() {
+                table[pid].block = BlockState::Running;
             }
         }
-        found
-    };
+    }
+}
 
-    let next_pid = match next {
-        Some(p) if p != cur => p,
-        _ => return,
-    };
+/// YIELD: hand the CPU to another ready process right away, the best
+/// class first but a worse one rather than none. False if there is no
+/// other one and the caller keeps running.
+pub fn yield_to_next(frame: &mut InterruptStackFrame, regs: &mut CpuRegisters) -> bool {
+    let cur = CURRENT_PID.load(Ordering::SeqCst);
+    match pick(cur, false) {
+        Some(next) => {
+            switch_to(next, frame, regs);
+            true
+        }
+        None => false,
+    }
+}
+
+/// YIELD: give the rest of the slice to another ready process, by the
+/// same rules as `yield_now`. Deterministic mode only moves the table's
+/// idea of who runs. Idle and init are kernel-resident and never switched
+/// away from here, so for them it does nothing.
+pub fn yield_syscall(frame: &mut InterruptStackFrame, regs: &mut CpuRegisters) {
+    if CURRENT_PID.load(Ordering::SeqCst) <= INIT_PID {
+        return;
+    }
+    match mode() {
+        Mode::Preemptive    => { yield_to_next(frame, regs); }
+        Mode::Deterministic => { yield_now(); }
+    }
+}
+
+/// Save the interrupted state of the current process in its slot and
+/// make `frame` and `regs` resume `next_pid` instead, once the interrupt
+/// or syscall handler returns
+fn switch_to(next_pid: usize, frame: &mut InterruptStackFrame, regs: &mut CpuRegisters) {
+    // Simpan state proses yang sedang jalan
+    save_stack_frame(**frame);
+    save_registers(*regs);
 
     // Ambil state proses berikutnya
     let (maybe_frame, next_regs, pt_frame, entry, stack) = {
@@ -100,16 +402,16 @@ pub fn schedule(
         )
     };
 
+    let slices = time_slices(next_pid);
+    PROC_TABLE.write()[next_pid].slice_left = slices - 1;
     CURRENT_PID.store(next_pid, Ordering::SeqCst);
 
     // Restore register proses berikutnya
     *regs = next_regs;
 
-    // Switch page table
-    unsafe {
-        let (_, flags) = Cr3::read();
-        Cr3::write(pt_frame, flags);
-    }
+    switch_address_space(pt_frame);
+    #[cfg(debug_assertions)]
+    assert_address_space(next_pid);
 
     // FIX BUG #2: schedule() TIDAK PERNAH iretq langsung.
     // Selalu write ke frame dan return normal ke caller (timer_handler → irq0 → iretq).
@@ -132,3 +434,523 @@ pub fn schedule(
         }
     }
 }
+
+/// Best priority class among the ready user processes
+fn best_priority() -> Option<u8> {
+    best_priority_except(None)
+}
+
+/// Runs from the timer interrupt, so it must not allocate
+fn best_priority_except(skip: Option<usize>) -> Option<u8> {
+    let foreground = crate::sys::console::foreground();
+    let table = PROC_TABLE.read();
+    (INIT_PID + 1..MAX_PROCS)
+        .filter(|&i| Some(i) != skip && table[i].is_runnable())
+        .map(|i| boosted(i, table[i].priority, foreground))
+        .min()
+}
+
+/// The ready process of the best class that follows `cur` in
+/// round-robin order, `cur` itself if no other one of that class is ready
+fn next_runnable(cur: usize) -> Option<usize> {
+    pick(cur, true)
+}
+
+/// Like `next_runnable`, leaving `cur` out of the running unless
+/// `with_cur`
+fn pick(cur: usize, with_cur: bool) -> Option<usize> {
+    let best = best_priority_except((!with_cur).then_some(cur))?;
+    // Cari proses berikutnya yang ready — scan 1..MAX_PROCS (bukan 1..NEXT_PID)
+    // Ini fix BUG #3: NEXT_PID tidak mencerminkan slot tertinggi yang aktif
+    for i in 1..MAX_PROCS {
+        let candidate = if cur == 0 {
+            i
+        } else {
+            ((cur - 1 + i) % (MAX_PROCS - 1)) + 1  // round-robin di range 1..MAX_PROCS
+        };
+        // Idle and init are kernel-resident, never switched to from here
+        if candidate == 0 || candidate == INIT_PID { continue; }
+        if candidate == cur && !with_cur { continue; }
+        if PROC_TABLE.read()[candidate].is_runnable() && effective_priority(candidate) == best {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+// ---------------------------------------------------------------------------
+// Address space switch
+// ---------------------------------------------------------------------------
+
+/// CR3 writes done on context switches
+static CR3_RELOADS: AtomicU64 = AtomicU64::new(0);
+/// Those of them that flushed TLB entries
+static TLB_FLUSHES: AtomicU64 = AtomicU64::new(0);
+
+/// Address spaces tagged at once. With PCIDs on, the TLB keeps entries
+/// of each tagged page table across switches; a page table that is not
+/// tagged takes over the least recently assigned tag and flushes it.
+const PCID_POOL: usize = 16;
+/// CR3 bit 63: keep the TLB entries of the PCID being loaded
+const CR3_NOFLUSH: u64 = 1 << 63;
+
+static PCID_ON: AtomicBool = AtomicBool::new(false);
+
+struct PcidPool {
+    /// Page table each tag currently stands for
+    owner: [Option<PhysFrame>; PCID_POOL],
+    /// Next tag to hand out when none is free
+    next:  usize,
+}
+
+static PCIDS: Mutex<PcidPool> = Mutex::new(PcidPool { owner: [None; PCID_POOL], next: 1 });
+
+/// Turn on PCIDs if the CPU has them; switches then tag address spaces
+/// instead of flushing the whole TLB. Without them CR3 writes stay plain.
+pub fn init_pcid() {
+    if !crate::sys::cpu::has_pcid() {
+        klog!("CPU: no PCID, context switches flush the TLB");
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        // CR4.PCIDE can only be set while CR3 holds PCID 0
+        let (active, low) = Cr3::read_raw();
+        if low != 0 {
+            unsafe { Cr3::write_raw(active, 0); }
+        }
+        unsafe { Cr4::write(Cr4::read() | Cr4Flags::PCID); }
+        PCIDS.lock().owner[0] = Some(active);
+        PCID_ON.store(true, Ordering::SeqCst);
+    });
+    klog!("CPU: PCID enabled, {} tagged address spaces", PCID_POOL);
+}
+
+/// Load `pt_frame` into CR3, unless it is already there: contexts that
+/// share a page table keep the TLB. With PCIDs on, a page table that
+/// still has its tag keeps its TLB entries too.
+pub fn switch_address_space(pt_frame: PhysFrame) {
+    let (active, flags) = Cr3::read();
+    if active == pt_frame {
+        return;
+    }
+    CR3_RELOADS.fetch_add(1, Ordering::Relaxed);
+    if !PCID_ON.load(Ordering::Relaxed) {
+        unsafe { Cr3::write(pt_frame, flags); }
+        TLB_FLUSHES.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let (pcid, tagged) = pcid_for(pt_frame);
+    if !tagged {
+        TLB_FLUSHES.fetch_add(1, Ordering::Relaxed);
+    }
+    let noflush = if tagged { CR3_NOFLUSH } else { 0 };
+    let value = pt_frame.start_address().as_u64() | pcid as u64 | noflush;
+    unsafe { asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags)); }
+}
+
+/// The tag of `pt_frame`, and whether it already had one (so its TLB
+/// entries are still valid)
+fn pcid_for(pt_frame: PhysFrame) -> (u16, bool) {
+    let mut pool = PCIDS.lock();
+    if let Some(i) = pool.owner.iter().position(|&o| o == Some(pt_frame)) {
+        return (i as u16, true);
+    }
+    let i = pool.owner.iter().position(Option::is_none).unwrap_or_else(|| {
+        // Tag 0 stays with the kernel's page table
+        let i = pool.next;
+        pool.next = if i + 1 == PCID_POOL { 1 } else { i + 1 };
+        i
+    });
+    pool.owner[i] = Some(pt_frame);
+    (i as u16, false)
+}
+
+/// Drop the tag of a page table that is being freed, so a new page
+/// table in the same frame starts with a flush
+pub fn forget_address_space(pt_frame: PhysFrame) {
+    let mut pool = PCIDS.lock();
+    for owner in pool.owner.iter_mut().filter(|o| **o == Some(pt_frame)) {
+        *owner = None;
+    }
+}
+
+/// `(CR3 loads, TLB flushes)` done by context switches
+pub fn switch_stats() -> (u64, u64) {
+    (CR3_RELOADS.load(Ordering::Relaxed), TLB_FLUSHES.load(Ordering::Relaxed))
+}
+
+// ---------------------------------------------------------------------------
+// Address space checks (debug builds)
+// ---------------------------------------------------------------------------
+
+/// Ways the active address space can disagree with the process table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpaceError {
+    /// CR3 does not hold the page table of the current process
+    WrongCr3 { active: PhysFrame, expected: PhysFrame },
+    /// The entry page is unmapped, or mapped without USER_ACCESSIBLE
+    EntryNotUser { addr: u64, flags: Option<PageTableFlags> },
+}
+
+/// Check that CR3 is `pid`'s page table and that its entry point is
+/// mapped for userspace in it
+pub fn check_address_space(pid: usize) -> Result<(), SpaceError> {
+    let (expected, entry) = {
+        let table = PROC_TABLE.read();
+        (table[pid].pt_frame, table[pid].code_base + table[pid].entry_point)
+    };
+    let (active, _) = Cr3::read();
+    if active != expected {
+        return Err(SpaceError::WrongCr3 { active, expected });
+    }
+
+    let offset = VirtAddr::new(crate::sys::mem::phys_mem_offset());
+    let mapper = unsafe { OffsetPageTable::new(crate::sys::mem::active_page_table(), offset) };
+    let flags = match mapper.translate(VirtAddr::new(entry)) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    };
+    match flags {
+        Some(f) if f.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE) => Ok(()),
+        _ => Err(SpaceError::EntryNotUser { addr: entry, flags }),
+    }
+}
+
+/// Stop right at the switch that broke the address space, instead of at
+/// some later page fault far from the cause
+#[cfg(debug_assertions)]
+fn assert_address_space(pid: usize) {
+    if let Err(e) = check_address_space(pid) {
+        kerror!("sched: bad address space after switch to pid {}: {:?}", pid, e);
+        panic!("sched: address space of pid {} is inconsistent: {:?}", pid, e);
+    }
+}
+
+#[test_case]
+fn background_job_gets_smaller_share() {
+    use crate::sys::console;
+    use crate::sys::process::Process;
+    use alloc::boxed::Box;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let saved_fg = console::foreground();
+        for pid in [2, 3] {
+            PROC_TABLE.write()[pid].id = pid;
+        }
+
+        // Two jobs at the same nice value: the foreground one runs longer
+        console::set_foreground(2);
+        let (fg, bg) = (time_slices(2), time_slices(3));
+        assert!(fg > bg);
+
+        // Sending job 2 to the background evens them out again
+        console::set_foreground(INIT_PID);
+        assert_eq!(time_slices(2), bg);
+
+        set_nice(3, NICE_MIN).unwrap();
+        assert!(time_slices(3) > time_slices(2));
+        assert!(set_nice(3, NICE_MAX + 1).is_err());
+
+        console::set_foreground(saved_fg);
+        for pid in [2, 3] {
+            PROC_TABLE.write()[pid] = Box::new(Process::new());
+        }
+    });
+}
+
+#[test_case]
+fn desynced_cr3_is_detected() {
+    use crate::sys::process::Process;
+    use alloc::boxed::Box;
+    use x86_64::PhysAddr;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let pid = 2;
+        let (kernel_pt, _) = Cr3::read();
+        let other = PhysFrame::containing_address(PhysAddr::new(kernel_pt.start_address().as_u64() + 4096));
+        {
+            let mut table = PROC_TABLE.write();
+            table[pid].id       = pid;
+            table[pid].pt_frame = other;
+        }
+        assert_eq!(
+            check_address_space(pid),
+            Err(SpaceError::WrongCr3 { active: kernel_pt, expected: other })
+        );
+
+        // Right table, but nothing is mapped at the entry point
+        {
+            let mut table = PROC_TABLE.write();
+            table[pid].pt_frame  = kernel_pt;
+            table[pid].code_base = 0x0080_0000;
+        }
+        assert!(matches!(check_address_space(pid), Err(SpaceError::EntryNotUser { .. })));
+
+        PROC_TABLE.write()[pid] = Box::new(Process::new());
+    });
+}
+
+#[test_case]
+fn same_page_table_skips_cr3_reload() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let (active, _) = Cr3::read();
+        let before = switch_stats();
+        switch_address_space(active);
+        assert_eq!(switch_stats(), before);
+        assert_eq!(Cr3::read().0, active);
+    });
+}
+
+#[test_case]
+fn pcid_tags_are_reused_then_recycled() {
+    use x86_64::PhysAddr;
+
+    let frame = |n: u64| PhysFrame::containing_address(PhysAddr::new(0x7000_0000 + n * 4096));
+    let saved = core::mem::replace(&mut *PCIDS.lock(), PcidPool { owner: [None; PCID_POOL], next: 1 });
+    PCIDS.lock().owner[0] = Some(frame(0));
+
+    let (a, fresh) = pcid_for(frame(1));
+    assert!(!fresh && a != 0);
+    assert_eq!(pcid_for(frame(1)), (a, true));
+
+    // A full pool recycles tags, never the kernel's
+    for n in 2..PCID_POOL as u64 + 4 {
+        let (tag, tagged) = pcid_for(frame(n));
+        assert!(!tagged && tag != 0);
+    }
+    assert_eq!(pcid_for(frame(0)), (0, true));
+
+    // A freed page table gives its tag up
+    let (b, _) = pcid_for(frame(100));
+    forget_address_space(frame(100));
+    assert_eq!(pcid_for(frame(100)), (b, false));
+
+    *PCIDS.lock() = saved;
+}
+
+#[test_case]
+fn deterministic_mode_interleaves_at_yields() {
+    use crate::sys::ipc::{self, Message};
+    use crate::sys::process::{current_pid, FakeProcess};
+
+    use alloc::vec::Vec;
+
+    let (producer, consumer) = (2, 3);
+    let enabled = interrupts::are_enabled();
+    let _consumer = FakeProcess::enter(consumer);
+    let _producer = FakeProcess::enter(producer);
+    set_mode(Mode::Deterministic);
+
+    // Timer ticks no longer switch processes
+    interrupts::enable_and_hlt();
+    interrupts::enable_and_hlt();
+    interrupts::disable();
+    assert_eq!(current_pid(), producer);
+
+    // Each side does one step, then hands over
+    let mut trace = Vec::new();
+    for n in 0..3u8 {
+        assert!(!ipc::has_message(consumer));
+        assert_eq!(ipc::send(consumer, 1, &[n]), 0);
+        trace.push(('p', n));
+        assert_eq!(yield_now(), consumer);
+
+        let mut msg = Message::empty();
+        assert_eq!(ipc::recv(&mut msg), 0);
+        trace.push(('c', msg.data[0]));
+        assert_eq!(yield_now(), producer);
+    }
+    assert_eq!(trace, [('p', 0), ('c', 0), ('p', 1), ('c', 1), ('p', 2), ('c', 2)]);
+
+    // A process that cannot run is passed over
+    PROC_TABLE.write()[consumer].stopped = true;
+    assert_eq!(yield_now(), producer);
+
+    set_mode(Mode::Preemptive);
+    if enabled {
+        interrupts::enable();
+    }
+}
+
+#[test_case]
+fn yield_syscall_follows_the_mode() {
+    use crate::sys::process::{current_pid, set_pid, FakeProcess};
+    use x86_64::VirtAddr;
+
+    let mut value = InterruptStackFrameValue {
+        instruction_pointer: VirtAddr::new(0x1000),
+        code_segment:        0x1B,
+        cpu_flags:           0x202,
+        stack_pointer:       VirtAddr::new(0x2000),
+        stack_segment:       0x23,
+    };
+    // SAFETY: InterruptStackFrame is a repr(C) wrapper of the value
+    let frame = unsafe { &mut *(&mut value as *mut InterruptStackFrameValue).cast::<InterruptStackFrame>() };
+    let mut regs = CpuRegisters::default();
+
+    interrupts::without_interrupts(|| {
+        let _second = FakeProcess::enter(3);
+        let _first  = FakeProcess::enter(2);
+        set_mode(Mode::Deterministic);
+
+        // The next process takes over without its frame being loaded
+        yield_syscall(frame, &mut regs);
+        assert_eq!(current_pid(), 3);
+        assert_eq!(frame.instruction_pointer.as_u64(), 0x1000);
+
+        // Init keeps the CPU
+        set_pid(INIT_PID);
+        yield_syscall(frame, &mut regs);
+        assert_eq!(current_pid(), INIT_PID);
+
+        set_mode(Mode::Preemptive);
+    });
+}
+
+#[test_case]
+fn better_class_runs_first_and_equals_take_turns() {
+    use crate::sys::console;
+    use crate::sys::process::Process;
+    use alloc::boxed::Box;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let saved_fg = console::foreground();
+        console::set_foreground(INIT_PID);
+        for pid in [2, 3, 4] {
+            PROC_TABLE.write()[pid].id = pid;
+        }
+        set_priority(3, 2).unwrap();
+        set_priority(4, 2).unwrap();
+        assert!(set_priority(2, PRIO_LOWEST + 1).is_err());
+
+        // 3 and 4 share the best class and alternate; 2 waits
+        assert_eq!(next_runnable(2), Some(3));
+        assert_eq!(next_runnable(3), Some(4));
+        assert_eq!(next_runnable(4), Some(3));
+
+        // Blocked processes are skipped whatever their class
+        PROC_TABLE.write()[3].block = BlockState::WaitingRecv;
+        PROC_TABLE.write()[4].block = BlockState::WaitingChild;
+        assert_eq!(next_runnable(3), Some(2));
+
+        // The foreground job moves one class up
+        PROC_TABLE.write()[3].block = BlockState::Running;
+        console::set_foreground(2);
+        set_priority(2, 2).unwrap();
+        assert_eq!(next_runnable(3), Some(2));
+
+        console::set_foreground(saved_fg);
+        for pid in [2, 3, 4] {
+            PROC_TABLE.write()[pid] = Box::new(Process::new());
+        }
+    });
+}
+
+#[test_case]
+fn yield_hands_over_even_to_a_worse_class() {
+    use crate::sys::console;
+    use crate::sys::process::Process;
+    use alloc::boxed::Box;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let saved_fg = console::foreground();
+        console::set_foreground(INIT_PID);
+        PROC_TABLE.write()[2].id = 2;
+
+        // Alone, there is nobody to yield to
+        assert_eq!(pick(2, false), None);
+
+        // The timer keeps the better class running, a yield does not
+        PROC_TABLE.write()[3].id = 3;
+        set_priority(3, PRIO_LOWEST).unwrap();
+        assert_eq!(next_runnable(2), Some(2));
+        assert_eq!(pick(2, false), Some(3));
+
+        console::set_foreground(saved_fg);
+        for pid in [2, 3] {
+            PROC_TABLE.write()[pid] = Box::new(Process::new());
+        }
+    });
+}
+
+#[test_case]
+fn sleepers_wake_when_their_time_comes() {
+    use crate::sys::process::FakeProcess;
+
+    interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+
+        fall_asleep(1_000);
+        assert!(is_asleep(2) && !PROC_TABLE.read()[2].is_runnable());
+        assert_eq!(NEXT_WAKE.load(Ordering::SeqCst), 1_000);
+
+        // Too early: still asleep, and still due at the same time
+        wake_sleepers(999);
+        assert!(is_asleep(2));
+        wake_sleepers(1_000);
+        assert!(PROC_TABLE.read()[2].is_runnable());
+        assert_eq!(NEXT_WAKE.load(Ordering::SeqCst), u64::MAX);
+    });
+}
+
+#[test_case]
+fn blocked_sender_retries_once_mailbox_empties() {
+    use crate::sys::ipc::Message;
+    use crate::sys::process::Process;
+    use alloc::boxed::Box;
+
+    interrupts::without_interrupts(|| {
+        for pid in [2, 3] {
+            PROC_TABLE.write()[pid].id = pid;
+        }
+        PROC_TABLE.write()[2].block = BlockState::WaitingSend { target: 3 };
+        PROC_TABLE.write()[3].mailbox = Some(Message::empty());
+
+        wake_senders();
+        assert!(!PROC_TABLE.read()[2].is_runnable());
+
+        PROC_TABLE.write()[3].mailbox = None;
+        wake_senders();
+        assert!(PROC_TABLE.read()[2].is_runnable());
+
+        for pid in [2, 3] {
+            PROC_TABLE.write()[pid] = Box::new(Process::new());
+        }
+    });
+}
+
+#[test_case]
+fn busy_processes_raise_the_load_average() {
+    use crate::sys::process::Process;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    interrupts::without_interrupts(|| {
+        let saved = load_average();
+        for load in &LOAD {
+            load.store(0, Ordering::Relaxed);
+        }
+        for pid in [2, 3, 4] {
+            PROC_TABLE.write()[pid].id = pid;
+        }
+
+        // Ten samples, under a minute: the short average climbs the fastest
+        let mut one = Vec::new();
+        for _ in 0..10 {
+            sample_load();
+            one.push(load_average()[0]);
+        }
+        assert!(one.windows(2).all(|w| w[0] < w[1]));
+        let [one, five, fifteen] = load_average();
+        assert!(one > 3 * LOAD_ONE / 2 && five < one && fifteen < five);
+        // Never past the number that was running
+        assert!(one < 3 * LOAD_ONE);
+
+        for pid in [2, 3, 4] {
+            PROC_TABLE.write()[pid] = Box::new(Process::new());
+        }
+        for (load, value) in LOAD.iter().zip(saved) {
+            load.store(value, Ordering::Relaxed);
+        }
+    });
+}
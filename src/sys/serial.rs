@@ -1,8 +1,11 @@
 //! Serial Port — UART 16550 (COM1 = 0x3F8)
 //!
-//! Used for early boot logging and debugging output.
+//! Used for early boot logging and debugging output. Received bytes go
+//! to the console like keystrokes, and are also kept in a serial-only
+//! buffer for `read_line`, for headless setups without a keyboard.
 
 use crate::sys;
+use alloc::string::String;
 use core::fmt;
 use core::fmt::Write;
 use lazy_static::lazy_static;
@@ -18,6 +21,12 @@ lazy_static! {
     };
 }
 
+/// Bytes kept for `read_line` while nobody reads them
+const INPUT_LIMIT: usize = 4096;
+
+/// Received characters not yet taken by `read_line`
+static INPUT: Mutex<String> = Mutex::new(String::new());
+
 pub fn init() {
     // Trigger lazy_static initialization
     let _ = PORT.lock();
@@ -38,17 +47,131 @@ pub fn print_fmt(args: fmt::Arguments) {
     });
 }
 
+/// Write to COM1 without taking the port lock, for the panic path where
+/// the lock may be held by the code that panicked. The port is already
+/// set up by `init`.
+pub fn write_fmt_unlocked(args: fmt::Arguments) {
+    let mut port = unsafe { SerialPort::new(0x3F8) };
+    port.write_fmt(args).ok();
+}
+
 fn on_interrupt() {
     let byte = interrupts::without_interrupts(|| {
         PORT.lock().receive()
     });
 
     if byte == 0xFF { return; } // ignore invalid byte
+    sys::workq::push(sys::workq::Work::Serial(byte));
+}
 
+/// Feed a queued byte to the console (bottom half, interrupts enabled)
+pub fn handle_byte(byte: u8) {
     let ch = match byte as char {
         '\r' => '\n',
         '\x7F' => '\x08', // DEL → BS
         c => c,
     };
+    interrupts::without_interrupts(|| {
+        let mut input = INPUT.lock();
+        if ch == '\x08' {
+            if !input.ends_with('\n') {
+                input.pop();
+            }
+            return;
+        }
+        if input.len() >= INPUT_LIMIT {
+            // Nobody is reading: forget the oldest input
+            let cut = input.find('\n').map_or(input.len(), |i| i + 1);
+            input.drain(..cut);
+        }
+        input.push(ch);
+    });
     sys::console::input_char(ch);
 }
+
+/// Move buffered input into `buf` without waiting; returns the byte count
+pub fn read(buf: &mut [u8]) -> usize {
+    sys::workq::run();
+    interrupts::without_interrupts(|| {
+        let mut input = INPUT.lock();
+        // Whole characters only
+        let mut n = 0;
+        for c in input.chars() {
+            if n + c.len_utf8() > buf.len() {
+                break;
+            }
+            c.encode_utf8(&mut buf[n..]);
+            n += c.len_utf8();
+        }
+        input.drain(..n);
+        n
+    })
+}
+
+/// Like `read`, but wait until at least one byte has arrived
+pub fn read_blocking(buf: &mut [u8]) -> usize {
+    loop {
+        let n = read(buf);
+        if n > 0 || buf.is_empty() {
+            return n;
+        }
+        interrupts::enable_and_hlt();
+    }
+}
+
+pub fn has_input() -> bool {
+    sys::workq::run();
+    interrupts::without_interrupts(|| !INPUT.lock().is_empty())
+}
+
+/// Read a line from COM1 only (blocking, until newline), newline included.
+/// The same input still reaches stdin too.
+pub fn read_line() -> String {
+    loop {
+        if let Some(line) = try_read_line() {
+            return line;
+        }
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Take a complete line from COM1 if one is waiting
+pub fn try_read_line() -> Option<String> {
+    sys::workq::run();
+    interrupts::without_interrupts(|| {
+        let mut input = INPUT.lock();
+        let pos = input.find('\n')?;
+        Some(input.drain(..=pos).collect())
+    })
+}
+
+#[test_case]
+fn serial_lines_are_read_separately() {
+    for &b in b"ab\x7Fc\rnext" {
+        handle_byte(b);
+    }
+    assert_eq!(try_read_line().as_deref(), Some("ac\n"));
+    assert_eq!(try_read_line(), None);
+    handle_byte(b'\n');
+    assert_eq!(try_read_line().as_deref(), Some("next\n"));
+    sys::console::STDIN.lock().clear();
+}
+
+#[test_case]
+fn dev_serial_reads_buffered_input() {
+    use sys::fs::{PollEvent, Resource};
+
+    let mut dev = sys::fs::open_resource("/dev/serial", 0).unwrap();
+    assert!(!dev.poll(PollEvent::Read));
+    for &b in b"hi\r" {
+        handle_byte(b);
+    }
+    assert!(dev.poll(PollEvent::Read));
+    let mut buf = [0u8; 8];
+    assert_eq!(dev.read(&mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"hi\n");
+    assert!(!dev.poll(PollEvent::Read));
+    assert!(matches!(sys::fs::open_resource("/dev/null", 0), Some(Resource::Device(sys::fs::Device::Null))));
+    assert!(sys::fs::open_resource("/dev/nope", 0).is_none());
+    sys::console::STDIN.lock().clear();
+}
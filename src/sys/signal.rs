@@ -0,0 +1,308 @@
+//! Signals — asynchronous notifications to processes
+//!
+//! Every process has a pending set and a blocked mask (bit n = signal n).
+//! Pending signals are acted on when the process is about to return to
+//! userspace, at the end of a syscall or a timer interrupt, and only if
+//! they are not blocked; blocked signals stay pending until unblocked.
+//!
+//! A handler runs on the process's own stack. Its return address is the
+//! restorer given to SIGACTION, which calls SIGRETURN to resume the
+//! interrupted code. One handler runs at a time: while it does, other
+//! catchable signals wait.
+//!
+//! SIGSTOP stops a process until SIGCONT, which resumes it as soon as it
+//! is sent. Both changes, and exits, are reported to the parent through
+//! WAITID and SIGCHLD.
+
+use crate::sys::process::{current_pid, ChildState, CpuRegisters, MAX_PROCS, MAX_PROC_MEM, PROC_TABLE};
+
+use x86_64::structures::idt::{InterruptStackFrame, InterruptStackFrameValue};
+use x86_64::VirtAddr;
+
+// ---------------------------------------------------------------------------
+// Signal numbers
+// ---------------------------------------------------------------------------
+
+pub const SIGINT:  u32 = 2;  // Interrupt from keyboard (Ctrl+C)
+pub const SIGKILL: u32 = 9;  // Kill, cannot be caught or blocked
+pub const SIGUSR1: u32 = 10; // User-defined
+pub const SIGUSR2: u32 = 12; // User-defined
+pub const SIGTERM: u32 = 15; // Polite request to terminate
+pub const SIGCHLD: u32 = 17; // Child exited, stopped or continued (ignored by default)
+pub const SIGCONT: u32 = 18; // Continue a stopped process
+pub const SIGSTOP: u32 = 19; // Stop, cannot be caught or blocked
+pub const SIGXCPU: u32 = 24; // CPU time soft limit exceeded
+
+pub const NSIG: usize = 32;
+
+/// Handler values with a special meaning
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// `how` argument of SIGPROCMASK
+pub const SIG_BLOCK:   usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+pub const fn bit(sig: u32) -> u32 { 1 << sig }
+
+/// Signals that can be neither caught nor blocked
+const UNBLOCKABLE: u32 = bit(SIGKILL) | bit(SIGSTOP);
+
+fn valid(sig: u32) -> bool {
+    sig != 0 && (sig as usize) < NSIG
+}
+
+// ---------------------------------------------------------------------------
+// Per-process state
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Default)]
+pub struct Signals {
+    pub pending: u32,
+    pub blocked: u32,
+    handlers:    [usize; NSIG],
+    restorer:    usize,
+    /// Context the running handler interrupted, and the mask to restore
+    saved:       Option<(InterruptStackFrameValue, CpuRegisters, u32)>,
+}
+
+impl Signals {
+    pub const fn new() -> Self {
+        Self { pending: 0, blocked: 0, handlers: [SIG_DFL; NSIG], restorer: 0, saved: None }
+    }
+
+    /// State of a newly spawned child: the mask carries over, handlers do
+    /// not (they point into the parent's image)
+    pub fn inherit(&self) -> Self {
+        Self { blocked: self.blocked, ..Self::new() }
+    }
+
+    /// State of a forked child: it runs the same image, so only the
+    /// pending signals stay behind
+    pub fn fork(&self) -> Self {
+        Self { pending: 0, ..*self }
+    }
+
+    fn deliverable(&self) -> u32 {
+        let blocked = if self.saved.is_some() { !UNBLOCKABLE } else { self.blocked };
+        self.pending & !blocked
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Ignore,
+    Terminate,
+    Stop,
+    Handle(usize),
+}
+
+fn default_action(sig: u32) -> Action {
+    match sig {
+        // SIGCONT did its work when it was sent
+        SIGCHLD | SIGCONT => Action::Ignore,
+        SIGSTOP => Action::Stop,
+        _       => Action::Terminate,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Syscall backends
+// ---------------------------------------------------------------------------
+
+/// Mark `sig` pending for `pid`. SIGCONT also resumes it right away.
+pub(crate) fn send(pid: usize, sig: u32) -> Result<(), ()> {
+    if !valid(sig) || pid >= MAX_PROCS {
+        return Err(());
+    }
+    {
+        let mut table = PROC_TABLE.write();
+        if !table[pid].is_alive() {
+            return Err(());
+        }
+        let s = &mut table[pid].signals;
+        s.pending |= bit(sig);
+        // The later of a stop and a continue wins
+        match sig {
+            SIGSTOP => s.pending &= !bit(SIGCONT),
+            SIGCONT => s.pending &= !bit(SIGSTOP),
+            _ => {}
+        }
+        // A stopped process still has to run to die
+        if sig == SIGKILL {
+            table[pid].stopped = false;
+        }
+    }
+    if sig == SIGCONT {
+        crate::sys::process::resume(pid);
+    }
+    Ok(())
+}
+
+/// Change the blocked mask of the current process; returns the old mask
+pub(crate) fn sigprocmask(how: usize, set: u32) -> Result<u32, ()> {
+    let mut table = PROC_TABLE.write();
+    let sig = &mut table[current_pid()].signals;
+    let old = sig.blocked;
+    sig.blocked = match how {
+        SIG_BLOCK   => old | set,
+        SIG_UNBLOCK => old & !set,
+        SIG_SETMASK => set,
+        _ => return Err(()),
+    } & !UNBLOCKABLE & !1;
+    Ok(old)
+}
+
+/// Install `handler` (or SIG_DFL / SIG_IGN) for `sig`; returns the old one
+pub(crate) fn sigaction(sig: u32, handler: usize, restorer: usize) -> Result<usize, ()> {
+    if !valid(sig) || bit(sig) & UNBLOCKABLE != 0 {
+        return Err(());
+    }
+    let mut table = PROC_TABLE.write();
+    let s = &mut table[current_pid()].signals;
+    let old = s.handlers[sig as usize];
+    s.handlers[sig as usize] = handler;
+    if handler > SIG_IGN {
+        s.restorer = restorer;
+    }
+    Ok(old)
+}
+
+/// Take the lowest deliverable signal of `pid` and decide what to do with it
+pub fn take(pid: usize) -> Option<(u32, Action)> {
+    let mut table = PROC_TABLE.write();
+    let s = &mut table[pid].signals;
+    let ready = s.deliverable();
+    if ready == 0 {
+        return None;
+    }
+    let sig = ready.trailing_zeros();
+    s.pending &= !bit(sig);
+    let action = match s.handlers[sig as usize] {
+        SIG_DFL => default_action(sig),
+        SIG_IGN => Action::Ignore,
+        h       => Action::Handle(h),
+    };
+    Some((sig, action))
+}
+
+// ---------------------------------------------------------------------------
+// Delivery
+// ---------------------------------------------------------------------------
+
+/// Act on pending signals of the current process before the CPU returns
+/// to userspace through `frame`. Returns true if the process was
+/// terminated; the caller must then resume the parent, as after EXIT.
+/// A stopped process is only marked as such: the scheduler passes it
+/// over from then on.
+pub fn deliver(frame: &mut InterruptStackFrame, regs: &mut CpuRegisters) -> bool {
+    if frame.code_segment & 3 != 3 {
+        return false; // interrupted kernel code, try again later
+    }
+    let pid = current_pid();
+    while let Some((sig, action)) = take(pid) {
+        match action {
+            Action::Ignore => continue,
+            Action::Terminate => {
+                crate::sys::process::terminate(ChildState::Killed(sig));
+                return true;
+            }
+            Action::Stop => {
+                crate::sys::process::stop(pid, sig);
+                break;
+            }
+            Action::Handle(handler) => {
+                if !enter_handler(pid, sig, handler, frame, regs) {
+                    kwarn!("signal: bad stack for pid {}, killing it", pid);
+                    crate::sys::process::terminate(ChildState::Killed(sig));
+                    return true;
+                }
+                break;
+            }
+        }
+    }
+    false
+}
+
+/// Redirect the return to userspace into `handler(sig)`, with the
+/// restorer as its return address
+fn enter_handler(
+    pid: usize,
+    sig: u32,
+    handler: usize,
+    frame: &mut InterruptStackFrame,
+    regs: &mut CpuRegisters,
+) -> bool {
+    let (code_base, restorer) = {
+        let table = PROC_TABLE.read();
+        (table[pid].code_base, table[pid].signals.restorer)
+    };
+
+    // Skip the red zone, align, then push the return address
+    let sp = ((frame.stack_pointer.as_u64() - 128) & !0xF) - 8;
+    if sp < code_base || sp + 8 > code_base + MAX_PROC_MEM as u64 {
+        return false;
+    }
+    unsafe { *(sp as *mut u64) = restorer as u64; }
+
+    {
+        let mut table = PROC_TABLE.write();
+        let s = &mut table[pid].signals;
+        s.saved = Some((**frame, *regs, s.blocked));
+        s.blocked |= bit(sig) & !UNBLOCKABLE;
+    }
+
+    let mut next = **frame;
+    next.instruction_pointer = VirtAddr::new(handler as u64);
+    next.stack_pointer       = VirtAddr::new(sp);
+    unsafe { frame.as_mut().write(next); }
+    regs.rdi = sig as usize;
+    true
+}
+
+/// Resume the code a handler interrupted. Returns false if no handler
+/// was running.
+pub fn sigreturn(frame: &mut InterruptStackFrame, regs: &mut CpuRegisters) -> bool {
+    let saved = {
+        let mut table = PROC_TABLE.write();
+        let s = &mut table[current_pid()].signals;
+        s.saved.take().map(|(f, r, mask)| {
+            s.blocked = mask;
+            (f, r)
+        })
+    };
+    match saved {
+        Some((f, r)) => {
+            unsafe { frame.as_mut().write(f); }
+            *regs = r;
+            true
+        }
+        None => false,
+    }
+}
+
+#[test_case]
+fn blocked_signal_waits_for_unblock() {
+    use crate::sys::process::FakeProcess;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let pid = 2;
+        let _process = FakeProcess::enter(pid);
+
+        sigprocmask(SIG_BLOCK, bit(SIGINT)).unwrap();
+        send(pid, SIGINT).unwrap();
+        assert_eq!(take(pid), None);
+        assert_ne!(PROC_TABLE.read()[pid].signals.pending & bit(SIGINT), 0);
+
+        sigprocmask(SIG_UNBLOCK, bit(SIGINT)).unwrap();
+        assert_eq!(take(pid), Some((SIGINT, Action::Terminate)));
+        assert_eq!(take(pid), None);
+
+        // SIGKILL ignores the mask and cannot be caught
+        sigprocmask(SIG_SETMASK, u32::MAX).unwrap();
+        assert!(sigaction(SIGKILL, 0x1000, 0).is_err());
+        send(pid, SIGKILL).unwrap();
+        assert_eq!(take(pid), Some((SIGKILL, Action::Terminate)));
+    });
+}
@@ -0,0 +1,23 @@
+//! Error numbers returned by syscalls
+//!
+//! Syscalls that can fail for more than one reason return one of these
+//! (negative, as `isize`) instead of a bare -1.
+
+pub const EPERM:  isize = -1;  // Operation not permitted
+pub const ENOENT: isize = -2;  // No such file or directory
+pub const EIO:    isize = -5;  // I/O error
+pub const ENOEXEC: isize = -8; // Exec format error
+pub const EBADF:  isize = -9;  // Bad file handle
+pub const ECHILD: isize = -10; // No child processes
+pub const EAGAIN: isize = -11; // Try again
+pub const ENOMEM: isize = -12; // Out of memory
+pub const EACCES: isize = -13; // Permission denied
+pub const EFAULT: isize = -14; // Bad address
+pub const EINVAL: isize = -22; // Invalid argument
+pub const EMFILE: isize = -24; // Too many open files
+pub const ENOTSOCK:     isize = -88;  // Handle is not a socket
+pub const EMSGSIZE:     isize = -90;  // Message too long
+pub const EADDRINUSE:   isize = -98;  // Address already in use
+pub const ENETUNREACH:  isize = -101; // Network is unreachable
+pub const ENOTCONN:     isize = -107; // Socket is not connected
+pub const ECONNREFUSED: isize = -111; // Connection refused
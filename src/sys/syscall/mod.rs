@@ -3,11 +3,13 @@
 //! Receives syscall number and raw arguments (usize),
 //! converts them to proper types, then calls the service layer.
 
+pub mod error;
 pub mod number;
 pub mod service;
 
 use crate::api::process::ExitCode;
 use crate::sys;
+use crate::sys::audit::{self, Reason};
 
 use core::arch::asm;
 
@@ -18,6 +20,30 @@ fn raw_str(ptr: *mut u8, len: usize) -> &'static str {
     }
 }
 
+/// One buffer of a READV/WRITEV call
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct IoVec {
+    pub base: usize,
+    pub len:  usize,
+}
+
+/// Most buffers a single READV/WRITEV may take
+pub const IOV_MAX: usize = 64;
+
+/// Check an iovec array and every buffer it points to; None if any of
+/// them is outside userspace
+fn user_iovecs(ptr: usize, count: usize) -> Option<&'static [IoVec]> {
+    let size = count.checked_mul(core::mem::size_of::<IoVec>())?;
+    if count > IOV_MAX || !validate_user_ptr(ptr, size) {
+        return None;
+    }
+    let iov = unsafe {
+        core::slice::from_raw_parts(sys::process::resolve_addr(ptr as u64) as *const IoVec, count)
+    };
+    iov.iter().all(|v| validate_user_ptr(v.base, v.len)).then_some(iov)
+}
+
 /// Validasi bahwa range ptr..ptr+len sepenuhnya ada di userspace address space
 /// FIX: cegah userspace baca/tulis memori kernel lewat syscall
 fn validate_user_ptr(ptr: usize, len: usize) -> bool {
@@ -35,7 +61,13 @@ fn validate_user_ptr(ptr: usize, len: usize) -> bool {
 }
 
 /// Receive syscall from IDT handler and forward to service layer
-pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    if !sys::process::syscall_allowed(n) {
+        if sys::process::seccomp_kills() {
+            sys::signal::send(sys::process::current_pid(), sys::signal::SIGKILL).ok();
+        }
+        return audit::deny(n, Reason::Filtered);
+    }
     match n {
         number::EXIT => {
             service::exit(ExitCode::from(a1)) as usize
@@ -47,27 +79,26 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
         }
 
         number::SPAWN => {
-            // a1=path_ptr, a2=path_len, a3=args_ptr, a4=args_len
+            // a1=path_ptr, a2=path_len, a3=args_ptr, a4=args_len, a5=flags
             if !validate_user_ptr(a1, a2) {
                 kdebug!("SPAWN: invalid path ptr {:#X} len {}", a1, a2);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr  = sys::process::resolve_addr(a1 as u64);
-            let len  = a2;
-            let path = raw_str(ptr, len);
+            let path = raw_str(ptr, a2);
             let args_ptr = a3;
             let args_len = a4;
-            service::spawn(path, args_ptr, args_len) as usize
+            service::spawn(path, a5, args_ptr, args_len) as usize
         }
 
         number::HALT => {
-            service::halt(a1)
+            service::halt(a1) as usize
         }
 
         number::OPEN => {
             if !validate_user_ptr(a1, a2) {
                 kdebug!("OPEN: invalid path ptr {:#X} len {}", a1, a2);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr   = sys::process::resolve_addr(a1 as u64);
             let len   = a2;
@@ -86,7 +117,7 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             // a2=buf_ptr, a3=buf_len
             if !validate_user_ptr(a2, a3) {
                 kdebug!("READ: invalid buf ptr {:#X} len {}", a2, a3);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr = sys::process::resolve_addr(a2 as u64);
             let len = a3;
@@ -99,7 +130,7 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             // a2=buf_ptr, a3=buf_len
             if !validate_user_ptr(a2, a3) {
                 kdebug!("WRITE: invalid buf ptr {:#X} len {}", a2, a3);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr = sys::process::resolve_addr(a2 as u64);
             let len = a3;
@@ -107,6 +138,181 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             service::write(handle, buf) as usize
         }
 
+        number::READV => {
+            // a1=handle, a2=pointer to IoVec array, a3=count
+            let Some(iov) = user_iovecs(a2, a3) else {
+                kdebug!("READV: invalid iovec array {:#X} count {}", a2, a3);
+                return audit::deny(n, Reason::BadPointer);
+            };
+            let mut bufs: alloc::vec::Vec<&mut [u8]> = iov.iter().map(|v| unsafe {
+                core::slice::from_raw_parts_mut(sys::process::resolve_addr(v.base as u64), v.len)
+            }).collect();
+            service::readv(a1, &mut bufs) as usize
+        }
+
+        number::WRITEV => {
+            // a1=handle, a2=pointer to IoVec array, a3=count
+            let Some(iov) = user_iovecs(a2, a3) else {
+                kdebug!("WRITEV: invalid iovec array {:#X} count {}", a2, a3);
+                return audit::deny(n, Reason::BadPointer);
+            };
+            let bufs: alloc::vec::Vec<&[u8]> = iov.iter().map(|v| unsafe {
+                core::slice::from_raw_parts(sys::process::resolve_addr(v.base as u64) as *const u8, v.len)
+            }).collect();
+            service::writev(a1, &bufs) as usize
+        }
+
+        number::FCNTL => {
+            // a1=handle, a2=command, a3=argument
+            service::fcntl(a1, a2, a3) as usize
+        }
+
+        number::SETRLIMIT => {
+            // a1=resource, a2=soft, a3=hard
+            service::setrlimit(a1, a2, a3) as usize
+        }
+
+        number::GETRLIMIT => {
+            // a1=resource, a2=pointer to RLimit
+            if !validate_user_ptr(a2, core::mem::size_of::<sys::process::RLimit>()) {
+                kdebug!("GETRLIMIT: invalid output ptr {:#X}", a2);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let out = unsafe { &mut *(sys::process::resolve_addr(a2 as u64) as *mut sys::process::RLimit) };
+            service::getrlimit(a1, out) as usize
+        }
+
+        number::FSYNC => {
+            service::fsync(a1) as usize
+        }
+
+        number::SYNC => {
+            service::sync();
+            0
+        }
+
+        number::GETPID => {
+            service::getpid()
+        }
+
+        number::GETPPID => {
+            service::getppid()
+        }
+
+        number::SETPRIO => {
+            // a1=pid (0 = self), a2=priority class, 0 first
+            service::setprio(a1, a2) as usize
+        }
+
+        number::PROC_STAT => {
+            // a1=pid (0 = self); returns CPU time in milliseconds
+            service::proc_stat(a1) as usize
+        }
+
+        number::MMAP_FILE => {
+            // a1=handle, a2=bytes from the start of the file, a3=PROT_* bits
+            service::mmap_file(a1, a2, a3) as usize
+        }
+
+        number::MSYNC => {
+            // a1=address inside a mapping, a2=length
+            service::msync(a1, a2) as usize
+        }
+
+        number::MUNMAP => {
+            // a1=start address of a mapping
+            service::munmap(a1) as usize
+        }
+
+        number::YIELD => {
+            // The switch itself needs the saved context, syscall_handler
+            // does it once 0 is in rax
+            0
+        }
+
+        number::COPY_RANGE => {
+            // a1=src_handle, a2=dst_handle, a3=len
+            service::copy_range(a1, a2, a3) as usize
+        }
+
+        number::SOCKET => {
+            service::socket() as usize
+        }
+
+        number::BIND | number::CONNECT => {
+            // a1=handle, a2=pointer to SocketAddr
+            if !validate_user_ptr(a2, core::mem::size_of::<sys::net::SocketAddr>()) {
+                kdebug!("BIND/CONNECT: invalid addr ptr {:#X}", a2);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let addr = unsafe { *(sys::process::resolve_addr(a2 as u64) as *const sys::net::SocketAddr) };
+            if n == number::BIND {
+                service::bind(a1, addr) as usize
+            } else {
+                service::connect(a1, addr) as usize
+            }
+        }
+
+        number::SOCK_SEND => {
+            // a1=handle, a2=data_ptr, a3=data_len
+            if !validate_user_ptr(a2, a3) {
+                kdebug!("SOCK_SEND: invalid data ptr {:#X} len {}", a2, a3);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let ptr  = sys::process::resolve_addr(a2 as u64);
+            let data = unsafe { core::slice::from_raw_parts(ptr, a3) };
+            service::sock_send(a1, data) as usize
+        }
+
+        number::SOCK_RECV => {
+            // a1=handle, a2=buf_ptr, a3=buf_len
+            if !validate_user_ptr(a2, a3) {
+                kdebug!("SOCK_RECV: invalid buf ptr {:#X} len {}", a2, a3);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let ptr = sys::process::resolve_addr(a2 as u64);
+            let buf = unsafe { core::slice::from_raw_parts_mut(ptr, a3) };
+            service::sock_recv(a1, buf) as usize
+        }
+
+        number::SENDTO => {
+            // a1=handle, a2=port, a3=data_ptr, a4=data_len
+            if !validate_user_ptr(a3, a4) {
+                kdebug!("SENDTO: invalid data ptr {:#X} len {}", a3, a4);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let ptr  = sys::process::resolve_addr(a3 as u64);
+            let data = unsafe { core::slice::from_raw_parts(ptr, a4) };
+            service::sendto(a1, a2 as u16, data) as usize
+        }
+
+        number::RECVFROM => {
+            // a1=handle, a2=buf_ptr, a3=buf_len, a4=pointer to u16 sender port
+            if !validate_user_ptr(a2, a3) || !validate_user_ptr(a4, core::mem::size_of::<u16>()) {
+                kdebug!("RECVFROM: invalid ptr {:#X} / {:#X}", a2, a4);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let ptr  = sys::process::resolve_addr(a2 as u64);
+            let buf  = unsafe { core::slice::from_raw_parts_mut(ptr, a3) };
+            let from = unsafe { &mut *(sys::process::resolve_addr(a4 as u64) as *mut u16) };
+            service::recvfrom(a1, buf, from) as usize
+        }
+
+        number::KILL => {
+            // a1=pid, a2=signal
+            service::kill(a1, a2 as u32) as usize
+        }
+
+        number::SIGACTION => {
+            // a1=signal, a2=handler (or SIG_DFL/SIG_IGN), a3=restorer
+            service::sigaction(a1 as u32, a2, a3) as usize
+        }
+
+        number::SIGPROCMASK => {
+            // a1=how, a2=set
+            service::sigprocmask(a1, a2 as u32) as usize
+        }
+
         number::DUP => {
             service::dup(a1, a2) as usize
         }
@@ -114,13 +320,13 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
         number::STAT => {
             if !validate_user_ptr(a1, a2) {
                 kdebug!("STAT: invalid path ptr");
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             // Validasi juga pointer output (a3) — ukuran FileInfo struct
             let info_size = core::mem::size_of::<sys::fs::FileInfo>();
             if !validate_user_ptr(a3, info_size) {
                 kdebug!("STAT: invalid output ptr {:#X}", a3);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr  = sys::process::resolve_addr(a1 as u64);
             let len  = a2;
@@ -132,7 +338,7 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
         number::REMOVE => {
             if !validate_user_ptr(a1, a2) {
                 kdebug!("REMOVE: invalid path ptr");
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr  = sys::process::resolve_addr(a1 as u64);
             let len  = a2;
@@ -148,7 +354,7 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             // a1=target_pid, a2=kind, a3=data_ptr, a4=data_len
             if !validate_user_ptr(a3, a4) {
                 kdebug!("SEND: invalid data ptr {:#X} len {}", a3, a4);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let target  = a1;
             let kind    = a2 as u32;
@@ -163,18 +369,65 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             let msg_size = core::mem::size_of::<sys::ipc::Message>();
             if !validate_user_ptr(a1, msg_size) {
                 kdebug!("RECV: invalid msg ptr {:#X}", a1);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let out = unsafe { &mut *(sys::process::resolve_addr(a1 as u64) as *mut sys::ipc::Message) };
             sys::ipc::recv(out)
         }
 
+        number::CALL => {
+            // a1=target_pid, a2=pointer to request Message, a3=pointer to reply Message
+            let msg_size = core::mem::size_of::<sys::ipc::Message>();
+            if !validate_user_ptr(a2, msg_size) || !validate_user_ptr(a3, msg_size) {
+                kdebug!("CALL: invalid msg ptr {:#X} / {:#X}", a2, a3);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let req = unsafe { &*(sys::process::resolve_addr(a2 as u64) as *const sys::ipc::Message) };
+            let out = unsafe { &mut *(sys::process::resolve_addr(a3 as u64) as *mut sys::ipc::Message) };
+            sys::ipc::call(a1, req, out)
+        }
+
+        number::REPLY => {
+            // a1=pointer to request Message, a2=data_ptr, a3=data_len
+            let msg_size = core::mem::size_of::<sys::ipc::Message>();
+            if !validate_user_ptr(a1, msg_size) || !validate_user_ptr(a2, a3) {
+                kdebug!("REPLY: invalid ptr {:#X} / {:#X}", a1, a2);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let req  = unsafe { &*(sys::process::resolve_addr(a1 as u64) as *const sys::ipc::Message) };
+            let ptr  = sys::process::resolve_addr(a2 as u64);
+            let data = unsafe { core::slice::from_raw_parts(ptr, a3) };
+            sys::ipc::reply(req, data)
+        }
+
+        number::REGISTER => {
+            // a1=name_ptr, a2=name_len
+            if !validate_user_ptr(a1, a2) {
+                kdebug!("REGISTER: invalid name ptr {:#X} len {}", a1, a2);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let name = raw_str(sys::process::resolve_addr(a1 as u64), a2);
+            sys::ipc::register(name)
+        }
+
+        number::SENDNAME => {
+            // a1=name_ptr, a2=name_len, a3=pointer to Message, a4=timeout_ms
+            let msg_size = core::mem::size_of::<sys::ipc::Message>();
+            if !validate_user_ptr(a1, a2) || !validate_user_ptr(a3, msg_size) {
+                kdebug!("SENDNAME: invalid ptr {:#X} / {:#X}", a1, a3);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let name = raw_str(sys::process::resolve_addr(a1 as u64), a2);
+            let msg  = unsafe { &*(sys::process::resolve_addr(a3 as u64) as *const sys::ipc::Message) };
+            sys::ipc::send_to_name(name, msg, a4 as u64)
+        }
+
         number::POLL => {
             // Validasi pointer list sebelum akses
             let entry_size = core::mem::size_of::<(usize, sys::fs::PollEvent)>();
             if !validate_user_ptr(a1, a2.saturating_mul(entry_size)) {
                 kdebug!("POLL: invalid list ptr {:#X} len {}", a1, a2);
-                return usize::MAX;
+                return audit::deny(n, Reason::BadPointer);
             }
             let ptr  = sys::process::resolve_addr(a1 as u64) as *const _;
             let len  = a2;
@@ -182,6 +435,63 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             service::poll(list) as usize
         }
 
+        number::POLLSET_CREATE => {
+            service::pollset_create() as usize
+        }
+
+        number::POLLSET_ADD => {
+            // a1=set, a2=handle, a3=event (0 = read, 1 = write)
+            service::pollset_add(a1, a2, a3) as usize
+        }
+
+        number::POLLSET_DEL => {
+            // a1=set, a2=handle
+            service::pollset_del(a1, a2) as usize
+        }
+
+        number::POLLSET_WAIT => {
+            // a1=set, a2=pointer to (handle, event) array, a3=capacity, a4=timeout ms
+            let entry_size = core::mem::size_of::<(usize, sys::fs::PollEvent)>();
+            if !validate_user_ptr(a2, a3.saturating_mul(entry_size)) {
+                kdebug!("POLLSET_WAIT: invalid events ptr {:#X} len {}", a2, a3);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let ptr = sys::process::resolve_addr(a2 as u64) as *mut _;
+            let out = unsafe { core::slice::from_raw_parts_mut(ptr, a3) };
+            service::pollset_wait(a1, out, a4) as usize
+        }
+
+        number::CHROOT => {
+            // a1=path ptr, a2=path len
+            if !validate_user_ptr(a1, a2) {
+                kdebug!("CHROOT: invalid path ptr");
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let ptr  = sys::process::resolve_addr(a1 as u64);
+            let path = raw_str(ptr, a2);
+            service::chroot(path) as usize
+        }
+
+        number::CAPGET => {
+            // a1=pointer to u32 for the children's capabilities
+            if !validate_user_ptr(a1, core::mem::size_of::<u32>()) {
+                kdebug!("CAPGET: invalid output ptr {:#X}", a1);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let child = unsafe { &mut *(sys::process::resolve_addr(a1 as u64) as *mut u32) };
+            service::capget(child) as usize
+        }
+
+        number::CAPSET => {
+            // a1=capabilities, a2=capabilities for children
+            service::capset(a1, a2) as usize
+        }
+
+        number::SECCOMP_SET => {
+            // a1=bitmap of allowed syscall numbers, a2=SECCOMP_* flags
+            service::seccomp_set(a1 as u64, a2) as usize
+        }
+
         number::ALLOC => {
             service::alloc_user(a1, a2) as usize
         }
@@ -191,6 +501,35 @@ pub fn dispatch(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
             0
         }
 
+        number::FORK => {
+            service::fork() as usize
+        }
+
+        number::BRK => {
+            // a1=bytes to grow the heap by (0 = only report the break)
+            service::brk(a1)
+        }
+
+        number::WAITID => {
+            // a1=child pid (0 = any), a2=W* options, a3=pointer to WaitInfo
+            if !validate_user_ptr(a3, core::mem::size_of::<sys::process::WaitInfo>()) {
+                kdebug!("WAITID: invalid output ptr {:#X}", a3);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let out = unsafe { &mut *(sys::process::resolve_addr(a3 as u64) as *mut sys::process::WaitInfo) };
+            service::waitid(a1, a2, out) as usize
+        }
+
+        number::WAIT => {
+            // a1=child pid (0 = any), a2=pointer to usize for the exit code
+            if !validate_user_ptr(a2, core::mem::size_of::<usize>()) {
+                kdebug!("WAIT: invalid status ptr {:#X}", a2);
+                return audit::deny(n, Reason::BadPointer);
+            }
+            let status = unsafe { &mut *(sys::process::resolve_addr(a2 as u64) as *mut usize) };
+            service::wait(a1, status) as usize
+        }
+
         _ => {
             kdebug!("unknown syscall: {:#X}", n);
             usize::MAX
@@ -240,6 +579,20 @@ pub unsafe fn syscall4(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) ->
     r
 }
 
+/// # Safety
+///
+/// The arguments must be what syscall `n` expects; the kernel reads and
+/// writes through any pointers among them.
+pub unsafe fn syscall5(n: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let r: usize;
+    asm!(
+        "int 0x80",
+        in("rax") n, in("rdi") a1, in("rsi") a2, in("rdx") a3, in("r8") a4, in("r9") a5,
+        lateout("rax") r
+    );
+    r
+}
+
 /// Macro shorthand for syscalls
 #[macro_export]
 macro_rules! syscall {
@@ -252,4 +605,9 @@ macro_rules! syscall {
     ($n:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr) => {
         $crate::sys::syscall::syscall4($n as usize, $a1 as usize, $a2 as usize, $a3 as usize, $a4 as usize)
     };
+    ($n:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr, $a5:expr) => {
+        $crate::sys::syscall::syscall5(
+            $n as usize, $a1 as usize, $a2 as usize, $a3 as usize, $a4 as usize, $a5 as usize
+        )
+    };
 }
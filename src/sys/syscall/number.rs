@@ -20,3 +20,46 @@ pub const FREE:    usize = 0x0E; // Free userspace memory
 pub const KIND:    usize = 0x0F; // Handle type (file/device/socket)
 pub const SEND:    usize = 0x10; // Send IPC message to process (blocks until received)
 pub const RECV:    usize = 0x11; // Wait for incoming message (blocks until available)
+pub const CALL:    usize = 0x12; // Send request and block until its reply arrives
+pub const REPLY:   usize = 0x13; // Answer a request received from CALL
+pub const COPY_RANGE: usize = 0x14; // Copy bytes between handles inside the kernel
+pub const FSYNC:   usize = 0x15; // Flush one handle to its backing store
+pub const SYNC:    usize = 0x16; // Flush every open file
+pub const SETRLIMIT: usize = 0x17; // Set a resource limit of the current process
+pub const GETRLIMIT: usize = 0x18; // Read a resource limit of the current process
+pub const SOCKET:  usize = 0x19; // Create a UDP socket handle
+pub const BIND:    usize = 0x1A; // Bind a socket to a local address
+pub const CONNECT: usize = 0x1B; // Set a socket's default destination
+pub const SOCK_SEND: usize = 0x1C; // Send a datagram to the connected peer
+pub const SOCK_RECV: usize = 0x1D; // Receive a datagram (blocking)
+pub const SENDTO:  usize = 0x1E; // Send a datagram to a local port
+pub const RECVFROM: usize = 0x1F; // Receive a datagram and the port it came from
+pub const KILL:    usize = 0x20; // Send a signal to a process
+pub const SIGACTION: usize = 0x21; // Set the handler of a signal
+pub const SIGPROCMASK: usize = 0x22; // Block or unblock signals
+pub const SIGRETURN: usize = 0x23; // Return from a signal handler
+pub const READV:   usize = 0x24; // Read from handle into several buffers
+pub const WRITEV:  usize = 0x25; // Write several buffers to handle
+pub const FCNTL:   usize = 0x26; // Get or set handle flags
+pub const POLLSET_CREATE: usize = 0x27; // Create an empty poll set handle
+pub const POLLSET_ADD:    usize = 0x28; // Watch a handle with a poll set
+pub const POLLSET_DEL:    usize = 0x29; // Stop watching a handle
+pub const POLLSET_WAIT:   usize = 0x2A; // Block until watched handles are ready
+pub const CHROOT:         usize = 0x2B; // Confine the process to a directory
+pub const CAPGET:         usize = 0x2C; // Read the capabilities of the process
+pub const CAPSET:         usize = 0x2D; // Drop capabilities of the process and its children
+pub const BRK:            usize = 0x2E; // Grow the userspace heap
+pub const FORK:           usize = 0x2F; // Duplicate the current process
+pub const WAITID:         usize = 0x30; // Wait for a child to exit, stop or continue
+pub const WAIT:           usize = 0x31; // Wait for a child to terminate and reap it
+pub const GETPID:         usize = 0x32; // PID of the current process
+pub const GETPPID:        usize = 0x33; // PID of the parent process
+pub const SETPRIO:        usize = 0x34; // Set the scheduling priority class of a process
+pub const YIELD:          usize = 0x35; // Give the CPU to another ready process
+pub const REGISTER:       usize = 0x36; // Register the current process under a service name
+pub const SENDNAME:       usize = 0x37; // Send an IPC message to a named service
+pub const PROC_STAT:      usize = 0x38; // CPU time a process has used
+pub const MMAP_FILE:      usize = 0x39; // Map a file into the address space
+pub const MSYNC:          usize = 0x3A; // Write a file mapping back to its file
+pub const MUNMAP:         usize = 0x3B; // Write back and remove a file mapping
+pub const SECCOMP_SET:    usize = 0x3C; // Narrow the syscalls the process may make
@@ -5,8 +5,18 @@
 
 use crate::api::process::ExitCode;
 use crate::sys;
+use crate::sys::audit::Reason;
+use crate::sys::fs::{PollEvent, O_NONBLOCK};
+use crate::sys::net::{NetError, Socket, SocketAddr, PRIVILEGED_PORTS};
+use crate::sys::pollset::PollSet;
+use crate::sys::syscall::number;
+use crate::sys::syscall::error::{
+    EADDRINUSE, EAGAIN, ECHILD, ECONNREFUSED, EINVAL, EIO, EMFILE, ENOENT, ENOEXEC, ENOMEM, EMSGSIZE, ENETUNREACH, ENOTCONN, ENOTSOCK, EPERM,
+};
 
-use crate::sys::process::Process;
+use crate::sys::process::{
+    ChildState, Process, WaitInfo, SPAWN_SUSPENDED, SECCOMP_KILL, CAP_ALL, CAP_KILL, CAP_MEM, CAP_MOUNT, CAP_NET, CAP_NICE, CAP_REBOOT, RLIMIT_AS, RLIM_INFINITY,
+};
 
 use alloc::vec;
 use core::alloc::Layout;
@@ -15,50 +25,241 @@ use core::alloc::Layout;
 // Process
 // ---------------------------------------------------------------------------
 
+/// Does the current process hold `cap`? Syscall `n` is audited if not.
+fn capable(n: usize, cap: u32) -> bool {
+    if sys::process::has_cap(cap) {
+        return true;
+    }
+    sys::audit::deny(n, Reason::Capability(cap));
+    false
+}
+
+/// Audit syscall `n` hitting resource limit `limit`, returning `err`
+fn over_limit(n: usize, limit: &'static str, err: isize) -> isize {
+    sys::audit::deny(n, Reason::Limit(limit));
+    err
+}
+
 pub fn exit(code: ExitCode) -> ExitCode {
-    sys::process::terminate();
+    sys::process::terminate(ChildState::Exited(code as usize));
     code
 }
 
+/// Only puts the caller to sleep; syscall_handler gives the CPU away
 pub fn sleep(seconds: f64) {
-    sys::clk::sleep(seconds);
+    sys::sched::fall_asleep(sys::clk::deadline_ns(seconds));
 }
 
-pub fn spawn(path: &str, args_ptr: usize, args_len: usize) -> ExitCode {
-    let path = match sys::fs::canonicalize(path) {
-        Ok(p) => p,
-        Err(_) => return ExitCode::NotFound,
-    };
+/// Returns the child's pid (the child itself sees 0), or EAGAIN if the
+/// caller cannot be duplicated: no free slot, too little memory, or it
+/// runs in the kernel
+pub fn fork() -> isize {
+    match sys::process::fork() {
+        Ok(pid) => pid as isize,
+        Err(()) => EAGAIN,
+    }
+}
 
-    if let Some(mut file) = sys::fs::open_file(&path) {
-        use crate::sys::fs::FileIO;
-        let mut buf = vec![0u8; file.size()];
-        if let Ok(n) = file.read(&mut buf) {
-            buf.truncate(n);
-            match Process::spawn(&buf, args_ptr, args_len) {
-                Ok(_) => unreachable!(), // kernel switches to child process
-                Err(e) => e,
-            }
-        } else {
-            ExitCode::IoError
+/// Wait for child `pid` (0 = any) to terminate and put its exit code in
+/// `status`. Returns the child's pid, or ECHILD if there is no such
+/// child.
+pub fn wait(pid: usize, status: &mut usize) -> isize {
+    match sys::process::wait(pid) {
+        Ok((child, code)) => {
+            *status = code as usize;
+            child as isize
         }
-    } else {
-        ExitCode::NotFound
+        Err(()) => ECHILD,
+    }
+}
+
+/// Wait for child `pid` (0 = any) to change state as `options` asks and
+/// describe the change in `out`. Returns 0, ECHILD if there is no such
+/// child, or EINVAL for bad options. With WNOHANG and no change yet,
+/// returns 0 with `out.pid` set to 0.
+pub fn waitid(pid: usize, options: usize, out: &mut WaitInfo) -> isize {
+    use sys::process::{WCONTINUED, WEXITED, WNOHANG, WSTOPPED};
+    let wanted = WEXITED | WSTOPPED | WCONTINUED;
+    if options & wanted == 0 || options & !(wanted | WNOHANG) != 0 {
+        return EINVAL;
+    }
+    match sys::process::wait_child(pid, options) {
+        Ok(info) => {
+            *out = info.unwrap_or_default();
+            0
+        }
+        Err(()) => ECHILD,
+    }
+}
+
+/// Returns the child's start-up failure code, or EAGAIN if the caller is
+/// spawning faster than RLIMIT_SPAWN allows. With SPAWN_SUSPENDED the
+/// stopped child's pid comes back instead, or a negative error.
+pub fn spawn(path: &str, flags: usize, args_ptr: usize, args_len: usize) -> isize {
+    if sys::process::charge_spawn().is_err() {
+        return over_limit(number::SPAWN, "RLIMIT_SPAWN", EAGAIN);
+    }
+    if flags & SPAWN_SUSPENDED == 0 {
+        return match read_binary(path) {
+            Ok(bin) => Process::spawn(&bin, flags, args_ptr, args_len),
+            Err(code) => code,
+        } as isize;
+    }
+    match read_binary(path).and_then(|bin| Process::spawn_suspended(&bin, flags, args_ptr, args_len)) {
+        Ok(pid) => pid as isize,
+        Err(code) => spawn_errno(code),
+    }
+}
+
+fn read_binary(path: &str) -> Result<alloc::vec::Vec<u8>, ExitCode> {
+    let path = sys::fs::resolve_command(path).ok_or(ExitCode::NotFound)?;
+    let mut file = sys::fs::open_file(&path).ok_or(ExitCode::NotFound)?;
+
+    use crate::sys::fs::FileIO;
+    let mut buf = vec![0u8; file.size()];
+    let n = file.read(&mut buf).map_err(|_| ExitCode::IoError)?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+/// The error a suspended spawn reports for a child that could not start
+fn spawn_errno(code: ExitCode) -> isize {
+    match code {
+        ExitCode::NotFound => ENOENT,
+        ExitCode::IoError  => EIO,
+        ExitCode::NoMemory => ENOMEM,
+        ExitCode::Busy     => EAGAIN,
+        _                  => ENOEXEC,
     }
 }
 
-pub fn halt(code: usize) -> usize {
+pub fn halt(code: usize) -> isize {
+    if !capable(number::HALT, CAP_REBOOT) {
+        return EPERM;
+    }
     match code {
-        0xCAFE => sys::idt::trigger_reset(),
+        0xCAFE => { sys::power::reboot(true, sys::power::ResetKind::Cold).ok(); }
         0xDEAD => {
-            sys::process::terminate();
-            sys::acpi::power_off();
+            sys::process::terminate(ChildState::Exited(0));
+            sys::power::power_off(true).ok();
         }
         _ => kdebug!("HALT: unknown code {:#X}", code),
     }
     0
 }
 
+/// SIGKILL to another process tears it down on the spot, whatever it is
+/// blocked in, since a blocked process never runs to take the signal.
+/// Idle and init cannot be killed that way.
+pub fn kill(pid: usize, sig: u32) -> isize {
+    let me = sys::process::current_pid();
+    if pid != me && !capable(number::KILL, CAP_KILL) {
+        return EPERM;
+    }
+    let sent = if sig == sys::signal::SIGKILL && pid != me {
+        sys::process::kill(pid)
+    } else {
+        sys::signal::send(pid, sig)
+    };
+    if sent.is_ok() { 0 } else { EINVAL }
+}
+
+/// Set the priority class of `pid`, 0 for the caller. Another process
+/// needs CAP_KILL, as for signalling it.
+pub fn setprio(pid: usize, prio: usize) -> isize {
+    let me = sys::process::current_pid();
+    let pid = if pid == 0 { me } else { pid };
+    if pid != me && !capable(number::SETPRIO, CAP_KILL) {
+        return EPERM;
+    }
+    let prio = prio.try_into().unwrap_or(u8::MAX);
+    // Any process may lower a priority; raising one takes CAP_NICE
+    let raising = sys::sched::priority(pid).is_some_and(|old| prio < old);
+    if raising && !capable(number::SETPRIO, CAP_NICE) {
+        return EPERM;
+    }
+    if sys::sched::set_priority(pid, prio).is_ok() { 0 } else { EINVAL }
+}
+
+pub fn sigaction(sig: u32, handler: usize, restorer: usize) -> isize {
+    match sys::signal::sigaction(sig, handler, restorer) {
+        Ok(old) => old as isize,
+        Err(()) => EINVAL,
+    }
+}
+
+/// Returns the previous mask
+pub fn sigprocmask(how: usize, set: u32) -> isize {
+    match sys::signal::sigprocmask(how, set) {
+        Ok(old) => old as isize,
+        Err(()) => EINVAL,
+    }
+}
+
+pub fn setrlimit(resource: usize, soft: usize, hard: usize) -> isize {
+    let raising = sys::process::rlimit(resource).is_some_and(|cur| hard > cur.hard);
+    if raising && !capable(number::SETRLIMIT, CAP_MEM) {
+        return EPERM;
+    }
+    match sys::process::set_rlimit(resource, sys::process::RLimit { soft, hard }) {
+        Ok(())  => 0,
+        Err(()) if resource < sys::process::RLIMIT_COUNT => EPERM,
+        Err(()) => EINVAL,
+    }
+}
+
+/// Make `path` the root of the current process. Needs CAP_MOUNT.
+pub fn chroot(path: &str) -> isize {
+    if !capable(number::CHROOT, CAP_MOUNT) {
+        return EPERM;
+    }
+    let dir = match sys::fs::canonicalize(path) {
+        Ok(p) => p,
+        Err(_) => return EINVAL,
+    };
+    if dir != "/" && !sys::fs::dir_exists(&dir) {
+        return ENOENT;
+    }
+    sys::process::chroot(&dir);
+    0
+}
+
+/// Returns the current capabilities; the ones children get are stored
+/// in `child`
+pub fn capget(child: &mut u32) -> isize {
+    let (caps, child_caps) = sys::process::caps();
+    *child = child_caps;
+    caps as isize
+}
+
+pub fn capset(caps: usize, child_caps: usize) -> isize {
+    if (caps | child_caps) & !(CAP_ALL as usize) != 0 {
+        return EINVAL;
+    }
+    match sys::process::set_caps(caps as u32, child_caps as u32) {
+        Ok(())  => 0,
+        Err(()) => EPERM,
+    }
+}
+
+/// EPERM if `allowed` would let back in a syscall already filtered out
+pub fn seccomp_set(allowed: u64, flags: usize) -> isize {
+    if flags & !SECCOMP_KILL != 0 {
+        return EINVAL;
+    }
+    match sys::process::set_seccomp(allowed, flags & SECCOMP_KILL != 0) {
+        Ok(())  => 0,
+        Err(()) => EPERM,
+    }
+}
+
+pub fn getrlimit(resource: usize, out: &mut sys::process::RLimit) -> isize {
+    match sys::process::rlimit(resource) {
+        Some(limit) => { *out = limit; 0 }
+        None        => EINVAL,
+    }
+}
+
 // ---------------------------------------------------------------------------
 // File / handle
 // ---------------------------------------------------------------------------
@@ -68,14 +269,41 @@ pub fn open(path: &str, flags: u8) -> isize {
         Ok(p) => p,
         Err(_) => return -1,
     };
+    if sys::fs::device_cap(&path).is_some_and(|cap| !capable(number::OPEN, cap)) {
+        return EPERM;
+    }
     if let Some(res) = sys::fs::open_resource(&path, flags) {
-        if let Ok(h) = sys::process::alloc_handle(res) {
-            return h as isize;
-        }
+        return match sys::process::alloc_handle(res) {
+            Ok(h) => {
+                sys::process::set_handle_flags(h, flags & O_NONBLOCK).ok();
+                h as isize
+            }
+            Err(_) => over_limit(number::OPEN, "RLIMIT_NOFILE", EMFILE),
+        };
     }
     -1
 }
 
+/// FCNTL commands
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+
+/// Returns the flags for F_GETFL, 0 for F_SETFL
+pub fn fcntl(handle: usize, cmd: usize, arg: usize) -> isize {
+    let result = match cmd {
+        F_GETFL => sys::process::handle_flags(handle).map(|f| f as isize).ok_or(()),
+        F_SETFL => sys::process::set_handle_flags(handle, arg as u8 & O_NONBLOCK).map(|_| 0),
+        _ => return EINVAL,
+    };
+    result.unwrap_or(-1)
+}
+
+/// EAGAIN if `handle` is non-blocking and `res` is not ready for `e`
+fn check_nonblock(handle: usize, res: &mut sys::fs::Resource, e: PollEvent) -> Result<(), isize> {
+    let nonblock = sys::process::handle_flags(handle).unwrap_or(0) & O_NONBLOCK != 0;
+    if nonblock && res.would_block(e) { Err(EAGAIN) } else { Ok(()) }
+}
+
 pub fn close(handle: usize) {
     if let Some(mut res) = sys::process::get_handle(handle) {
         res.close();
@@ -85,6 +313,9 @@ pub fn close(handle: usize) {
 
 pub fn read(handle: usize, buf: &mut [u8]) -> isize {
     if let Some(mut res) = sys::process::get_handle(handle) {
+        if let Err(e) = check_nonblock(handle, &mut res, PollEvent::Read) {
+            return e;
+        }
         if let Ok(n) = res.read(buf) {
             sys::process::update_handle(handle, *res);
             return n as isize;
@@ -93,16 +324,151 @@ pub fn read(handle: usize, buf: &mut [u8]) -> isize {
     -1
 }
 
+/// Returns EIO if the write failed, e.g. the kernel heap is full
 pub fn write(handle: usize, buf: &[u8]) -> isize {
-    if let Some(mut res) = sys::process::get_handle(handle) {
-        if let Ok(n) = res.write(buf) {
-            sys::process::update_handle(handle, *res);
-            return n as isize;
+    if let Some(res) = sys::process::try_get_handle(handle) {
+        let Ok(mut res) = res else {
+            return EIO;
+        };
+        if let Err(e) = check_nonblock(handle, &mut res, PollEvent::Write) {
+            return e;
         }
+        return match res.write(buf) {
+            Ok(n) => {
+                sys::process::update_handle(handle, *res);
+                n as isize
+            }
+            Err(()) => EIO,
+        };
     }
     -1
 }
 
+/// Fill `bufs` in order, stopping at the first short read. Returns the
+/// total read, or -1 if nothing could be read.
+pub fn readv(handle: usize, bufs: &mut [&mut [u8]]) -> isize {
+    let Some(mut res) = sys::process::get_handle(handle) else {
+        return -1;
+    };
+    if let Err(e) = check_nonblock(handle, &mut res, PollEvent::Read) {
+        return e;
+    }
+    let mut total = 0;
+    for buf in bufs.iter_mut() {
+        match res.read(buf) {
+            Ok(n) => {
+                total += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            Err(()) if total == 0 => return -1,
+            Err(()) => break,
+        }
+    }
+    sys::process::update_handle(handle, *res);
+    total as isize
+}
+
+/// Write `bufs` in order as if they were one buffer. Returns the total
+/// written; EIO if the first write fails.
+pub fn writev(handle: usize, bufs: &[&[u8]]) -> isize {
+    let Some(mut res) = sys::process::get_handle(handle) else {
+        return -1;
+    };
+    if let Err(e) = check_nonblock(handle, &mut res, PollEvent::Write) {
+        return e;
+    }
+    let mut total = 0;
+    for buf in bufs {
+        match res.write(buf) {
+            Ok(n) => {
+                total += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            Err(()) if total == 0 => return EIO,
+            Err(()) => break,
+        }
+    }
+    sys::process::update_handle(handle, *res);
+    total as isize
+}
+
+pub fn fsync(handle: usize) -> isize {
+    match sys::process::get_handle(handle) {
+        Some(mut res) => if res.sync().is_ok() { 0 } else { EIO },
+        None => -1,
+    }
+}
+
+pub fn sync() {
+    sys::process::sync_all();
+}
+
+pub fn getpid() -> usize {
+    sys::process::current_pid()
+}
+
+pub fn getppid() -> usize {
+    sys::process::parent_pid()
+}
+
+/// Milliseconds of CPU time `pid` (0 for the caller) has spent in
+/// userspace, or EINVAL if there is no such process
+pub fn proc_stat(pid: usize) -> isize {
+    let pid = if pid == 0 { sys::process::current_pid() } else { pid };
+    if pid >= sys::process::MAX_PROCS || sys::process::PROC_TABLE.read()[pid].id != pid {
+        return EINVAL;
+    }
+    sys::process::cpu_time_ms(pid) as isize
+}
+
+/// Address the first `len` bytes of the file open as `handle` are
+/// mapped at, or EBADF if it is not a file, EINVAL for a bad length or
+/// PROT_* bits, ENOMEM if there is no room below the stack
+pub fn mmap_file(handle: usize, len: usize, prot: usize) -> isize {
+    match sys::mmap::map(handle, len, prot) {
+        Ok(addr) => addr as isize,
+        Err(e)   => e,
+    }
+}
+
+/// EINVAL unless `addr..addr + len` lies within one mapping, EIO if the
+/// file could not be written
+pub fn msync(addr: usize, len: usize) -> isize {
+    match sys::mmap::msync(addr as u64, len) {
+        Ok(())  => 0,
+        Err(e)  => e,
+    }
+}
+
+pub fn munmap(addr: usize) -> isize {
+    match sys::mmap::unmap(addr as u64) {
+        Ok(())  => 0,
+        Err(e)  => e,
+    }
+}
+
+pub fn copy_range(src: usize, dst: usize, len: usize) -> isize {
+    if src == dst {
+        return -1;
+    }
+    let (mut s, mut d) = match (sys::process::get_handle(src), sys::process::get_handle(dst)) {
+        (Some(s), Some(d)) => (s, d),
+        _ => return -1,
+    };
+    match sys::fs::copy_range(&mut s, &mut d, len) {
+        Ok(n) => {
+            sys::process::update_handle(src, *s);
+            sys::process::update_handle(dst, *d);
+            n as isize
+        }
+        Err(_) => -1,
+    }
+}
+
 pub fn dup(src: usize, dst: usize) -> isize {
     if let Some(res) = sys::process::get_handle(src) {
         sys::process::update_handle(dst, *res);
@@ -125,7 +491,11 @@ pub fn stat(path: &str, info: &mut sys::fs::FileInfo) -> isize {
 }
 
 pub fn remove(path: &str) -> isize {
-    if sys::fs::remove(path).is_ok() { 0 } else { -1 }
+    let path = match sys::fs::canonicalize(path) {
+        Ok(p) => p,
+        Err(_) => return -1,
+    };
+    if sys::fs::remove(&path).is_ok() { 0 } else { -1 }
 }
 
 pub fn kind(handle: usize) -> isize {
@@ -138,15 +508,73 @@ pub fn kind(handle: usize) -> isize {
 
 pub fn poll(handles: &[(usize, sys::fs::PollEvent)]) -> isize {
     for (i, (handle, event)) in handles.iter().enumerate() {
-        if let Some(mut res) = sys::process::get_handle(*handle) {
-            if res.poll(*event) {
-                return i as isize;
-            }
+        if sys::pollset::is_ready(*handle, *event) {
+            return i as isize;
         }
     }
     -1
 }
 
+// ---------------------------------------------------------------------------
+// Poll sets
+// ---------------------------------------------------------------------------
+
+fn with_pollset<F>(set: usize, f: F) -> isize
+where
+    F: FnOnce(&mut PollSet) -> isize,
+{
+    let mut res = match sys::process::get_handle(set) {
+        Some(res) => res,
+        None => return EINVAL,
+    };
+    let ret = match res.as_mut() {
+        sys::fs::Resource::PollSet(p) => f(p),
+        _ => return EINVAL,
+    };
+    sys::process::update_handle(set, *res);
+    ret
+}
+
+pub fn pollset_create() -> isize {
+    match sys::process::alloc_handle(sys::fs::Resource::PollSet(PollSet::new())) {
+        Ok(h)  => h as isize,
+        Err(_) => over_limit(number::POLLSET_CREATE, "RLIMIT_NOFILE", EMFILE),
+    }
+}
+
+/// `event`: 0 = readable, 1 = writable. Members must be open handles
+/// (or the mailbox) and cannot themselves be poll sets.
+pub fn pollset_add(set: usize, handle: usize, event: usize) -> isize {
+    let event = match event {
+        0 => PollEvent::Read,
+        1 => PollEvent::Write,
+        _ => return EINVAL,
+    };
+    if handle != sys::ipc::MAILBOX {
+        match sys::process::get_handle(handle).as_deref() {
+            Some(sys::fs::Resource::PollSet(_)) | None => return EINVAL,
+            Some(_) => {}
+        }
+    }
+    with_pollset(set, |p| if p.add(handle, event).is_ok() { 0 } else { EMFILE })
+}
+
+pub fn pollset_del(set: usize, handle: usize) -> isize {
+    with_pollset(set, |p| if p.remove(handle).is_ok() { 0 } else { ENOENT })
+}
+
+/// Fill `out` with ready members; returns how many (0 on timeout)
+pub fn pollset_wait(set: usize, out: &mut [(usize, PollEvent)], timeout_ms: usize) -> isize {
+    let members = match sys::process::get_handle(set).as_deref() {
+        Some(sys::fs::Resource::PollSet(p)) => p.clone(),
+        _ => return EINVAL,
+    };
+    let ready = members.wait(timeout_ms);
+    let n = ready.len().min(out.len());
+    out[..n].copy_from_slice(&ready[..n]);
+    n as isize
+}
+
 // ---------------------------------------------------------------------------
 // Userspace memory
 // ---------------------------------------------------------------------------
@@ -162,3 +590,395 @@ pub unsafe fn free_user(ptr: *mut u8, size: usize, align: usize) {
         sys::process::user_free(ptr, layout);
     }
 }
+
+/// Grow the heap by `increment` bytes. Returns the new break, or
+/// usize::MAX if the heap cannot grow that far or would pass RLIMIT_AS.
+pub fn brk(increment: usize) -> usize {
+    let (start, end) = sys::process::heap_range();
+    if increment > 0 {
+        let limit = sys::process::rlimit(RLIMIT_AS).map_or(RLIM_INFINITY, |l| l.soft);
+        if ((end - start) as usize).saturating_add(increment) > limit {
+            return sys::audit::deny(number::BRK, Reason::Limit("RLIMIT_AS"));
+        }
+    }
+    match sys::process::grow_heap(increment) {
+        Ok(brk) => brk as usize,
+        Err(()) => usize::MAX,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Sockets
+// ---------------------------------------------------------------------------
+
+fn net_error(e: NetError) -> isize {
+    match e {
+        NetError::AddrInUse    => EADDRINUSE,
+        NetError::Unreachable  => ENETUNREACH,
+        NetError::Refused      => ECONNREFUSED,
+        NetError::NotConnected => ENOTCONN,
+        NetError::TooBig       => EMSGSIZE,
+    }
+}
+
+/// Run `f` on the socket behind `handle` and store the updated socket back
+fn with_socket<F>(handle: usize, f: F) -> isize
+where
+    F: FnOnce(&mut Socket) -> Result<usize, NetError>,
+{
+    let mut res = match sys::process::get_handle(handle) {
+        Some(res) => res,
+        None => return EINVAL,
+    };
+    let sock = match res.as_mut() {
+        sys::fs::Resource::Socket(s) => s,
+        _ => return ENOTSOCK,
+    };
+    let ret = match f(sock) {
+        Ok(n)  => n as isize,
+        Err(e) => net_error(e),
+    };
+    sys::process::update_handle(handle, *res);
+    ret
+}
+
+pub fn socket() -> isize {
+    match sys::process::alloc_handle(sys::fs::Resource::Socket(Socket::new())) {
+        Ok(h)  => h as isize,
+        Err(_) => over_limit(number::SOCKET, "RLIMIT_NOFILE", EMFILE),
+    }
+}
+
+pub fn bind(handle: usize, addr: SocketAddr) -> isize {
+    if addr.port != 0 && addr.port < PRIVILEGED_PORTS && !capable(number::BIND, CAP_NET) {
+        return EPERM;
+    }
+    with_socket(handle, |s| s.bind(addr).map(|_| 0))
+}
+
+pub fn connect(handle: usize, addr: SocketAddr) -> isize {
+    with_socket(handle, |s| s.connect(addr).map(|_| 0))
+}
+
+pub fn sock_send(handle: usize, data: &[u8]) -> isize {
+    with_socket(handle, |s| s.send(data))
+}
+
+pub fn sock_recv(handle: usize, buf: &mut [u8]) -> isize {
+    with_socket(handle, |s| s.recv_from(buf).map(|(n, _)| n))
+}
+
+/// Send to whatever socket is bound to `port` on this machine
+pub fn sendto(handle: usize, port: u16, data: &[u8]) -> isize {
+    with_socket(handle, |s| s.send_to(SocketAddr::loopback(port), data))
+}
+
+/// Receive a datagram; the sender's port is stored in `from`
+pub fn recvfrom(handle: usize, buf: &mut [u8], from: &mut u16) -> isize {
+    with_socket(handle, |s| {
+        let (n, addr) = s.recv_from(buf)?;
+        *from = addr.port;
+        Ok(n)
+    })
+}
+
+#[test_case]
+fn datagrams_between_processes_by_port() {
+    use crate::sys::process::{set_pid, FakeProcess};
+
+    // Two stand-in processes; keep the scheduler away from them
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _first  = FakeProcess::enter(2);
+        let _second = FakeProcess::enter(3);
+
+        set_pid(2);
+        let a = socket() as usize;
+        assert_eq!(bind(a, SocketAddr::loopback(9100)), 0);
+
+        set_pid(3);
+        let b = socket() as usize;
+        assert_eq!(bind(b, SocketAddr::loopback(9101)), 0);
+        assert_eq!(sendto(b, 9100, b"hello"), 5);
+        assert_eq!(sendto(b, 9102, b"lost"), ECONNREFUSED);
+
+        set_pid(2);
+        let mut buf  = [0u8; 8];
+        let mut from = 0;
+        assert_eq!(recvfrom(a, &mut buf, &mut from), 5);
+        assert_eq!((&buf[..5], from), (&b"hello"[..], 9101));
+        assert_eq!(sendto(a, from, b"hi"), 2);
+        close(a);
+
+        set_pid(3);
+        assert_eq!(recvfrom(b, &mut buf, &mut from), 2);
+        assert_eq!((&buf[..2], from), (&b"hi"[..], 9100));
+        // The port went away with the socket
+        assert_eq!(sendto(b, 9100, b"x"), ECONNREFUSED);
+        close(b);
+    });
+}
+
+#[test_case]
+fn write_fails_with_eio_when_the_heap_is_full() {
+    use crate::sys::process::FakeProcess;
+    use crate::sys::syscall::{dispatch, number};
+    use alloc::vec::Vec;
+
+    const SIZE: usize = 64 * 1024;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+
+        sys::fs::write_file("/tmp/oom-write", &[1; SIZE]).unwrap();
+        let h = open("/tmp/oom-write", 0) as usize;
+
+        // Take every block the file would fit in, keeping a little room
+        // for the small allocations on the way
+        let spare = vec![0u8; 4096];
+        let mut hog: Vec<Vec<u8>> = Vec::with_capacity(64);
+        let mut size = sys::mem::free_memory();
+        while size >= SIZE && hog.len() < hog.capacity() {
+            let mut block = Vec::new();
+            match block.try_reserve_exact(size) {
+                Ok(()) => hog.push(block),
+                Err(_) => size /= 2,
+            }
+        }
+        drop(spare);
+        assert_eq!(dispatch(number::WRITE, h, 0x0080_0000, 0, 0, 0) as isize, EIO);
+
+        drop(hog);
+        assert_eq!(dispatch(number::WRITE, h, 0x0080_0000, 0, 0, 0), 0);
+        close(h);
+        sys::fs::remove("/tmp/oom-write").ok();
+    });
+}
+
+#[test_case]
+fn chrooted_process_cannot_reach_outside() {
+    use crate::sys::process::{FakeProcess, PROC_TABLE};
+
+    sys::fs::mkdir("/tmp/chroot-test/jail");
+    sys::fs::write_file("/tmp/chroot-test/jail/inside", b"in").ok();
+    sys::fs::mkdir("/tmp/chroot-test/jail/bin");
+    sys::fs::write_file("/tmp/chroot-test/jail/bin/tool", b"in").ok();
+    sys::fs::write_file("/tmp/chroot-test/secret", b"out").ok();
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+
+        PROC_TABLE.write()[2].data.caps = 0;
+        assert_eq!(chroot("/tmp/chroot-test/jail"), EPERM);
+        PROC_TABLE.write()[2].data.caps = CAP_ALL;
+        assert_eq!(chroot("/tmp/chroot-test/missing"), ENOENT);
+        assert_eq!(chroot("/tmp/chroot-test/jail"), 0);
+
+        // What cat would open: `..` stops at the new root
+        for path in ["../secret", "../../secret", "/../../tmp/chroot-test/secret"] {
+            let full = sys::fs::canonicalize(path).unwrap();
+            assert!(full.starts_with("/tmp/chroot-test/jail/"));
+            assert!(sys::fs::open_file(&full).is_none());
+            assert_eq!(open(path, 0), -1);
+        }
+        let h = open("/inside", 0);
+        assert!(h >= 0);
+        close(h as usize);
+
+        // Commands come from the jail's own /bin
+        assert_eq!(sys::fs::resolve_command("tool").as_deref(), Some("/tmp/chroot-test/jail/bin/tool"));
+
+        // Nor can it delete what lies outside
+        remove("../secret");
+        remove("/tmp/chroot-test/secret");
+    });
+    assert!(sys::fs::exists("/tmp/chroot-test/secret"));
+
+    for path in [
+        "/tmp/chroot-test/jail/inside", "/tmp/chroot-test/jail/bin/tool", "/tmp/chroot-test/jail/bin/.dir",
+        "/tmp/chroot-test/secret", "/tmp/chroot-test/jail/.dir",
+    ] {
+        sys::fs::remove(path).ok();
+    }
+}
+
+#[test_case]
+fn dropped_capabilities_are_enforced() {
+    use crate::sys::process::{FakeProcess, CAP_MEM};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let process = FakeProcess::enter(2);
+
+        // Root-derived, yet without CAP_REBOOT after dropping it
+        let kept = CAP_ALL & !(CAP_REBOOT | CAP_KILL | CAP_NET);
+        assert_eq!(capset(kept as usize, CAP_MEM as usize), 0);
+        assert_eq!(halt(0xCAFE), EPERM);
+        assert_eq!(kill(process.saved, 15), EPERM);
+        let s = socket() as usize;
+        assert_eq!(bind(s, SocketAddr::loopback(80)), EPERM);
+        close(s);
+
+        // Dropped bits stay dropped, children get only what was asked
+        assert_eq!(capset(CAP_ALL as usize, 0), EPERM);
+        assert_eq!(capset(1 << 31, 0), EINVAL);
+        let mut child = 0;
+        assert_eq!(capget(&mut child), kept as isize);
+        assert_eq!(child, CAP_MEM);
+
+        // Moving down a class is free, moving up takes CAP_NICE
+        assert_eq!(capset((kept & !CAP_NICE) as usize, 0), 0);
+        assert_eq!(setprio(0, sys::sched::PRIO_LOWEST as usize), 0);
+        assert_eq!(setprio(0, sys::sched::PRIO_DEFAULT as usize), EPERM);
+
+        // The raw disk is for CAP_MOUNT only
+        assert_eq!(capset((kept & !(CAP_NICE | CAP_MOUNT)) as usize, 0), 0);
+        assert_eq!(open("/dev/hda", 0), EPERM);
+    });
+}
+
+#[test_case]
+fn filtered_syscalls_fail_or_kill() {
+    use crate::sys::process::{syscall_allowed, FakeProcess, PROC_TABLE};
+    use crate::sys::signal::{bit, SIGKILL};
+    use crate::sys::syscall::dispatch;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+
+        // READ is refused, WRITE still goes through
+        let allowed = !(1u64 << number::READ);
+        assert_eq!(seccomp_set(allowed, 0), 0);
+        assert_eq!(dispatch(number::READ, 3, 0x0080_0000, 0, 0, 0) as isize, EPERM);
+        assert_eq!(dispatch(number::WRITE, 3, 0x0080_0000, 0, 0, 0), 0);
+        assert!(syscall_allowed(number::EXIT));
+        assert!(!syscall_allowed(64));
+
+        // Narrowing is one way
+        assert_eq!(seccomp_set(u64::MAX, 0), EPERM);
+        assert_eq!(seccomp_set(allowed, 1 << 7), EINVAL);
+
+        // In kill mode a filtered syscall also sends SIGKILL
+        assert_eq!(seccomp_set(allowed, SECCOMP_KILL), 0);
+        assert_eq!(dispatch(number::READ, 3, 0x0080_0000, 0, 0, 0) as isize, EPERM);
+        assert_ne!(PROC_TABLE.read()[2].signals.pending & bit(SIGKILL), 0);
+    });
+}
+
+#[test_case]
+fn file_writes_reach_the_vfs_on_close() {
+    use crate::sys::fs::{O_CREATE, O_TRUNC};
+
+    let path = "/tmp/close-test";
+    let h = open(path, O_CREATE | O_TRUNC) as usize;
+    assert_eq!(write(h, b"kept"), 4);
+    assert_eq!(sys::fs::open_file(path).unwrap().size(), 0);
+    close(h);
+    assert_eq!(sys::fs::open_file(path).unwrap().data(), b"kept");
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn writev_concatenates_buffers() {
+    let path = "/tmp/writev-test";
+    sys::fs::write_file(path, b"").ok();
+    let h = sys::process::alloc_handle(sys::fs::open_resource(path, 0).unwrap()).unwrap();
+
+    assert_eq!(writev(h, &[b"one ", b"", b"two ", b"three"]), 13);
+    assert_eq!(fsync(h), 0);
+    sys::process::free_handle(h);
+
+    let h = sys::process::alloc_handle(sys::fs::open_resource(path, 0).unwrap()).unwrap();
+    let (mut a, mut b) = ([0u8; 4], [0u8; 16]);
+    assert_eq!(readv(h, &mut [&mut a, &mut b]), 13);
+    assert_eq!((&a, &b[..9]), (b"one ", &b"two three"[..]));
+
+    sys::process::free_handle(h);
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn nonblocking_console_read_returns_eagain() {
+    sys::console::STDIN.lock().clear();
+    assert_eq!(fcntl(0, F_SETFL, O_NONBLOCK as usize), 0);
+    assert_eq!(fcntl(0, F_GETFL, 0), O_NONBLOCK as isize);
+
+    let mut buf = [0u8; 16];
+    assert_eq!(read(0, &mut buf), EAGAIN);
+
+    assert_eq!(fcntl(0, F_SETFL, 0), 0);
+    assert_eq!(fcntl(sys::process::MAX_HANDLES - 1, F_GETFL, 0), -1);
+}
+
+#[test_case]
+fn spawn_searches_path_for_bare_names() {
+    sys::fs::write_file("/tmp/path-test/hello", b"not a binary").ok();
+    let saved = sys::process::env_var("PATH");
+    sys::process::set_env_var("PATH", "/nowhere:/tmp/path-test/");
+
+    assert_eq!(sys::fs::resolve_command("hello").as_deref(), Some("/tmp/path-test/hello"));
+    assert_eq!(sys::fs::resolve_command("/tmp/path-test/hello").as_deref(), Some("/tmp/path-test/hello"));
+    assert_eq!(sys::fs::resolve_command("missing"), None);
+
+    // Found through PATH, then rejected by the loader rather than not found
+    assert_eq!(spawn("hello", 0, 0, 0), ExitCode::ExecError as isize);
+    assert_eq!(spawn("missing", 0, 0, 0), ExitCode::NotFound as isize);
+
+    sys::process::set_env_var("PATH", saved.as_deref().unwrap_or(sys::fs::DEFAULT_PATH));
+    sys::fs::remove("/tmp/path-test/hello").ok();
+}
+
+#[test_case]
+fn pollset_wait_reports_only_ready_handle() {
+    sys::console::STDIN.lock().clear();
+    let set = pollset_create() as usize;
+    let (a, b) = (socket() as usize, socket() as usize);
+    assert_eq!(bind(a, SocketAddr::loopback(9200)), 0);
+    assert_eq!(bind(b, SocketAddr::loopback(9201)), 0);
+
+    for h in [0, a, b] {
+        assert_eq!(pollset_add(set, h, 0), 0);
+    }
+    assert_eq!(pollset_add(set, set, 0), EINVAL);
+
+    let mut out = [(0, PollEvent::Read); 4];
+    assert_eq!(pollset_wait(set, &mut out, 0), 0);
+
+    assert_eq!(sendto(a, 9201, b"ping"), 4);
+    assert_eq!(pollset_wait(set, &mut out, 0), 1);
+    assert_eq!(out[0], (b, PollEvent::Read));
+
+    // Removed members are no longer reported
+    assert_eq!(pollset_del(set, b), 0);
+    assert_eq!(pollset_wait(set, &mut out, 10), 0);
+    assert_eq!(pollset_del(set, b), ENOENT);
+
+    for h in [a, b, set] {
+        close(h);
+    }
+}
+
+#[test_case]
+fn spawn_flags_come_in_their_own_register() {
+    use crate::sys::process::FakeProcess;
+    use crate::sys::syscall::dispatch;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _process = FakeProcess::enter(2);
+
+        // No such program: its exit code, or an errno when the pid of a
+        // suspended child was asked for
+        let path = 0x0080_0000;
+        assert_eq!(dispatch(number::SPAWN, path, 0, 0, 0, 0), ExitCode::NotFound as usize);
+        assert_eq!(dispatch(number::SPAWN, path, 0, 0, 0, SPAWN_SUSPENDED) as isize, ENOENT);
+    });
+}
+
+#[test_case]
+fn getpid_and_getppid_name_the_caller() {
+    use crate::sys::process::{FakeProcess, PROC_TABLE};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let process = FakeProcess::enter(2);
+        PROC_TABLE.write()[2].parent_id = process.saved;
+        assert_eq!((getpid(), getppid()), (2, process.saved));
+    });
+}
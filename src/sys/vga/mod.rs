@@ -1,7 +1,9 @@
-//! VGA Text Mode Driver — 80×25, 16 colors
+//! VGA Text Mode Driver — 80×25 or 80×50, 16 colors
 //!
 //! Writes directly to the VGA framebuffer at 0xB8000.
 
+mod mode;
+
 use core::fmt;
 use lazy_static::lazy_static;
 use spin::Mutex;
@@ -14,11 +16,19 @@ use x86_64::instructions::port::Port;
 
 const VGA_ADDR: usize = 0xB8000;
 const COLS: usize     = 80;
-const ROWS: usize     = 25;
+/// Rows of the tallest mode; the framebuffer view covers this many
+const MAX_ROWS: usize = 50;
+const TAB_WIDTH: usize = 8;
+
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA:  u16 = 0x3D5;
+/// Cursor start register; bit 5 turns the cursor off
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CURSOR_DISABLE: u8 = 1 << 5;
 
 #[allow(dead_code)]
 #[repr(u8)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Color {
     Black        = 0,
     Blue         = 1,
@@ -38,14 +48,34 @@ pub enum Color {
     White        = 15,
 }
 
+/// SGR color numbers 0-7 (black, red, green, yellow, blue, magenta,
+/// cyan, white) in VGA order, then their bright variants
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black, Color::Red, Color::Green, Color::Brown,
+    Color::Blue, Color::Magenta, Color::Cyan, Color::LightGray,
+    Color::DarkGray, Color::LightRed, Color::LightGreen, Color::Yellow,
+    Color::LightBlue, Color::Pink, Color::LightCyan, Color::White,
+];
+
+const DEFAULT_FG: Color = Color::LightGray;
+const DEFAULT_BG: Color = Color::Black;
+
 #[repr(transparent)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Attr(u8);
 
 impl Attr {
     const fn new(fg: Color, bg: Color) -> Self {
         Self((bg as u8) << 4 | (fg as u8))
     }
+
+    fn with_fg(self, fg: Color) -> Self {
+        Self(self.0 & 0xF0 | fg as u8)
+    }
+
+    fn with_bg(self, bg: Color) -> Self {
+        Self(self.0 & 0x0F | (bg as u8) << 4)
+    }
 }
 
 #[repr(C)]
@@ -55,6 +85,22 @@ struct VgaChar {
     attr:  Attr,
 }
 
+// ---------------------------------------------------------------------------
+// ANSI escape parser state
+// ---------------------------------------------------------------------------
+
+const MAX_PARAMS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Escape {
+    /// Plain text
+    None,
+    /// Got ESC, waiting for '['
+    Esc,
+    /// Inside `ESC [ params... final`
+    Csi,
+}
+
 // ---------------------------------------------------------------------------
 // Writer
 // ---------------------------------------------------------------------------
@@ -63,16 +109,133 @@ pub struct VgaWriter {
     col:    usize,
     row:    usize,
     attr:   Attr,
-    buf:    &'static mut [[VgaChar; COLS]; ROWS],
+    buf:    &'static mut [[VgaChar; COLS]; MAX_ROWS],
+    /// Rows on screen in the current mode
+    rows:   usize,
+    escape: Escape,
+    params: [usize; MAX_PARAMS],
+    nparam: usize,
+    /// The sequence started with `?` (DEC private mode)
+    private: bool,
+    /// Position stored by `ESC [ s`
+    saved:  (usize, usize),
+    cursor_visible: bool,
 }
 
 impl VgaWriter {
     fn new() -> Self {
         Self {
-            col:  0,
-            row:  0,
-            attr: Attr::new(Color::LightGray, Color::Black),
-            buf:  unsafe { &mut *(VGA_ADDR as *mut [[VgaChar; COLS]; ROWS]) },
+            col:    0,
+            row:    0,
+            attr:   Attr::new(DEFAULT_FG, DEFAULT_BG),
+            buf:    unsafe { &mut *(VGA_ADDR as *mut [[VgaChar; COLS]; MAX_ROWS]) },
+            rows:   VgaMode::Text80x25.rows(),
+            escape: Escape::None,
+            params: [0; MAX_PARAMS],
+            nparam: 0,
+            private: false,
+            saved:  (0, 0),
+            cursor_visible: true,
+        }
+    }
+
+    /// Feed one byte through the escape parser. Returns true if it was
+    /// part of an escape sequence and must not be printed.
+    fn escape_byte(&mut self, byte: u8) -> bool {
+        match self.escape {
+            Escape::None if byte == 0x1B => {
+                self.escape = Escape::Esc;
+                true
+            }
+            Escape::None => false,
+            Escape::Esc => {
+                if byte == b'[' {
+                    self.escape = Escape::Csi;
+                    self.params = [0; MAX_PARAMS];
+                    self.nparam = 0;
+                    self.private = false;
+                } else {
+                    self.escape = Escape::None; // unsupported, drop it
+                }
+                true
+            }
+            Escape::Csi => {
+                match byte {
+                    b'0'..=b'9' => {
+                        let i = self.nparam.min(MAX_PARAMS - 1);
+                        self.params[i] = self.params[i]
+                            .saturating_mul(10)
+                            .saturating_add((byte - b'0') as usize);
+                    }
+                    b';' => self.nparam += 1,
+                    0x40..=0x7E => {
+                        self.nparam += 1;
+                        self.escape = Escape::None;
+                        self.csi(byte);
+                    }
+                    b'?' => self.private = true,
+                    _ => {} // other markers are accepted and ignored
+                }
+                true
+            }
+        }
+    }
+
+    /// Parameter `i` of the current sequence, with `default` for 0/missing
+    fn param(&self, i: usize, default: usize) -> usize {
+        match self.params.get(i) {
+            Some(&n) if i < self.nparam && n != 0 => n,
+            _ => default,
+        }
+    }
+
+    /// Run a complete `ESC [ ... final` sequence. Unknown ones are ignored.
+    fn csi(&mut self, cmd: u8) {
+        if self.private {
+            // Only cursor visibility, `ESC [ ? 25 h` / `l`
+            match (self.param(0, 0), cmd) {
+                (25, b'h') => self.show_cursor(),
+                (25, b'l') => self.hide_cursor(),
+                _ => {}
+            }
+            return;
+        }
+        let n = self.param(0, 1);
+        match cmd {
+            b'A' => self.row = self.row.saturating_sub(n),
+            b'B' => self.row = (self.row + n).min(self.rows - 1),
+            b'C' => self.col = (self.col + n).min(COLS - 1),
+            b'D' => self.col = self.col.saturating_sub(n),
+            b'H' | b'f' => {
+                self.row = (self.param(0, 1) - 1).min(self.rows - 1);
+                self.col = (self.param(1, 1) - 1).min(COLS - 1);
+            }
+            b'J' if self.param(0, 0) >= 2 => {
+                // Erase the whole screen; the cursor stays where it is
+                let (row, col) = (self.row, self.col);
+                self.clear();
+                (self.row, self.col) = (row, col);
+            }
+            b'm' => self.sgr(),
+            b's' => self.saved = (self.row, self.col),
+            b'u' => (self.row, self.col) = self.saved,
+            _ => {}
+        }
+    }
+
+    /// `ESC [ ... m`: colors. Attributes we cannot show are ignored.
+    fn sgr(&mut self) {
+        for i in 0..self.nparam.min(MAX_PARAMS) {
+            let n = self.params[i];
+            self.attr = match n {
+                0       => Attr::new(DEFAULT_FG, DEFAULT_BG),
+                30..=37 => self.attr.with_fg(ANSI_COLORS[n - 30]),
+                39      => self.attr.with_fg(DEFAULT_FG),
+                40..=47 => self.attr.with_bg(ANSI_COLORS[n - 40]),
+                49      => self.attr.with_bg(DEFAULT_BG),
+                90..=97 => self.attr.with_fg(ANSI_COLORS[n - 90 + 8]),
+                _       => self.attr,
+            };
         }
     }
 
@@ -80,6 +243,17 @@ impl VgaWriter {
         match byte {
             b'\n' => self.newline(),
             b'\r' => self.col = 0,
+            b'\t' => {
+                let stop = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                if stop > COLS {
+                    self.newline();
+                } else {
+                    while self.col < stop {
+                        self.put(b' ');
+                        self.col += 1;
+                    }
+                }
+            }
             b'\x08' => { // Backspace
                 if self.col > 0 { self.col -= 1; }
                 self.put(b' ');
@@ -99,7 +273,7 @@ impl VgaWriter {
 
     fn newline(&mut self) {
         self.col = 0;
-        if self.row < ROWS - 1 {
+        if self.row < self.rows - 1 {
             self.row += 1;
         } else {
             self.scroll();
@@ -107,20 +281,21 @@ impl VgaWriter {
     }
 
     fn scroll(&mut self) {
-        for r in 1..ROWS {
+        for r in 1..self.rows {
             for c in 0..COLS {
                 self.buf[r - 1][c] = self.buf[r][c];
             }
         }
         let blank = VgaChar { ascii: b' ', attr: self.attr };
         for c in 0..COLS {
-            self.buf[ROWS - 1][c] = blank;
+            self.buf[self.rows - 1][c] = blank;
         }
     }
 
     fn clear(&mut self) {
         let blank = VgaChar { ascii: b' ', attr: self.attr };
-        for row in self.buf.iter_mut() {
+        let rows = self.rows;
+        for row in self.buf[..rows].iter_mut() {
             for cell in row.iter_mut() {
                 *cell = blank;
             }
@@ -141,12 +316,36 @@ impl VgaWriter {
         }
     }
 
-    /// Process minimal ANSI escape sequences (color, clear)
+    fn cursor_start(&self) -> u8 {
+        unsafe {
+            Port::<u8>::new(CRTC_INDEX).write(CRTC_CURSOR_START);
+            Port::<u8>::new(CRTC_DATA).read()
+        }
+    }
+
+    fn set_cursor_start(&self, value: u8) {
+        unsafe {
+            Port::<u8>::new(CRTC_INDEX).write(CRTC_CURSOR_START);
+            Port::<u8>::new(CRTC_DATA).write(value);
+        }
+    }
+
+    pub fn show_cursor(&mut self) {
+        self.set_cursor_start(self.cursor_start() & !CURSOR_DISABLE);
+        self.cursor_visible = true;
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.set_cursor_start(self.cursor_start() | CURSOR_DISABLE);
+        self.cursor_visible = false;
+    }
+
+    /// Write text, interpreting ANSI cursor, erase and color sequences
     fn write_str_ansi(&mut self, s: &str) {
-        // Simple implementation: pass through as-is without ANSI parsing
-        // (ANSI parsing is optional and can be added later)
         for byte in s.bytes() {
-            self.write_byte(byte);
+            if !self.escape_byte(byte) {
+                self.write_byte(byte);
+            }
         }
         self.set_cursor(self.row, self.col);
     }
@@ -167,8 +366,190 @@ lazy_static! {
     pub static ref WRITER: Mutex<VgaWriter> = Mutex::new(VgaWriter::new());
 }
 
+/// Screen size in character cells (columns, rows)
+pub fn size() -> (usize, usize) {
+    (COLS, interrupts::without_interrupts(|| WRITER.lock().rows))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VgaMode {
+    /// 8×16 font, the BIOS default
+    Text80x25,
+    /// 8×8 font derived from the 8×16 one
+    Text80x50,
+}
+
+impl VgaMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "80x25" => Some(Self::Text80x25),
+            "80x50" => Some(Self::Text80x50),
+            _ => None,
+        }
+    }
+
+    fn rows(self) -> usize {
+        match self {
+            Self::Text80x25 => 25,
+            Self::Text80x50 => 50,
+        }
+    }
+}
+
+/// Switch text mode. Text already on screen stays where it is; lines
+/// that no longer fit scroll off the top.
+pub fn set_mode(mode: VgaMode) {
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        match mode {
+            VgaMode::Text80x25 => mode::use_rom_font(),
+            VgaMode::Text80x50 => mode::use_8x8_font(),
+        }
+        let (old, new) = (w.rows, mode.rows());
+        while w.row >= new {
+            w.scroll();
+            w.row -= 1;
+        }
+        w.rows = new;
+        let blank = VgaChar { ascii: b' ', attr: w.attr };
+        for row in w.buf[old.min(new)..new].iter_mut() {
+            row.fill(blank);
+        }
+        let (row, col) = (w.row, w.col.min(COLS - 1));
+        w.set_cursor(row, col);
+    });
+}
+
 pub fn init() {
     interrupts::without_interrupts(|| {
         WRITER.lock().clear();
     });
 }
+
+/// Color of the text written from now on (VGA only; serial output is
+/// colored with escape sequences instead)
+pub fn set_color(fg: Color, bg: Color) {
+    interrupts::without_interrupts(|| {
+        WRITER.lock().attr = Attr::new(fg, bg);
+    });
+}
+
+/// Back to light gray on black
+pub fn reset_color() {
+    set_color(DEFAULT_FG, DEFAULT_BG);
+}
+
+/// Back to the boot state: default colors, no half-parsed escape
+/// sequence, blank screen with the cursor at the top left
+pub fn reset() {
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.attr   = Attr::new(DEFAULT_FG, DEFAULT_BG);
+        w.escape = Escape::None;
+        w.saved  = (0, 0);
+        w.clear();
+        w.set_cursor(0, 0);
+    });
+}
+
+#[test_case]
+fn cursor_save_restore_and_moves() {
+    use fmt::Write;
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.write_str("\x1b[H\x1b[3B\x1b[5C\x1b[s\x1b[H\x1b[uX").ok();
+        assert_eq!((w.row, w.col), (3, 6));
+        assert_eq!(w.buf[3][5].ascii, b'X');
+
+        // Moves clamp at the screen edges; unknown sequences vanish
+        w.write_str("\x1b[99A\x1b[200C\x1b[?25h\x1b[1;31m").ok();
+        assert_eq!((w.row, w.col), (0, COLS - 1));
+        w.write_str("\x1b[0m\x1b[25;1H").ok();
+    });
+}
+
+#[test_case]
+fn sgr_colors_and_clear() {
+    use fmt::Write;
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.write_str("\x1b[2J\x1b[H\x1b[32mok\x1b[0m").ok();
+        assert_eq!(w.buf[0][0].ascii, b'o');
+        assert_eq!(w.buf[0][0].attr, Attr::new(Color::Green, DEFAULT_BG));
+        assert_eq!(w.attr, Attr::new(DEFAULT_FG, DEFAULT_BG));
+        assert_eq!(w.buf[0][2].ascii, b' ');
+
+        w.write_str("\x1b[44;91m\x1b[5;7H").ok();
+        assert_eq!(w.attr, Attr::new(Color::LightRed, Color::Blue));
+        assert_eq!((w.row, w.col), (4, 6));
+
+        // Unknown sequences are swallowed, not printed
+        w.write_str("\x1b[0m\x1b[7q\x1b[HA").ok();
+        assert_eq!(w.buf[0][0].ascii, b'A');
+        assert_eq!(w.buf[0][1].ascii, b'k');
+        w.write_str("\x1b[25;1H").ok();
+    });
+}
+
+#[test_case]
+fn tabs_advance_to_next_stop() {
+    use fmt::Write;
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.write_str("\x1b[1;1Hab\tc").ok();
+        assert_eq!((w.row, w.col), (0, 9));
+        assert_eq!(w.buf[0][8].ascii, b'c');
+        assert!(w.buf[0][2..8].iter().all(|c| c.ascii == b' '));
+
+        // A tab that would run past the last column starts a new line
+        w.write_str("\x1b[1;80Hx\ty").ok();
+        assert_eq!((w.row, w.col), (1, 1));
+        assert_eq!(w.buf[1][0].ascii, b'y');
+        w.write_str("\x1b[25;1H").ok();
+    });
+}
+
+#[test_case]
+fn set_color_changes_following_text() {
+    let attr = || interrupts::without_interrupts(|| WRITER.lock().attr);
+    set_color(Color::Yellow, Color::Blue);
+    assert_eq!(attr(), Attr::new(Color::Yellow, Color::Blue));
+    reset_color();
+    assert_eq!(attr(), Attr::new(DEFAULT_FG, DEFAULT_BG));
+}
+
+#[test_case]
+fn private_mode_toggles_cursor() {
+    use fmt::Write;
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.write_str("\x1b[?25l").ok();
+        assert!(!w.cursor_visible);
+        w.write_str("\x1b[?25h").ok();
+        assert!(w.cursor_visible);
+        // Private sequences do not fall through to their plain meaning
+        w.write_str("\x1b[1;1Hx\x1b[?2J").ok();
+        assert_eq!(w.buf[0][0].ascii, b'x');
+        w.write_str("\x1b[25;1H").ok();
+    });
+}
+
+#[test_case]
+fn fifty_line_mode_uses_every_row() {
+    use fmt::Write;
+    set_mode(VgaMode::Text80x50);
+    assert_eq!(size(), (COLS, 50));
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.write_str("\x1b[99;1Hz").ok();
+        assert_eq!((w.row, w.buf[49][0].ascii), (49, b'z'));
+    });
+
+    // Back to 25 lines, the cursor line scrolls into view
+    set_mode(VgaMode::Text80x25);
+    assert_eq!(size(), (COLS, 25));
+    interrupts::without_interrupts(|| {
+        let w = WRITER.lock();
+        assert_eq!((w.row, w.buf[24][0].ascii), (24, b'z'));
+    });
+}
@@ -0,0 +1,119 @@
+//! Text mode registers — character height and font slots
+//!
+//! 80×50 uses an 8×8 font. Rather than carry one in the kernel, it is
+//! derived from the 8×16 font the BIOS left in plane 2, by merging each
+//! pair of scan lines, and stored in the second font slot. A mode switch
+//! selects the slot and sets the character height; the vertical timing
+//! stays the same, so its 400 scan lines make 25 or 50 rows.
+
+use crate::sys;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+const SEQ_INDEX:  u16 = 0x3C4;
+const GC_INDEX:   u16 = 0x3CE;
+const CRTC_INDEX: u16 = 0x3D4;
+
+const SEQ_MAP_MASK: u8 = 0x02;
+const SEQ_CHAR_MAP: u8 = 0x03;
+const SEQ_MEM_MODE: u8 = 0x04;
+const GC_READ_MAP:  u8 = 0x04;
+const GC_MODE:      u8 = 0x05;
+const GC_MISC:      u8 = 0x06;
+const CRTC_MAX_SCAN:     u8 = 0x09;
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_END:   u8 = 0x0B;
+
+/// Plane 2 appears here while it is opened for font access
+const FONT_PHYS: u64 = 0xA0000;
+/// Bytes between glyphs in a font slot
+const GLYPH_STRIDE: usize = 32;
+/// Offset of font slot 1, where the 8×8 font goes
+const SLOT_8X8: usize = 0x4000;
+/// Character map select values: both maps on slot 0, or on slot 1
+const CHAR_MAP_ROM: u8 = 0x00;
+const CHAR_MAP_8X8: u8 = 0x05;
+
+fn write_reg(index: u16, reg: u8, value: u8) {
+    unsafe {
+        Port::<u8>::new(index).write(reg);
+        Port::<u8>::new(index + 1).write(value);
+    }
+}
+
+fn read_reg(index: u16, reg: u8) -> u8 {
+    unsafe {
+        Port::<u8>::new(index).write(reg);
+        Port::<u8>::new(index + 1).read()
+    }
+}
+
+/// Map plane 2 alone at 0xA0000, without odd/even addressing
+fn open_font_plane() {
+    write_reg(SEQ_INDEX, SEQ_MAP_MASK, 0x04);
+    write_reg(SEQ_INDEX, SEQ_MEM_MODE, 0x07);
+    write_reg(GC_INDEX, GC_READ_MAP, 0x02);
+    write_reg(GC_INDEX, GC_MODE, 0x00);
+    write_reg(GC_INDEX, GC_MISC, 0x04);
+}
+
+/// Back to the text mode setup: planes 0 and 1 at 0xB8000, odd/even
+fn close_font_plane() {
+    write_reg(SEQ_INDEX, SEQ_MAP_MASK, 0x03);
+    write_reg(SEQ_INDEX, SEQ_MEM_MODE, 0x03);
+    write_reg(GC_INDEX, GC_READ_MAP, 0x00);
+    write_reg(GC_INDEX, GC_MODE, 0x10);
+    write_reg(GC_INDEX, GC_MISC, 0x0E);
+}
+
+/// An 8×8 glyph from an 8×16 one: each line is two lines OR-ed, so thin
+/// strokes survive
+pub fn halve_glyph(tall: &[u8; 16]) -> [u8; 8] {
+    core::array::from_fn(|i| tall[2 * i] | tall[2 * i + 1])
+}
+
+fn load_8x8_font() {
+    let plane = sys::mem::phys_to_virt(PhysAddr::new(FONT_PHYS)).as_mut_ptr::<u8>();
+    open_font_plane();
+    for ch in 0..256 {
+        unsafe {
+            let glyph = plane.add(ch * GLYPH_STRIDE);
+            let tall: [u8; 16] = core::array::from_fn(|i| glyph.add(i).read_volatile());
+            let small = glyph.add(SLOT_8X8);
+            for (i, line) in halve_glyph(&tall).into_iter().enumerate() {
+                small.add(i).write_volatile(line);
+            }
+        }
+    }
+    close_font_plane();
+}
+
+/// Character cell height in scan lines, with the cursor on its last two
+fn set_char_height(height: u8) {
+    let keep = |reg| read_reg(CRTC_INDEX, reg) & 0xE0;
+    write_reg(CRTC_INDEX, CRTC_MAX_SCAN, keep(CRTC_MAX_SCAN) | (height - 1));
+    write_reg(CRTC_INDEX, CRTC_CURSOR_START, keep(CRTC_CURSOR_START) | (height - 2));
+    write_reg(CRTC_INDEX, CRTC_CURSOR_END, keep(CRTC_CURSOR_END) | (height - 1));
+}
+
+pub fn use_rom_font() {
+    write_reg(SEQ_INDEX, SEQ_CHAR_MAP, CHAR_MAP_ROM);
+    set_char_height(16);
+}
+
+pub fn use_8x8_font() {
+    load_8x8_font();
+    write_reg(SEQ_INDEX, SEQ_CHAR_MAP, CHAR_MAP_8X8);
+    set_char_height(8);
+}
+
+#[test_case]
+fn halved_glyph_keeps_thin_lines() {
+    let mut tall = [0u8; 16];
+    tall[3]  = 0x18; // single-line stroke on an odd line
+    tall[10] = 0xFF;
+    let small = halve_glyph(&tall);
+    assert_eq!(small[1], 0x18);
+    assert_eq!(small[5], 0xFF);
+    assert_eq!(small.iter().filter(|&&b| b != 0).count(), 2);
+}
@@ -7,7 +7,7 @@
 use crate::sys::pci;
 use crate::sys::mem::virt_to_phys;
 
-use core::sync::atomic::{fence, Ordering};
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
 use spin::{Mutex, Once};
 use x86_64::instructions::port::Port;
 use x86_64::VirtAddr;
@@ -44,6 +44,12 @@ const QUEUE_SIZE: usize = 8;
 /// Ukuran satu sektor disk
 pub const SECTOR_SIZE: usize = 512;
 
+/// Most sectors one request can transfer
+pub const MAX_SECTORS: usize = 8;
+
+/// Read requests sent to the device, for measuring caches
+static READS: AtomicUsize = AtomicUsize::new(0);
+
 // ---------------------------------------------------------------------------
 // Virtqueue descriptor
 // ---------------------------------------------------------------------------
@@ -128,9 +134,14 @@ static mut VQ_MEM: VirtqueueMem = VirtqueueMem {
     used_avail_event: 0,
 };
 
+/// Exactly one page, page aligned, so it is physically contiguous for a
+/// multi-sector transfer
+#[repr(C, align(4096))]
+struct DataBuf([u8; SECTOR_SIZE * MAX_SECTORS]);
+
 // Buffer untuk satu request sekaligus (driver ini single-threaded)
 static mut REQ_HDR:    VirtioBlkReq   = VirtioBlkReq { kind: 0, reserved: 0, sector: 0 };
-static mut DATA_BUF:   [u8; SECTOR_SIZE] = [0u8; SECTOR_SIZE];
+static mut DATA_BUF:   DataBuf        = DataBuf([0u8; SECTOR_SIZE * MAX_SECTORS]);
 static mut STATUS_BUF: u8             = 0xFF;
 
 // ---------------------------------------------------------------------------
@@ -171,11 +182,17 @@ impl VirtioBlk {
             .unwrap_or(vaddr)
     }
 
-    /// Kirim satu block request dan tunggu selesai (polling)
+    /// Kirim satu block request dan tunggu selesai (polling).
+    /// Transfers as many whole sectors as `buf` covers, up to MAX_SECTORS.
     fn do_request(&mut self, sector: u64, buf: &mut [u8], write: bool) -> Result<(), &'static str> {
-        if sector >= self.capacity {
+        let sectors = buf.len().div_ceil(SECTOR_SIZE).clamp(1, MAX_SECTORS);
+        let len = sectors * SECTOR_SIZE;
+        if sector + sectors as u64 > self.capacity {
             return Err("virtio: sector out of range");
         }
+        if !write {
+            READS.fetch_add(1, Ordering::Relaxed);
+        }
 
         unsafe {
             // Setup request header
@@ -185,11 +202,9 @@ impl VirtioBlk {
             STATUS_BUF       = 0xFF; // reset status
 
             if write {
-                let n = buf.len().min(SECTOR_SIZE);
-                DATA_BUF[..n].copy_from_slice(&buf[..n]);
-                if n < SECTOR_SIZE {
-                    DATA_BUF[n..].fill(0);
-                }
+                let n = buf.len().min(len);
+                DATA_BUF.0[..n].copy_from_slice(&buf[..n]);
+                DATA_BUF.0[n..len].fill(0);
             }
 
             let slot = (self.avail_idx as usize) % QUEUE_SIZE;
@@ -207,8 +222,8 @@ impl VirtioBlk {
             };
 
             VQ_MEM.desc[d1] = VirtqDesc {
-                addr:  Self::to_phys(DATA_BUF.as_ptr() as u64),
-                len:   SECTOR_SIZE as u32,
+                addr:  Self::to_phys(DATA_BUF.0.as_ptr() as u64),
+                len:   len as u32,
                 flags: VIRTQ_DESC_F_NEXT | if write { 0 } else { VIRTQ_DESC_F_WRITE },
                 next:  d2 as u16,
             };
@@ -253,8 +268,8 @@ impl VirtioBlk {
             }
 
             if !write {
-                let n = buf.len().min(SECTOR_SIZE);
-                buf[..n].copy_from_slice(&DATA_BUF[..n]);
+                let n = buf.len().min(len);
+                buf[..n].copy_from_slice(&DATA_BUF.0[..n]);
             }
 
             Ok(())
@@ -355,6 +370,17 @@ pub fn read_sector(sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
         .do_request(sector, buf, false)
 }
 
+/// Read `buf.len() / SECTOR_SIZE` consecutive sectors (at most
+/// MAX_SECTORS) with a single request
+pub fn read_sectors(sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+    read_sector(sector, buf)
+}
+
+/// Read requests sent so far
+pub fn read_count() -> usize {
+    READS.load(Ordering::Relaxed)
+}
+
 /// Tulis satu sektor dari buf ke disk (buf minimal 512 bytes)
 pub fn write_sector(sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
     DEVICE.get()
@@ -0,0 +1,130 @@
+//! Work queue — deferred interrupt handling for Chilena
+//!
+//! IRQ handlers (top half) only push a small work item into a bounded
+//! lock-free ring and return. The items are processed later (bottom half)
+//! by `run()`: from the console read/poll paths and the sleep wait with
+//! interrupts enabled, and on each timer tick that interrupted
+//! userspace, where no kernel lock can be held. Decoding and STDIN
+//! locking never happen inside the IRQ that queued the byte.
+//!
+//! Single-core, and IRQ handlers never nest, so there is exactly one
+//! producer at a time; `run()` guards itself to keep a single consumer.
+
+use crate::sys;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+// ---------------------------------------------------------------------------
+// Work items
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Work {
+    /// Raw byte read from the PS/2 data port
+    Scancode(u8),
+    /// Raw byte received on COM1
+    Serial(u8),
+}
+
+impl Work {
+    fn encode(self) -> u32 {
+        match self {
+            Work::Scancode(b) => 1 << 8 | b as u32,
+            Work::Serial(b)   => 2 << 8 | b as u32,
+        }
+    }
+
+    fn decode(v: u32) -> Option<Self> {
+        let b = v as u8;
+        match v >> 8 {
+            1 => Some(Work::Scancode(b)),
+            2 => Some(Work::Serial(b)),
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Ring buffer
+// ---------------------------------------------------------------------------
+
+/// Queue capacity — must be a power of two
+const CAPACITY: usize = 256;
+
+static ITEMS:    [AtomicU32; CAPACITY] = [const { AtomicU32::new(0) }; CAPACITY];
+/// Index of the next item to take (consumer side)
+static HEAD:     AtomicUsize = AtomicUsize::new(0);
+/// Index of the next free slot (producer side)
+static TAIL:     AtomicUsize = AtomicUsize::new(0);
+/// Items thrown away because the queue was full
+static DROPPED:  AtomicUsize = AtomicUsize::new(0);
+static DRAINING: AtomicBool  = AtomicBool::new(false);
+
+/// Queue a work item. Safe to call from interrupt context.
+/// Returns false (and counts a drop) if the queue is full.
+pub fn push(work: Work) -> bool {
+    let tail = TAIL.load(Ordering::Relaxed);
+    let head = HEAD.load(Ordering::Acquire);
+    if tail.wrapping_sub(head) >= CAPACITY {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    ITEMS[tail % CAPACITY].store(work.encode(), Ordering::Relaxed);
+    TAIL.store(tail.wrapping_add(1), Ordering::Release);
+    true
+}
+
+fn pop() -> Option<Work> {
+    let head = HEAD.load(Ordering::Relaxed);
+    let tail = TAIL.load(Ordering::Acquire);
+    if head == tail {
+        return None;
+    }
+    let v = ITEMS[head % CAPACITY].load(Ordering::Relaxed);
+    HEAD.store(head.wrapping_add(1), Ordering::Release);
+    Work::decode(v)
+}
+
+/// Number of items lost to a full queue since boot
+pub fn dropped() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+// ---------------------------------------------------------------------------
+// Bottom half
+// ---------------------------------------------------------------------------
+
+/// Process every queued item. Must not be called from interrupt context.
+pub fn run() {
+    if DRAINING.swap(true, Ordering::Acquire) {
+        return; // someone else is already draining
+    }
+    while let Some(work) = pop() {
+        match work {
+            Work::Scancode(b) => sys::keyboard::handle_scancode(b),
+            Work::Serial(b)   => sys::serial::handle_byte(b),
+        }
+    }
+    DRAINING.store(false, Ordering::Release);
+}
+
+#[test_case]
+fn keyboard_flood_is_not_dropped() {
+    use crate::sys::console::{self, STDIN};
+
+    const PRESSES: usize = CAPACITY / 2; // press + release per key
+    let lost = dropped();
+
+    console::disable_echo();
+    STDIN.lock().clear();
+    for _ in 0..PRESSES {
+        assert!(push(Work::Scancode(0x1E))); // 'a' down
+        assert!(push(Work::Scancode(0x9E))); // 'a' up
+    }
+    run();
+
+    assert_eq!(STDIN.lock().matches('a').count(), PRESSES);
+    assert_eq!(dropped(), lost);
+    STDIN.lock().clear();
+    console::enable_echo();
+}
@@ -0,0 +1,114 @@
+//! alias / unalias — command shortcuts
+//!
+//! Aliases replace the first word of a command line. They are kept in
+//! memory and mirrored to /ini/aliases, which the shell loads at start.
+
+use crate::sys;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use spin::Mutex;
+
+const ALIAS_FILE: &str = "/ini/aliases";
+/// Limit on chained expansions (`a` → `b` → ...), stops alias loops
+const MAX_DEPTH: usize = 16;
+
+static ALIASES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Replace the first word of `line` while it names an alias
+pub fn expand(line: &str) -> String {
+    let aliases = ALIASES.lock();
+    let mut line = line.to_string();
+    let mut seen = alloc::vec::Vec::new();
+    for _ in 0..MAX_DEPTH {
+        let (first, rest) = match line.split_once(char::is_whitespace) {
+            Some((f, r)) => (f.to_string(), r.to_string()),
+            None         => (line.clone(), String::new()),
+        };
+        if seen.contains(&first) {
+            break;
+        }
+        match aliases.get(&first) {
+            Some(exp) if rest.is_empty() => line = exp.clone(),
+            Some(exp) => line = alloc::format!("{} {}", exp, rest),
+            None      => break,
+        }
+        seen.push(first);
+    }
+    line
+}
+
+/// Parse `name=expansion` (quotes around the expansion are optional)
+fn define(def: &str) -> Result<(), ()> {
+    let (name, exp) = def.split_once('=').ok_or(())?;
+    let name = name.trim();
+    let exp  = exp.trim().trim_matches(|c| c == '\'' || c == '"');
+    if name.is_empty() || name.contains(char::is_whitespace) || exp.is_empty() {
+        return Err(());
+    }
+    ALIASES.lock().insert(name.to_string(), exp.to_string());
+    Ok(())
+}
+
+/// Load aliases saved by a previous session
+pub fn load() {
+    if let Some(f) = sys::fs::open_file(ALIAS_FILE) {
+        let text = String::from_utf8_lossy(f.data()).into_owned();
+        for line in text.lines() {
+            define(line).ok();
+        }
+    }
+}
+
+fn save() {
+    if !sys::fs::is_mounted() {
+        return;
+    }
+    let mut text = String::new();
+    for (name, exp) in ALIASES.lock().iter() {
+        text.push_str(&alloc::format!("{}='{}'\n", name, exp));
+    }
+    sys::fs::write_file(ALIAS_FILE, text.as_bytes()).ok();
+}
+
+pub fn run(args: &[&str]) {
+    if args.is_empty() {
+        for (name, exp) in ALIASES.lock().iter() {
+            println!("alias {}='{}'", name, exp);
+        }
+        return;
+    }
+    if define(&args.join(" ")).is_err() {
+        println!("alias: usage: alias name='command'");
+        return;
+    }
+    save();
+}
+
+pub fn unalias(args: &[&str]) {
+    let name = match args.first() {
+        Some(n) => n,
+        None => { println!("unalias: usage: unalias <name>"); return; }
+    };
+    if ALIASES.lock().remove(*name).is_none() {
+        println!("unalias: '{}' not found", name);
+        return;
+    }
+    save();
+}
+
+#[test_case]
+fn alias_expands_first_word_only() {
+    define("p=echo").unwrap();
+    define("loop1=loop2").unwrap();
+    define("loop2=loop1 x").unwrap();
+
+    assert_eq!(expand("p hi"), "echo hi");
+    assert_eq!(expand("echo p"), "echo p");
+    // Recursion stops once a name repeats
+    assert!(expand("loop1").starts_with("loop"));
+
+    let mut aliases = ALIASES.lock();
+    for name in ["p", "loop1", "loop2"] {
+        aliases.remove(name);
+    }
+}
@@ -4,13 +4,14 @@ use crate::sys;
 
 pub fn run(args: &[&str]) {
     let path = args.first().copied().unwrap_or("/");
-    let full_path = match sys::fs::canonicalize(path) {
-        Ok(p) => p,
-        Err(_) => { println!("cd: invalid path"); return; }
+    let (view, full_path) = match (sys::fs::view_path(path), sys::fs::canonicalize(path)) {
+        (Ok(v), Ok(p)) => (v, p),
+        _ => { println!("cd: invalid path"); return; }
     };
     if full_path != "/" && !sys::fs::dir_exists(&full_path) {
         println!("cd: directory '{}' not found", path);
         return;
     }
-    sys::process::set_cwd(&full_path);
+    // cwd is kept as the process sees it, inside its root
+    sys::process::set_cwd(&view);
 }
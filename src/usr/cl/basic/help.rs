@@ -5,14 +5,44 @@ pub fn run() {
     println!("  help           — show this message");
     println!("  echo [text]    — print text");
     println!("  cd [path]      — change directory");
+    println!("  basename <p> [suffix] — last component of a path");
+    println!("  dirname <p>    — path without its last component");
     println!("  info           — system information");
-    println!("  ls [path]      — list files");
+    println!("  alias [n='c']  — list or define a command alias");
+    println!("  unalias <n>    — remove an alias");
+    println!("  test <expr>    — check a condition, also [ expr ]");
+    println!("  ls [path]      — list files and directories (default: cwd)");
     println!("  cat [file]     — show file contents");
     println!("  write [f] [t]  — write text to file");
+    println!("  cp <src> <dst> — copy a file");
+    println!("  ln <old> <new> — add another name (hard link) for a file");
     println!("  mkdir [path]   — create directory");
+    println!("  mount          — list mounted filesystems");
+    println!("  fs compact     — release memory held by the VFS");
+    println!("  unpack <tar> [dir] — extract a tar archive");
+    println!("  dd if=<src> of=<dst> [bs=] [count=] — copy blocks (/dev/zero, /dev/random, /dev/hda)");
+    println!("  edit <file>    — full-screen text editor");
+    println!("  wc [-lwc] [f]  — count lines, words, bytes");
+    println!("  sort [-nr] [f] — sort lines");
+    println!("  uniq [-c] [f]  — collapse repeated lines");
+    println!("  xargs [-n N] <cmd> — run cmd with words from stdin");
+    println!("  a | b          — pipe output of a into b");
     println!("  install        — setup initial filesystem");
+    println!("  dmesg [-w]     — show (or follow) the kernel log");
+    println!("  meminfo [regions] — frame usage, or the physical memory map");
+    println!("  ps             — list processes, their wait state and CPU time");
+    println!("  top            — live process list by CPU usage (q quits)");
+    println!("  uptime         — time since boot and 1/5/15 minute load averages");
+    println!("  kill <pid>     — terminate a process, even a blocked one");
+    println!("  hostname [name] — show or set (CAP_MOUNT) the hostname");
     println!("  send <pid> <m> — send IPC message");
     println!("  recv           — receive IPC message");
-    println!("  reboot         — restart the system");
+    println!("  reset          — restore terminal mode and clear screen");
+    println!("  renice <n> <pid> — set a process's priority (-20..19)");
+    println!("  nice <p> <pid> — set a process's priority class (0 first..7)");
+    println!("  disown <pid>   — keep a job running after the shell exits");
+    println!("  reboot [-f] [-w] — restart the system (-w: warm reset)");
+    println!("  halt [-f]      — power off the system");
+    println!("  ulimit [-n|-v|-t|-r] [v] — show/set resource limits");
     println!("  exit           — exit the shell");
 }
@@ -1,9 +1,12 @@
 //! info — display system information
 
+use crate::api::console::{reset_color, set_color, Color};
 use crate::sys;
 
 pub fn run() {
+    set_color(Color::LightCyan, Color::Black);
     println!("=== Chilena System Info ===");
+    reset_color();
     println!("Kernel  : Chilena v{}", crate::VERSION);
     println!("Uptime  : {:.3} seconds", sys::clk::uptime_secs());
     println!("Date    : {}", sys::clk::date_string());
@@ -11,6 +14,8 @@ pub fn run() {
         sys::mem::total_memory() >> 20,
         sys::mem::free_memory()  >> 20,
     );
+    let (used, free) = sys::mem::frame_stats();
+    println!("Frames  : {} used, {} free", used, free);
     println!("CWD     : {}", sys::process::cwd());
     if let Some(user) = sys::process::current_user() {
         println!("User    : {}", user);
@@ -2,5 +2,8 @@
 
 pub mod help;
 pub mod echo;
+pub mod alias;
 pub mod cd;
 pub mod info;
+pub mod path;
+pub mod test;
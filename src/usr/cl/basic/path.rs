@@ -0,0 +1,24 @@
+//! basename, dirname — print part of a path
+
+use crate::sys::path;
+
+pub fn basename(args: &[&str]) {
+    let (name, suffix) = match args {
+        [name] => (*name, ""),
+        [name, suffix] => (*name, *suffix),
+        _ => { println!("basename: usage: basename <path> [suffix]"); return; }
+    };
+    let base = path::basename(name);
+    // The suffix goes only if something is left of the name
+    match base.strip_suffix(suffix) {
+        Some(stem) if !stem.is_empty() => println!("{}", stem),
+        _ => println!("{}", base),
+    }
+}
+
+pub fn dirname(args: &[&str]) {
+    match args {
+        [name] => println!("{}", path::dirname(name)),
+        _ => println!("dirname: usage: dirname <path>"),
+    }
+}
@@ -0,0 +1,93 @@
+//! test / [ — evaluate a condition for scripts
+//!
+//! The common POSIX operators: `-f` and `-d`,
+//! `-z`/`-n`, `=`/`!=` and `-eq`/`-ne`/`-lt`/`-le`/`-gt`/`-ge`, each
+//! optionally negated with a leading `!`. The result is the command's
+//! exit status: success when the condition holds, failure otherwise.
+
+use crate::api::process::ExitCode;
+use crate::sys::fs::FileInfo;
+
+pub fn run(cmd: &str, args: &[&str]) -> Result<(), ExitCode> {
+    let args = if cmd == "[" {
+        match args.split_last() {
+            Some((&"]", rest)) => rest,
+            _ => {
+                println!("[: missing ']'");
+                return Err(ExitCode::Failure);
+            }
+        }
+    } else {
+        args
+    };
+    match eval(args) {
+        Ok(true)  => Ok(()),
+        Ok(false) => Err(ExitCode::Failure),
+        Err(())   => {
+            println!("{}: bad expression '{}'", cmd, args.join(" "));
+            Err(ExitCode::Failure)
+        }
+    }
+}
+
+/// Look `path` up in the VFS
+fn stat(path: &str) -> Option<FileInfo> {
+    let path = crate::sys::fs::canonicalize(path).ok()?;
+    crate::sys::fs::stat(&path)
+}
+
+/// Evaluate `args`. Err on a malformed expression.
+fn eval(args: &[&str]) -> Result<bool, ()> {
+    if let Some((&"!", rest)) = args.split_first() {
+        return eval(rest).map(|b| !b);
+    }
+    match *args {
+        [] => Ok(false),
+        [s] => Ok(!s.is_empty()),
+        ["-n", s] => Ok(!s.is_empty()),
+        ["-z", s] => Ok(s.is_empty()),
+        ["-f", path] => Ok(stat(path).is_some_and(|i| !i.is_dir)),
+        ["-d", path] => Ok(stat(path).is_some_and(|i| i.is_dir)),
+        [a, "=", b]  => Ok(a == b),
+        [a, "!=", b] => Ok(a != b),
+        [a, op, b] => {
+            let a: i64 = a.parse().map_err(|_| ())?;
+            let b: i64 = b.parse().map_err(|_| ())?;
+            match op {
+                "-eq" => Ok(a == b),
+                "-ne" => Ok(a != b),
+                "-lt" => Ok(a < b),
+                "-le" => Ok(a <= b),
+                "-gt" => Ok(a > b),
+                "-ge" => Ok(a >= b),
+                _ => Err(()),
+            }
+        }
+        _ => Err(()),
+    }
+}
+
+#[test_case]
+fn operators_match_known_conditions() {
+    crate::sys::fs::write_file("/tmp/test-op", b"x").unwrap();
+    crate::sys::fs::mkdir("/tmp/test-dir");
+
+    assert_eq!(eval(&["-f", "/tmp/test-op"]), Ok(true));
+    assert_eq!(eval(&["-f", "/tmp/test-dir"]), Ok(false));
+    assert_eq!(eval(&["-d", "/tmp/test-dir"]), Ok(true));
+    assert_eq!(eval(&["-d", "/tmp/nowhere"]), Ok(false));
+
+    assert_eq!(eval(&["-z", ""]), Ok(true));
+    assert_eq!(eval(&["-n", ""]), Ok(false));
+    assert_eq!(eval(&["abc", "=", "abc"]), Ok(true));
+    assert_eq!(eval(&["abc", "!=", "abc"]), Ok(false));
+
+    assert_eq!(eval(&["3", "-lt", "10"]), Ok(true));
+    assert_eq!(eval(&["-2", "-gt", "1"]), Ok(false));
+    assert_eq!(eval(&["7", "-eq", "7"]), Ok(true));
+    assert_eq!(eval(&["!", "7", "-eq", "7"]), Ok(false));
+    assert_eq!(eval(&["x", "-lt", "1"]), Err(()));
+
+    crate::sys::fs::remove("/tmp/test-op").ok();
+    crate::sys::fs::remove("/tmp/test-dir/.dir").ok();
+}
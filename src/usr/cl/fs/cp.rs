@@ -0,0 +1,31 @@
+//! cp — copy a file
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    if args.len() < 2 {
+        println!("cp: usage: cp <src> <dst>");
+        return;
+    }
+    let (src, dst) = match (sys::fs::canonicalize(args[0]), sys::fs::canonicalize_new(args[1])) {
+        (Ok(s), Ok(d)) => (s, d),
+        _ => { println!("cp: no such directory for '{}'", args[1]); return; }
+    };
+
+    let mut from = match sys::fs::open_resource(&src, 0) {
+        Some(r) => r,
+        None => { println!("cp: file '{}' not found", args[0]); return; }
+    };
+
+    // Start from an empty destination, then copy in-kernel
+    let mut to = match sys::fs::open_resource(&dst, sys::fs::O_CREATE | sys::fs::O_TRUNC) {
+        Some(r) => r,
+        None => { println!("cp: cannot create '{}'", dst); return; }
+    };
+
+    let len = from.size();
+    match sys::fs::copy_range(&mut from, &mut to, len) {
+        Ok(n) if to.sync().is_ok() => println!("Copied {} bytes to '{}'", n, dst),
+        _ => println!("cp: copy failed"),
+    }
+}
@@ -0,0 +1,182 @@
+//! dd — copy fixed-size blocks between files and devices
+//!
+//! `dd if=<src> of=<dst> [bs=<n>] [count=<m>] [skip=<k>] [seek=<k>]`
+//! copies `count` blocks of `bs` bytes (512 by default; all of the input
+//! without a count), starting `skip` blocks into the input and `seek`
+//! blocks into the output. An output file is created empty first, so
+//! only devices can be written at an offset. Endless inputs such as
+//! /dev/zero need a count.
+
+use crate::sys;
+use crate::sys::fs::{Device, Resource};
+use alloc::vec;
+
+/// Largest block size accepted, so one block fits the kernel heap easily
+const MAX_BS: usize = 1 << 20;
+
+struct Options<'a> {
+    input:  &'a str,
+    output: &'a str,
+    bs:     usize,
+    count:  Option<u64>,
+    skip:   u64,
+    seek:   u64,
+}
+
+fn parse<'a>(args: &[&'a str]) -> Result<Options<'a>, &'static str> {
+    let mut opts = Options { input: "", output: "", bs: 512, count: None, skip: 0, seek: 0 };
+    for arg in args {
+        let (key, value) = arg.split_once('=').ok_or("operands look like key=value")?;
+        let number = || value.parse::<u64>().map_err(|_| "bs, count, skip and seek take a number");
+        match key {
+            "if"    => opts.input  = value,
+            "of"    => opts.output = value,
+            "bs"    => opts.bs     = number()? as usize,
+            "count" => opts.count  = Some(number()?),
+            "skip"  => opts.skip   = number()?,
+            "seek"  => opts.seek   = number()?,
+            _       => return Err("unknown operand"),
+        }
+    }
+    if opts.input.is_empty() || opts.output.is_empty() {
+        return Err("if= and of= are required");
+    }
+    if opts.bs == 0 || opts.bs > MAX_BS {
+        return Err("bs must be between 1 and 1048576");
+    }
+    Ok(opts)
+}
+
+/// Blocks copied: (full, partial) read, (full, partial) written, bytes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Copied {
+    read:    (u64, u64),
+    written: (u64, u64),
+    bytes:   u64,
+}
+
+/// Fill `buf` from `from`, reading again after short reads; less than
+/// a full block only at the end of the input
+fn read_block(from: &mut Resource, buf: &mut [u8]) -> Result<usize, ()> {
+    let mut n = 0;
+    while n < buf.len() {
+        match from.read(&mut buf[n..])? {
+            0 => break,
+            got => n += got,
+        }
+    }
+    Ok(n)
+}
+
+/// Copy blocks until `count` were read or the input ends. On a failed
+/// read or write, what was copied before it is the error.
+fn copy(from: &mut Resource, to: &mut Resource, bs: usize, count: Option<u64>) -> Result<Copied, Copied> {
+    let mut buf = vec![0u8; bs];
+    let mut done = Copied::default();
+    while count.is_none_or(|c| done.read.0 + done.read.1 < c) {
+        let n = read_block(from, &mut buf).map_err(|_| done.clone())?;
+        if n == 0 {
+            break;
+        }
+        if n == bs { done.read.0 += 1 } else { done.read.1 += 1 }
+        let written = to.write(&buf[..n]).map_err(|_| done.clone())?;
+        if written == bs { done.written.0 += 1 } else { done.written.1 += 1 }
+        done.bytes += written as u64;
+        if n < bs || written < n {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+fn open_output(path: &str) -> Option<Resource> {
+    sys::fs::open_resource(path, sys::fs::O_WRONLY | sys::fs::O_CREATE | sys::fs::O_TRUNC)
+}
+
+pub fn run(args: &[&str]) {
+    let opts = match parse(args) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("dd: {}", e);
+            println!("dd: usage: dd if=<src> of=<dst> [bs=<n>] [count=<m>] [skip=<k>] [seek=<k>]");
+            return;
+        }
+    };
+    // Devices are there already; a file is created
+    let dst = if opts.output.starts_with("/dev/") {
+        sys::fs::canonicalize(opts.output)
+    } else {
+        sys::fs::canonicalize_new(opts.output)
+    };
+    let (src, dst) = match (sys::fs::canonicalize(opts.input), dst) {
+        (Ok(s), Ok(d)) => (s, d),
+        _ => { println!("dd: no such directory for '{}'", opts.output); return; }
+    };
+
+    let mut from = match sys::fs::open_resource(&src, 0) {
+        Some(r) => r,
+        None => { println!("dd: cannot open '{}'", opts.input); return; }
+    };
+    // Copied whole, these would fill the kernel heap
+    if opts.count.is_none() && matches!(from, Resource::Device(Device::Zero | Device::Random(_))) {
+        println!("dd: '{}' never ends, give a count=", opts.input);
+        return;
+    }
+    let mut to = match open_output(&dst) {
+        Some(r) => r,
+        None => { println!("dd: cannot open '{}'", opts.output); return; }
+    };
+    let bs = opts.bs as u64;
+    if from.seek(opts.skip * bs).is_err() {
+        println!("dd: cannot skip {} blocks of '{}'", opts.skip, opts.input);
+        return;
+    }
+    let seekable = matches!(to, Resource::Device(_)) || opts.seek == 0;
+    if !seekable || to.seek(opts.seek * bs).is_err() {
+        println!("dd: cannot seek {} blocks into '{}'", opts.seek, opts.output);
+        return;
+    }
+
+    let (done, ok) = match copy(&mut from, &mut to, opts.bs, opts.count) {
+        Ok(done) => (done, true),
+        Err(done) => (done, false),
+    };
+    let synced = to.sync().is_ok();
+    println!("{}+{} records in", done.read.0, done.read.1);
+    println!("{}+{} records out", done.written.0, done.written.1);
+    println!("{} bytes copied", done.bytes);
+    if !ok || !synced {
+        println!("dd: error writing '{}'", opts.output);
+    }
+}
+
+#[test_case]
+fn dd_copies_zero_blocks_to_a_file() {
+    let path = "/tmp/dd-zero";
+    sys::console::begin_capture();
+    run(&["if=/dev/zero", "of=/tmp/dd-zero", "bs=512", "count=4"]);
+    let out = sys::console::end_capture();
+    assert!(out.contains("4+0 records in\n4+0 records out\n2048 bytes copied"));
+
+    sys::console::begin_capture();
+    run(&["if=/dev/random", "of=/tmp/dd-endless"]);
+    let out = sys::console::end_capture();
+    assert!(out.contains("never ends") && !sys::fs::exists("/tmp/dd-endless"));
+
+    let file = sys::fs::open_file(path).unwrap();
+    assert_eq!(file.size(), 2048);
+    assert!(file.data().iter().all(|&b| b == 0));
+
+    // Skipping into a file and a short last block
+    sys::fs::write_file("/tmp/dd-src", b"0123456789").unwrap();
+    let mut from = sys::fs::open_resource("/tmp/dd-src", 0).unwrap();
+    let mut to = open_output(path).unwrap();
+    from.seek(4).unwrap();
+    let done = copy(&mut from, &mut to, 4, None).unwrap();
+    assert_eq!(done, Copied { read: (1, 1), written: (1, 1), bytes: 6 });
+    to.sync().unwrap();
+    assert_eq!(sys::fs::open_file(path).unwrap().data(), b"456789");
+
+    sys::fs::remove(path).ok();
+    sys::fs::remove("/tmp/dd-src").ok();
+}
@@ -0,0 +1,14 @@
+//! fs — filesystem maintenance
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    match args {
+        ["compact"] => {
+            let stats = sys::fs::compact();
+            println!("fs: compacted {} file(s), compressed {}, freed {} bytes",
+                     stats.files, stats.compressed, stats.freed);
+        }
+        _ => println!("fs: usage: fs compact"),
+    }
+}
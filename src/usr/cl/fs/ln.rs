@@ -0,0 +1,19 @@
+//! ln — create a hard link
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    if args.len() != 2 {
+        println!("ln: usage: ln <old> <new>");
+        return;
+    }
+    let (old, new) = match (sys::fs::canonicalize(args[0]), sys::fs::canonicalize_new(args[1])) {
+        (Ok(o), Ok(n)) => (o, n),
+        _ => { println!("ln: no such directory for '{}'", args[1]); return; }
+    };
+    if !sys::fs::exists(&old) {
+        println!("ln: file '{}' not found", args[0]);
+    } else if sys::fs::link(&old, &new).is_err() {
+        println!("ln: cannot create '{}': file exists", args[1]);
+    }
+}
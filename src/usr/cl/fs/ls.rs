@@ -1,23 +1,54 @@
-//! ls — list files in the filesystem
+//! ls — list a directory, the working directory by default
 
 use crate::sys;
 
 pub fn run(args: &[&str]) {
-    let dir = args.first().copied().unwrap_or("/");
+    let dir = args.first().copied().unwrap_or(".");
     let full_dir = match sys::fs::canonicalize(dir) {
         Ok(p) => p,
         Err(_) => { println!("ls: invalid path"); return; }
     };
+    if !sys::fs::dir_exists(&full_dir) {
+        println!("ls: No such directory '{}'", dir);
+        return;
+    }
 
-    let files = sys::fs::list_files(&full_dir);
-
-    if files.is_empty() {
+    let entries = sys::fs::read_dir(&full_dir);
+    if entries.is_empty() {
         println!("(empty)");
-    } else {
-        for f in files.iter().filter(|f| !f.name.ends_with("/.dir")) {
-            println!("  {:>8} B  {}", f.size, f.name);
+        return;
+    }
+    for e in &entries {
+        if e.is_dir {
+            println!("  {:>10}  {}/", "<dir>", e.name);
+        } else {
+            println!("  {:>8} B  {}", e.size, e.name);
         }
-        let visible = files.iter().filter(|f| !f.name.ends_with("/.dir")).count();
-        println!("--- {} file(s)", visible);
+    }
+    let dirs = entries.iter().filter(|e| e.is_dir).count();
+    println!("--- {} file(s), {} dir(s)", entries.len() - dirs, dirs);
+}
+
+#[test_case]
+fn ls_lists_the_working_directory() {
+    let saved = sys::process::cwd();
+    sys::fs::mkdir("/tmp/ls-test");
+    sys::fs::mkdir("/tmp/ls-test/sub");
+    sys::fs::write_file("/tmp/ls-test/notes", b"12345").unwrap();
+
+    sys::process::set_cwd("/tmp/ls-test");
+    sys::console::begin_capture();
+    run(&[]);
+    run(&["missing"]);
+    let out = sys::console::end_capture();
+    sys::process::set_cwd(&saved);
+
+    assert!(out.contains("         5 B  notes\n"));
+    assert!(out.contains("       <dir>  sub/\n"));
+    assert!(out.contains("--- 1 file(s), 1 dir(s)\n"));
+    assert!(out.contains("ls: No such directory 'missing'"));
+
+    for path in ["/tmp/ls-test/notes", "/tmp/ls-test/sub/.dir", "/tmp/ls-test/.dir"] {
+        sys::fs::remove(path).ok();
     }
 }
@@ -7,9 +7,9 @@ pub fn run(args: &[&str]) {
         Some(p) => p,
         None => { println!("mkdir: directory name required"); return; }
     };
-    let full_path = match sys::fs::canonicalize(path) {
+    let full_path = match sys::fs::canonicalize_new(path) {
         Ok(p) => p,
-        Err(_) => { println!("mkdir: invalid path"); return; }
+        Err(_) => { println!("mkdir: no such directory for '{}'", path); return; }
     };
     if sys::fs::dir_exists(&full_path) {
         println!("mkdir: '{}' already exists", full_path);
@@ -4,3 +4,9 @@ pub mod ls;
 pub mod cat;
 pub mod write;
 pub mod mkdir;
+pub mod cp;
+pub mod dd;
+pub mod fsctl;
+pub mod ln;
+pub mod mount;
+pub mod unpack;
@@ -0,0 +1,11 @@
+//! mount — list mounted filesystems
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    if !args.is_empty() {
+        println!("mount: usage: mount");
+        return;
+    }
+    print!("{}", sys::fs::mount::table());
+}
@@ -0,0 +1,22 @@
+//! unpack — extract a tar archive into the VFS
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    if args.is_empty() || args.len() > 2 {
+        println!("unpack: usage: unpack <archive> [dir]");
+        return;
+    }
+    let (archive, dest) = match (sys::fs::canonicalize(args[0]), sys::fs::canonicalize(args.get(1).unwrap_or(&"."))) {
+        (Ok(a), Ok(d)) => (a, d),
+        _ => { println!("unpack: invalid path"); return; }
+    };
+    let file = match sys::fs::open_file(&archive) {
+        Some(f) => f,
+        None => { println!("unpack: file '{}' not found", args[0]); return; }
+    };
+    match sys::fs::tar::unpack(file.data(), &dest) {
+        Ok(n)  => println!("Unpacked {} file(s) into '{}'", n, dest),
+        Err(_) => println!("unpack: '{}' is not a valid archive", args[0]),
+    }
+}
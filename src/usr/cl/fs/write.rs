@@ -9,9 +9,9 @@ pub fn run(args: &[&str]) {
     }
     let path = args[0];
     let text = args[1..].join(" ");
-    let full_path = match sys::fs::canonicalize(path) {
+    let full_path = match sys::fs::canonicalize_new(path) {
         Ok(p) => p,
-        Err(_) => { println!("write: invalid path"); return; }
+        Err(_) => { println!("write: no such directory for '{}'", path); return; }
     };
     let mut data = text.as_bytes().to_vec();
     data.push(b'\n');
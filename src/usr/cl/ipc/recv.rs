@@ -1,14 +1,57 @@
 //! recv — receive an IPC message
 
+use alloc::string::String;
+use core::fmt::Write;
+
 pub fn run() {
-    println!("recv: waiting for message...");
+    println!("recv: I am PID {}, waiting for message...", crate::api::process::getpid());
     let mut msg = crate::sys::ipc::Message::empty();
     let result = crate::api::syscall::recv(&mut msg);
     if result == 0 {
-        let data = &msg.data[..msg.data.iter().position(|&b| b == 0).unwrap_or(64)];
-        let text = alloc::string::String::from_utf8_lossy(data);
-        println!("recv: message from PID {} > {}", msg.sender, text);
+        let payload = msg.payload();
+        match text(payload) {
+            Some(text) => println!("recv: message from PID {} > {}", msg.sender, text),
+            None => {
+                println!("recv: message from PID {} ({} bytes)", msg.sender, payload.len());
+                print!("{}", hex_dump(payload));
+            }
+        }
     } else {
         println!("recv: failed to receive message");
     }
 }
+
+/// The payload as text, if it is UTF-8 without control characters
+fn text(payload: &[u8]) -> Option<&str> {
+    let s = core::str::from_utf8(payload).ok()?;
+    let printable = s.chars().all(|c| !c.is_control() || matches!(c, '\n' | '\t'));
+    printable.then_some(s)
+}
+
+/// 16 bytes per line: offset, hex bytes, then the printable ASCII
+fn hex_dump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        write!(out, "{:04x}: ", i * 16).ok();
+        for b in line {
+            write!(out, "{:02x} ", b).ok();
+        }
+        for _ in line.len()..16 {
+            out.push_str("   ");
+        }
+        out.push('|');
+        for &b in line {
+            out.push(if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' });
+        }
+        out.push_str("|\n");
+    }
+    out
+}
+
+#[test_case]
+fn embedded_nul_shows_whole_payload() {
+    let payload = b"ab\0cd";
+    assert_eq!(text(payload), None);
+    assert_eq!(hex_dump(payload), "0000: 61 62 00 63 64                                  |ab.cd|\n");
+    assert_eq!(text(b"hello world"), Some("hello world"));
+}
@@ -5,3 +5,4 @@ pub mod basic;
 pub mod fs;
 pub mod ipc;
 pub mod system;
+pub mod text;
@@ -5,7 +5,7 @@ use crate::api::process::ExitCode;
 use crate::usr::cl;
 use alloc::string::ToString;
 
-const PROMPT: &str = "\x1b[36mchilena\x1b[0m:\x1b[33m{cwd}\x1b[0m$ ";
+const PROMPT: &str = "\x1b[36m{user}@{host}\x1b[0m:\x1b[33m{cwd}\x1b[0m$ ";
 const BANNER: &str = r"
   ____  _     _ _                
  / ___|| |__ (_) | ___ _ __  __ _
@@ -19,12 +19,18 @@ const BANNER: &str = r"
 pub fn run_interactive() -> Result<(), ExitCode> {
     println!("{}", BANNER);
     println!("Chilena v{} — type 'help' for commands.\n", crate::VERSION);
+    cl::basic::alias::load();
+    sys::bootguard::boot_succeeded();
 
     loop {
         let prompt = build_prompt();
         print!("{}", prompt);
 
         let line = sys::console::read_line();
+        if line.is_empty() {
+            println!();
+            break; // end of input — the session is over
+        }
         let line = line.trim().to_string();
 
         if line.is_empty() { continue; }
@@ -33,9 +39,19 @@ pub fn run_interactive() -> Result<(), ExitCode> {
             break;
         }
     }
+    end_session();
     Ok(())
 }
 
+/// Ask the jobs started from this shell to exit, so none are left
+/// running unattended; `disown` exempts one
+fn end_session() {
+    let jobs = sys::process::hangup(sys::process::current_pid());
+    if jobs > 0 {
+        println!("shell: sent SIGTERM to {} job(s)", jobs);
+    }
+}
+
 /// Run a shell script from a file
 pub fn run_script(path: &str) -> Result<(), ExitCode> {
     use crate::sys::fs::FileIO;
@@ -58,11 +74,44 @@ pub fn run_script(path: &str) -> Result<(), ExitCode> {
 }
 
 fn build_prompt() -> alloc::string::String {
-    let cwd = sys::process::cwd();
-    PROMPT.replace("{cwd}", &cwd)
+    let user = sys::process::current_user().unwrap_or_else(|| "root".into());
+    PROMPT.replace("{user}", &user)
+        .replace("{host}", &sys::hostname::get())
+        .replace("{cwd}", &sys::process::cwd())
+}
+
+pub(crate) fn exec_line(line: &str) -> Result<(), ExitCode> {
+    if line.contains('|') {
+        return exec_pipeline(line);
+    }
+    exec_command(line)
+}
+
+/// Run `a | b | c`: each stage's output is captured and becomes the
+/// stdin of the next one. Stages run one after the other.
+fn exec_pipeline(line: &str) -> Result<(), ExitCode> {
+    let stages: alloc::vec::Vec<&str> = line.split('|').map(str::trim).collect();
+    if stages.iter().any(|s| s.is_empty()) {
+        println!("shell: syntax error near '|'");
+        return Ok(());
+    }
+
+    let mut input = None;
+    let mut result = Ok(());
+    for (i, stage) in stages.iter().enumerate() {
+        let last = i + 1 == stages.len();
+        sys::console::set_pipe_input(input.take());
+        if !last { sys::console::begin_capture(); }
+        result = exec_command(stage);
+        if !last { input = Some(sys::console::end_capture()); }
+        sys::console::set_pipe_input(None);
+        if result.is_err() { break; }
+    }
+    result
 }
 
-fn exec_line(line: &str) -> Result<(), ExitCode> {
+pub(crate) fn exec_command(line: &str) -> Result<(), ExitCode> {
+    let line = cl::basic::alias::expand(line);
     let parts: alloc::vec::Vec<&str> = line.split_whitespace().collect();
     if parts.is_empty() { return Ok(()); }
 
@@ -74,13 +123,31 @@ fn exec_line(line: &str) -> Result<(), ExitCode> {
         "help"    => cl::basic::help::run(),
         "echo"    => cl::basic::echo::run(args),
         "cd"      => cl::basic::cd::run(args),
+        "basename" => cl::basic::path::basename(args),
+        "dirname" => cl::basic::path::dirname(args),
         "info"    => cl::basic::info::run(),
+        "alias"   => cl::basic::alias::run(args),
+        "unalias" => cl::basic::alias::unalias(args),
+        "test" | "[" => return cl::basic::test::run(cmd, args),
 
         // fs
         "ls"      => cl::fs::ls::run(args),
         "cat"     => cl::fs::cat::run(args),
         "write"   => cl::fs::write::run(args),
         "mkdir"   => cl::fs::mkdir::run(args),
+        "cp"      => cl::fs::cp::run(args),
+        "ln"      => cl::fs::ln::run(args),
+        "mount"   => cl::fs::mount::run(args),
+        "fs"      => cl::fs::fsctl::run(args),
+        "unpack"  => cl::fs::unpack::run(args),
+        "dd"      => cl::fs::dd::run(args),
+
+        // text
+        "edit"    => cl::text::edit::run(args),
+        "wc"      => cl::text::wc::run(args),
+        "sort"    => cl::text::sort::run(args),
+        "uniq"    => cl::text::uniq::run(args),
+        "xargs"   => return cl::text::xargs::run(args),
 
         // ipc
         "send"    => cl::ipc::send::run(args),
@@ -88,7 +155,20 @@ fn exec_line(line: &str) -> Result<(), ExitCode> {
 
         // system
         "install" => cl::system::install::run(),
-        "reboot"  => cl::system::reboot::run(),
+        "dmesg"   => cl::system::dmesg::run(args),
+        "meminfo" => cl::system::meminfo::run(args),
+        "reboot"  => cl::system::reboot::run(args),
+        "halt"    => cl::system::reboot::halt(args),
+        "reset"   => cl::system::reset::run(),
+        "renice"  => cl::system::renice::run(args),
+        "nice"    => cl::system::nice::run(args),
+        "disown"  => cl::system::disown::run(args),
+        "ulimit"  => cl::system::ulimit::run(args),
+        "top"     => cl::system::top::run(args),
+        "ps"      => cl::system::ps::run(args),
+        "hostname" => cl::system::hostname::run(args),
+        "uptime"  => cl::system::uptime::run(args),
+        "kill"    => cl::system::kill::run(args),
 
         "exit"    => return Err(ExitCode::Success),
 
@@ -98,3 +178,121 @@ fn exec_line(line: &str) -> Result<(), ExitCode> {
     }
     Ok(())
 }
+
+#[test_case]
+fn pipeline_feeds_next_stage() {
+    sys::fs::write_file("/tmp/pipe-test", b"a b\nc\n").ok();
+
+    sys::console::begin_capture();
+    exec_line("cat /tmp/pipe-test | wc -l").ok();
+    let out = sys::console::end_capture();
+
+    assert_eq!(out.trim(), "2");
+    sys::fs::remove("/tmp/pipe-test").ok();
+}
+
+#[test_case]
+fn alias_runs_expansion() {
+    exec_line("alias p=echo").ok();
+
+    sys::console::begin_capture();
+    exec_line("p hi").ok();
+    let out = sys::console::end_capture();
+
+    exec_line("unalias p").ok();
+    assert_eq!(out, "hi\n");
+}
+
+#[test_case]
+fn closed_input_ends_session() {
+    sys::console::disable_echo();
+    sys::console::STDIN.lock().clear();
+    sys::console::input_char(sys::console::EOT);
+
+    sys::console::begin_capture();
+    let result = run_interactive();
+    sys::console::end_capture();
+
+    sys::console::enable_echo();
+    assert_eq!(result, Ok(()));
+}
+
+#[test_case]
+fn exit_terminates_jobs_unless_disowned() {
+    use sys::process::{Process, PROC_TABLE};
+    use sys::signal::{bit, SIGTERM};
+
+    let (job, kept) = (2, 3);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        for pid in [job, kept] {
+            let mut table = PROC_TABLE.write();
+            table[pid].id        = pid;
+            table[pid].parent_id = sys::process::current_pid();
+        }
+        sys::process::disown(kept).unwrap();
+
+        sys::console::begin_capture();
+        end_session();
+        let out = sys::console::end_capture();
+
+        let table = PROC_TABLE.read();
+        assert_ne!(table[job].signals.pending & bit(SIGTERM), 0);
+        assert_eq!(table[kept].signals.pending & bit(SIGTERM), 0);
+        assert!(out.contains("1 job(s)"));
+        drop(table);
+        for pid in [job, kept] {
+            PROC_TABLE.write()[pid] = alloc::boxed::Box::new(Process::new());
+        }
+    });
+}
+
+#[test_case]
+fn reset_restores_line_editing() {
+    use sys::console::{self, STDIN, BS};
+
+    console::enable_raw();
+    console::disable_echo();
+    exec_line("reset").ok();
+    assert!(!console::is_raw());
+
+    // Backspace edits the line again instead of being read as a key
+    console::begin_capture();
+    for c in ['a', 'b', BS] {
+        console::input_char(c);
+    }
+    console::end_capture();
+    assert_eq!(STDIN.lock().as_str(), "a");
+    STDIN.lock().clear();
+}
+
+#[test_case]
+fn mount_lists_root_and_disk() {
+    use sys::fs::mount::{self, FsKind};
+
+    sys::fs::mount_memfs();
+    mount::mount("/mnt", FsKind::Disk, "virtio0", false).unwrap();
+
+    sys::console::begin_capture();
+    exec_line("mount").ok();
+    let out = sys::console::end_capture();
+    mount::umount("/mnt").unwrap();
+
+    assert!(out.contains("memfs on / type memfs (rw)"));
+    assert!(out.contains("virtio0 on /mnt type disk (rw)"));
+    assert!(out.contains("on /proc type procfs"));
+}
+
+#[test_case]
+fn prompt_shows_the_new_hostname() {
+    exec_line("hostname testbox").ok();
+    let prompt = build_prompt();
+
+    sys::console::begin_capture();
+    exec_line("hostname").ok();
+    let out = sys::console::end_capture();
+
+    exec_line(&alloc::format!("hostname {}", sys::hostname::DEFAULT)).ok();
+    sys::fs::remove("/etc/hostname").ok();
+    assert!(prompt.contains("root@testbox:"));
+    assert_eq!(out, "testbox\n");
+}
@@ -0,0 +1,18 @@
+//! disown — keep a job running after the shell exits
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    let pid = match args {
+        [pid] => pid.parse::<usize>().ok(),
+        _ => None,
+    };
+    match pid {
+        Some(pid) => {
+            if sys::process::disown(pid).is_err() {
+                println!("disown: no such job: {}", pid);
+            }
+        }
+        None => println!("disown: usage: disown <pid>"),
+    }
+}
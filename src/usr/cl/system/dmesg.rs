@@ -0,0 +1,49 @@
+//! dmesg — show the kernel log
+
+use crate::sys;
+use crate::sys::fs::{FileIO, PollEvent};
+use crate::sys::log::{Kmsg, Level};
+use alloc::string::String;
+
+pub fn run(args: &[&str]) {
+    match args.first() {
+        Some(&"-n") => return set_level(args.get(1)),
+        Some(&"-w") | None => {}
+        Some(other) => {
+            println!("dmesg: unknown option '{}'", other);
+            println!("dmesg: usage: dmesg [-w] | dmesg -n <debug|info|warn|error>");
+            return;
+        }
+    }
+    let follow = args.first() == Some(&"-w");
+    if follow {
+        println!("(following — press Enter to stop)");
+    }
+
+    let mut kmsg  = Kmsg::new();
+    let mut stdin = sys::console::shared();
+    let mut buf   = [0u8; 256];
+    loop {
+        if kmsg.poll(PollEvent::Read) {
+            if let Ok(n) = kmsg.read(&mut buf) {
+                print!("{}", String::from_utf8_lossy(&buf[..n]));
+            }
+            continue;
+        }
+        if !follow {
+            break;
+        }
+        if stdin.poll(PollEvent::Read) {
+            sys::console::read_line();
+            break;
+        }
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
+fn set_level(arg: Option<&&str>) {
+    match arg.and_then(|s| Level::parse(s)) {
+        Some(level) => sys::log::set_log_level(level),
+        None => println!("dmesg: level must be one of debug, info, warn, error"),
+    }
+}
@@ -0,0 +1,18 @@
+//! hostname — show or set the system's name
+
+use crate::sys;
+use crate::sys::syscall::error::{EINVAL, EPERM};
+
+pub fn run(args: &[&str]) {
+    match args {
+        [] => println!("{}", sys::hostname::get()),
+        [name] => match sys::hostname::set(name) {
+            Ok(()) => {}
+            Err(EPERM) => println!("hostname: setting the hostname needs CAP_MOUNT"),
+            Err(EINVAL) => println!("hostname: '{}' is not a valid hostname (letters, digits and '-', up to {})",
+                name, sys::hostname::MAX_LEN),
+            Err(_) => println!("hostname: could not save the hostname"),
+        },
+        _ => println!("hostname: usage: hostname [name]"),
+    }
+}
@@ -0,0 +1,20 @@
+//! kill — terminate a process
+
+use crate::api;
+use crate::sys::signal::SIGKILL;
+
+pub fn run(args: &[&str]) {
+    if args.len() != 1 {
+        println!("kill: usage: kill <pid>");
+        return;
+    }
+    let pid: usize = match args[0].parse() {
+        Ok(p) => p,
+        Err(_) => { println!("kill: pid must be a number"); return; }
+    };
+    match api::syscall::kill(pid, SIGKILL) {
+        0 => {}
+        e if e == crate::sys::syscall::error::EPERM => println!("kill: not allowed to kill PID {}", pid),
+        _ => println!("kill: no process with PID {} that can be killed", pid),
+    }
+}
@@ -0,0 +1,14 @@
+//! meminfo — physical frame usage and the boot memory map
+
+use crate::sys;
+
+pub fn run(args: &[&str]) {
+    match args {
+        [] => {
+            let (total, used) = sys::mem::with_frame_allocator(|a| (a.total_frames(), a.used_frames()));
+            println!("frames: {} used of {} ({} KB free)", used, total, (total - used) * 4);
+        }
+        ["regions"] => print!("{}", sys::mem::iomem()),
+        _ => println!("meminfo: usage: meminfo [regions]"),
+    }
+}
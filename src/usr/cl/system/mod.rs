@@ -1,4 +1,16 @@
 //! system — system management commands
 
+pub mod disown;
+pub mod dmesg;
+pub mod hostname;
 pub mod install;
+pub mod kill;
+pub mod meminfo;
+pub mod nice;
+pub mod ps;
 pub mod reboot;
+pub mod renice;
+pub mod reset;
+pub mod top;
+pub mod ulimit;
+pub mod uptime;
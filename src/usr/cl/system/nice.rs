@@ -0,0 +1,22 @@
+//! nice — change the priority class of a process
+
+use crate::sys;
+use crate::sys::sched::{PRIO_HIGHEST, PRIO_LOWEST};
+
+pub fn run(args: &[&str]) {
+    if args.len() != 2 {
+        println!("nice: usage: nice <priority> <pid>");
+        return;
+    }
+    let prio: u8 = match args[0].parse() {
+        Ok(p) if (PRIO_HIGHEST..=PRIO_LOWEST).contains(&p) => p,
+        _ => { println!("nice: priority must be between {} and {}", PRIO_HIGHEST, PRIO_LOWEST); return; }
+    };
+    let pid: usize = match args[1].parse() {
+        Ok(p) => p,
+        Err(_) => { println!("nice: pid must be a number"); return; }
+    };
+    if sys::sched::set_priority(pid, prio).is_err() {
+        println!("nice: no process with PID {}", pid);
+    }
+}
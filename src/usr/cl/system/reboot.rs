@@ -1,6 +1,61 @@
-//! reboot — restart the system
+//! reboot / halt — restart or power off the system
+//!
+//! Open files are synced first. If user processes are still running the
+//! command refuses unless given `-f`, which stops them. `reboot` does a
+//! cold reset (triple fault) unless given `-w` for a BIOS warm reset.
 
-pub fn run() {
-    println!("Rebooting...");
-    unsafe { crate::sys::syscall::syscall1(crate::sys::syscall::number::HALT, 0xCAFE); }
+use crate::sys;
+use crate::sys::power::ResetKind;
+
+pub fn run(args: &[&str]) {
+    let kind = match reset_kind(args) {
+        Some(kind) => kind,
+        None => {
+            println!("reboot: usage: reboot [-f] [-w|-c]");
+            return;
+        }
+    };
+    if prepare("reboot", args) {
+        println!("Rebooting...");
+        sys::power::reset(kind);
+    }
+}
+
+pub fn halt(args: &[&str]) {
+    if prepare("halt", args) {
+        println!("Shutting down...");
+        sys::acpi::power_off();
+    }
+}
+
+/// `-w` asks for a warm reset, `-c` (the default) for a cold one
+fn reset_kind(args: &[&str]) -> Option<ResetKind> {
+    let mut kind = ResetKind::Cold;
+    for arg in args {
+        match *arg {
+            "-f" => {}
+            "-w" => kind = ResetKind::Warm,
+            "-c" => kind = ResetKind::Cold,
+            _ => return None,
+        }
+    }
+    Some(kind)
+}
+
+fn prepare(cmd: &str, args: &[&str]) -> bool {
+    match sys::power::prepare(args.contains(&"-f")) {
+        Ok(()) => true,
+        Err(p) => {
+            println!("{}: {} process(es) still running; use '{} -f' to stop them", cmd, p.jobs, cmd);
+            false
+        }
+    }
+}
+
+#[test_case]
+fn plain_reboot_stays_cold() {
+    assert_eq!(reset_kind(&[]), Some(ResetKind::Cold));
+    assert_eq!(reset_kind(&["-f"]), Some(ResetKind::Cold));
+    assert_eq!(reset_kind(&["-f", "-w"]), Some(ResetKind::Warm));
+    assert_eq!(reset_kind(&["--now"]), None);
 }
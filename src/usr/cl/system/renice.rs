@@ -0,0 +1,22 @@
+//! renice — change the priority of a process
+
+use crate::sys;
+use crate::sys::sched::{NICE_MAX, NICE_MIN};
+
+pub fn run(args: &[&str]) {
+    if args.len() != 2 {
+        println!("renice: usage: renice <nice> <pid>");
+        return;
+    }
+    let nice: i8 = match args[0].parse() {
+        Ok(n) if (NICE_MIN..=NICE_MAX).contains(&n) => n,
+        _ => { println!("renice: nice must be between {} and {}", NICE_MIN, NICE_MAX); return; }
+    };
+    let pid: usize = match args[1].parse() {
+        Ok(p) => p,
+        Err(_) => { println!("renice: pid must be a number"); return; }
+    };
+    if sys::sched::set_nice(pid, nice).is_err() {
+        println!("renice: no process with PID {}", pid);
+    }
+}
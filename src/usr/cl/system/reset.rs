@@ -0,0 +1,10 @@
+//! reset — restore the terminal after a program left it in a bad state
+
+use crate::sys;
+
+pub fn run() {
+    sys::console::reset();
+    // Serial terminals: default colors, cursor visible, screen cleared
+    print!("\x1b[0m\x1b[?25h\x1b[2J\x1b[H");
+    sys::vga::reset();
+}
@@ -0,0 +1,113 @@
+//! top — live process monitor
+//!
+//! Redraws a full-screen table of processes every second, busiest first,
+//! until `q` is pressed. %CPU is the share of the last interval a process
+//! spent in userspace.
+
+use crate::sys;
+use crate::sys::console;
+use crate::sys::process::ProcStat;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Time between refreshes
+const INTERVAL_NS: u64 = 1_000_000_000;
+
+/// CPU time of each pid at the previous refresh
+type Sample = BTreeMap<usize, u64>;
+
+pub fn run(args: &[&str]) {
+    if !args.is_empty() {
+        println!("top: usage: top");
+        return;
+    }
+    console::enable_raw();
+    let mut prev = Sample::new();
+    let mut last = sys::clk::uptime_ns();
+    loop {
+        let now   = sys::clk::uptime_ns();
+        let procs = sys::process::stats();
+        let (_, rows) = sys::vga::size();
+        print!("{}", render(&procs, &prev, now - last, rows));
+        prev = procs.iter().map(|p| (p.pid, p.cpu_ns)).collect();
+        last = now;
+        if wait_for_quit(now + INTERVAL_NS) {
+            break;
+        }
+    }
+    console::disable_raw();
+    print!("\x1b[2J\x1b[H");
+}
+
+/// Wait until the uptime reaches `until`; true if `q` was pressed first
+fn wait_for_quit(until: u64) -> bool {
+    while sys::clk::uptime_ns() < until {
+        match console::try_read_char() {
+            Some('q' | 'Q') => return true,
+            Some(_) => {}
+            None => x86_64::instructions::interrupts::enable_and_hlt(),
+        }
+    }
+    false
+}
+
+/// Share of the last `elapsed` ns `p` spent running, in tenths of a
+/// percent. Nothing is known about a process missing from `prev` yet.
+fn cpu_tenths(p: &ProcStat, prev: &Sample, elapsed: u64) -> u64 {
+    match prev.get(&p.pid) {
+        Some(&before) if elapsed > 0 => (p.cpu_ns.saturating_sub(before) * 1000 / elapsed).min(1000),
+        _ => 0,
+    }
+}
+
+/// One screen of `rows` lines: a summary, the column titles and as many
+/// processes as fit, busiest first
+fn render(procs: &[ProcStat], prev: &Sample, elapsed: u64, rows: usize) -> String {
+    let mut busiest: Vec<(u64, &ProcStat)> = procs.iter().map(|p| (cpu_tenths(p, prev, elapsed), p)).collect();
+    busiest.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.pid.cmp(&b.1.pid)));
+
+    let running = procs.iter().filter(|p| p.state == 'R').count();
+    let mut out = String::from("\x1b[2J\x1b[H");
+    writeln!(out, "{} processes, {} running — q to quit", procs.len(), running).ok();
+    write!(out, "  PID  PPID S  %CPU  MEM(K) COMMAND").ok();
+    for (tenths, p) in busiest.iter().take(rows.saturating_sub(2)) {
+        write!(out, "\n{:5} {:5} {} {:3}.{} {:7} {}",
+            p.pid, p.parent, p.state, tenths / 10, tenths % 10, p.memory / 1024, p.name).ok();
+    }
+    out
+}
+
+#[test_case]
+fn top_shows_cpu_usage_as_it_changes() {
+    use crate::sys::process::{Process, PROC_TABLE};
+    use alloc::boxed::Box;
+
+    let pid = 2;
+    let row = |out: &str| String::from(out.lines().find(|l| l.ends_with(" spin")).unwrap());
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        {
+            let mut table = PROC_TABLE.write();
+            table[pid].id   = pid;
+            table[pid].name = "spin".into();
+        }
+
+        // The first screen has nothing to compare against yet
+        let first = sys::process::stats();
+        assert!(first.iter().any(|p| p.pid == pid && p.state == 'R'));
+        let out = render(&first, &Sample::new(), 0, 25);
+        assert_eq!(row(&out), "    2     0 R   0.0       0 spin");
+
+        // Half of the next second spent running
+        PROC_TABLE.write()[pid].cpu_ns += INTERVAL_NS / 2;
+        let prev: Sample = first.iter().map(|p| (p.pid, p.cpu_ns)).collect();
+        let out = render(&sys::process::stats(), &prev, INTERVAL_NS, 25);
+        assert_eq!(row(&out), "    2     0 R  50.0       0 spin");
+
+        // Busiest first, right under the column titles
+        assert!(out.lines().nth(2).unwrap().ends_with(" spin"));
+
+        PROC_TABLE.write()[pid] = Box::new(Process::new());
+    });
+}
@@ -0,0 +1,50 @@
+//! ulimit — show or set resource limits of the shell (inherited by children)
+
+use crate::sys;
+use crate::sys::process::{
+    RLimit, RLIM_INFINITY, RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIMIT_SPAWN,
+};
+
+const LIMITS: [(&str, usize, &str); 4] = [
+    ("-n", RLIMIT_NOFILE, "open handles"),
+    ("-v", RLIMIT_AS,     "memory (bytes)"),
+    ("-t", RLIMIT_CPU,    "cpu time (ms)"),
+    ("-r", RLIMIT_SPAWN,  "spawns/second"),
+];
+
+pub fn run(args: &[&str]) {
+    if args.is_empty() {
+        for (flag, res, name) in LIMITS {
+            if let Some(limit) = sys::process::rlimit(res) {
+                println!("  {}  {:<16} {}", flag, name, show(limit.soft));
+            }
+        }
+        return;
+    }
+
+    let res = match LIMITS.iter().find(|(flag, _, _)| *flag == args[0]) {
+        Some((_, res, _)) => *res,
+        None => { println!("ulimit: usage: ulimit [-n|-v|-t|-r] [value|unlimited]"); return; }
+    };
+
+    let limit = match sys::process::rlimit(res) {
+        Some(l) => l,
+        None => return,
+    };
+    let value = match args.get(1) {
+        None => { println!("{}", show(limit.soft)); return; }
+        Some(&"unlimited") => RLIM_INFINITY,
+        Some(v) => match v.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => { println!("ulimit: invalid value '{}'", v); return; }
+        },
+    };
+
+    if sys::process::set_rlimit(res, RLimit { soft: value, hard: limit.hard }).is_err() {
+        println!("ulimit: value exceeds hard limit ({})", show(limit.hard));
+    }
+}
+
+fn show(v: usize) -> alloc::string::String {
+    if v == RLIM_INFINITY { "unlimited".into() } else { alloc::format!("{}", v) }
+}
@@ -0,0 +1,45 @@
+//! uptime — how long the system has been up, and how busy it has been
+
+use crate::sys;
+use crate::sys::sched::LOAD_ONE;
+use alloc::format;
+use alloc::string::String;
+
+pub fn run(args: &[&str]) {
+    if !args.is_empty() {
+        println!("uptime: usage: uptime");
+        return;
+    }
+    let [one, five, fifteen] = sys::sched::load_average();
+    println!("up {}, load average: {}, {}, {}",
+        since_boot(sys::clk::uptime_ns() / 1_000_000_000),
+        load(one), load(five), load(fifteen));
+}
+
+/// `2 days, 3 hours, 4 min`, leaving out leading zero days and hours
+fn since_boot(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86_400, secs / 3_600 % 24, secs / 60 % 60);
+    let plural = |n: u64| if n == 1 { "" } else { "s" };
+    let mut out = String::new();
+    if days > 0 {
+        out += &format!("{} day{}, ", days, plural(days));
+    }
+    if days > 0 || hours > 0 {
+        out += &format!("{} hour{}, ", hours, plural(hours));
+    }
+    out + &format!("{} min", mins)
+}
+
+/// A fixed point load to two decimals
+fn load(value: u64) -> String {
+    format!("{}.{:02}", value / LOAD_ONE, value % LOAD_ONE * 100 / LOAD_ONE)
+}
+
+#[test_case]
+fn uptime_is_spelled_out_and_loads_have_two_decimals() {
+    assert_eq!(since_boot(59), "0 min");
+    assert_eq!(since_boot(3_600 + 5 * 60), "1 hour, 5 min");
+    assert_eq!(since_boot(2 * 86_400 + 60), "2 days, 0 hours, 1 min");
+    assert_eq!(load(0), "0.00");
+    assert_eq!(load(LOAD_ONE * 3 / 2), "1.50");
+}
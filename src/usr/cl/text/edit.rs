@@ -0,0 +1,247 @@
+//! edit — minimal full-screen text editor
+//!
+//! Arrow keys / Home / End move, Enter splits a line, Backspace and
+//! Delete join lines at the edges. Ctrl+S saves, Ctrl+Q quits (twice
+//! if there are unsaved changes).
+
+use crate::sys;
+use crate::sys::console;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const CTRL_Q: char = '\x11';
+const CTRL_S: char = '\x13';
+const DEL:    char = '\x7F';
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Save,
+    Quit,
+}
+
+struct Editor {
+    lines: Vec<Vec<char>>,
+    row:   usize,
+    col:   usize,
+    /// First line shown on screen
+    top:   usize,
+    dirty: bool,
+    /// Ctrl+Q was refused over unsaved changes; another one right away quits
+    quit_armed: bool,
+}
+
+impl Editor {
+    fn from_text(text: &str) -> Self {
+        let mut lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+        if lines.is_empty() {
+            lines.push(Vec::new());
+        }
+        Self { lines, row: 0, col: 0, top: 0, dirty: false, quit_armed: false }
+    }
+
+    fn text(&self) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            out.extend(line.iter());
+            out.push('\n');
+        }
+        out
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].len()
+    }
+
+    /// Apply an editing or movement key
+    fn apply(&mut self, key: Key) {
+        self.quit_armed = false;
+        match key {
+            Key::Char(c) => {
+                self.lines[self.row].insert(self.col, c);
+                self.col += 1;
+                self.dirty = true;
+            }
+            Key::Enter => {
+                let rest = self.lines[self.row].split_off(self.col);
+                self.lines.insert(self.row + 1, rest);
+                self.row += 1;
+                self.col = 0;
+                self.dirty = true;
+            }
+            Key::Backspace => {
+                if self.col > 0 {
+                    self.col -= 1;
+                    self.lines[self.row].remove(self.col);
+                    self.dirty = true;
+                } else if self.row > 0 {
+                    let line = self.lines.remove(self.row);
+                    self.row -= 1;
+                    self.col = self.line_len();
+                    self.lines[self.row].extend(line);
+                    self.dirty = true;
+                }
+            }
+            Key::Delete => {
+                if self.col < self.line_len() {
+                    self.lines[self.row].remove(self.col);
+                    self.dirty = true;
+                } else if self.row + 1 < self.lines.len() {
+                    let next = self.lines.remove(self.row + 1);
+                    self.lines[self.row].extend(next);
+                    self.dirty = true;
+                }
+            }
+            Key::Up    => if self.row > 0 { self.row -= 1 },
+            Key::Down  => if self.row + 1 < self.lines.len() { self.row += 1 },
+            Key::Left  => if self.col > 0 { self.col -= 1 },
+            Key::Right => if self.col < self.line_len() { self.col += 1 },
+            Key::Home  => self.col = 0,
+            Key::End   => self.col = self.line_len(),
+            Key::Save | Key::Quit => {}
+        }
+        self.col = self.col.min(self.line_len());
+    }
+
+    fn save(&mut self, path: &str) -> Result<(), ()> {
+        sys::fs::write_file(path, self.text().as_bytes())?;
+        self.dirty = false;
+        self.quit_armed = false;
+        Ok(())
+    }
+
+    /// May Ctrl+Q quit now? With unsaved changes it takes two in a row.
+    fn may_quit(&mut self) -> bool {
+        let confirmed = !self.dirty || self.quit_armed;
+        self.quit_armed = !confirmed;
+        confirmed
+    }
+
+    /// Redraw the whole screen, keeping the cursor line visible
+    fn draw(&mut self, path: &str, status: &str) {
+        let (cols, rows) = sys::vga::size();
+        let height = rows - 1; // last row is the status line
+
+        if self.row < self.top {
+            self.top = self.row;
+        } else if self.row >= self.top + height {
+            self.top = self.row + 1 - height;
+        }
+
+        let mut screen = String::from("\x1b[2J\x1b[H");
+        for line in self.lines.iter().skip(self.top).take(height) {
+            screen.extend(line.iter().take(cols - 1));
+            screen.push('\n');
+        }
+        screen.push_str(&alloc::format!(
+            "\x1b[{};1H-- {}{} -- {}",
+            rows, path, if self.dirty { " [+]" } else { "" }, status
+        ));
+        screen.push_str(&alloc::format!(
+            "\x1b[{};{}H", self.row - self.top + 1, self.col.min(cols - 1) + 1
+        ));
+        print!("{}", screen);
+    }
+}
+
+/// Read one key from the raw console, decoding VT100 escape sequences
+fn read_key() -> Key {
+    loop {
+        let key = match console::read_char() {
+            CTRL_S         => Key::Save,
+            CTRL_Q         => Key::Quit,
+            '\n' | '\r'    => Key::Enter,
+            console::BS    => Key::Backspace,
+            DEL            => Key::Delete,
+            console::ESC   => {
+                if console::read_char() != '[' { continue; }
+                match console::read_char() {
+                    'A' => Key::Up,
+                    'B' => Key::Down,
+                    'C' => Key::Right,
+                    'D' => Key::Left,
+                    'H' => Key::Home,
+                    'F' => Key::End,
+                    '3' => { console::read_char(); Key::Delete } // ESC [ 3 ~
+                    _   => continue,
+                }
+            }
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        return key;
+    }
+}
+
+pub fn run(args: &[&str]) {
+    let path = match args.first() {
+        Some(p) => p,
+        None => { println!("edit: usage: edit <file>"); return; }
+    };
+    let full_path = match sys::fs::canonicalize_new(path) {
+        Ok(p) => p,
+        Err(_) => { println!("edit: no such directory for '{}'", path); return; }
+    };
+
+    let text = sys::fs::open_file(&full_path)
+        .map(|f| String::from_utf8_lossy(f.data()).into_owned())
+        .unwrap_or_default();
+    let mut ed = Editor::from_text(&text);
+
+    console::enable_raw();
+    let mut status = "Ctrl+S save, Ctrl+Q quit";
+    loop {
+        ed.draw(&full_path, status);
+        status = "Ctrl+S save, Ctrl+Q quit";
+        match read_key() {
+            Key::Save => {
+                status = if ed.save(&full_path).is_ok() { "saved" } else { "save failed" };
+            }
+            Key::Quit if ed.may_quit() => break,
+            Key::Quit => status = "unsaved changes — Ctrl+Q again to discard",
+            key => ed.apply(key),
+        }
+    }
+    console::disable_raw();
+    print!("\x1b[2J\x1b[H");
+}
+
+#[test_case]
+fn edit_save_and_reopen() {
+    let path = "/tmp/edit-test";
+    sys::fs::write_file(path, b"one\ntwo\n").ok();
+
+    let text = String::from_utf8_lossy(sys::fs::open_file(path).unwrap().data()).into_owned();
+    let mut ed = Editor::from_text(&text);
+    for key in [Key::Down, Key::End, Key::Char('!'), Key::Enter, Key::Char('3')] {
+        ed.apply(key);
+    }
+    assert!(ed.dirty);
+    ed.save(path).unwrap();
+
+    assert_eq!(sys::fs::open_file(path).unwrap().data(), b"one\ntwo!\n3\n");
+    sys::fs::remove(path).ok();
+}
+
+#[test_case]
+fn quit_with_unsaved_changes_needs_confirming() {
+    let mut ed = Editor::from_text("one\n");
+    assert!(ed.may_quit());
+
+    ed.apply(Key::Char('!'));
+    assert!(!ed.may_quit());
+    assert!(ed.dirty);
+    // Any other key in between asks again
+    ed.apply(Key::Left);
+    assert!(!ed.may_quit());
+    assert!(ed.may_quit());
+}
@@ -0,0 +1,57 @@
+//! text — text processing commands
+//!
+//! Commands here read a file argument or, without one, stdin — which is
+//! the previous stage's output when run inside a `|` pipeline. Outside
+//! one they print their usage rather than wait on the keyboard.
+
+pub mod edit;
+pub mod sort;
+pub mod uniq;
+pub mod wc;
+pub mod xargs;
+
+use crate::sys;
+use crate::sys::fs::FileIO;
+use alloc::string::String;
+
+/// Feed the input of `cmd` to `f` chunk by chunk: the file at `path`, or
+/// stdin until EOF. Prints an error and returns Err if the file is missing.
+pub(crate) fn for_each_chunk(cmd: &str, path: Option<&str>, mut f: impl FnMut(&[u8])) -> Result<(), ()> {
+    match path {
+        Some(path) => {
+            let full = sys::fs::canonicalize(path)?;
+            let mut file = match sys::fs::open_file(&full) {
+                Some(file) => file,
+                None => { println!("{}: file '{}' not found", cmd, path); return Err(()); }
+            };
+            let mut buf = [0u8; 512];
+            while let Ok(n @ 1..) = file.read(&mut buf) {
+                f(&buf[..n]);
+            }
+        }
+        None => loop {
+            let line = sys::console::read_line();
+            if line.is_empty() { break; }
+            f(line.as_bytes());
+        },
+    }
+    Ok(())
+}
+
+/// Whether there is input to read at `path`: a file, or stdin fed by a
+/// pipeline. Reading the keyboard instead would sit waiting for Ctrl+D,
+/// so print `usage` and say no.
+pub fn has_input(path: Option<&str>, usage: &str) -> bool {
+    if path.is_none() && !sys::console::is_piped() {
+        println!("{}", usage);
+        return false;
+    }
+    true
+}
+
+/// Whole input of `cmd` as a string (see `for_each_chunk`)
+pub fn read_input(cmd: &str, path: Option<&str>) -> Option<String> {
+    let mut data = alloc::vec::Vec::new();
+    for_each_chunk(cmd, path, |chunk| data.extend_from_slice(chunk)).ok()?;
+    Some(String::from_utf8_lossy(&data).into_owned())
+}
@@ -0,0 +1,72 @@
+//! sort — sort lines of text
+
+use super::{has_input, read_input};
+use alloc::vec::Vec;
+
+const USAGE: &str = "sort: usage: sort [-n] [-r] [file]";
+
+/// Numeric sort key: the leading number of the line (non-numbers sort first)
+fn number(line: &str) -> Option<i64> {
+    let line = line.trim_start();
+    let end = line
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+    line[..end].parse().ok()
+}
+
+fn sort_lines(lines: &mut [&str], numeric: bool, reverse: bool) {
+    lines.sort_by(|a, b| {
+        let ord = if numeric {
+            number(a).cmp(&number(b)).then_with(|| a.cmp(b))
+        } else {
+            a.cmp(b)
+        };
+        if reverse { ord.reverse() } else { ord }
+    });
+}
+
+pub fn run(args: &[&str]) {
+    let (mut numeric, mut reverse, mut path) = (false, false, None);
+    for arg in args {
+        match *arg {
+            "-n" => numeric = true,
+            "-r" => reverse = true,
+            "-nr" | "-rn" => { numeric = true; reverse = true; }
+            a if a.starts_with('-') => {
+                println!("sort: unknown option '{}'", a);
+                println!("{}", USAGE);
+                return;
+            }
+            a => path = Some(a),
+        }
+    }
+
+    if !has_input(path, USAGE) {
+        return;
+    }
+
+    let text = match read_input("sort", path) {
+        Some(t) => t,
+        None => return,
+    };
+    let mut lines: Vec<&str> = text.lines().collect();
+    sort_lines(&mut lines, numeric, reverse);
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+#[test_case]
+fn sorts_lexically_and_numerically() {
+    let mut lines = ["pear", "10 b", "apple", "9 a"];
+    sort_lines(&mut lines, false, false);
+    assert_eq!(lines, ["10 b", "9 a", "apple", "pear"]);
+
+    sort_lines(&mut lines, true, false);
+    assert_eq!(lines, ["apple", "pear", "9 a", "10 b"]);
+
+    sort_lines(&mut lines, false, true);
+    assert_eq!(lines[0], "pear");
+}
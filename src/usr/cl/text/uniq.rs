@@ -0,0 +1,55 @@
+//! uniq — collapse adjacent duplicate lines
+
+use super::{has_input, read_input};
+use alloc::vec::Vec;
+
+const USAGE: &str = "uniq: usage: uniq [-c] [file]";
+
+/// Adjacent runs of equal lines, with their lengths
+fn runs<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<(usize, &'a str)> {
+    let mut out: Vec<(usize, &str)> = Vec::new();
+    for line in lines {
+        match out.last_mut() {
+            Some((n, prev)) if *prev == line => *n += 1,
+            _ => out.push((1, line)),
+        }
+    }
+    out
+}
+
+pub fn run(args: &[&str]) {
+    let (mut count, mut path) = (false, None);
+    for arg in args {
+        match *arg {
+            "-c" => count = true,
+            a if a.starts_with('-') => {
+                println!("uniq: unknown option '{}'", a);
+                println!("{}", USAGE);
+                return;
+            }
+            a => path = Some(a),
+        }
+    }
+
+    if !has_input(path, USAGE) {
+        return;
+    }
+
+    let text = match read_input("uniq", path) {
+        Some(t) => t,
+        None => return,
+    };
+    for (n, line) in runs(text.lines()) {
+        if count {
+            println!("{:>7} {}", n, line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+#[test_case]
+fn collapses_adjacent_repeats() {
+    let text = "a\na\nb\na\na\na\n";
+    assert_eq!(runs(text.lines()), [(2, "a"), (1, "b"), (3, "a")]);
+}
@@ -0,0 +1,88 @@
+//! wc — count lines, words and bytes
+
+use super::{for_each_chunk, has_input};
+
+const USAGE: &str = "wc: usage: wc [-l] [-w] [-c] [file]";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+    /// Whether the previous chunk ended inside a word
+    in_word: bool,
+}
+
+impl Counts {
+    fn add(&mut self, chunk: &[u8]) {
+        self.bytes += chunk.len();
+        for &b in chunk {
+            if b == b'\n' {
+                self.lines += 1;
+            }
+            if b.is_ascii_whitespace() {
+                self.in_word = false;
+            } else if !self.in_word {
+                self.in_word = true;
+                self.words += 1;
+            }
+        }
+    }
+}
+
+pub fn run(args: &[&str]) {
+    let mut show = (false, false, false);
+    let mut path = None;
+    for arg in args {
+        match *arg {
+            "-l" => show.0 = true,
+            "-w" => show.1 = true,
+            "-c" => show.2 = true,
+            a if a.starts_with('-') => {
+                println!("wc: unknown option '{}'", a);
+                println!("{}", USAGE);
+                return;
+            }
+            a => path = Some(a),
+        }
+    }
+    if show == (false, false, false) {
+        show = (true, true, true);
+    }
+
+    if !has_input(path, USAGE) {
+        return;
+    }
+
+    let mut counts = Counts::default();
+    if for_each_chunk("wc", path, |chunk| counts.add(chunk)).is_err() {
+        return;
+    }
+
+    let mut out = alloc::string::String::new();
+    for (on, n) in [(show.0, counts.lines), (show.1, counts.words), (show.2, counts.bytes)] {
+        if on {
+            out.push_str(&alloc::format!("{:>7}", n));
+        }
+    }
+    match path {
+        Some(p) => println!("{} {}", out, p),
+        None    => println!("{}", out),
+    }
+}
+
+#[test_case]
+fn counts_across_chunks() {
+    let mut c = Counts::default();
+    c.add(b"hello wor");
+    c.add(b"ld\n  two\nthree");
+    assert_eq!((c.lines, c.words, c.bytes), (2, 4, 24));
+}
+
+#[test_case]
+fn keyboard_stdin_gets_usage_instead_of_waiting() {
+    crate::sys::console::begin_capture();
+    run(&["-l"]);
+    let out = crate::sys::console::end_capture();
+    assert_eq!(out.trim(), USAGE);
+}
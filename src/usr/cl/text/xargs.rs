@@ -0,0 +1,75 @@
+//! xargs — build command lines from stdin
+//!
+//! `xargs [-n N] <command> [args]` reads whitespace-separated words from
+//! stdin and runs the command with them appended, all at once or N at a
+//! time with `-n`. Without a command it runs `echo`.
+
+use super::{has_input, read_input};
+use crate::api::process::ExitCode;
+use crate::usr::cl::shell;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const USAGE: &str = "xargs: usage: xargs [-n N] <command> [args]";
+
+pub fn run(args: &[&str]) -> Result<(), ExitCode> {
+    let (batch, cmd) = match args {
+        ["-n", n, rest @ ..] => match n.parse::<usize>() {
+            Ok(n) if n > 0 => (Some(n), rest),
+            _ => {
+                println!("xargs: invalid count '{}'", n);
+                return Err(ExitCode::Failure);
+            }
+        },
+        _ => (None, args),
+    };
+    let cmd = if cmd.is_empty() { &["echo"][..] } else { cmd };
+
+    if !has_input(None, USAGE) {
+        return Err(ExitCode::Failure);
+    }
+    let input = read_input("xargs", None).unwrap_or_default();
+    let words: Vec<&str> = input.split_whitespace().collect();
+
+    let mut result = Ok(());
+    for line in command_lines(cmd, &words, batch) {
+        // Only the words are input; the command itself reads no stdin
+        crate::sys::console::set_pipe_input(Some(String::new()));
+        if shell::exec_command(&line).is_err() {
+            result = Err(ExitCode::Failure);
+        }
+    }
+    crate::sys::console::set_pipe_input(None);
+    result
+}
+
+/// One command line per batch of `words` (all of them when `batch` is None)
+fn command_lines(cmd: &[&str], words: &[&str], batch: Option<usize>) -> Vec<String> {
+    let base = cmd.join(" ");
+    let chunk = batch.unwrap_or(words.len()).max(1);
+    if words.is_empty() {
+        // Like GNU xargs, run the command once even with no input
+        return alloc::vec![base];
+    }
+    words.chunks(chunk)
+        .map(|part| alloc::format!("{} {}", base, part.join(" ")))
+        .collect()
+}
+
+#[test_case]
+fn echo_words_together_or_one_by_one() {
+    use crate::sys::console;
+
+    console::begin_capture();
+    shell::exec_line("echo a b c | xargs echo").ok();
+    assert_eq!(console::end_capture(), "a b c\n");
+
+    console::begin_capture();
+    shell::exec_line("echo a b c | xargs -n 1 echo").ok();
+    assert_eq!(console::end_capture(), "a\nb\nc\n");
+
+    // Nothing piped in: usage, not a wait on the keyboard
+    console::begin_capture();
+    assert!(shell::exec_line("xargs echo").is_err());
+    assert_eq!(console::end_capture().trim(), USAGE);
+}